@@ -1,4 +1,4 @@
--- database: ../database.sqlite
+-- Schema as first shipped
 -- USERS
 CREATE TABLE IF NOT EXISTS user (
     user_id TEXT PRIMARY KEY,
@@ -52,7 +52,6 @@ CREATE TABLE IF NOT EXISTS message (
     is_read INTEGER NOT NULL DEFAULT 0,
     is_sent INTEGER NOT NULL DEFAULT 0,
     is_delivered INTEGER NOT NULL DEFAULT 0,
-    is_failed INTEGER NOT NULL DEFAULT 0,
     sender_username TEXT,
     reply_to_message_id TEXT
 );
@@ -98,15 +97,3 @@ CREATE TABLE IF NOT EXISTS user_keys (
     private_key3 TEXT,
     private_key4 TEXT
 );
-
--- LOCAL DELETES (for tracking locally deleted chats to prevent re-adding)
-CREATE TABLE IF NOT EXISTS local_deletes (
-    chat_id TEXT PRIMARY KEY,
-    deleted_at INTEGER NOT NULL,
-    user_id TEXT NOT NULL,
-    is_creator INTEGER NOT NULL DEFAULT 0
-);
-
--- Create index for local deletes
-CREATE INDEX IF NOT EXISTS idx_local_deletes_user_id ON local_deletes(user_id);
-CREATE INDEX IF NOT EXISTS idx_local_deletes_chat_id ON local_deletes(chat_id);
@@ -0,0 +1,11 @@
+-- LOCAL DELETES (for tracking locally deleted chats to prevent re-adding)
+CREATE TABLE IF NOT EXISTS local_deletes (
+    chat_id TEXT PRIMARY KEY,
+    deleted_at INTEGER NOT NULL,
+    user_id TEXT NOT NULL,
+    is_creator INTEGER NOT NULL DEFAULT 0
+);
+
+-- Create index for local deletes
+CREATE INDEX IF NOT EXISTS idx_local_deletes_user_id ON local_deletes(user_id);
+CREATE INDEX IF NOT EXISTS idx_local_deletes_chat_id ON local_deletes(chat_id);
@@ -0,0 +1,8 @@
+-- PRESENCE (last known status of friends and chat participants)
+-- last_seen and updated_at are Unix milliseconds
+CREATE TABLE IF NOT EXISTS presence (
+    user_id TEXT PRIMARY KEY,
+    status TEXT NOT NULL DEFAULT 'offline',
+    last_seen INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,23 @@
+-- MESSAGE SEARCH (FTS5 index over message.content, kept in sync by triggers)
+CREATE VIRTUAL TABLE IF NOT EXISTS message_fts USING fts5(
+    content,
+    content = 'message',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS message_fts_after_insert AFTER INSERT ON message BEGIN
+    INSERT INTO message_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS message_fts_after_delete AFTER DELETE ON message BEGIN
+    INSERT INTO message_fts(message_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS message_fts_after_update AFTER UPDATE OF content ON message BEGIN
+    INSERT INTO message_fts(message_fts, rowid, content) VALUES ('delete', old.id, old.content);
+    INSERT INTO message_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+-- Index the history that existed before this migration
+INSERT INTO message_fts(message_fts) VALUES ('rebuild');
@@ -0,0 +1,76 @@
+-- Rebuild message and participant with foreign keys to chat so deleting a chat
+-- removes its messages and participants with it. Rows whose chat is already gone
+-- are orphans and are not copied over.
+
+-- MESSAGES
+CREATE TABLE message_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id TEXT,
+    client_message_id TEXT UNIQUE NOT NULL,
+    chat_id TEXT NOT NULL REFERENCES chat(chat_id) ON DELETE CASCADE,
+    sender_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    is_read INTEGER NOT NULL DEFAULT 0,
+    is_sent INTEGER NOT NULL DEFAULT 0,
+    is_delivered INTEGER NOT NULL DEFAULT 0,
+    is_failed INTEGER NOT NULL DEFAULT 0,
+    sender_username TEXT,
+    reply_to_message_id TEXT
+);
+
+-- Keep ids so the search index rowids still line up
+INSERT INTO message_new (
+    id, message_id, client_message_id, chat_id, sender_id, content, timestamp,
+    is_read, is_sent, is_delivered, is_failed, sender_username, reply_to_message_id
+)
+SELECT
+    id, message_id, client_message_id, chat_id, sender_id, content, timestamp,
+    is_read, is_sent, is_delivered, is_failed, sender_username, reply_to_message_id
+FROM message
+WHERE chat_id IN (SELECT chat_id FROM chat);
+
+-- Also drops the indices and search triggers on the old table
+DROP TABLE message;
+ALTER TABLE message_new RENAME TO message;
+
+CREATE INDEX IF NOT EXISTS idx_message_chat_id ON message(chat_id);
+CREATE INDEX IF NOT EXISTS idx_message_timestamp ON message(timestamp);
+CREATE INDEX IF NOT EXISTS idx_message_sender_id ON message(sender_id);
+CREATE INDEX IF NOT EXISTS idx_message_message_id ON message(message_id);
+
+CREATE TRIGGER IF NOT EXISTS message_fts_after_insert AFTER INSERT ON message BEGIN
+    INSERT INTO message_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS message_fts_after_delete AFTER DELETE ON message BEGIN
+    INSERT INTO message_fts(message_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS message_fts_after_update AFTER UPDATE OF content ON message BEGIN
+    INSERT INTO message_fts(message_fts, rowid, content) VALUES ('delete', old.id, old.content);
+    INSERT INTO message_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+-- Drop index entries for the orphans that were left behind
+INSERT INTO message_fts(message_fts) VALUES ('rebuild');
+
+-- PARTICIPANTS
+CREATE TABLE participant_new (
+    participant_id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    joined_at INTEGER NOT NULL,
+    role TEXT NOT NULL,
+    chat_id TEXT NOT NULL REFERENCES chat(chat_id) ON DELETE CASCADE
+);
+
+INSERT INTO participant_new (participant_id, user_id, username, joined_at, role, chat_id)
+SELECT participant_id, user_id, username, joined_at, role, chat_id
+FROM participant
+WHERE chat_id IN (SELECT chat_id FROM chat);
+
+DROP TABLE participant;
+ALTER TABLE participant_new RENAME TO participant;
+
+CREATE INDEX IF NOT EXISTS idx_participant_chat_id ON participant(chat_id);
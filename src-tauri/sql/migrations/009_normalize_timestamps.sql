@@ -0,0 +1,101 @@
+-- Convert stored timestamps to milliseconds. Units are detected by magnitude with
+-- the same bands as Timestamp::from_any: below 1e11 seconds, below 1e14 milliseconds,
+-- below 1e17 microseconds, anything larger nanoseconds. NULL stays NULL.
+
+UPDATE message SET timestamp = CASE
+    WHEN timestamp < 100000000000 THEN timestamp * 1000
+    WHEN timestamp < 100000000000000000 THEN timestamp / 1000
+    ELSE timestamp / 1000000
+END
+WHERE timestamp > 0 AND (timestamp < 100000000000 OR timestamp >= 100000000000000);
+
+UPDATE chat SET created_at = CASE
+    WHEN created_at < 100000000000 THEN created_at * 1000
+    WHEN created_at < 100000000000000000 THEN created_at / 1000
+    ELSE created_at / 1000000
+END
+WHERE created_at > 0 AND (created_at < 100000000000 OR created_at >= 100000000000000);
+
+UPDATE chat SET last_message_timestamp = CASE
+    WHEN last_message_timestamp < 100000000000 THEN last_message_timestamp * 1000
+    WHEN last_message_timestamp < 100000000000000000 THEN last_message_timestamp / 1000
+    ELSE last_message_timestamp / 1000000
+END
+WHERE last_message_timestamp > 0 AND (last_message_timestamp < 100000000000 OR last_message_timestamp >= 100000000000000);
+
+UPDATE participant SET joined_at = CASE
+    WHEN joined_at < 100000000000 THEN joined_at * 1000
+    WHEN joined_at < 100000000000000000 THEN joined_at / 1000
+    ELSE joined_at / 1000000
+END
+WHERE joined_at > 0 AND (joined_at < 100000000000 OR joined_at >= 100000000000000);
+
+UPDATE local_deletes SET deleted_at = CASE
+    WHEN deleted_at < 100000000000 THEN deleted_at * 1000
+    WHEN deleted_at < 100000000000000000 THEN deleted_at / 1000
+    ELSE deleted_at / 1000000
+END
+WHERE deleted_at > 0 AND (deleted_at < 100000000000 OR deleted_at >= 100000000000000);
+
+UPDATE secure_tokens SET created_at = CASE
+    WHEN created_at < 100000000000 THEN created_at * 1000
+    WHEN created_at < 100000000000000000 THEN created_at / 1000
+    ELSE created_at / 1000000
+END
+WHERE created_at > 0 AND (created_at < 100000000000 OR created_at >= 100000000000000);
+
+UPDATE presence SET last_seen = CASE
+    WHEN last_seen < 100000000000 THEN last_seen * 1000
+    WHEN last_seen < 100000000000000000 THEN last_seen / 1000
+    ELSE last_seen / 1000000
+END
+WHERE last_seen > 0 AND (last_seen < 100000000000 OR last_seen >= 100000000000000);
+
+UPDATE presence SET updated_at = CASE
+    WHEN updated_at < 100000000000 THEN updated_at * 1000
+    WHEN updated_at < 100000000000000000 THEN updated_at / 1000
+    ELSE updated_at / 1000000
+END
+WHERE updated_at > 0 AND (updated_at < 100000000000 OR updated_at >= 100000000000000);
+
+UPDATE user SET last_seen = CASE
+    WHEN last_seen < 100000000000 THEN last_seen * 1000
+    WHEN last_seen < 100000000000000000 THEN last_seen / 1000
+    ELSE last_seen / 1000000
+END
+WHERE last_seen > 0 AND (last_seen < 100000000000 OR last_seen >= 100000000000000);
+
+UPDATE user SET created_at = CASE
+    WHEN created_at < 100000000000 THEN created_at * 1000
+    WHEN created_at < 100000000000000000 THEN created_at / 1000
+    ELSE created_at / 1000000
+END
+WHERE created_at > 0 AND (created_at < 100000000000 OR created_at >= 100000000000000);
+
+UPDATE user SET updated_at = CASE
+    WHEN updated_at < 100000000000 THEN updated_at * 1000
+    WHEN updated_at < 100000000000000000 THEN updated_at / 1000
+    ELSE updated_at / 1000000
+END
+WHERE updated_at > 0 AND (updated_at < 100000000000 OR updated_at >= 100000000000000);
+
+UPDATE user SET deleted_at = CASE
+    WHEN deleted_at < 100000000000 THEN deleted_at * 1000
+    WHEN deleted_at < 100000000000000000 THEN deleted_at / 1000
+    ELSE deleted_at / 1000000
+END
+WHERE deleted_at > 0 AND (deleted_at < 100000000000 OR deleted_at >= 100000000000000);
+
+UPDATE friend SET created_at = CASE
+    WHEN created_at < 100000000000 THEN created_at * 1000
+    WHEN created_at < 100000000000000000 THEN created_at / 1000
+    ELSE created_at / 1000000
+END
+WHERE created_at > 0 AND (created_at < 100000000000 OR created_at >= 100000000000000);
+
+UPDATE friend SET updated_at = CASE
+    WHEN updated_at < 100000000000 THEN updated_at * 1000
+    WHEN updated_at < 100000000000000000 THEN updated_at / 1000
+    ELSE updated_at / 1000000
+END
+WHERE updated_at > 0 AND (updated_at < 100000000000 OR updated_at >= 100000000000000);
@@ -0,0 +1,3 @@
+-- Message pages walk one chat in (timestamp, id) order; this index serves both
+-- directions without sorting, however long the chat is
+CREATE INDEX IF NOT EXISTS idx_message_chat_timestamp ON message(chat_id, timestamp, id);
@@ -0,0 +1,16 @@
+-- MESSAGE RECEIPTS (delivery and read state per recipient)
+-- Keyed by server message id. A receipt can arrive before the message it refers to,
+-- so there is no foreign key; the trigger below drops receipts with their message.
+CREATE TABLE IF NOT EXISTS message_receipt (
+    message_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    -- 'delivered' or 'read'; only ever moves forward
+    status TEXT NOT NULL,
+    at INTEGER NOT NULL,
+    PRIMARY KEY (message_id, user_id)
+);
+
+CREATE TRIGGER IF NOT EXISTS message_receipt_after_message_delete AFTER DELETE ON message
+WHEN old.message_id IS NOT NULL BEGIN
+    DELETE FROM message_receipt WHERE message_id = old.message_id;
+END;
@@ -0,0 +1,62 @@
+-- Replace the independent is_sent / is_delivered / is_read / is_failed flags with one
+-- status column that only moves forward (see message_status.rs). Each row gets the
+-- furthest state its flags describe.
+
+CREATE TABLE message_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id TEXT,
+    client_message_id TEXT UNIQUE NOT NULL,
+    chat_id TEXT NOT NULL REFERENCES chat(chat_id) ON DELETE CASCADE,
+    sender_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'sent', 'delivered', 'read', 'failed')),
+    sender_username TEXT,
+    reply_to_message_id TEXT
+);
+
+-- Keep ids so the search index rowids still line up
+INSERT INTO message_new (
+    id, message_id, client_message_id, chat_id, sender_id, content, timestamp,
+    status, sender_username, reply_to_message_id
+)
+SELECT
+    id, message_id, client_message_id, chat_id, sender_id, content, timestamp,
+    CASE
+        WHEN is_read != 0 THEN 'read'
+        WHEN is_delivered != 0 THEN 'delivered'
+        WHEN is_sent != 0 THEN 'sent'
+        WHEN is_failed != 0 THEN 'failed'
+        ELSE 'pending'
+    END,
+    sender_username, reply_to_message_id
+FROM message;
+
+-- Also drops the indices and triggers on the old table
+DROP TABLE message;
+ALTER TABLE message_new RENAME TO message;
+
+CREATE INDEX IF NOT EXISTS idx_message_chat_id ON message(chat_id);
+CREATE INDEX IF NOT EXISTS idx_message_timestamp ON message(timestamp);
+CREATE INDEX IF NOT EXISTS idx_message_sender_id ON message(sender_id);
+CREATE INDEX IF NOT EXISTS idx_message_message_id ON message(message_id);
+CREATE INDEX IF NOT EXISTS idx_message_chat_timestamp ON message(chat_id, timestamp, id);
+
+CREATE TRIGGER IF NOT EXISTS message_fts_after_insert AFTER INSERT ON message BEGIN
+    INSERT INTO message_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS message_fts_after_delete AFTER DELETE ON message BEGIN
+    INSERT INTO message_fts(message_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS message_fts_after_update AFTER UPDATE OF content ON message BEGIN
+    INSERT INTO message_fts(message_fts, rowid, content) VALUES ('delete', old.id, old.content);
+    INSERT INTO message_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS message_receipt_after_message_delete AFTER DELETE ON message
+WHEN old.message_id IS NOT NULL BEGIN
+    DELETE FROM message_receipt WHERE message_id = old.message_id;
+END;
@@ -0,0 +1,73 @@
+-- CHAT SUMMARY (last message and unread count)
+-- Kept in step with the message table by the triggers below, so every write path
+-- (sync, websocket, import, retention, deletes) updates it. Unread means a message
+-- from someone else that has reached the server but has not been read; our own
+-- messages are the ones sent by an account that has signed in on this device.
+-- Each trigger adjusts the summary by the row that changed. Only the backfill at the
+-- end and a change to which accounts are our own recount a chat.
+
+-- The first cached message replaces whatever count the chat row was seeded with
+CREATE TRIGGER IF NOT EXISTS chat_summary_after_message_insert AFTER INSERT ON message BEGIN
+    UPDATE chat SET
+        unread_count = (CASE WHEN EXISTS (SELECT 1 FROM message m WHERE m.chat_id = new.chat_id AND m.id != new.id)
+                THEN unread_count ELSE 0 END)
+            + (new.status IN ('sent', 'delivered')
+                AND new.sender_id NOT IN (SELECT user_id FROM user WHERE is_own_account = 1)),
+        last_message_content = CASE WHEN last_message_timestamp IS NULL OR new.timestamp >= last_message_timestamp
+            THEN new.content ELSE last_message_content END,
+        last_message_timestamp = CASE WHEN last_message_timestamp IS NULL OR new.timestamp >= last_message_timestamp
+            THEN new.timestamp ELSE last_message_timestamp END
+    WHERE chat_id = new.chat_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_summary_after_message_unread_update
+AFTER UPDATE OF chat_id, sender_id, status ON message BEGIN
+    UPDATE chat SET unread_count = MAX(unread_count - (old.status IN ('sent', 'delivered')
+            AND old.sender_id NOT IN (SELECT user_id FROM user WHERE is_own_account = 1)), 0)
+    WHERE chat_id = old.chat_id;
+    UPDATE chat SET unread_count = unread_count + (new.status IN ('sent', 'delivered')
+            AND new.sender_id NOT IN (SELECT user_id FROM user WHERE is_own_account = 1))
+    WHERE chat_id = new.chat_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_summary_after_message_last_update
+AFTER UPDATE OF chat_id, content, timestamp ON message BEGIN
+    UPDATE chat SET
+        last_message_content = (SELECT m.content FROM message m WHERE m.chat_id = chat.chat_id ORDER BY m.timestamp DESC, m.id DESC LIMIT 1),
+        last_message_timestamp = (SELECT m.timestamp FROM message m WHERE m.chat_id = chat.chat_id ORDER BY m.timestamp DESC, m.id DESC LIMIT 1)
+    WHERE chat_id IN (old.chat_id, new.chat_id);
+END;
+
+-- Retention deletes old messages in bulk; only removing the newest one re-reads the chat
+CREATE TRIGGER IF NOT EXISTS chat_summary_after_message_delete AFTER DELETE ON message BEGIN
+    UPDATE chat SET unread_count = MAX(unread_count - (old.status IN ('sent', 'delivered')
+            AND old.sender_id NOT IN (SELECT user_id FROM user WHERE is_own_account = 1)), 0)
+    WHERE chat_id = old.chat_id;
+    UPDATE chat SET
+        last_message_content = (SELECT m.content FROM message m WHERE m.chat_id = chat.chat_id ORDER BY m.timestamp DESC, m.id DESC LIMIT 1),
+        last_message_timestamp = (SELECT m.timestamp FROM message m WHERE m.chat_id = chat.chat_id ORDER BY m.timestamp DESC, m.id DESC LIMIT 1)
+    WHERE chat_id = old.chat_id AND old.timestamp >= last_message_timestamp;
+END;
+
+-- Signing in with a new account on this device turns its cached messages into our own
+CREATE TRIGGER IF NOT EXISTS chat_summary_after_own_account_insert
+AFTER INSERT ON user WHEN new.is_own_account = 1 BEGIN
+    UPDATE chat SET unread_count = (SELECT COUNT(*) FROM message m WHERE m.chat_id = chat.chat_id AND m.status IN ('sent', 'delivered')
+            AND m.sender_id NOT IN (SELECT user_id FROM user WHERE is_own_account = 1))
+    WHERE chat_id IN (SELECT chat_id FROM message WHERE sender_id = new.user_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_summary_after_own_account_update
+AFTER UPDATE OF is_own_account ON user WHEN new.is_own_account IS NOT old.is_own_account BEGIN
+    UPDATE chat SET unread_count = (SELECT COUNT(*) FROM message m WHERE m.chat_id = chat.chat_id AND m.status IN ('sent', 'delivered')
+            AND m.sender_id NOT IN (SELECT user_id FROM user WHERE is_own_account = 1))
+    WHERE chat_id IN (SELECT chat_id FROM message WHERE sender_id = new.user_id);
+END;
+
+-- Bring existing chats up to date; chats without cached messages keep what they have
+UPDATE chat SET
+    last_message_content = (SELECT m.content FROM message m WHERE m.chat_id = chat.chat_id ORDER BY m.timestamp DESC, m.id DESC LIMIT 1),
+    last_message_timestamp = (SELECT m.timestamp FROM message m WHERE m.chat_id = chat.chat_id ORDER BY m.timestamp DESC, m.id DESC LIMIT 1),
+    unread_count = (SELECT COUNT(*) FROM message m WHERE m.chat_id = chat.chat_id AND m.status IN ('sent', 'delivered')
+        AND m.sender_id NOT IN (SELECT user_id FROM user WHERE is_own_account = 1))
+WHERE EXISTS (SELECT 1 FROM message m WHERE m.chat_id = chat.chat_id);
@@ -0,0 +1,194 @@
+// Change feed from the repository to the frontend.
+//
+// Every mutating repository call publishes a `ChangeRecord` on the repository's
+// `ChangeBus`. `spawn_forwarder` drains the bus, coalesces records that arrive close
+// together and emits them as one `db-changed` event, so views can patch themselves
+// instead of re-querying after each command.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+const BUS_CAPACITY: usize = 1024;
+// Records published within this window of the first one go out together
+const COALESCE_WINDOW: Duration = Duration::from_millis(50);
+const MAX_BATCH: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeTable {
+    User,
+    UserKeys,
+    Chat,
+    ChatLocalDelete,
+    Message,
+    // Keyed "message_id:user_id"
+    MessageReceipt,
+    Friend,
+    Participant,
+    Presence,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOp {
+    Insert,
+    Update,
+    // Written with INSERT ... ON CONFLICT, so it may have been either
+    Upsert,
+    Delete,
+    // Every row of the table was removed or replaced (clear, restore, recovery)
+    Reset,
+}
+
+// Keys are the table's natural id: chat_id, user_id, participant_id, and
+// client_message_id for messages since it is stable before the server id arrives.
+// Rows removed by ON DELETE CASCADE are not reported separately.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeRecord {
+    pub table: ChangeTable,
+    pub op: ChangeOp,
+    // None for resets
+    pub key: Option<String>,
+    // The row after the change; None for deletes and resets
+    pub row: Option<Value>,
+}
+
+impl ChangeRecord {
+    pub fn row<T: Serialize>(table: ChangeTable, op: ChangeOp, key: impl Into<String>, row: &T) -> Self {
+        ChangeRecord {
+            table,
+            op,
+            key: Some(key.into()),
+            row: serde_json::to_value(row).ok(),
+        }
+    }
+
+    pub fn delete(table: ChangeTable, key: impl Into<String>) -> Self {
+        ChangeRecord { table, op: ChangeOp::Delete, key: Some(key.into()), row: None }
+    }
+
+    pub fn reset(table: ChangeTable) -> Self {
+        ChangeRecord { table, op: ChangeOp::Reset, key: None, row: None }
+    }
+}
+
+// Broadcast bus shared by all clones of a repository. Publishing never blocks; a
+// subscriber that falls more than BUS_CAPACITY records behind is told it lagged.
+#[derive(Clone)]
+pub struct ChangeBus {
+    sender: broadcast::Sender<ChangeRecord>,
+}
+
+impl ChangeBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BUS_CAPACITY);
+        ChangeBus { sender }
+    }
+
+    pub fn publish(&self, record: ChangeRecord) {
+        // No subscribers (tests, early startup) is not an error
+        let _ = self.sender.send(record);
+    }
+
+    pub fn publish_all(&self, records: impl IntoIterator<Item = ChangeRecord>) {
+        for record in records {
+            self.publish(record);
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeRecord> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for ChangeBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DbChangedPayload {
+    pub changes: Vec<ChangeRecord>,
+    // Records were dropped before this batch; views should reload from the database
+    pub lagged: bool,
+}
+
+// Keep one record per row, in order of first appearance. A later record replaces an
+// earlier one for the same row (an insert followed by updates stays an insert), and a
+// reset drops everything before it for that table.
+pub fn coalesce(records: Vec<ChangeRecord>) -> Vec<ChangeRecord> {
+    let mut out: Vec<ChangeRecord> = Vec::with_capacity(records.len());
+    let mut index: HashMap<(ChangeTable, String), usize> = HashMap::new();
+
+    for record in records {
+        let key = match &record.key {
+            Some(key) => key.clone(),
+            None => {
+                out.retain(|r| r.table != record.table);
+                index = out.iter().enumerate()
+                    .filter_map(|(i, r)| r.key.clone().map(|key| ((r.table, key), i)))
+                    .collect();
+                out.push(record);
+                continue;
+            }
+        };
+
+        match index.get(&(record.table, key.clone())) {
+            Some(&i) => {
+                let op = match (out[i].op, record.op) {
+                    (ChangeOp::Insert, ChangeOp::Update | ChangeOp::Upsert) => ChangeOp::Insert,
+                    (_, op) => op,
+                };
+                out[i] = ChangeRecord { op, ..record };
+            }
+            None => {
+                index.insert((record.table, key), out.len());
+                out.push(record);
+            }
+        }
+    }
+
+    out
+}
+
+// Forward the bus to the frontend as batched `db-changed` events
+pub fn spawn_forwarder(app: AppHandle, bus: &ChangeBus) {
+    let mut receiver = bus.subscribe();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let mut batch = Vec::new();
+            let mut lagged = false;
+
+            match receiver.recv().await {
+                Ok(record) => batch.push(record),
+                Err(RecvError::Lagged(skipped)) => {
+                    println!("[Changes] Change feed lagged, {} records dropped", skipped);
+                    lagged = true;
+                }
+                Err(RecvError::Closed) => break,
+            }
+
+            let deadline = tokio::time::Instant::now() + COALESCE_WINDOW;
+            while batch.len() < MAX_BATCH {
+                match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                    Ok(Ok(record)) => batch.push(record),
+                    Ok(Err(RecvError::Lagged(skipped))) => {
+                        println!("[Changes] Change feed lagged, {} records dropped", skipped);
+                        lagged = true;
+                    }
+                    Ok(Err(RecvError::Closed)) | Err(_) => break,
+                }
+            }
+
+            let payload = DbChangedPayload { changes: coalesce(batch), lagged };
+            if let Err(e) = app.emit("db-changed", &payload) {
+                println!("[Changes] Failed to emit db-changed: {}", e);
+            }
+        }
+    });
+}
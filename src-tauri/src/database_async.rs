@@ -1,12 +1,24 @@
-use sqlx::{sqlite::{SqlitePool, SqlitePoolOptions}, Row, Error as SqlxError};
+use sqlx::{sqlite::{SqliteAutoVacuum, SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions}, Row, Error as SqlxError};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
-use lazy_static::lazy_static;
-use tokio::sync::OnceCell;
-
-// Global database pool
-lazy_static! {
-    static ref DB_POOL: OnceCell<SqlitePool> = OnceCell::new();
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use crate::changes::{ChangeBus, ChangeOp, ChangeRecord, ChangeTable};
+use crate::repo::{ChatRepo, FriendRepo, MessageRepo, ParticipantRepo, UserRepo};
+use crate::message_status::MessageStatus;
+use crate::timestamp::Timestamp;
+
+// sqlx/SQLite implementation of the repository traits. Clones share the same pool; the
+// app keeps one in Tauri managed state. The pool is behind a lock rather than set-once
+// so a restore can swap it. Every write is published on `changes`.
+#[derive(Clone)]
+pub struct SqliteRepo {
+    pool: Arc<RwLock<Option<SqlitePool>>>,
+    // None for an in-memory database
+    path: Option<PathBuf>,
+    changes: ChangeBus,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -20,11 +32,11 @@ pub struct User {
     pub role: Option<String>,
     pub token_hash: Option<String>,
     pub verified: bool,
-    pub created_at: i64,
-    pub updated_at: i64,
-    pub deleted_at: Option<i64>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    pub deleted_at: Option<Timestamp>,
     pub is_dark_mode: bool,
-    pub last_seen: i64,
+    pub last_seen: Timestamp,
     pub color_scheme: Option<String>,
 }
 
@@ -32,18 +44,26 @@ pub struct User {
 pub struct Chat {
     pub chat_id: String,
     pub name: Option<String>,
-    pub created_at: i64,
+    pub created_at: Timestamp,
     pub creator_id: Option<String>,
     pub is_group: bool,
     pub group_name: Option<String>,
     pub description: Option<String>,
     pub unread_count: i32,
     pub last_message_content: Option<String>,
-    pub last_message_timestamp: Option<i64>,
+    pub last_message_timestamp: Option<Timestamp>,
     pub participants: Option<String>,
+    // Set for imported chats the server does not know; written only by import and delta sync
+    #[serde(default)]
+    pub is_local_only: bool,
+    #[serde(default)]
+    pub is_read_only: bool,
 }
 
+// Serialized with the legacy is_* flags next to `status` so the frontend and older
+// archives keep working. On input `status` wins; without it the flags are mapped.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(into = "MessageWire", from = "MessageWire")]
 pub struct Message {
     pub id: Option<i64>,
     pub message_id: Option<String>,
@@ -51,15 +71,76 @@ pub struct Message {
     pub chat_id: String,
     pub sender_id: String,
     pub content: String,
-    pub timestamp: i64,
-    pub is_read: bool,
-    pub is_sent: bool,
-    pub is_delivered: bool,
-    pub is_failed: bool,
+    pub timestamp: Timestamp,
+    pub status: MessageStatus,
     pub sender_username: Option<String>,
     pub reply_to_message_id: Option<String>,
 }
 
+#[derive(Serialize, Deserialize)]
+struct MessageWire {
+    id: Option<i64>,
+    message_id: Option<String>,
+    client_message_id: String,
+    chat_id: String,
+    sender_id: String,
+    content: String,
+    timestamp: Timestamp,
+    #[serde(default)]
+    status: Option<MessageStatus>,
+    #[serde(default)]
+    is_read: bool,
+    #[serde(default)]
+    is_sent: bool,
+    #[serde(default)]
+    is_delivered: bool,
+    #[serde(default)]
+    is_failed: bool,
+    sender_username: Option<String>,
+    reply_to_message_id: Option<String>,
+}
+
+impl From<Message> for MessageWire {
+    fn from(message: Message) -> Self {
+        MessageWire {
+            id: message.id,
+            message_id: message.message_id,
+            client_message_id: message.client_message_id,
+            chat_id: message.chat_id,
+            sender_id: message.sender_id,
+            content: message.content,
+            timestamp: message.timestamp,
+            status: Some(message.status),
+            is_read: message.status.is_read(),
+            is_sent: message.status.is_sent(),
+            is_delivered: message.status.is_delivered(),
+            is_failed: message.status.is_failed(),
+            sender_username: message.sender_username,
+            reply_to_message_id: message.reply_to_message_id,
+        }
+    }
+}
+
+impl From<MessageWire> for Message {
+    fn from(wire: MessageWire) -> Self {
+        let status = wire.status.unwrap_or_else(|| {
+            MessageStatus::from_flags(wire.is_sent, wire.is_delivered, wire.is_read, wire.is_failed)
+        });
+        Message {
+            id: wire.id,
+            message_id: wire.message_id,
+            client_message_id: wire.client_message_id,
+            chat_id: wire.chat_id,
+            sender_id: wire.sender_id,
+            content: wire.content,
+            timestamp: wire.timestamp,
+            status,
+            sender_username: wire.sender_username,
+            reply_to_message_id: wire.reply_to_message_id,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Friend {
     pub user_id: String,
@@ -67,18 +148,25 @@ pub struct Friend {
     pub email: String,
     pub name: String,
     pub picture: Option<String>,
-    pub created_at: Option<i64>,
-    pub updated_at: Option<i64>,
+    pub created_at: Option<Timestamp>,
+    pub updated_at: Option<Timestamp>,
     pub status: Option<String>,
     pub is_favorite: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Presence {
+    pub user_id: String,
+    pub status: String,
+    pub last_seen: Timestamp,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Participant {
     pub participant_id: String,
     pub user_id: String,
     pub username: String,
-    pub joined_at: i64,
+    pub joined_at: Timestamp,
     pub role: String,
     pub chat_id: String,
 }
@@ -193,711 +281,1866 @@ pub fn get_db_path() -> PathBuf {
     PathBuf::from("chat.db")
 }
 
-pub async fn get_pool() -> Result<SqlitePool, SqlxError> {
-    if let Some(pool) = DB_POOL.get() {
-        return Ok(pool.clone());
+impl SqliteRepo {
+    // Repository over a database file; the pool is opened by `initialize` or on first use
+    pub fn new(db_path: PathBuf) -> Self {
+        SqliteRepo {
+            pool: Arc::new(RwLock::new(None)),
+            path: Some(db_path),
+            changes: ChangeBus::new(),
+        }
     }
     
-    // Initialize the pool if it doesn't exist with timeout
-    let timeout_duration = std::time::Duration::from_secs(10);
-    let init_future = initialize_database();
-    let timeout_future = tokio::time::sleep(timeout_duration);
+    // Private, migrated database that lives as long as the repository (and its clones).
+    // Every connection to sqlite::memory: gets its own database, so the pool holds exactly
+    // one connection and never lets it expire.
+    pub async fn in_memory() -> Result<Self, SqlxError> {
+        let connect_options = SqliteConnectOptions::from_str("sqlite::memory:")?
+            .foreign_keys(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(connect_options)
+            .await?;
+        crate::migrations::run_migrations(&pool).await?;
+        
+        Ok(SqliteRepo {
+            pool: Arc::new(RwLock::new(Some(pool))),
+            path: None,
+            changes: ChangeBus::new(),
+        })
+    }
     
-    let pool = tokio::select! {
-        pool_result = init_future => pool_result?,
-        _ = timeout_future => {
-            println!("[Database] Database initialization timeout after {} seconds", timeout_duration.as_secs());
-            return Err(SqlxError::Configuration("Database initialization timeout".into()));
+    pub async fn pool(&self) -> Result<SqlitePool, SqlxError> {
+        if let Some(pool) = self.pool.read().await.as_ref() {
+            return Ok(pool.clone());
+        }
+        
+        // Initialize the pool if it doesn't exist with timeout
+        let timeout_duration = std::time::Duration::from_secs(10);
+        let init_future = self.initialize();
+        let timeout_future = tokio::time::sleep(timeout_duration);
+        
+        tokio::select! {
+            pool_result = init_future => pool_result,
+            _ = timeout_future => {
+                println!("[Database] Database initialization timeout after {} seconds", timeout_duration.as_secs());
+                Err(SqlxError::Configuration("Database initialization timeout".into()))
+            }
         }
-    };
-    
-    // Store the pool in the global static for future use
-    if let Err(_) = DB_POOL.set(pool.clone()) {
-        eprintln!("[Database] Warning: Failed to store pool in global static");
     }
     
-    Ok(pool)
-}
-
-pub async fn check_database_ready() -> Result<(), SqlxError> {
-    let db_path = get_db_path();
-    println!("[Database] Checking if database is ready at: {:?}", db_path);
+    fn file_path(&self) -> Result<PathBuf, SqlxError> {
+        self.path.clone().ok_or_else(|| SqlxError::Configuration("In-memory database has no file".into()))
+    }
     
-    // Check if database file exists
-    if !db_path.exists() {
-        return Err(SqlxError::Configuration("Database file does not exist".into()));
+    // Keep the first pool that was opened; a second concurrent initialization just uses its own
+    async fn store_pool(&self, pool: &SqlitePool) {
+        let mut current = self.pool.write().await;
+        if current.is_some() {
+            eprintln!("[Database] Warning: Database pool was already initialized");
+        } else {
+            *current = Some(pool.clone());
+        }
     }
     
-    // Try to connect to the database
-    let database_url = format!("sqlite:{}", db_path.display());
-    let pool = SqlitePoolOptions::new()
-        .max_connections(1)
-        .acquire_timeout(std::time::Duration::from_secs(5))
-        .connect(&database_url)
-        .await?;
+    pub async fn check_database_ready(&self) -> Result<(), SqlxError> {
+        let db_path = self.file_path()?;
+        println!("[Database] Checking if database is ready at: {:?}", db_path);
     
-    // Test a simple query
-    let _: i32 = sqlx::query_scalar("SELECT 1")
-        .fetch_one(&pool)
-        .await?;
+        // Check if database file exists
+        if !db_path.exists() {
+            return Err(SqlxError::Configuration("Database file does not exist".into()));
+        }
     
-    pool.close().await;
-    println!("[Database] Database is ready and accessible");
-    Ok(())
-}
-
-pub async fn initialize_database() -> Result<SqlitePool, SqlxError> {
-    let db_path = get_db_path();
-    println!("[Database] Initializing database at: {:?}", db_path);
+        // Try to connect to the database
+        let database_url = format!("sqlite:{}", db_path.display());
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(std::time::Duration::from_secs(5))
+            .connect(&database_url)
+            .await?;
     
-    // Log platform information for debugging
-    #[cfg(target_os = "linux")]
-    {
-        println!("[Database] Running on Linux");
-        if let Ok(appimage) = std::env::var("APPIMAGE") {
-            println!("[Database] APPIMAGE path: {}", appimage);
-        }
-        if let Ok(current_exe) = std::env::current_exe() {
-            println!("[Database] Current executable: {:?}", current_exe);
-        }
+        // Test a simple query
+        let _: i32 = sqlx::query_scalar("SELECT 1")
+            .fetch_one(&pool)
+            .await?;
+    
+        pool.close().await;
+        println!("[Database] Database is ready and accessible");
+        Ok(())
     }
     
-    #[cfg(target_os = "macos")]
-    {
-        println!("[Database] Running on macOS");
-        if let Ok(app_bundle) = std::env::var("APP_BUNDLE_PATH") {
-            println!("[Database] APP_BUNDLE_PATH: {}", app_bundle);
+    pub async fn initialize(&self) -> Result<SqlitePool, SqlxError> {
+        let db_path = self.file_path()?;
+        println!("[Database] Initializing database at: {:?}", db_path);
+    
+        // Log platform information for debugging
+        #[cfg(target_os = "linux")]
+        {
+            println!("[Database] Running on Linux");
+            if let Ok(appimage) = std::env::var("APPIMAGE") {
+                println!("[Database] APPIMAGE path: {}", appimage);
+            }
+            if let Ok(current_exe) = std::env::current_exe() {
+                println!("[Database] Current executable: {:?}", current_exe);
+            }
         }
-        if let Ok(home) = std::env::var("HOME") {
-            println!("[Database] HOME directory: {}", home);
-        }
-        if let Ok(current_exe) = std::env::current_exe() {
-            println!("[Database] Current executable: {:?}", current_exe);
-            // Check if we're running from a .app bundle
-            if current_exe.to_string_lossy().contains(".app") {
-                println!("[Database] Running from macOS .app bundle");
-                // Show the app bundle structure
-                if let Some(contents_dir) = current_exe.ancestors().find(|p| p.ends_with("Contents")) {
-                    println!("[Database] Found Contents directory: {:?}", contents_dir);
-                    let resources_dir = contents_dir.join("Resources");
-                    if resources_dir.exists() {
-                        println!("[Database] Resources directory exists: {:?}", resources_dir);
-                        // List what's in Resources
-                        if let Ok(entries) = std::fs::read_dir(&resources_dir) {
-                            for entry in entries.flatten() {
-                                println!("[Database] Resource: {:?}", entry.path());
+    
+        #[cfg(target_os = "macos")]
+        {
+            println!("[Database] Running on macOS");
+            if let Ok(app_bundle) = std::env::var("APP_BUNDLE_PATH") {
+                println!("[Database] APP_BUNDLE_PATH: {}", app_bundle);
+            }
+            if let Ok(home) = std::env::var("HOME") {
+                println!("[Database] HOME directory: {}", home);
+            }
+            if let Ok(current_exe) = std::env::current_exe() {
+                println!("[Database] Current executable: {:?}", current_exe);
+                // Check if we're running from a .app bundle
+                if current_exe.to_string_lossy().contains(".app") {
+                    println!("[Database] Running from macOS .app bundle");
+                    // Show the app bundle structure
+                    if let Some(contents_dir) = current_exe.ancestors().find(|p| p.ends_with("Contents")) {
+                        println!("[Database] Found Contents directory: {:?}", contents_dir);
+                        let resources_dir = contents_dir.join("Resources");
+                        if resources_dir.exists() {
+                            println!("[Database] Resources directory exists: {:?}", resources_dir);
+                            // List what's in Resources
+                            if let Ok(entries) = std::fs::read_dir(&resources_dir) {
+                                for entry in entries.flatten() {
+                                    println!("[Database] Resource: {:?}", entry.path());
+                                }
                             }
+                        } else {
+                            println!("[Database] Resources directory does not exist: {:?}", resources_dir);
                         }
-                    } else {
-                        println!("[Database] Resources directory does not exist: {:?}", resources_dir);
                     }
+                } else {
+                    println!("[Database] Not running from .app bundle");
                 }
-            } else {
-                println!("[Database] Not running from .app bundle");
             }
         }
-    }
-    
-    #[cfg(target_os = "windows")]
-    {
-        println!("[Database] Running on Windows");
-    }
     
-    // Create parent directory if it doesn't exist
-    if let Some(parent) = db_path.parent() {
-        if let Err(e) = std::fs::create_dir_all(parent) {
-            eprintln!("[Database] Failed to create parent directory: {}", e);
-            return Err(SqlxError::Configuration(format!("Failed to create directory: {}", e).into()));
+        #[cfg(target_os = "windows")]
+        {
+            println!("[Database] Running on Windows");
         }
-        println!("[Database] Parent directory created/verified: {:?}", parent);
-    }
     
-    // Check if database already exists and has data
-    let db_exists = db_path.exists();
-    let db_has_data = if db_exists {
-        println!("[Database] Database file exists, checking if it has data...");
-        // Check if database has tables
-        match sqlx::sqlite::SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect(&format!("sqlite:{}", db_path.display()))
-            .await
-        {
-            Ok(pool) => {
-                let result = sqlx::query("SELECT name FROM sqlite_master WHERE type='table' AND name='user'")
-                    .fetch_optional(&pool)
-                    .await;
-                pool.close().await;
-                let has_data = result.is_ok() && result.unwrap().is_some();
-                println!("[Database] Database has data: {}", has_data);
-                has_data
-            }
-            Err(e) => {
-                println!("[Database] Failed to check existing database: {}", e);
-                false
+        // Create parent directory if it doesn't exist
+        if let Some(parent) = db_path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                eprintln!("[Database] Failed to create parent directory: {}", e);
+                return Err(SqlxError::Configuration(format!("Failed to create directory: {}", e).into()));
             }
+            println!("[Database] Parent directory created/verified: {:?}", parent);
         }
-    } else {
-        println!("[Database] Database file does not exist, will create new one");
-        false
-    };
     
-    // Create the database URL
-    let database_url = format!("sqlite:{}", db_path.display());
-    println!("[Database] Database URL: {}", database_url);
+        // Create the database URL
+        let database_url = format!("sqlite:{}", db_path.display());
+        println!("[Database] Database URL: {}", database_url);
     
-    // Create connection pool with timeout
-    let pool_future = SqlitePoolOptions::new()
-        .max_connections(5)
-        .acquire_timeout(std::time::Duration::from_secs(30))
-        .idle_timeout(std::time::Duration::from_secs(300))
-        .connect(&database_url);
-    
-    let timeout_duration = std::time::Duration::from_secs(15);
-    let timeout_future = tokio::time::sleep(timeout_duration);
-    
-    let pool = tokio::select! {
-        pool_result = pool_future => {
-            match pool_result {
-                Ok(pool) => {
-                    println!("[Database] Successfully connected to database");
-                    pool
-                },
-                Err(e) => {
-                    eprintln!("[Database] Failed to connect to database: {}", e);
-                    return Err(e);
+        // Create connection pool with timeout
+        let pool_future = open_pool(&db_path);
+    
+        let timeout_duration = std::time::Duration::from_secs(15);
+        let timeout_future = tokio::time::sleep(timeout_duration);
+    
+        let pool = tokio::select! {
+            pool_result = pool_future => {
+                match pool_result {
+                    Ok(pool) => {
+                        println!("[Database] Successfully connected to database");
+                        pool
+                    },
+                    Err(e) => {
+                        eprintln!("[Database] Failed to connect to database: {}", e);
+                        return Err(e);
+                    }
                 }
+            },
+            _ = timeout_future => {
+                println!("[Database] Database connection timeout after {} seconds", timeout_duration.as_secs());
+                return Err(SqlxError::Configuration("Database connection timeout".into()));
             }
-        },
-        _ = timeout_future => {
-            println!("[Database] Database connection timeout after {} seconds", timeout_duration.as_secs());
-            return Err(SqlxError::Configuration("Database connection timeout".into()));
-        }
-    };
+        };
     
-    println!("[Database] Connected to database");
+        println!("[Database] Connected to database");
+    
+        // Create or upgrade the schema to the latest version
+        let migrate_future = crate::migrations::run_migrations(&pool);
+        let migrate_timeout = tokio::time::sleep(timeout_duration);
     
-    // Only create schema if database doesn't exist or has no data
-    if !db_has_data {
-        println!("[Database] Creating initial database schema...");
-        
-        // Create schema with timeout
-        let schema_sql = include_str!("../sql/full_tauri_schema.sql");
-        println!("[Database] Schema SQL length: {} characters", schema_sql.len());
-        
-        let schema_future = sqlx::query(schema_sql).execute(&pool);
-        let schema_timeout = tokio::time::sleep(timeout_duration);
-        
         tokio::select! {
-            result = schema_future => {
+            result = migrate_future => {
                 match result {
-                    Ok(_) => println!("[Database] Executed schema successfully"),
+                    Ok(report) => println!("[Database] Schema at version {} (latest {})", report.to_version, report.latest_version),
                     Err(e) => {
-                        eprintln!("[Database] Failed to execute schema: {}", e);
+                        eprintln!("[Database] Failed to migrate schema: {}", e);
                         return Err(e);
                     }
                 }
             },
-            _ = schema_timeout => {
-                println!("[Database] Schema execution timeout after {} seconds", timeout_duration.as_secs());
-                return Err(SqlxError::Configuration("Schema execution timeout".into()));
+            _ = migrate_timeout => {
+                println!("[Database] Schema migration timeout after {} seconds", timeout_duration.as_secs());
+                return Err(SqlxError::Configuration("Schema migration timeout".into()));
             }
         }
-        
-        // Simple verification with timeout
-        let verify_future = sqlx::query_scalar("SELECT 1").fetch_one(&pool);
-        let verify_timeout = tokio::time::sleep(timeout_duration);
-        
-        tokio::select! {
-            result = verify_future => {
-                let _: i32 = result?;
-                println!("[Database] Database verification successful");
-            },
-            _ = verify_timeout => {
-                println!("[Database] Database verification timeout after {} seconds", timeout_duration.as_secs());
-                return Err(SqlxError::Configuration("Database verification timeout".into()));
-            }
-        }
-        
-        println!("[Database] Initial database schema created successfully");
-    } else {
-        println!("[Database] Database already exists with data, skipping schema creation");
-    }
     
-    println!("[Database] Database initialized successfully");
+        println!("[Database] Database initialized successfully");
     
-    // Store the pool in the global static
-    if let Err(_) = DB_POOL.set(pool.clone()) {
-        eprintln!("[Database] Warning: Failed to store pool in global static");
-    }
+        self.store_pool(&pool).await;
     
-    Ok(pool)
+        Ok(pool)
+    }
 }
 
-// Database operations
-pub async fn insert_or_update_user(user: &User) -> Result<(), SqlxError> {
-    println!("[Database] Starting insert_or_update_user for: {}", user.username);
-    
-    let pool = get_pool().await?;
-    
-    sqlx::query(
-        "INSERT OR REPLACE INTO user (
-            user_id, username, email, name, password, picture,
-            role, token_hash, verified, created_at, updated_at,
-            deleted_at, is_dark_mode, last_seen, color_scheme
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&user.user_id)
-    .bind(&user.username)
-    .bind(&user.email)
-    .bind(&user.name)
-    .bind(&user.password)
-    .bind(&user.picture)
-    .bind(&user.role)
-    .bind(&user.token_hash)
-    .bind(user.verified)
-    .bind(user.created_at)
-    .bind(user.updated_at)
-    .bind(user.deleted_at)
-    .bind(user.is_dark_mode)
-    .bind(user.last_seen)
-    .bind(&user.color_scheme)
-    .execute(&pool)
-    .await?;
+fn open_pool(db_path: &std::path::Path) -> impl std::future::Future<Output = Result<SqlitePool, SqlxError>> {
+    // Foreign keys are off by default in SQLite and the pragma is per connection,
+    // so set it in the connect options to cover every pooled connection.
+    // auto_vacuum only takes effect on a new file; db_incremental_vacuum converts older ones.
+    let connect_options = SqliteConnectOptions::new()
+        .filename(db_path)
+        .foreign_keys(true)
+        .auto_vacuum(SqliteAutoVacuum::Incremental)
+        .journal_mode(SqliteJournalMode::Wal);
     
-    println!("[Database] Successfully inserted/updated user: {}", user.username);
-    Ok(())
+    SqlitePoolOptions::new()
+        .max_connections(5)
+        .acquire_timeout(std::time::Duration::from_secs(30))
+        .idle_timeout(std::time::Duration::from_secs(300))
+        .connect_with(connect_options)
 }
 
-pub async fn get_user_by_id(user_id: &str) -> Result<Option<User>, SqlxError> {
-    let pool = get_pool().await?;
+// Change feed. Rows are read back after the write so records carry what is actually
+// stored (including columns the write preserved). A failed read-back is logged and
+// never fails the write itself.
+impl SqliteRepo {
+    pub fn changes(&self) -> &ChangeBus {
+        &self.changes
+    }
     
-    let row = sqlx::query("SELECT * FROM user WHERE user_id = ?")
-        .bind(user_id)
-        .fetch_optional(&pool)
-        .await?;
+    async fn publish_user(&self, op: ChangeOp, user_id: &str) {
+        match self.get_user_by_id(user_id).await {
+            // Credentials stay out of the feed
+            Ok(Some(user)) => self.changes.publish(ChangeRecord::row(ChangeTable::User, op, user_id, &User {
+                password: None,
+                token_hash: None,
+                ..user
+            })),
+            Ok(None) => self.changes.publish(ChangeRecord::delete(ChangeTable::User, user_id)),
+            Err(e) => println!("[Database] Change feed: failed to read user {}: {}", user_id, e),
+        }
+    }
     
-    if let Some(row) = row {
-        Ok(Some(User {
-            user_id: row.get("user_id"),
-            username: row.get("username"),
-            email: row.get("email"),
-            name: row.get("name"),
-            password: row.get("password"),
-            picture: row.get("picture"),
-            role: row.get("role"),
-            token_hash: row.get("token_hash"),
-            verified: row.get("verified"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            deleted_at: row.get("deleted_at"),
-            is_dark_mode: row.get("is_dark_mode"),
-            last_seen: row.get("last_seen"),
-            color_scheme: row.get("color_scheme"),
-        }))
-    } else {
-        Ok(None)
+    async fn publish_chat(&self, op: ChangeOp, chat_id: &str) {
+        match self.get_chat_by_id(chat_id).await {
+            Ok(Some(chat)) => self.changes.publish(ChangeRecord::row(ChangeTable::Chat, op, chat_id, &chat)),
+            Ok(None) => self.changes.publish(ChangeRecord::delete(ChangeTable::Chat, chat_id)),
+            Err(e) => println!("[Database] Change feed: failed to read chat {}: {}", chat_id, e),
+        }
     }
-}
-
-pub async fn get_user_by_token(token: &str) -> Result<Option<User>, SqlxError> {
-    let pool = get_pool().await?;
     
-    let row = sqlx::query("SELECT * FROM user WHERE token_hash = ?")
-        .bind(token)
-        .fetch_optional(&pool)
-        .await?;
+    async fn publish_friend(&self, op: ChangeOp, user_id: &str) {
+        match self.get_friend_by_id(user_id).await {
+            Ok(Some(friend)) => self.changes.publish(ChangeRecord::row(ChangeTable::Friend, op, user_id, &friend)),
+            Ok(None) => self.changes.publish(ChangeRecord::delete(ChangeTable::Friend, user_id)),
+            Err(e) => println!("[Database] Change feed: failed to read friend {}: {}", user_id, e),
+        }
+    }
     
-    if let Some(row) = row {
-        Ok(Some(User {
-            user_id: row.get("user_id"),
-            username: row.get("username"),
-            email: row.get("email"),
-            name: row.get("name"),
-            password: row.get("password"),
-            picture: row.get("picture"),
-            role: row.get("role"),
-            token_hash: row.get("token_hash"),
-            verified: row.get("verified"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            deleted_at: row.get("deleted_at"),
-            is_dark_mode: row.get("is_dark_mode"),
-            last_seen: row.get("last_seen"),
-            color_scheme: row.get("color_scheme"),
-        }))
-    } else {
-        Ok(None)
+    async fn publish_participant(&self, op: ChangeOp, participant_id: &str) {
+        match self.get_participant_by_id(participant_id).await {
+            Ok(Some(participant)) => self.changes.publish(ChangeRecord::row(ChangeTable::Participant, op, participant_id, &participant)),
+            Ok(None) => self.changes.publish(ChangeRecord::delete(ChangeTable::Participant, participant_id)),
+            Err(e) => println!("[Database] Change feed: failed to read participant {}: {}", participant_id, e),
+        }
     }
-}
-
-pub async fn get_most_recent_user() -> Result<Option<User>, SqlxError> {
-    let pool = get_pool().await?;
     
-    let row = sqlx::query("SELECT * FROM user ORDER BY updated_at DESC LIMIT 1")
-        .fetch_optional(&pool)
-        .await?;
+    // Messages come straight from RETURNING, so no read-back is needed. The chat summary
+    // triggers may have changed their chats too, so those are re-read and published.
+    async fn publish_messages<'a>(&self, op: ChangeOp, messages: impl IntoIterator<Item = &'a Message>) {
+        let mut chat_ids: Vec<String> = Vec::new();
+        for message in messages {
+            self.changes.publish(ChangeRecord::row(ChangeTable::Message, op, &message.client_message_id, message));
+            if !chat_ids.contains(&message.chat_id) {
+                chat_ids.push(message.chat_id.clone());
+            }
+        }
+        self.publish_chats(&chat_ids).await;
+    }
     
-    if let Some(row) = row {
-        Ok(Some(User {
-            user_id: row.get("user_id"),
-            username: row.get("username"),
-            email: row.get("email"),
-            name: row.get("name"),
-            password: row.get("password"),
-            picture: row.get("picture"),
-            role: row.get("role"),
-            token_hash: row.get("token_hash"),
-            verified: row.get("verified"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            deleted_at: row.get("deleted_at"),
-            is_dark_mode: row.get("is_dark_mode"),
-            last_seen: row.get("last_seen"),
-            color_scheme: row.get("color_scheme"),
-        }))
-    } else {
-        Ok(None)
+    async fn publish_chats(&self, chat_ids: &[String]) {
+        for chat_id in chat_ids {
+            self.publish_chat(ChangeOp::Update, chat_id).await;
+        }
     }
-}
-
-pub async fn update_user_token(user_id: &str, token: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
     
-    sqlx::query("UPDATE user SET token_hash = ?, updated_at = ? WHERE user_id = ?")
-        .bind(token)
-        .bind(chrono::Utc::now().timestamp())
-        .bind(user_id)
-        .execute(&pool)
-        .await?;
+    fn publish_deleted(&self, table: ChangeTable, keys: impl IntoIterator<Item = String>) {
+        self.changes.publish_all(keys.into_iter().map(|key| ChangeRecord::delete(table, key)));
+    }
     
-    Ok(())
-}
-
-pub async fn clear_user_data() -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn publish_delta(&self, table: ChangeTable, result: &DeltaResult) {
+        for (op, ids) in [(ChangeOp::Insert, &result.added), (ChangeOp::Update, &result.updated), (ChangeOp::Delete, &result.removed)] {
+            for id in ids {
+                match table {
+                    ChangeTable::Chat => self.publish_chat(op, id).await,
+                    ChangeTable::Friend => self.publish_friend(op, id).await,
+                    ChangeTable::Participant => self.publish_participant(op, id).await,
+                    _ => self.changes.publish(ChangeRecord { table, op, key: Some(id.clone()), row: None }),
+                }
+            }
+        }
+    }
     
-    sqlx::query("DELETE FROM user").execute(&pool).await?;
-    Ok(())
-}
-
-// Chat operations
-pub async fn insert_or_update_chat(chat: &Chat) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    // Restore and recovery replace the whole file
+    fn publish_reset_all(&self) {
+        self.changes.publish_all([
+            ChangeTable::User, ChangeTable::UserKeys, ChangeTable::Chat, ChangeTable::ChatLocalDelete,
+            ChangeTable::Message, ChangeTable::MessageReceipt, ChangeTable::Friend, ChangeTable::Participant,
+            ChangeTable::Presence,
+        ].map(ChangeRecord::reset));
+    }
     
-    sqlx::query(
-        "INSERT OR REPLACE INTO chat (
-            chat_id, name, created_at, creator_id, is_group,
-            group_name, description, unread_count, last_message_content,
-            last_message_timestamp, participants
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&chat.chat_id)
-    .bind(&chat.name)
-    .bind(chat.created_at)
-    .bind(&chat.creator_id)
-    .bind(chat.is_group)
-    .bind(&chat.group_name)
-    .bind(&chat.description)
-    .bind(chat.unread_count)
-    .bind(&chat.last_message_content)
-    .bind(chat.last_message_timestamp)
-    .bind(&chat.participants)
-    .execute(&pool)
-    .await?;
+    // Status acks from the server name messages by server id
+    async fn advance_status_by_server_ids(&self, server_ids: &[&str], status: MessageStatus) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        let mut updated = Vec::new();
+        for server_id in server_ids {
+            updated.extend(advance_message_status(&mut tx, "message_id = ?", server_id, status).await?);
+        }
+        tx.commit().await?;
     
-    Ok(())
+        self.publish_messages(ChangeOp::Update, &updated).await;
+        Ok(())
+    }
 }
 
-pub async fn get_chat_by_id(chat_id: &str) -> Result<Option<Chat>, SqlxError> {
-    let pool = get_pool().await?;
+impl UserRepo for SqliteRepo {
+    async fn insert_or_update_user(&self, user: &User) -> Result<(), SqlxError> {
+        println!("[Database] Starting insert_or_update_user for: {}", user.username);
     
-    let row = sqlx::query("SELECT * FROM chat WHERE chat_id = ?")
-        .bind(chat_id)
-        .fetch_optional(&pool)
+        let pool = self.pool().await?;
+    
+        // REPLACE rewrites the whole row; carry the read receipt setting and the own-account
+        // flag over since the frontend's user object does not include them
+        sqlx::query(
+            "INSERT OR REPLACE INTO user (
+                user_id, username, email, name, password, picture,
+                role, token_hash, verified, created_at, updated_at,
+                deleted_at, is_dark_mode, last_seen, color_scheme, send_read_receipts, is_own_account
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                COALESCE((SELECT send_read_receipts FROM user WHERE user_id = ?1), 1),
+                MAX(COALESCE(?8, '') != '', COALESCE((SELECT is_own_account FROM user WHERE user_id = ?1), 0)))"
+        )
+        .bind(&user.user_id)
+        .bind(&user.username)
+        .bind(&user.email)
+        .bind(&user.name)
+        .bind(&user.password)
+        .bind(&user.picture)
+        .bind(&user.role)
+        .bind(&user.token_hash)
+        .bind(user.verified)
+        .bind(user.created_at)
+        .bind(user.updated_at)
+        .bind(user.deleted_at)
+        .bind(user.is_dark_mode)
+        .bind(user.last_seen)
+        .bind(&user.color_scheme)
+        .execute(&pool)
         .await?;
     
-    if let Some(row) = row {
-        Ok(Some(Chat {
-            chat_id: row.get("chat_id"),
-            name: row.get("name"),
-            created_at: row.get("created_at"),
-            creator_id: row.get("creator_id"),
-            is_group: row.get("is_group"),
-            group_name: row.get("group_name"),
-            description: row.get("description"),
-            unread_count: row.get("unread_count"),
-            last_message_content: row.get("last_message_content"),
-            last_message_timestamp: row.get("last_message_timestamp"),
-            participants: row.get("participants"),
-        }))
-    } else {
-        Ok(None)
+        println!("[Database] Successfully inserted/updated user: {}", user.username);
+        self.publish_user(ChangeOp::Upsert, &user.user_id).await;
+        Ok(())
     }
-}
 
-pub async fn get_all_chats() -> Result<Vec<Chat>, SqlxError> {
-    let pool = get_pool().await?;
-    
-    println!("[Database] Loading all chats from database...");
-    
-    let rows = sqlx::query("SELECT * FROM chat ORDER BY last_message_timestamp DESC NULLS LAST")
-        .fetch_all(&pool)
-        .await?;
+    async fn get_user_by_id(&self, user_id: &str) -> Result<Option<User>, SqlxError> {
+        let pool = self.pool().await?;
     
-    println!("[Database] Found {} chats in database", rows.len());
+        let row = sqlx::query("SELECT * FROM user WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&pool)
+            .await?;
     
-    let chats: Vec<Chat> = rows.iter().map(|row| {
-        let chat_id: String = row.get("chat_id");
-        let name: Option<String> = row.get("name");
-        println!("[Database] Loading chat: id={}, name={:?}", chat_id, name);
-        
-        Chat {
-            chat_id,
-            name,
-            created_at: row.get("created_at"),
-            creator_id: row.get("creator_id"),
-            is_group: row.get("is_group"),
-            group_name: row.get("group_name"),
-            description: row.get("description"),
-            unread_count: row.get("unread_count"),
-            last_message_content: row.get("last_message_content"),
-            last_message_timestamp: row.get("last_message_timestamp"),
-            participants: row.get("participants"),
+        if let Some(row) = row {
+            Ok(Some(User {
+                user_id: row.get("user_id"),
+                username: row.get("username"),
+                email: row.get("email"),
+                name: row.get("name"),
+                password: row.get("password"),
+                picture: row.get("picture"),
+                role: row.get("role"),
+                token_hash: row.get("token_hash"),
+                verified: row.get("verified"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+                deleted_at: row.get("deleted_at"),
+                is_dark_mode: row.get("is_dark_mode"),
+                last_seen: row.get("last_seen"),
+                color_scheme: row.get("color_scheme"),
+            }))
+        } else {
+            Ok(None)
         }
-    }).collect();
-    
-    println!("[Database] Successfully loaded {} chats", chats.len());
-    
-    Ok(chats)
-}
+    }
 
-pub async fn update_chat_unread_count(chat_id: &str, unread_count: i32) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn get_user_by_token(&self, token: &str) -> Result<Option<User>, SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("UPDATE chat SET unread_count = ? WHERE chat_id = ?")
-        .bind(unread_count)
-        .bind(chat_id)
-        .execute(&pool)
-        .await?;
+        let row = sqlx::query("SELECT * FROM user WHERE token_hash = ?")
+            .bind(token)
+            .fetch_optional(&pool)
+            .await?;
     
-    Ok(())
-}
+        if let Some(row) = row {
+            Ok(Some(User {
+                user_id: row.get("user_id"),
+                username: row.get("username"),
+                email: row.get("email"),
+                name: row.get("name"),
+                password: row.get("password"),
+                picture: row.get("picture"),
+                role: row.get("role"),
+                token_hash: row.get("token_hash"),
+                verified: row.get("verified"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+                deleted_at: row.get("deleted_at"),
+                is_dark_mode: row.get("is_dark_mode"),
+                last_seen: row.get("last_seen"),
+                color_scheme: row.get("color_scheme"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
 
-pub async fn update_chat_last_message(chat_id: &str, content: Option<&str>, timestamp: Option<i64>) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn get_most_recent_user(&self) -> Result<Option<User>, SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("UPDATE chat SET last_message_content = ?, last_message_timestamp = ? WHERE chat_id = ?")
-        .bind(content)
-        .bind(timestamp)
-        .bind(chat_id)
-        .execute(&pool)
-        .await?;
+        let row = sqlx::query("SELECT * FROM user ORDER BY updated_at DESC LIMIT 1")
+            .fetch_optional(&pool)
+            .await?;
     
-    Ok(())
-}
+        if let Some(row) = row {
+            Ok(Some(User {
+                user_id: row.get("user_id"),
+                username: row.get("username"),
+                email: row.get("email"),
+                name: row.get("name"),
+                password: row.get("password"),
+                picture: row.get("picture"),
+                role: row.get("role"),
+                token_hash: row.get("token_hash"),
+                verified: row.get("verified"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+                deleted_at: row.get("deleted_at"),
+                is_dark_mode: row.get("is_dark_mode"),
+                last_seen: row.get("last_seen"),
+                color_scheme: row.get("color_scheme"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
 
-pub async fn delete_chat(chat_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    
-    println!("[Database] Attempting to delete chat: {}", chat_id);
-    
-    // First check if the chat exists
-    let chat_exists = sqlx::query("SELECT COUNT(*) as count FROM chat WHERE chat_id = ?")
-        .bind(chat_id)
-        .fetch_one(&pool)
-        .await?;
+    async fn update_user_token(&self, user_id: &str, token: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    let count: i64 = chat_exists.get("count");
-    println!("[Database] Found {} chat(s) with chat_id: {}", count, chat_id);
+        sqlx::query("UPDATE user SET token_hash = ?1, is_own_account = MAX(is_own_account, ?1 != ''), updated_at = ?2 WHERE user_id = ?3")
+            .bind(token)
+            .bind(Timestamp::now())
+            .bind(user_id)
+            .execute(&pool)
+            .await?;
     
-    if count == 0 {
-        println!("[Database] Warning: No chat found with chat_id: {}", chat_id);
-        return Ok(());
+        self.publish_user(ChangeOp::Update, user_id).await;
+        Ok(())
     }
-    
-    // Delete the chat
-    let result = sqlx::query("DELETE FROM chat WHERE chat_id = ?")
-        .bind(chat_id)
-        .execute(&pool)
-        .await?;
-    
-    println!("[Database] Successfully deleted {} row(s) from chat table for chat_id: {}", result.rows_affected(), chat_id);
-    
-    Ok(())
-}
 
-pub async fn clear_chat_data() -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn clear_user_data(&self) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("DELETE FROM chat").execute(&pool).await?;
-    Ok(())
-}
+        sqlx::query("DELETE FROM user").execute(&pool).await?;
+        self.changes.publish(ChangeRecord::reset(ChangeTable::User));
+        Ok(())
+    }
 
-// Message operations
-pub async fn insert_or_update_message(message: &Message) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    
-    println!("[Database] Inserting/updating message: chat_id={}, sender_id={}, content={}", 
-             message.chat_id, message.sender_id, message.content);
-    
-    sqlx::query(
-        "INSERT OR REPLACE INTO message (
-            message_id, client_message_id, chat_id, sender_id, content,
-            timestamp, is_read, is_sent, is_delivered, is_failed,
-            sender_username, reply_to_message_id
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&message.message_id)
-    .bind(&message.client_message_id)
-    .bind(&message.chat_id)
-    .bind(&message.sender_id)
-    .bind(&message.content)
-    .bind(message.timestamp)
-    .bind(message.is_read)
-    .bind(message.is_sent)
-    .bind(message.is_delivered)
-    .bind(message.is_failed)
-    .bind(&message.sender_username)
-    .bind(&message.reply_to_message_id)
-    .execute(&pool)
-    .await?;
-    
-    println!("[Database] Successfully inserted/updated message for chat: {}", message.chat_id);
-    
-    Ok(())
-}
-
-pub async fn insert_messages(messages: &[Message]) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    
-    for message in messages {
+    // User keys operations
+    async fn insert_or_update_user_keys(&self, keys: &UserKeys) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+    
         sqlx::query(
-            "INSERT OR REPLACE INTO message (
-                message_id, client_message_id, chat_id, sender_id, content,
-                timestamp, is_read, is_sent, is_delivered, is_failed,
-                sender_username, reply_to_message_id
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+            "INSERT OR REPLACE INTO user_keys (
+                user_id, key1, key2, key3, key4, private_key1, private_key2, private_key3, private_key4
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
         )
-        .bind(&message.message_id)
-        .bind(&message.client_message_id)
-        .bind(&message.chat_id)
-        .bind(&message.sender_id)
-        .bind(&message.content)
-        .bind(message.timestamp)
-        .bind(message.is_read)
-        .bind(message.is_sent)
-        .bind(message.is_delivered)
-        .bind(message.is_failed)
-        .bind(&message.sender_username)
-        .bind(&message.reply_to_message_id)
+        .bind(&keys.user_id)
+        .bind(&keys.key1)
+        .bind(&keys.key2)
+        .bind(&keys.key3)
+        .bind(&keys.key4)
+        .bind(&keys.private_key1)
+        .bind(&keys.private_key2)
+        .bind(&keys.private_key3)
+        .bind(&keys.private_key4)
         .execute(&pool)
         .await?;
-    }
     
-    Ok(())
-}
+        // Key material itself is never published
+        self.changes.publish(ChangeRecord { table: ChangeTable::UserKeys, op: ChangeOp::Upsert, key: Some(keys.user_id.clone()), row: None });
+        Ok(())
+    }
 
-pub async fn get_messages_for_chat(chat_id: &str) -> Result<Vec<Message>, SqlxError> {
-    let pool = get_pool().await?;
+    async fn get_user_keys(&self, user_id: &str) -> Result<Option<UserKeys>, SqlxError> {
+        let pool = self.pool().await?;
     
-    println!("[Database] Getting messages for chat: {}", chat_id);
+        let row = sqlx::query("SELECT * FROM user_keys WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&pool)
+            .await?;
     
-    let rows = sqlx::query("SELECT * FROM message WHERE chat_id = ? ORDER BY timestamp ASC")
-        .bind(chat_id)
-        .fetch_all(&pool)
-        .await?;
+        if let Some(row) = row {
+            Ok(Some(UserKeys {
+                user_id: row.get("user_id"),
+                key1: row.get("key1"),
+                key2: row.get("key2"),
+                key3: row.get("key3"),
+                key4: row.get("key4"),
+                private_key1: row.get("private_key1"),
+                private_key2: row.get("private_key2"),
+                private_key3: row.get("private_key3"),
+                private_key4: row.get("private_key4"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Dark mode operations
+    async fn update_dark_mode(&self, user_id: &str, is_dark_mode: bool) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    println!("[Database] Found {} messages for chat {}", rows.len(), chat_id);
+        sqlx::query("UPDATE user SET is_dark_mode = ? WHERE user_id = ?")
+            .bind(is_dark_mode)
+            .bind(user_id)
+            .execute(&pool)
+            .await?;
     
-    let messages: Vec<Message> = rows.iter().map(|row| Message {
-        id: row.get("id"),
-        message_id: row.get("message_id"),
-        client_message_id: row.get("client_message_id"),
-        chat_id: row.get("chat_id"),
-        sender_id: row.get("sender_id"),
-        content: row.get("content"),
-        timestamp: row.get("timestamp"),
-        is_read: row.get("is_read"),
-        is_sent: row.get("is_sent"),
-        is_delivered: row.get("is_delivered"),
-        is_failed: row.get("is_failed"),
+        self.publish_user(ChangeOp::Update, user_id).await;
+        Ok(())
+    }
+
+    async fn get_dark_mode(&self, user_id: &str) -> Result<bool, SqlxError> {
+        let pool = self.pool().await?;
+        let result = sqlx::query("SELECT is_dark_mode FROM user WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&pool)
+            .await?;
+
+        Ok(result.map(|row| row.get::<bool, _>("is_dark_mode")).unwrap_or(false))
+    }
+
+    async fn update_send_read_receipts(&self, user_id: &str, enabled: bool) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+        sqlx::query("UPDATE user SET send_read_receipts = ? WHERE user_id = ?")
+            .bind(enabled)
+            .bind(user_id)
+            .execute(&pool)
+            .await?;
+        self.publish_user(ChangeOp::Update, user_id).await;
+        Ok(())
+    }
+
+    async fn get_send_read_receipts(&self, user_id: &str) -> Result<bool, SqlxError> {
+        let pool = self.pool().await?;
+        let result = sqlx::query("SELECT send_read_receipts FROM user WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&pool)
+            .await?;
+
+        // On unless the user turned it off
+        Ok(result.map(|row| row.get::<bool, _>("send_read_receipts")).unwrap_or(true))
+    }
+
+    async fn update_color_scheme(&self, user_id: &str, color_scheme: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+        sqlx::query("UPDATE user SET color_scheme = ? WHERE user_id = ?")
+            .bind(color_scheme)
+            .bind(user_id)
+            .execute(&pool)
+            .await?;
+        self.publish_user(ChangeOp::Update, user_id).await;
+        Ok(())
+    }
+
+    async fn get_color_scheme(&self, user_id: &str) -> Result<String, SqlxError> {
+        let pool = self.pool().await?;
+        let result = sqlx::query("SELECT color_scheme FROM user WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&pool)
+            .await?;
+
+        Ok(result.map(|row| row.get::<String, _>("color_scheme")).unwrap_or_else(|| "blue".to_string()))
+    }
+
+    async fn get_usernames(&self, user_ids: &[String]) -> Result<HashMap<String, String>, SqlxError> {
+        if user_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let pool = self.pool().await?;
+    
+        let placeholders = user_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        let query = format!(
+            "SELECT user_id, username FROM ({}) WHERE user_id IN ({}) ORDER BY priority",
+            KNOWN_USERNAMES, placeholders
+        );
+        let mut query_builder = sqlx::query_as::<_, (String, String)>(&query);
+        for user_id in user_ids {
+            query_builder = query_builder.bind(user_id);
+        }
+    
+        // Rows come lowest priority first, so the best source is inserted last
+        Ok(query_builder.fetch_all(&pool).await?.into_iter().collect())
+    }
+}
+// Delta sync
+// Ids touched by applying one server snapshot to the local cache
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeltaResult {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl DeltaResult {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl ChatRepo for SqliteRepo {
+    async fn insert_or_update_chat(&self, chat: &Chat) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+    
+        // Upsert so local-only settings such as message_ttl_secs survive a chat sync. The
+        // summary columns only seed a new row; after that the message triggers own them.
+        sqlx::query(
+            "INSERT INTO chat (
+                chat_id, name, created_at, creator_id, is_group,
+                group_name, description, unread_count, last_message_content,
+                last_message_timestamp, participants
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(chat_id) DO UPDATE SET
+                name = excluded.name,
+                created_at = excluded.created_at,
+                creator_id = excluded.creator_id,
+                is_group = excluded.is_group,
+                group_name = excluded.group_name,
+                description = excluded.description,
+                participants = excluded.participants"
+        )
+        .bind(&chat.chat_id)
+        .bind(&chat.name)
+        .bind(chat.created_at)
+        .bind(&chat.creator_id)
+        .bind(chat.is_group)
+        .bind(&chat.group_name)
+        .bind(&chat.description)
+        .bind(chat.unread_count)
+        .bind(&chat.last_message_content)
+        .bind(chat.last_message_timestamp)
+        .bind(&chat.participants)
+        .execute(&pool)
+        .await?;
+    
+        self.publish_chat(ChangeOp::Upsert, &chat.chat_id).await;
+        Ok(())
+    }
+
+    async fn get_chat_by_id(&self, chat_id: &str) -> Result<Option<Chat>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let row = sqlx::query("SELECT * FROM chat WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_optional(&pool)
+            .await?;
+    
+        if let Some(row) = row {
+            Ok(Some(Chat {
+                chat_id: row.get("chat_id"),
+                name: row.get("name"),
+                created_at: row.get("created_at"),
+                creator_id: row.get("creator_id"),
+                is_group: row.get("is_group"),
+                group_name: row.get("group_name"),
+                description: row.get("description"),
+                unread_count: row.get("unread_count"),
+                last_message_content: row.get("last_message_content"),
+                last_message_timestamp: row.get("last_message_timestamp"),
+                participants: row.get("participants"),
+                is_local_only: row.get("is_local_only"),
+                is_read_only: row.get("is_read_only"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_all_chats(&self) -> Result<Vec<Chat>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        println!("[Database] Loading all chats from database...");
+    
+        // Most recent activity first; a chat with no messages yet counts from its creation
+        let rows = sqlx::query("SELECT * FROM chat ORDER BY COALESCE(last_message_timestamp, created_at) DESC, chat_id")
+            .fetch_all(&pool)
+            .await?;
+    
+        println!("[Database] Found {} chats in database", rows.len());
+    
+        let chats: Vec<Chat> = rows.iter().map(|row| {
+            let chat_id: String = row.get("chat_id");
+            let name: Option<String> = row.get("name");
+            println!("[Database] Loading chat: id={}, name={:?}", chat_id, name);
+        
+            Chat {
+                chat_id,
+                name,
+                created_at: row.get("created_at"),
+                creator_id: row.get("creator_id"),
+                is_group: row.get("is_group"),
+                group_name: row.get("group_name"),
+                description: row.get("description"),
+                unread_count: row.get("unread_count"),
+                last_message_content: row.get("last_message_content"),
+                last_message_timestamp: row.get("last_message_timestamp"),
+                participants: row.get("participants"),
+                is_local_only: row.get("is_local_only"),
+                is_read_only: row.get("is_read_only"),
+            }
+        }).collect();
+    
+        println!("[Database] Successfully loaded {} chats", chats.len());
+    
+        Ok(chats)
+    }
+
+    // Message retention (disappearing messages)
+    async fn set_chat_message_ttl(&self, chat_id: &str, ttl_secs: Option<i64>) -> Result<bool, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let result = sqlx::query("UPDATE chat SET message_ttl_secs = ? WHERE chat_id = ?")
+            .bind(ttl_secs)
+            .bind(chat_id)
+            .execute(&pool)
+            .await?;
+    
+        let updated = result.rows_affected() > 0;
+        if updated {
+            self.publish_chat(ChangeOp::Update, chat_id).await;
+        }
+        Ok(updated)
+    }
+
+    async fn get_chat_message_ttl(&self, chat_id: &str) -> Result<Option<i64>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let ttl: Option<Option<i64>> = sqlx::query_scalar("SELECT message_ttl_secs FROM chat WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_optional(&pool)
+            .await?;
+    
+        Ok(ttl.flatten())
+    }
+
+    async fn delete_chat(&self, chat_id: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+    
+        println!("[Database] Attempting to delete chat: {}", chat_id);
+    
+        // First check if the chat exists
+        let chat_exists = sqlx::query("SELECT COUNT(*) as count FROM chat WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_one(&pool)
+            .await?;
+    
+        let count: i64 = chat_exists.get("count");
+        println!("[Database] Found {} chat(s) with chat_id: {}", count, chat_id);
+    
+        if count == 0 {
+            println!("[Database] Warning: No chat found with chat_id: {}", chat_id);
+            return Ok(());
+        }
+    
+        // Delete the chat; its messages and participants are removed by ON DELETE CASCADE
+        let result = sqlx::query("DELETE FROM chat WHERE chat_id = ?")
+            .bind(chat_id)
+            .execute(&pool)
+            .await?;
+    
+        println!("[Database] Successfully deleted {} row(s) from chat table for chat_id: {}", result.rows_affected(), chat_id);
+    
+        self.changes.publish(ChangeRecord::delete(ChangeTable::Chat, chat_id));
+        Ok(())
+    }
+
+    async fn clear_chat_data(&self) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+    
+        sqlx::query("DELETE FROM chat").execute(&pool).await?;
+        self.changes.publish(ChangeRecord::reset(ChangeTable::Chat));
+        Ok(())
+    }
+
+    /// Add a chat to local deletes tracking
+    async fn add_local_delete(&self, chat_id: &str, user_id: &str, is_creator: bool) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+        let timestamp = Timestamp::now();
+    
+        sqlx::query(
+            "INSERT OR REPLACE INTO local_deletes (chat_id, deleted_at, user_id, is_creator) VALUES (?, ?, ?, ?)"
+        )
+        .bind(chat_id)
+        .bind(timestamp)
+        .bind(user_id)
+        .bind(is_creator)
+        .execute(&pool)
+        .await?;
+    
+        self.changes.publish(ChangeRecord::row(ChangeTable::ChatLocalDelete, ChangeOp::Upsert, chat_id, &serde_json::json!({
+            "chat_id": chat_id,
+            "user_id": user_id,
+            "deleted_at": timestamp,
+            "is_creator": is_creator,
+        })));
+        Ok(())
+    }
+
+    /// Check if a chat is locally deleted
+    async fn is_chat_locally_deleted(&self, chat_id: &str, user_id: &str) -> Result<bool, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let result = sqlx::query("SELECT 1 FROM local_deletes WHERE chat_id = ? AND user_id = ?")
+            .bind(chat_id)
+            .bind(user_id)
+            .fetch_optional(&pool)
+            .await?;
+    
+        Ok(result.is_some())
+    }
+
+    /// Remove a chat from local deletes tracking
+    async fn remove_local_delete(&self, chat_id: &str, user_id: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+    
+        sqlx::query("DELETE FROM local_deletes WHERE chat_id = ? AND user_id = ?")
+            .bind(chat_id)
+            .bind(user_id)
+            .execute(&pool)
+            .await?;
+    
+        self.changes.publish(ChangeRecord::delete(ChangeTable::ChatLocalDelete, chat_id));
+        Ok(())
+    }
+
+    /// Clean up local deletes for chats that no longer exist on server
+    async fn cleanup_local_deletes(&self, server_chat_ids: &[String], user_id: &str) -> Result<(), SqlxError> {
+        if server_chat_ids.is_empty() {
+            return Ok(());
+        }
+    
+        let pool = self.pool().await?;
+    
+        // Create placeholders for the IN clause
+        let placeholders = server_chat_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        let query = format!("DELETE FROM local_deletes WHERE user_id = ? AND chat_id NOT IN ({}) RETURNING chat_id", placeholders);
+    
+        let mut query_builder = sqlx::query_scalar(&query).bind(user_id);
+    
+        // Bind all server chat IDs
+        for chat_id in server_chat_ids {
+            query_builder = query_builder.bind(chat_id);
+        }
+    
+        let removed: Vec<String> = query_builder.fetch_all(&pool).await?;
+    
+        self.publish_deleted(ChangeTable::ChatLocalDelete, removed);
+        Ok(())
+    }
+
+    /// Get all locally deleted chat IDs for a user
+    async fn get_local_deleted_chat_ids(&self, user_id: &str) -> Result<Vec<String>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let rows = sqlx::query("SELECT chat_id FROM local_deletes WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_all(&pool)
+            .await?;
+    
+        let chat_ids: Vec<String> = rows.iter().map(|row| row.get("chat_id")).collect();
+        Ok(chat_ids)
+    }
+
+    /// Make the cached chats match the server's list in one transaction.
+    ///
+    /// Chats the user deleted locally are skipped, chats missing from the server are
+    /// removed (messages and participants cascade) unless they were imported from an
+    /// archive, in which case they become read-only and local-only. Local-delete markers
+    /// for chats the server no longer has are dropped. Only server-owned columns are
+    /// updated, so generated names, last-message summaries, unread counts and retention
+    /// settings are kept.
+    async fn apply_chat_delta(&self, server_chats: &[Chat], user_id: &str) -> Result<DeltaResult, SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        let mut result = DeltaResult::default();
+    
+        let locally_deleted: std::collections::HashSet<String> = sqlx::query_scalar("SELECT chat_id FROM local_deletes WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .collect();
+        let cached: std::collections::HashMap<String, bool> = sqlx::query_as("SELECT chat_id, imported_at IS NOT NULL FROM chat")
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .collect();
+    
+        let mut kept = std::collections::HashSet::new();
+        for chat in server_chats {
+            if locally_deleted.contains(&chat.chat_id) {
+                println!("[Database] Skipping locally deleted chat: {}", chat.chat_id);
+                continue;
+            }
+            kept.insert(chat.chat_id.clone());
+        
+            let changed = sqlx::query(
+                "INSERT INTO chat (
+                    chat_id, name, created_at, creator_id, is_group,
+                    group_name, description, unread_count, last_message_content,
+                    last_message_timestamp, participants
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(chat_id) DO UPDATE SET
+                    name = COALESCE(excluded.name, chat.name),
+                    created_at = excluded.created_at,
+                    creator_id = excluded.creator_id,
+                    is_group = excluded.is_group,
+                    group_name = COALESCE(excluded.group_name, chat.group_name),
+                    description = COALESCE(excluded.description, chat.description),
+                    participants = excluded.participants,
+                    is_local_only = 0,
+                    is_read_only = 0
+                WHERE COALESCE(excluded.name, chat.name) IS NOT chat.name
+                   OR excluded.created_at IS NOT chat.created_at
+                   OR excluded.creator_id IS NOT chat.creator_id
+                   OR excluded.is_group IS NOT chat.is_group
+                   OR COALESCE(excluded.group_name, chat.group_name) IS NOT chat.group_name
+                   OR COALESCE(excluded.description, chat.description) IS NOT chat.description
+                   OR excluded.participants IS NOT chat.participants
+                   OR chat.is_local_only != 0
+                   OR chat.is_read_only != 0"
+            )
+            .bind(&chat.chat_id)
+            .bind(&chat.name)
+            .bind(chat.created_at)
+            .bind(&chat.creator_id)
+            .bind(chat.is_group)
+            .bind(&chat.group_name)
+            .bind(&chat.description)
+            .bind(chat.unread_count)
+            .bind(&chat.last_message_content)
+            .bind(chat.last_message_timestamp)
+            .bind(&chat.participants)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() > 0;
+        
+            if !cached.contains_key(&chat.chat_id) {
+                result.added.push(chat.chat_id.clone());
+            } else if changed {
+                result.updated.push(chat.chat_id.clone());
+            }
+        }
+    
+        for (chat_id, imported) in cached.iter().filter(|(id, _)| !kept.contains(*id)) {
+            if *imported {
+                // Keep the archived history, but nothing can be sent to it any more
+                let flagged = sqlx::query(
+                    "UPDATE chat SET is_local_only = 1, is_read_only = 1
+                     WHERE chat_id = ? AND (is_local_only = 0 OR is_read_only = 0)"
+                )
+                .bind(chat_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected() > 0;
+                if flagged {
+                    result.updated.push(chat_id.clone());
+                }
+                continue;
+            }
+            sqlx::query("DELETE FROM chat WHERE chat_id = ?")
+                .bind(chat_id)
+                .execute(&mut *tx)
+                .await?;
+            result.removed.push(chat_id.clone());
+        }
+    
+        // A local delete only needs remembering while the server still lists the chat
+        let server_ids: std::collections::HashSet<&str> = server_chats.iter().map(|chat| chat.chat_id.as_str()).collect();
+        let mut forgotten = Vec::new();
+        for chat_id in locally_deleted.iter().filter(|id| !server_ids.contains(id.as_str())) {
+            sqlx::query("DELETE FROM local_deletes WHERE user_id = ? AND chat_id = ?")
+                .bind(user_id)
+                .bind(chat_id)
+                .execute(&mut *tx)
+                .await?;
+            forgotten.push(chat_id.clone());
+        }
+    
+        tx.commit().await?;
+    
+        println!("[Database] Chat delta applied: {} added, {} updated, {} removed",
+                 result.added.len(), result.updated.len(), result.removed.len());
+    
+        self.publish_delta(ChangeTable::Chat, &result).await;
+        self.publish_deleted(ChangeTable::ChatLocalDelete, forgotten);
+        Ok(result)
+    }
+}
+
+// Messages removed from one chat by a retention purge
+#[derive(Debug, Serialize, Clone)]
+pub struct ExpiredMessages {
+    pub chat_id: String,
+    pub message_ids: Vec<String>,
+    pub client_message_ids: Vec<String>,
+    // Summary after the purge; None when the chat has no messages left
+    pub last_message_content: Option<String>,
+    pub last_message_timestamp: Option<Timestamp>,
+}
+
+// Message operations
+// message and participant rows must reference a chat. Something can arrive for a
+// chat we have not synced yet; insert a placeholder that the next chat sync fills in
+// rather than dropping the row. Returns whether a placeholder was created.
+async fn ensure_chat_row<'e, E>(executor: E, chat_id: &str) -> Result<bool, SqlxError>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    let result = sqlx::query("INSERT OR IGNORE INTO chat (chat_id, created_at) VALUES (?, ?)")
+        .bind(chat_id)
+        .bind(Timestamp::now())
+        .execute(executor)
+        .await?;
+    let created = result.rows_affected() > 0;
+    if created {
+        println!("[Database] Created placeholder chat row for unknown chat: {}", chat_id);
+    }
+    Ok(created)
+}
+
+// The status a message write may store: whatever is stored already, advanced towards
+// `to`. Upserts go through this so a stale copy never moves a message backwards.
+async fn forward_status<'e, E>(executor: E, client_message_id: &str, to: MessageStatus) -> Result<MessageStatus, SqlxError>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    let current: Option<MessageStatus> = sqlx::query_scalar("SELECT status FROM message WHERE client_message_id = ?")
+        .bind(client_message_id)
+        .fetch_optional(executor)
+        .await?;
+    Ok(current.map_or(to, |current| current.advanced(to)))
+}
+
+// Messages from anyone but us. Own messages are those of any account that has signed in
+// on this device (user.is_own_account), which stays set after logout.
+const NOT_OWN_MESSAGE: &str = "sender_id NOT IN (SELECT user_id FROM user WHERE is_own_account = 1)";
+
+// Move every message matching `filter` (a WHERE clause with one parameter) towards
+// `to`. Transitions `MessageStatus::advance` rejects are skipped, so this can be
+// called with stale or out-of-order acks. Returns the rows that changed.
+async fn advance_message_status(conn: &mut sqlx::SqliteConnection, filter: &str, param: &str, to: MessageStatus) -> Result<Vec<Message>, SqlxError> {
+    let rows = sqlx::query(&format!("SELECT id, client_message_id, status FROM message WHERE {}", filter))
+        .bind(param)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut updated = Vec::new();
+    for row in rows {
+        let current: MessageStatus = row.get("status");
+        let next = match current.advance(to) {
+            Some(next) if next != current => next,
+            Some(_) => continue,
+            None => {
+                let client_message_id: String = row.get("client_message_id");
+                println!("[Database] Ignoring status change {} -> {} for message {}", current, to, client_message_id);
+                continue;
+            }
+        };
+        let id: i64 = row.get("id");
+        let row = sqlx::query("UPDATE message SET status = ? WHERE id = ? RETURNING *")
+            .bind(next)
+            .bind(id)
+            .fetch_one(&mut *conn)
+            .await?;
+        updated.push(message_from_row(&row));
+    }
+    Ok(updated)
+}
+
+/// How an incoming message was reconciled with the local store
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IncomingMessageOutcome {
+    /// No matching row existed; a new row was inserted
+    Inserted,
+    /// Merged into an existing row (our pending outbox entry or an earlier copy)
+    Merged,
+    /// Redelivery of a row we already have in the same state
+    Unchanged,
+}
+
+fn message_from_row(row: &sqlx::sqlite::SqliteRow) -> Message {
+    Message {
+        id: row.get("id"),
+        message_id: row.get("message_id"),
+        client_message_id: row.get("client_message_id"),
+        chat_id: row.get("chat_id"),
+        sender_id: row.get("sender_id"),
+        content: row.get("content"),
+        timestamp: row.get("timestamp"),
+        status: row.get("status"),
         sender_username: row.get("sender_username"),
         reply_to_message_id: row.get("reply_to_message_id"),
-    }).collect();
+    }
+}
+
+// Usernames we already know locally, best source first: profiles fetched for a user,
+// then friends, then chat participants. Names that are just the id do not count.
+const KNOWN_USERNAMES: &str =
+    "SELECT user_id, username, 3 AS priority FROM user WHERE username != '' AND username != user_id
+     UNION ALL
+     SELECT user_id, username, 2 FROM friend WHERE username IS NOT NULL AND username != '' AND username != user_id
+     UNION ALL
+     SELECT user_id, username, 1 FROM participant WHERE username != '' AND username != user_id";
+
+// Message search
+// Snippets mark matched terms with these control characters so the UI can highlight
+// them without having to escape message text first
+pub const SEARCH_HIGHLIGHT_START: char = '\u{2}';
+pub const SEARCH_HIGHLIGHT_END: char = '\u{3}';
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MessageSearchFilter {
+    pub chat_id: Option<String>,
+    pub sender_id: Option<String>,
+    // Inclusive bounds
+    pub from: Option<Timestamp>,
+    pub to: Option<Timestamp>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MessageSearchHit {
+    pub message: Message,
+    pub chat_name: Option<String>,
+    pub is_group: bool,
+    pub snippet: String,
+    pub rank: f64,
+    // Neighbouring messages in the same chat, for context around the hit
+    pub previous_content: Option<String>,
+    pub next_content: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MessageSearchPage {
+    pub hits: Vec<MessageSearchHit>,
+    pub next_cursor: Option<String>,
+}
+
+// Turn user input into an FTS5 query: every word is matched literally (so quotes,
+// dashes and operators in the input are harmless) and the last word as a prefix,
+// which keeps results useful while the user is still typing
+fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input.split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    let last = terms.last()?;
+    let mut query = terms[..terms.len() - 1].to_vec();
+    query.push(format!("{}*", last));
+    Some(query.join(" "))
+}
+
+fn encode_search_cursor(offset: i64) -> String {
+    use base64::{Engine as _, engine::general_purpose};
+    general_purpose::URL_SAFE_NO_PAD.encode(format!("offset:{}", offset))
+}
+
+fn decode_search_cursor(cursor: &str) -> Option<i64> {
+    use base64::{Engine as _, engine::general_purpose};
+    let bytes = general_purpose::URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    String::from_utf8(bytes).ok()?
+        .strip_prefix("offset:")?
+        .parse()
+        .ok()
+        .filter(|offset: &i64| *offset >= 0)
+}
+
+// Message pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageDirection {
+    Older,
+    Newer,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MessagePage {
+    // Oldest first in either direction
+    pub messages: Vec<Message>,
+    // Continues from the edge of this page in the requested direction
+    pub next_cursor: Option<String>,
+    // More rows exist locally past next_cursor
+    pub has_more: bool,
+}
+
+// Keyset cursor over (timestamp, id), so rows inserted while paging never shift a page
+pub fn encode_page_cursor(timestamp: Timestamp, id: i64) -> String {
+    use base64::{Engine as _, engine::general_purpose};
+    general_purpose::URL_SAFE_NO_PAD.encode(format!("page:{}:{}", timestamp, id))
+}
+
+pub fn decode_page_cursor(cursor: &str) -> Option<(Timestamp, i64)> {
+    use base64::{Engine as _, engine::general_purpose};
+    let bytes = general_purpose::URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    let text = String::from_utf8(bytes).ok()?;
+    let (timestamp, id) = text.strip_prefix("page:")?.split_once(':')?;
+    Some((Timestamp::from_millis(timestamp.parse().ok()?), id.parse().ok()?))
+}
+
+// Read receipts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptStatus {
+    Delivered,
+    Read,
+}
+
+impl ReceiptStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReceiptStatus::Delivered => "delivered",
+            ReceiptStatus::Read => "read",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "delivered" => Some(ReceiptStatus::Delivered),
+            "read" => Some(ReceiptStatus::Read),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MessageReceipt {
+    pub message_id: String,
+    pub user_id: String,
+    // From the chat's participant list, when we have it
+    pub username: Option<String>,
+    pub status: ReceiptStatus,
+    pub at: Timestamp,
+}
+
+// "Read by N of M" for one message
+#[derive(Debug, Serialize, Clone)]
+pub struct MessageReadState {
+    pub message_id: String,
+    pub chat_id: String,
+    // Everyone in the chat except the sender
+    pub recipient_count: i64,
+    // Includes recipients who have also read it
+    pub delivered_count: i64,
+    pub read_count: i64,
+    // Earliest reader first
+    pub readers: Vec<MessageReceipt>,
+}
+
+impl MessageRepo for SqliteRepo {
+    async fn insert_or_update_message(&self, message: &Message) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    println!("[Database] Returning {} messages for chat {}", messages.len(), chat_id);
+        println!("[Database] Inserting/updating message: chat_id={}, sender_id={}, content={}", 
+                 message.chat_id, message.sender_id, message.content);
     
-    Ok(messages)
-}
+        let mut tx = pool.begin().await?;
+        let created_chat = ensure_chat_row(&mut *tx, &message.chat_id).await?;
+        let status = forward_status(&mut *tx, &message.client_message_id, message.status).await?;
+    
+        // Upsert rather than INSERT OR REPLACE so the row keeps its id and the search index triggers fire
+        let row = sqlx::query(
+            "INSERT INTO message (
+                message_id, client_message_id, chat_id, sender_id, content,
+                timestamp, status, sender_username, reply_to_message_id
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(client_message_id) DO UPDATE SET
+                message_id = excluded.message_id,
+                chat_id = excluded.chat_id,
+                sender_id = excluded.sender_id,
+                content = excluded.content,
+                timestamp = excluded.timestamp,
+                status = excluded.status,
+                sender_username = excluded.sender_username,
+                reply_to_message_id = excluded.reply_to_message_id
+            RETURNING *"
+        )
+        .bind(&message.message_id)
+        .bind(&message.client_message_id)
+        .bind(&message.chat_id)
+        .bind(&message.sender_id)
+        .bind(&message.content)
+        .bind(message.timestamp)
+        .bind(status)
+        .bind(&message.sender_username)
+        .bind(&message.reply_to_message_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        if created_chat {
+            self.publish_chat(ChangeOp::Insert, &message.chat_id).await;
+        }
+        println!("[Database] Successfully inserted/updated message for chat: {}", message.chat_id);
+    
+        self.publish_messages(ChangeOp::Upsert, [&message_from_row(&row)]).await;
+        Ok(())
+    }
+
+    async fn insert_messages(&self, messages: &[Message]) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+    
+        let mut stored = Vec::with_capacity(messages.len());
+        let mut created_chats = Vec::new();
+        let mut tx = pool.begin().await?;
+        for message in messages {
+            if ensure_chat_row(&mut *tx, &message.chat_id).await? {
+                created_chats.push(message.chat_id.clone());
+            }
+            let status = forward_status(&mut *tx, &message.client_message_id, message.status).await?;
+        
+            let row = sqlx::query(
+                "INSERT INTO message (
+                    message_id, client_message_id, chat_id, sender_id, content,
+                    timestamp, status, sender_username, reply_to_message_id
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(client_message_id) DO UPDATE SET
+                    message_id = excluded.message_id,
+                    chat_id = excluded.chat_id,
+                    sender_id = excluded.sender_id,
+                    content = excluded.content,
+                    timestamp = excluded.timestamp,
+                    status = excluded.status,
+                    sender_username = excluded.sender_username,
+                    reply_to_message_id = excluded.reply_to_message_id
+                RETURNING *"
+            )
+            .bind(&message.message_id)
+            .bind(&message.client_message_id)
+            .bind(&message.chat_id)
+            .bind(&message.sender_id)
+            .bind(&message.content)
+            .bind(message.timestamp)
+            .bind(status)
+            .bind(&message.sender_username)
+            .bind(&message.reply_to_message_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            stored.push(message_from_row(&row));
+        }
+        tx.commit().await?;
+    
+        for chat_id in &created_chats {
+            self.publish_chat(ChangeOp::Insert, chat_id).await;
+        }
+        self.publish_messages(ChangeOp::Upsert, &stored).await;
+        Ok(())
+    }
+
+    /// Store a message received from the server, merging it into the row we already
+    /// have for it instead of inserting a duplicate.
+    ///
+    /// A row matches when it carries the same server `message_id`, the same
+    /// `client_message_id` (the echo of a message we sent), or is a pending outbox
+    /// row (no server id yet, not sent) in the same chat from the same sender with the
+    /// same content. Lookup and write happen in one `BEGIN IMMEDIATE` transaction so
+    /// concurrent deliveries of the same message cannot both insert.
+    async fn reconcile_incoming_message(&self, message: &Message) -> Result<(IncomingMessageOutcome, Message), SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+    
+        let mut existing = None;
+        if let Some(server_id) = &message.message_id {
+            existing = sqlx::query("SELECT * FROM message WHERE message_id = ? ORDER BY id ASC LIMIT 1")
+                .bind(server_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        }
+        if existing.is_none() {
+            existing = sqlx::query("SELECT * FROM message WHERE client_message_id = ?")
+                .bind(&message.client_message_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        }
+        if existing.is_none() {
+            // Content-only match; with two identical unsent texts ("ok", "ok") we cannot
+            // tell which echo this is, so store it as a new row instead of guessing
+            let mut candidates = sqlx::query(
+                "SELECT * FROM message
+                 WHERE chat_id = ? AND sender_id = ? AND content = ? AND status IN ('pending', 'failed')
+                   AND (message_id IS NULL OR message_id = client_message_id)
+                 ORDER BY id ASC LIMIT 2"
+            )
+            .bind(&message.chat_id)
+            .bind(&message.sender_id)
+            .bind(&message.content)
+            .fetch_all(&mut *tx)
+            .await?;
+            if candidates.len() == 1 {
+                existing = candidates.pop();
+            } else if candidates.len() > 1 {
+                println!("[Database] Incoming message {:?} matches several pending rows by content, not merging",
+                         message.message_id);
+            }
+        }
+    
+        let Some(row) = existing else {
+            let chat_created = ensure_chat_row(&mut *tx, &message.chat_id).await?;
+        
+            let row = sqlx::query(
+                "INSERT INTO message (
+                    message_id, client_message_id, chat_id, sender_id, content,
+                    timestamp, status, sender_username, reply_to_message_id
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                RETURNING *"
+            )
+            .bind(&message.message_id)
+            .bind(&message.client_message_id)
+            .bind(&message.chat_id)
+            .bind(&message.sender_id)
+            .bind(&message.content)
+            .bind(message.timestamp)
+            .bind(message.status)
+            .bind(&message.sender_username)
+            .bind(&message.reply_to_message_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            tx.commit().await?;
+        
+            println!("[Database] Inserted incoming message {:?} for chat {}", message.message_id, message.chat_id);
+            if chat_created {
+                self.publish_chat(ChangeOp::Insert, &message.chat_id).await;
+            }
+            let inserted = message_from_row(&row);
+            self.publish_messages(ChangeOp::Insert, [&inserted]).await;
+            return Ok((IncomingMessageOutcome::Inserted, inserted));
+        };
+    
+        let current = message_from_row(&row);
+        // The server has the message, so it is at least sent; local content and names win over the server copy
+        let merged = Message {
+            id: current.id,
+            message_id: message.message_id.clone().or(current.message_id.clone()),
+            client_message_id: current.client_message_id.clone(),
+            chat_id: current.chat_id.clone(),
+            sender_id: current.sender_id.clone(),
+            content: current.content.clone(),
+            timestamp: message.timestamp,
+            status: current.status.advanced(MessageStatus::Sent).advanced(message.status),
+            sender_username: current.sender_username.clone().or(message.sender_username.clone()),
+            reply_to_message_id: current.reply_to_message_id.clone().or(message.reply_to_message_id.clone()),
+        };
+    
+        if merged.message_id == current.message_id
+            && merged.timestamp == current.timestamp
+            && merged.status == current.status
+            && merged.sender_username == current.sender_username
+            && merged.reply_to_message_id == current.reply_to_message_id
+        {
+            tx.commit().await?;
+            println!("[Database] Incoming message {:?} already stored, nothing to do", message.message_id);
+            return Ok((IncomingMessageOutcome::Unchanged, current));
+        }
+    
+        sqlx::query(
+            "UPDATE message SET message_id = ?, timestamp = ?, status = ?, sender_username = ?, reply_to_message_id = ?
+             WHERE id = ?"
+        )
+        .bind(&merged.message_id)
+        .bind(merged.timestamp)
+        .bind(merged.status)
+        .bind(&merged.sender_username)
+        .bind(&merged.reply_to_message_id)
+        .bind(merged.id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    
+        println!("[Database] Merged incoming message {:?} into local row {:?} (client id {})",
+                 message.message_id, merged.id, merged.client_message_id);
+        self.publish_messages(ChangeOp::Update, [&merged]).await;
+        Ok((IncomingMessageOutcome::Merged, merged))
+    }
+
+    async fn get_messages_for_chat(&self, chat_id: &str) -> Result<Vec<Message>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        println!("[Database] Getting messages for chat: {}", chat_id);
+    
+        // Timestamps are milliseconds, so same-millisecond messages fall back to arrival order
+        let rows = sqlx::query("SELECT * FROM message WHERE chat_id = ? ORDER BY timestamp ASC, id ASC")
+            .bind(chat_id)
+            .fetch_all(&pool)
+            .await?;
+    
+        println!("[Database] Found {} messages for chat {}", rows.len(), chat_id);
+    
+        let messages: Vec<Message> = rows.iter().map(|row| Message {
+            id: row.get("id"),
+            message_id: row.get("message_id"),
+            client_message_id: row.get("client_message_id"),
+            chat_id: row.get("chat_id"),
+            sender_id: row.get("sender_id"),
+            content: row.get("content"),
+            timestamp: row.get("timestamp"),
+            status: row.get("status"),
+            sender_username: row.get("sender_username"),
+            reply_to_message_id: row.get("reply_to_message_id"),
+        }).collect();
+    
+        println!("[Database] Returning {} messages for chat {}", messages.len(), chat_id);
+    
+        Ok(messages)
+    }
+
+    async fn get_messages_before_timestamp(&self, chat_id: &str, before_timestamp: Timestamp, limit: i32) -> Result<Vec<Message>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let rows = sqlx::query("SELECT * FROM message WHERE chat_id = ? AND timestamp < ? ORDER BY timestamp DESC, id DESC LIMIT ?")
+            .bind(chat_id)
+            .bind(before_timestamp)
+            .bind(limit)
+            .fetch_all(&pool)
+            .await?;
+    
+        let messages: Vec<Message> = rows.iter().map(|row| Message {
+            id: row.get("id"),
+            message_id: row.get("message_id"),
+            client_message_id: row.get("client_message_id"),
+            chat_id: row.get("chat_id"),
+            sender_id: row.get("sender_id"),
+            content: row.get("content"),
+            timestamp: row.get("timestamp"),
+            status: row.get("status"),
+            sender_username: row.get("sender_username"),
+            reply_to_message_id: row.get("reply_to_message_id"),
+        }).collect();
+    
+        Ok(messages)
+    }
+
+    // One page of a chat in (timestamp, id) order. Without a cursor, older pages start
+    // at the newest message and newer pages at the oldest.
+    async fn get_message_page(&self, chat_id: &str, cursor: Option<&str>, direction: PageDirection, limit: i64) -> Result<MessagePage, SqlxError> {
+        let pool = self.pool().await?;
+        
+        let (timestamp, id) = match cursor {
+            Some(cursor) => decode_page_cursor(cursor)
+                .ok_or_else(|| SqlxError::Protocol(format!("Invalid page cursor: {}", cursor)))?,
+            None => match direction {
+                PageDirection::Older => (Timestamp::from_millis(i64::MAX), i64::MAX),
+                PageDirection::Newer => (Timestamp::from_millis(i64::MIN), i64::MIN),
+            },
+        };
+        let sql = match direction {
+            PageDirection::Older => "SELECT * FROM message WHERE chat_id = ? AND (timestamp, id) < (?, ?)
+                                     ORDER BY timestamp DESC, id DESC LIMIT ?",
+            PageDirection::Newer => "SELECT * FROM message WHERE chat_id = ? AND (timestamp, id) > (?, ?)
+                                     ORDER BY timestamp ASC, id ASC LIMIT ?",
+        };
+        
+        // One extra row tells us whether there is another page
+        let rows = sqlx::query(sql)
+            .bind(chat_id)
+            .bind(timestamp)
+            .bind(id)
+            .bind(limit + 1)
+            .fetch_all(&pool)
+            .await?;
+        
+        let has_more = rows.len() as i64 > limit;
+        let mut messages: Vec<Message> = rows.iter().take(limit as usize).map(message_from_row).collect();
+        let edge = messages.last().and_then(|m| m.id.map(|id| encode_page_cursor(m.timestamp, id)));
+        if direction == PageDirection::Older {
+            messages.reverse();
+        }
+        
+        Ok(MessagePage {
+            messages,
+            next_cursor: edge.or_else(|| cursor.map(str::to_string)),
+            has_more,
+        })
+    }
+
+    async fn get_last_message(&self, chat_id: &str) -> Result<Option<Message>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let row = sqlx::query("SELECT * FROM message WHERE chat_id = ? ORDER BY timestamp DESC, id DESC LIMIT 1")
+            .bind(chat_id)
+            .fetch_optional(&pool)
+            .await?;
+    
+        if let Some(row) = row {
+            Ok(Some(Message {
+                id: row.get("id"),
+                message_id: row.get("message_id"),
+                client_message_id: row.get("client_message_id"),
+                chat_id: row.get("chat_id"),
+                sender_id: row.get("sender_id"),
+                content: row.get("content"),
+                timestamp: row.get("timestamp"),
+                status: row.get("status"),
+                sender_username: row.get("sender_username"),
+                reply_to_message_id: row.get("reply_to_message_id"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_oldest_server_message_id(&self, chat_id: &str) -> Result<Option<String>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        // Outbox rows have no server id yet, or only their client id as a placeholder
+        sqlx::query_scalar(
+            "SELECT message_id FROM message
+             WHERE chat_id = ? AND message_id IS NOT NULL AND status NOT IN ('pending', 'failed')
+             ORDER BY timestamp ASC, id ASC LIMIT 1"
+        )
+        .bind(chat_id)
+        .fetch_optional(&pool)
+        .await
+    }
+
+    async fn get_message_by_id(&self, message_id: &str) -> Result<Option<Message>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let row = sqlx::query("SELECT * FROM message WHERE message_id = ?")
+            .bind(message_id)
+            .fetch_optional(&pool)
+            .await?;
+    
+        if let Some(row) = row {
+            Ok(Some(Message {
+                id: row.get("id"),
+                message_id: row.get("message_id"),
+                client_message_id: row.get("client_message_id"),
+                chat_id: row.get("chat_id"),
+                sender_id: row.get("sender_id"),
+                content: row.get("content"),
+                timestamp: row.get("timestamp"),
+                status: row.get("status"),
+                sender_username: row.get("sender_username"),
+                reply_to_message_id: row.get("reply_to_message_id"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_message_by_client_id(&self, client_message_id: &str) -> Result<Option<Message>, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let row = sqlx::query("SELECT * FROM message WHERE client_message_id = ?")
+            .bind(client_message_id)
+            .fetch_optional(&pool)
+            .await?;
+    
+        if let Some(row) = row {
+            Ok(Some(Message {
+                id: row.get("id"),
+                message_id: row.get("message_id"),
+                client_message_id: row.get("client_message_id"),
+                chat_id: row.get("chat_id"),
+                sender_id: row.get("sender_id"),
+                content: row.get("content"),
+                timestamp: row.get("timestamp"),
+                status: row.get("status"),
+                sender_username: row.get("sender_username"),
+                reply_to_message_id: row.get("reply_to_message_id"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn update_message_status(&self, client_message_id: &str, status: MessageStatus) -> Result<Option<Message>, SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        let updated = advance_message_status(&mut tx, "client_message_id = ?", client_message_id, status).await?;
+        tx.commit().await?;
+    
+        self.publish_messages(ChangeOp::Update, &updated).await;
+        Ok(updated.into_iter().next())
+    }
+
+    // The *_sent_status calls predate `status`: true acks the send, false marks it failed
+    async fn update_message_sent_status(&self, client_message_id: &str, is_sent: bool) -> Result<(), SqlxError> {
+        let status = if is_sent { MessageStatus::Sent } else { MessageStatus::Failed };
+        self.update_message_status(client_message_id, status).await?;
+        Ok(())
+    }
 
-pub async fn get_messages_before_timestamp(chat_id: &str, before_timestamp: i64, limit: i32) -> Result<Vec<Message>, SqlxError> {
-    let pool = get_pool().await?;
+    // New methods that work with server IDs (message_id field)
+    async fn update_message_sent_status_by_server_id(&self, server_id: &str, is_sent: bool) -> Result<(), SqlxError> {
+        let status = if is_sent { MessageStatus::Sent } else { MessageStatus::Failed };
+        self.advance_status_by_server_ids(&[server_id], status).await
+    }
+
+    async fn mark_message_delivered_by_server_id_new(&self, server_id: &str) -> Result<(), SqlxError> {
+        self.advance_status_by_server_ids(&[server_id], MessageStatus::Delivered).await
+    }
+
+    async fn mark_message_read_by_server_id_new(&self, server_id: &str) -> Result<(), SqlxError> {
+        self.advance_status_by_server_ids(&[server_id], MessageStatus::Read).await
+    }
+
+    async fn mark_messages_read_by_server_ids(&self, message_ids: &[String]) -> Result<(), SqlxError> {
+        let ids: Vec<&str> = message_ids.iter().map(String::as_str).collect();
+        self.advance_status_by_server_ids(&ids, MessageStatus::Read).await
+    }
+
+    /// Record that `user_id` received or read a message. A receipt only moves forward,
+    /// so a late 'delivered' never downgrades 'read'. The message's own status follows
+    /// the furthest receipt ("anyone has").
+    async fn record_message_receipt(&self, message_id: &str, user_id: &str, status: ReceiptStatus, at: Timestamp) -> Result<bool, SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+    
+        let changed = sqlx::query(
+            "INSERT INTO message_receipt (message_id, user_id, status, at) VALUES (?, ?, ?, ?)
+             ON CONFLICT(message_id, user_id) DO UPDATE SET status = excluded.status, at = excluded.at
+             WHERE message_receipt.status = 'delivered' AND excluded.status = 'read'"
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .bind(status.as_str())
+        .bind(at)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected() > 0;
+    
+        if !changed {
+            tx.commit().await?;
+            return Ok(false);
+        }
     
-    let rows = sqlx::query("SELECT * FROM message WHERE chat_id = ? AND timestamp < ? ORDER BY timestamp DESC LIMIT ?")
-        .bind(chat_id)
-        .bind(before_timestamp)
-        .bind(limit)
+        let to = match status {
+            ReceiptStatus::Delivered => MessageStatus::Delivered,
+            ReceiptStatus::Read => MessageStatus::Read,
+        };
+        let updated = advance_message_status(&mut tx, "message_id = ?", message_id, to).await?;
+        tx.commit().await?;
+    
+        let receipt = MessageReceipt {
+            message_id: message_id.to_string(),
+            user_id: user_id.to_string(),
+            username: None,
+            status,
+            at,
+        };
+        self.changes.publish(ChangeRecord::row(ChangeTable::MessageReceipt, ChangeOp::Upsert, format!("{}:{}", message_id, user_id), &receipt));
+        self.publish_messages(ChangeOp::Update, &updated).await;
+        Ok(true)
+    }
+
+    async fn get_message_read_state(&self, message_id: &str) -> Result<Option<MessageReadState>, SqlxError> {
+        let Some(message) = self.get_message_by_id(message_id).await? else {
+            return Ok(None);
+        };
+        let pool = self.pool().await?;
+    
+        // The sender's own receipts (other devices) do not count
+        let rows = sqlx::query(
+            "SELECT r.user_id, p.username, r.status, r.at
+             FROM message_receipt r
+             LEFT JOIN participant p ON p.chat_id = ? AND p.user_id = r.user_id
+             WHERE r.message_id = ? AND r.user_id != ?
+             ORDER BY r.at ASC, r.user_id ASC"
+        )
+        .bind(&message.chat_id)
+        .bind(message_id)
+        .bind(&message.sender_id)
         .fetch_all(&pool)
         .await?;
     
-    let messages: Vec<Message> = rows.iter().map(|row| Message {
-        id: row.get("id"),
-        message_id: row.get("message_id"),
-        client_message_id: row.get("client_message_id"),
-        chat_id: row.get("chat_id"),
-        sender_id: row.get("sender_id"),
-        content: row.get("content"),
-        timestamp: row.get("timestamp"),
-        is_read: row.get("is_read"),
-        is_sent: row.get("is_sent"),
-        is_delivered: row.get("is_delivered"),
-        is_failed: row.get("is_failed"),
-        sender_username: row.get("sender_username"),
-        reply_to_message_id: row.get("reply_to_message_id"),
-    }).collect();
+        let receipts: Vec<MessageReceipt> = rows.iter().filter_map(|row| {
+            Some(MessageReceipt {
+                message_id: message_id.to_string(),
+                user_id: row.get("user_id"),
+                username: row.get("username"),
+                status: ReceiptStatus::parse(row.get("status"))?,
+                at: row.get("at"),
+            })
+        }).collect();
+    
+        let participants: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM participant WHERE chat_id = ? AND user_id != ?")
+            .bind(&message.chat_id)
+            .bind(&message.sender_id)
+            .fetch_one(&pool)
+            .await?;
     
-    Ok(messages)
-}
+        let delivered_count = receipts.len() as i64;
+        let readers: Vec<MessageReceipt> = receipts.into_iter().filter(|r| r.status == ReceiptStatus::Read).collect();
+        Ok(Some(MessageReadState {
+            message_id: message_id.to_string(),
+            chat_id: message.chat_id,
+            // The participant list can lag behind; never report more readers than recipients
+            recipient_count: participants.max(delivered_count),
+            delivered_count,
+            read_count: readers.len() as i64,
+            readers,
+        }))
+    }
 
-pub async fn get_last_message(chat_id: &str) -> Result<Option<Message>, SqlxError> {
-    let pool = get_pool().await?;
+    async fn count_unread_messages(&self, chat_id: &str) -> Result<i32, SqlxError> {
+        let pool = self.pool().await?;
     
-    let row = sqlx::query("SELECT * FROM message WHERE chat_id = ? ORDER BY timestamp DESC LIMIT 1")
-        .bind(chat_id)
-        .fetch_optional(&pool)
-        .await?;
+        let count: i32 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM message WHERE chat_id = ? AND status IN ('sent', 'delivered') AND {}", NOT_OWN_MESSAGE))
+            .bind(chat_id)
+            .fetch_one(&pool)
+            .await?;
     
-    if let Some(row) = row {
-        Ok(Some(Message {
-            id: row.get("id"),
-            message_id: row.get("message_id"),
-            client_message_id: row.get("client_message_id"),
-            chat_id: row.get("chat_id"),
-            sender_id: row.get("sender_id"),
-            content: row.get("content"),
-            timestamp: row.get("timestamp"),
-            is_read: row.get("is_read"),
-            is_sent: row.get("is_sent"),
-            is_delivered: row.get("is_delivered"),
-            is_failed: row.get("is_failed"),
-            sender_username: row.get("sender_username"),
-            reply_to_message_id: row.get("reply_to_message_id"),
-        }))
-    } else {
-        Ok(None)
+        Ok(count)
     }
-}
 
-pub async fn get_message_by_id(message_id: &str) -> Result<Option<Message>, SqlxError> {
-    let pool = get_pool().await?;
+    async fn get_unread_messages(&self, chat_id: &str) -> Result<Vec<Message>, SqlxError> {
+        let pool = self.pool().await?;
     
-    let row = sqlx::query("SELECT * FROM message WHERE message_id = ?")
-        .bind(message_id)
-        .fetch_optional(&pool)
-        .await?;
+        let rows = sqlx::query(&format!("SELECT * FROM message WHERE chat_id = ? AND status IN ('sent', 'delivered') AND {} ORDER BY timestamp ASC, id ASC", NOT_OWN_MESSAGE))
+            .bind(chat_id)
+            .fetch_all(&pool)
+            .await?;
     
-    if let Some(row) = row {
-        Ok(Some(Message {
+        let messages: Vec<Message> = rows.iter().map(|row| Message {
             id: row.get("id"),
             message_id: row.get("message_id"),
             client_message_id: row.get("client_message_id"),
@@ -905,731 +2148,1715 @@ pub async fn get_message_by_id(message_id: &str) -> Result<Option<Message>, Sqlx
             sender_id: row.get("sender_id"),
             content: row.get("content"),
             timestamp: row.get("timestamp"),
-            is_read: row.get("is_read"),
-            is_sent: row.get("is_sent"),
-            is_delivered: row.get("is_delivered"),
-            is_failed: row.get("is_failed"),
+            status: row.get("status"),
             sender_username: row.get("sender_username"),
             reply_to_message_id: row.get("reply_to_message_id"),
-        }))
-    } else {
-        Ok(None)
+        }).collect();
+    
+        Ok(messages)
     }
-}
 
-pub async fn get_message_by_client_id(client_message_id: &str) -> Result<Option<Message>, SqlxError> {
-    let pool = get_pool().await?;
+    // Returns the messages that became read, for read receipts
+    async fn mark_messages_as_read(&self, chat_id: &str) -> Result<Vec<Message>, SqlxError> {
+        let pool = self.pool().await?;
     
-    let row = sqlx::query("SELECT * FROM message WHERE client_message_id = ?")
-        .bind(client_message_id)
-        .fetch_optional(&pool)
-        .await?;
+        // Pending and failed rows are our own outbox, not unread messages. Our sent messages
+        // are skipped too: their status is the recipient's, so Read there means "they read it".
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        let filter = format!("chat_id = ? AND status IN ('sent', 'delivered') AND {}", NOT_OWN_MESSAGE);
+        let updated = advance_message_status(&mut tx, &filter, chat_id, MessageStatus::Read).await?;
+        tx.commit().await?;
     
-    if let Some(row) = row {
-        Ok(Some(Message {
-            id: row.get("id"),
-            message_id: row.get("message_id"),
-            client_message_id: row.get("client_message_id"),
-            chat_id: row.get("chat_id"),
-            sender_id: row.get("sender_id"),
-            content: row.get("content"),
-            timestamp: row.get("timestamp"),
-            is_read: row.get("is_read"),
-            is_sent: row.get("is_sent"),
-            is_delivered: row.get("is_delivered"),
-            is_failed: row.get("is_failed"),
-            sender_username: row.get("sender_username"),
-            reply_to_message_id: row.get("reply_to_message_id"),
-        }))
-    } else {
-        Ok(None)
+        self.publish_messages(ChangeOp::Update, &updated).await;
+        Ok(updated)
     }
-}
 
-pub async fn update_message_sent_status(client_message_id: &str, is_sent: bool) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn update_message_id_by_client(&self, client_message_id: &str, server_id: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("UPDATE message SET is_sent = ? WHERE client_message_id = ?")
-        .bind(is_sent)
-        .bind(client_message_id)
-        .execute(&pool)
-        .await?;
+        let rows = sqlx::query("UPDATE message SET message_id = ? WHERE client_message_id = ? RETURNING *")
+            .bind(server_id)
+            .bind(client_message_id)
+            .fetch_all(&pool)
+            .await?;
     
-    Ok(())
-}
+        self.publish_messages(ChangeOp::Update, &rows.iter().map(message_from_row).collect::<Vec<_>>()).await;
+        Ok(())
+    }
 
+    async fn delete_message_by_id(&self, message_id: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+    
+        let removed: Vec<(String, String)> = sqlx::query_as("DELETE FROM message WHERE message_id = ? RETURNING client_message_id, chat_id")
+            .bind(message_id)
+            .fetch_all(&pool)
+            .await?;
+    
+        let mut chat_ids: Vec<String> = Vec::new();
+        for (_, chat_id) in &removed {
+            if !chat_ids.contains(chat_id) {
+                chat_ids.push(chat_id.clone());
+            }
+        }
+        self.publish_deleted(ChangeTable::Message, removed.into_iter().map(|(client_message_id, _)| client_message_id));
+        self.publish_chats(&chat_ids).await;
+        Ok(())
+    }
 
+    async fn delete_message_by_client_id(&self, client_message_id: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+    
+        let removed: Vec<(String, String)> = sqlx::query_as("DELETE FROM message WHERE client_message_id = ? RETURNING client_message_id, chat_id")
+            .bind(client_message_id)
+            .fetch_all(&pool)
+            .await?;
+    
+        let mut chat_ids: Vec<String> = Vec::new();
+        for (_, chat_id) in &removed {
+            if !chat_ids.contains(chat_id) {
+                chat_ids.push(chat_id.clone());
+            }
+        }
+        self.publish_deleted(ChangeTable::Message, removed.into_iter().map(|(client_message_id, _)| client_message_id));
+        self.publish_chats(&chat_ids).await;
+        Ok(())
+    }
 
-// New methods that work with server IDs (message_id field)
-pub async fn update_message_sent_status_by_server_id(server_id: &str, is_sent: bool) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn clear_message_data(&self) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("UPDATE message SET is_sent = ? WHERE message_id = ?")
-        .bind(is_sent)
-        .bind(server_id)
-        .execute(&pool)
+        sqlx::query("DELETE FROM message").execute(&pool).await?;
+        self.changes.publish(ChangeRecord::reset(ChangeTable::Message));
+        // Every chat summary was emptied by the triggers
+        self.changes.publish(ChangeRecord::reset(ChangeTable::Chat));
+        Ok(())
+    }
+
+    async fn search_messages(
+        &self,
+        query: &str,
+        filter: &MessageSearchFilter,
+        limit: i64,
+        cursor: Option<&str>,
+    ) -> Result<MessageSearchPage, SqlxError> {
+        let pool = self.pool().await?;
+    
+        let Some(fts_query) = fts_query(query) else {
+            return Ok(MessageSearchPage { hits: Vec::new(), next_cursor: None });
+        };
+        let offset = match cursor {
+            Some(cursor) => decode_search_cursor(cursor)
+                .ok_or_else(|| SqlxError::Protocol(format!("Invalid search cursor: {}", cursor)))?,
+            None => 0,
+        };
+    
+        println!("[Database] Searching messages: query={}, offset={}, limit={}", fts_query, offset, limit);
+    
+        // One extra row tells us whether there is another page
+        let rows = sqlx::query(
+            "SELECT m.id, m.message_id, m.client_message_id, m.chat_id, m.sender_id, m.content,
+                    m.timestamp, m.status,
+                    m.sender_username, m.reply_to_message_id,
+                    COALESCE(c.group_name, c.name) AS chat_name,
+                    COALESCE(c.is_group, 0) AS chat_is_group,
+                    snippet(message_fts, 0, ?, ?, '…', 16) AS snippet,
+                    bm25(message_fts) AS rank,
+                    (SELECT p.content FROM message p
+                     WHERE p.chat_id = m.chat_id
+                       AND (p.timestamp < m.timestamp OR (p.timestamp = m.timestamp AND p.id < m.id))
+                     ORDER BY p.timestamp DESC, p.id DESC LIMIT 1) AS previous_content,
+                    (SELECT n.content FROM message n
+                     WHERE n.chat_id = m.chat_id
+                       AND (n.timestamp > m.timestamp OR (n.timestamp = m.timestamp AND n.id > m.id))
+                     ORDER BY n.timestamp ASC, n.id ASC LIMIT 1) AS next_content
+             FROM message_fts
+             JOIN message m ON m.id = message_fts.rowid
+             LEFT JOIN chat c ON c.chat_id = m.chat_id
+             WHERE message_fts MATCH ?
+               AND (? IS NULL OR m.chat_id = ?)
+               AND (? IS NULL OR m.sender_id = ?)
+               AND (? IS NULL OR m.timestamp >= ?)
+               AND (? IS NULL OR m.timestamp <= ?)
+             ORDER BY rank, m.timestamp DESC, m.id DESC
+             LIMIT ? OFFSET ?"
+        )
+        .bind(SEARCH_HIGHLIGHT_START.to_string())
+        .bind(SEARCH_HIGHLIGHT_END.to_string())
+        .bind(&fts_query)
+        .bind(&filter.chat_id)
+        .bind(&filter.chat_id)
+        .bind(&filter.sender_id)
+        .bind(&filter.sender_id)
+        .bind(filter.from)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.to)
+        .bind(limit + 1)
+        .bind(offset)
+        .fetch_all(&pool)
         .await?;
     
-    Ok(())
-}
+        let has_more = rows.len() as i64 > limit;
+        let hits: Vec<MessageSearchHit> = rows.iter()
+            .take(limit as usize)
+            .map(|row| MessageSearchHit {
+                message: message_from_row(row),
+                chat_name: row.get("chat_name"),
+                is_group: row.get("chat_is_group"),
+                snippet: row.get("snippet"),
+                rank: row.get("rank"),
+                previous_content: row.get("previous_content"),
+                next_content: row.get("next_content"),
+            })
+            .collect();
+    
+        println!("[Database] Message search returned {} hits (more: {})", hits.len(), has_more);
+    
+        Ok(MessageSearchPage {
+            next_cursor: has_more.then(|| encode_search_cursor(offset + hits.len() as i64)),
+            hits,
+        })
+    }
 
-pub async fn mark_message_delivered_by_server_id_new(server_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    // Clear all messages for a specific chat
+    async fn clear_messages_for_chat(&self, chat_id: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("UPDATE message SET is_delivered = ? WHERE message_id = ?")
-        .bind(true)
-        .bind(server_id)
-        .execute(&pool)
+        let removed: Vec<String> = sqlx::query_scalar("DELETE FROM message WHERE chat_id = ? RETURNING client_message_id")
+            .bind(chat_id)
+            .fetch_all(&pool)
+            .await?;
+    
+        let cleared = !removed.is_empty();
+        self.publish_deleted(ChangeTable::Message, removed);
+        if cleared {
+            self.publish_chat(ChangeOp::Update, chat_id).await;
+        }
+        Ok(())
+    }
+
+    // Delete messages older than their chat's TTL and refresh the affected chat summaries.
+    // The search index is updated by the message_fts triggers.
+    async fn purge_expired_messages(&self, now: Timestamp) -> Result<Vec<ExpiredMessages>, SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+    
+        let rows = sqlx::query(
+            "SELECT m.id, m.chat_id, m.message_id, m.client_message_id
+             FROM message m
+             JOIN chat c ON c.chat_id = m.chat_id
+             WHERE c.message_ttl_secs IS NOT NULL AND c.message_ttl_secs > 0
+               AND m.timestamp + c.message_ttl_secs * 1000 <= ?"
+        )
+        .bind(now)
+        .fetch_all(&mut *tx)
         .await?;
     
-    Ok(())
+        if rows.is_empty() {
+            tx.commit().await?;
+            return Ok(Vec::new());
+        }
+    
+        let mut expired: Vec<ExpiredMessages> = Vec::new();
+        for row in &rows {
+            let id: i64 = row.get("id");
+            let chat_id: String = row.get("chat_id");
+            let message_id: Option<String> = row.get("message_id");
+            let client_message_id: String = row.get("client_message_id");
+        
+            sqlx::query("DELETE FROM message WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        
+            let index = match expired.iter().position(|e| e.chat_id == chat_id) {
+                Some(index) => index,
+                None => {
+                    expired.push(ExpiredMessages {
+                        chat_id: chat_id.clone(),
+                        message_ids: Vec::new(),
+                        client_message_ids: Vec::new(),
+                        last_message_content: None,
+                        last_message_timestamp: None,
+                    });
+                    expired.len() - 1
+                }
+            };
+            if let Some(message_id) = message_id {
+                expired[index].message_ids.push(message_id);
+            }
+            expired[index].client_message_ids.push(client_message_id);
+        }
+    
+        // The triggers recomputed the summaries; report what they are now
+        for chat in &mut expired {
+            let last = sqlx::query("SELECT last_message_content, last_message_timestamp FROM chat WHERE chat_id = ?")
+                .bind(&chat.chat_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        
+            chat.last_message_content = last.as_ref().and_then(|row| row.get("last_message_content"));
+            chat.last_message_timestamp = last.as_ref().and_then(|row| row.get("last_message_timestamp"));
+        }
+    
+        tx.commit().await?;
+    
+        println!("[Database] Purged {} expired messages from {} chats", rows.len(), expired.len());
+    
+        for chat in &expired {
+            self.publish_deleted(ChangeTable::Message, chat.client_message_ids.iter().cloned());
+            self.publish_chat(ChangeOp::Update, &chat.chat_id).await;
+        }
+        Ok(expired)
+    }
+
+    async fn get_unresolved_sender_ids(&self) -> Result<Vec<String>, SqlxError> {
+        let pool = self.pool().await?;
+        let query = format!(
+            "SELECT DISTINCT sender_id FROM message
+             WHERE COALESCE(sender_username, '') = ''
+               AND sender_id NOT IN (SELECT user_id FROM ({}))
+             ORDER BY sender_id",
+            KNOWN_USERNAMES
+        );
+        sqlx::query_scalar(&query).fetch_all(&pool).await
+    }
+
+    // Fill in every missing sender name we can resolve locally, in one statement
+    async fn fill_sender_usernames(&self) -> Result<Vec<Message>, SqlxError> {
+        let pool = self.pool().await?;
+        let query = format!(
+            "WITH known AS ({})
+             UPDATE message SET sender_username = (
+                 SELECT known.username FROM known WHERE known.user_id = message.sender_id
+                 ORDER BY known.priority DESC LIMIT 1
+             )
+             WHERE COALESCE(sender_username, '') = ''
+               AND sender_id IN (SELECT user_id FROM known)
+             RETURNING *",
+            KNOWN_USERNAMES
+        );
+        let rows = sqlx::query(&query).fetch_all(&pool).await?;
+        let updated: Vec<Message> = rows.iter().map(message_from_row).collect();
+    
+        if !updated.is_empty() {
+            println!("[Database] Filled in sender usernames for {} messages", updated.len());
+        }
+        self.publish_messages(ChangeOp::Update, &updated).await;
+        Ok(updated)
+    }
 }
 
-pub async fn mark_message_read_by_server_id_new(server_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+impl FriendRepo for SqliteRepo {
+    async fn insert_or_update_friend(&self, friend: &Friend) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("UPDATE message SET is_read = ? WHERE message_id = ?")
-        .bind(true)
-        .bind(server_id)
+        sqlx::query(
+            "INSERT OR REPLACE INTO friend (
+                user_id, username, email, name, picture, created_at,
+                updated_at, status, is_favorite
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&friend.user_id)
+        .bind(&friend.username)
+        .bind(&friend.email)
+        .bind(&friend.name)
+        .bind(&friend.picture)
+        .bind(friend.created_at)
+        .bind(friend.updated_at)
+        .bind(&friend.status)
+        .bind(friend.is_favorite)
         .execute(&pool)
         .await?;
     
-    Ok(())
-}
+        self.publish_friend(ChangeOp::Upsert, &friend.user_id).await;
+        Ok(())
+    }
 
-pub async fn mark_messages_read_by_server_ids(message_ids: &[String]) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn get_all_friends(&self) -> Result<Vec<Friend>, SqlxError> {
+        let pool = self.pool().await?;
     
-    for message_id in message_ids {
-        sqlx::query("UPDATE message SET is_read = ? WHERE message_id = ?")
-            .bind(true)
-            .bind(message_id)
-            .execute(&pool)
+        let rows = sqlx::query("SELECT * FROM friend ORDER BY name ASC")
+            .fetch_all(&pool)
             .await?;
+    
+        let friends: Vec<Friend> = rows.iter().map(|row| Friend {
+            user_id: row.get("user_id"),
+            username: row.get("username"),
+            email: row.get("email"),
+            name: row.get("name"),
+            picture: row.get("picture"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+            status: row.get("status"),
+            is_favorite: row.get("is_favorite"),
+        }).collect();
+    
+        Ok(friends)
     }
+
+    async fn get_friend_by_id(&self, user_id: &str) -> Result<Option<Friend>, SqlxError> {
+        let pool = self.pool().await?;
     
-    Ok(())
-}
+        let row = sqlx::query("SELECT * FROM friend WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&pool)
+            .await?;
+    
+        if let Some(row) = row {
+            let friend = Friend {
+                user_id: row.get("user_id"),
+                username: row.get("username"),
+                email: row.get("email"),
+                name: row.get("name"),
+                picture: row.get("picture"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+                status: row.get("status"),
+                is_favorite: row.get("is_favorite"),
+            };
+            Ok(Some(friend))
+        } else {
+            Ok(None)
+        }
+    }
 
-pub async fn count_unread_messages(chat_id: &str) -> Result<i32, SqlxError> {
-    let pool = get_pool().await?;
+    async fn delete_friend(&self, user_id: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    let count: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM message WHERE chat_id = ? AND is_read = ?")
-        .bind(chat_id)
-        .bind(false)
-        .fetch_one(&pool)
-        .await?;
+        sqlx::query("DELETE FROM friend WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&pool)
+            .await?;
     
-    Ok(count)
-}
+        self.changes.publish(ChangeRecord::delete(ChangeTable::Friend, user_id));
+        Ok(())
+    }
 
-pub async fn get_unread_messages(chat_id: &str) -> Result<Vec<Message>, SqlxError> {
-    let pool = get_pool().await?;
+    async fn clear_friend_data(&self) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    let rows = sqlx::query("SELECT * FROM message WHERE chat_id = ? AND is_read = ? ORDER BY timestamp ASC")
-        .bind(chat_id)
-        .bind(false)
-        .fetch_all(&pool)
-        .await?;
+        sqlx::query("DELETE FROM friend").execute(&pool).await?;
+        self.changes.publish(ChangeRecord::reset(ChangeTable::Friend));
+        Ok(())
+    }
+
+    // Additional friend operations needed by frontend
+    async fn update_friend_status(&self, user_id: &str, status: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    let messages: Vec<Message> = rows.iter().map(|row| Message {
-        id: row.get("id"),
-        message_id: row.get("message_id"),
-        client_message_id: row.get("client_message_id"),
-        chat_id: row.get("chat_id"),
-        sender_id: row.get("sender_id"),
-        content: row.get("content"),
-        timestamp: row.get("timestamp"),
-        is_read: row.get("is_read"),
-        is_sent: row.get("is_sent"),
-        is_delivered: row.get("is_delivered"),
-        is_failed: row.get("is_failed"),
-        sender_username: row.get("sender_username"),
-        reply_to_message_id: row.get("reply_to_message_id"),
-    }).collect();
+        sqlx::query("UPDATE friend SET status = ? WHERE user_id = ?")
+            .bind(status)
+            .bind(user_id)
+            .execute(&pool)
+            .await?;
     
-    Ok(messages)
-}
+        self.publish_friend(ChangeOp::Update, user_id).await;
+        Ok(())
+    }
 
-pub async fn mark_messages_as_read(chat_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    /// Make the cached friends match the server's list in one transaction
+    async fn apply_friend_delta(&self, server_friends: &[Friend]) -> Result<DeltaResult, SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        let mut result = DeltaResult::default();
+    
+        let cached: std::collections::HashSet<String> = sqlx::query_scalar("SELECT user_id FROM friend")
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .collect();
+    
+        let mut kept = std::collections::HashSet::new();
+        for friend in server_friends {
+            kept.insert(friend.user_id.clone());
+        
+            let changed = sqlx::query(
+                "INSERT INTO friend (
+                    user_id, username, email, name, picture, created_at,
+                    updated_at, status, is_favorite
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(user_id) DO UPDATE SET
+                    username = excluded.username,
+                    email = excluded.email,
+                    name = excluded.name,
+                    picture = excluded.picture,
+                    updated_at = excluded.updated_at,
+                    status = excluded.status,
+                    is_favorite = excluded.is_favorite
+                WHERE excluded.username IS NOT friend.username
+                   OR excluded.email IS NOT friend.email
+                   OR excluded.name IS NOT friend.name
+                   OR excluded.picture IS NOT friend.picture
+                   OR excluded.status IS NOT friend.status
+                   OR excluded.is_favorite IS NOT friend.is_favorite"
+            )
+            .bind(&friend.user_id)
+            .bind(&friend.username)
+            .bind(&friend.email)
+            .bind(&friend.name)
+            .bind(&friend.picture)
+            .bind(friend.created_at)
+            .bind(friend.updated_at)
+            .bind(&friend.status)
+            .bind(friend.is_favorite)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() > 0;
+        
+            if !cached.contains(&friend.user_id) {
+                result.added.push(friend.user_id.clone());
+            } else if changed {
+                result.updated.push(friend.user_id.clone());
+            }
+        }
     
-    sqlx::query("UPDATE message SET is_read = ? WHERE chat_id = ?")
-        .bind(true)
-        .bind(chat_id)
-        .execute(&pool)
-        .await?;
+        for user_id in cached.difference(&kept) {
+            sqlx::query("DELETE FROM friend WHERE user_id = ?")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+            result.removed.push(user_id.clone());
+        }
+    
+        tx.commit().await?;
     
-    Ok(())
+        println!("[Database] Friend delta applied: {} added, {} updated, {} removed",
+                 result.added.len(), result.updated.len(), result.removed.len());
+    
+        self.publish_delta(ChangeTable::Friend, &result).await;
+        Ok(result)
+    }
 }
 
-pub async fn update_message_id_by_client(client_message_id: &str, server_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+impl ParticipantRepo for SqliteRepo {
+    async fn insert_or_update_participant(&self, participant: &Participant) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("UPDATE message SET message_id = ? WHERE client_message_id = ?")
-        .bind(server_id)
-        .bind(client_message_id)
+        if ensure_chat_row(&pool, &participant.chat_id).await? {
+            self.publish_chat(ChangeOp::Insert, &participant.chat_id).await;
+        }
+    
+        sqlx::query(
+            "INSERT OR REPLACE INTO participant (
+                participant_id, user_id, username, joined_at, role, chat_id
+            ) VALUES (?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&participant.participant_id)
+        .bind(&participant.user_id)
+        .bind(&participant.username)
+        .bind(participant.joined_at)
+        .bind(&participant.role)
+        .bind(&participant.chat_id)
         .execute(&pool)
         .await?;
     
-    Ok(())
-}
+        self.publish_participant(ChangeOp::Upsert, &participant.participant_id).await;
+        Ok(())
+    }
 
-pub async fn delete_message_by_id(message_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn get_participants_for_chat(&self, chat_id: &str) -> Result<Vec<Participant>, SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("DELETE FROM message WHERE message_id = ?")
-        .bind(message_id)
-        .execute(&pool)
-        .await?;
+        let rows = sqlx::query("SELECT * FROM participant WHERE chat_id = ? ORDER BY joined_at ASC")
+            .bind(chat_id)
+            .fetch_all(&pool)
+            .await?;
     
-    Ok(())
-}
+        let participants: Vec<Participant> = rows.iter().map(|row| Participant {
+            participant_id: row.get("participant_id"),
+            user_id: row.get("user_id"),
+            username: row.get("username"),
+            joined_at: row.get("joined_at"),
+            role: row.get("role"),
+            chat_id: row.get("chat_id"),
+        }).collect();
+    
+        Ok(participants)
+    }
 
-pub async fn delete_message_by_client_id(client_message_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn clear_participant_data(&self) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("DELETE FROM message WHERE client_message_id = ?")
-        .bind(client_message_id)
-        .execute(&pool)
-        .await?;
+        sqlx::query("DELETE FROM participant").execute(&pool).await?;
+        self.changes.publish(ChangeRecord::reset(ChangeTable::Participant));
+        Ok(())
+    }
+
+    // Additional participant operations needed by frontend
+    async fn get_participant_by_id(&self, participant_id: &str) -> Result<Option<Participant>, SqlxError> {
+        let pool = self.pool().await?;
     
-    Ok(())
-}
+        let row = sqlx::query("SELECT * FROM participant WHERE participant_id = ?")
+            .bind(participant_id)
+            .fetch_optional(&pool)
+            .await?;
+    
+        if let Some(row) = row {
+            Ok(Some(Participant {
+                participant_id: row.get("participant_id"),
+                user_id: row.get("user_id"),
+                username: row.get("username"),
+                joined_at: row.get("joined_at"),
+                role: row.get("role"),
+                chat_id: row.get("chat_id"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
 
-pub async fn clear_message_data() -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn get_participant_by_user_id_and_chat_id(&self, user_id: &str, chat_id: &str) -> Result<Option<Participant>, SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("DELETE FROM message").execute(&pool).await?;
-    Ok(())
-}
+        let row = sqlx::query("SELECT * FROM participant WHERE user_id = ? AND chat_id = ?")
+            .bind(user_id)
+            .bind(chat_id)
+            .fetch_optional(&pool)
+            .await?;
+    
+        if let Some(row) = row {
+            Ok(Some(Participant {
+                participant_id: row.get("participant_id"),
+                user_id: row.get("user_id"),
+                username: row.get("username"),
+                joined_at: row.get("joined_at"),
+                role: row.get("role"),
+                chat_id: row.get("chat_id"),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
 
-// Friend operations
-pub async fn insert_or_update_friend(friend: &Friend) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    async fn delete_participant(&self, participant_id: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query(
-        "INSERT OR REPLACE INTO friend (
-            user_id, username, email, name, picture, created_at,
-            updated_at, status, is_favorite
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&friend.user_id)
-    .bind(&friend.username)
-    .bind(&friend.email)
-    .bind(&friend.name)
-    .bind(&friend.picture)
-    .bind(friend.created_at)
-    .bind(friend.updated_at)
-    .bind(&friend.status)
-    .bind(friend.is_favorite)
-    .execute(&pool)
-    .await?;
+        sqlx::query("DELETE FROM participant WHERE participant_id = ?")
+            .bind(participant_id)
+            .execute(&pool)
+            .await?;
     
-    Ok(())
-}
+        self.changes.publish(ChangeRecord::delete(ChangeTable::Participant, participant_id));
+        Ok(())
+    }
 
-pub async fn get_all_friends() -> Result<Vec<Friend>, SqlxError> {
-    let pool = get_pool().await?;
+    async fn update_participant_role(&self, participant_id: &str, role: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    let rows = sqlx::query("SELECT * FROM friend ORDER BY name ASC")
-        .fetch_all(&pool)
-        .await?;
+        sqlx::query("UPDATE participant SET role = ? WHERE participant_id = ?")
+            .bind(role)
+            .bind(participant_id)
+            .execute(&pool)
+            .await?;
     
-    let friends: Vec<Friend> = rows.iter().map(|row| Friend {
-        user_id: row.get("user_id"),
-        username: row.get("username"),
-        email: row.get("email"),
-        name: row.get("name"),
-        picture: row.get("picture"),
-        created_at: row.get("created_at"),
-        updated_at: row.get("updated_at"),
-        status: row.get("status"),
-        is_favorite: row.get("is_favorite"),
-    }).collect();
+        self.publish_participant(ChangeOp::Update, participant_id).await;
+        Ok(())
+    }
+
+    // Remove all participants for a specific chat
+    async fn remove_all_participants_for_chat(&self, chat_id: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    Ok(friends)
-}
+        let removed: Vec<String> = sqlx::query_scalar("DELETE FROM participant WHERE chat_id = ? RETURNING participant_id")
+            .bind(chat_id)
+            .fetch_all(&pool)
+            .await?;
+    
+        self.publish_deleted(ChangeTable::Participant, removed);
+        Ok(())
+    } 
 
-pub async fn get_friend_by_id(user_id: &str) -> Result<Option<Friend>, SqlxError> {
-    let pool = get_pool().await?;
+    /// Make the cached participants of one chat match the server's list in one transaction
+    async fn apply_participant_delta(&self, chat_id: &str, server_participants: &[Participant]) -> Result<DeltaResult, SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        let mut result = DeltaResult::default();
     
-    let row = sqlx::query("SELECT * FROM friend WHERE user_id = ?")
-        .bind(user_id)
-        .fetch_optional(&pool)
-        .await?;
+        let chat_created = ensure_chat_row(&mut *tx, chat_id).await?;
     
-    if let Some(row) = row {
-        let friend = Friend {
-            user_id: row.get("user_id"),
-            username: row.get("username"),
-            email: row.get("email"),
-            name: row.get("name"),
-            picture: row.get("picture"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-            status: row.get("status"),
-            is_favorite: row.get("is_favorite"),
-        };
-        Ok(Some(friend))
-    } else {
-        Ok(None)
+        let cached: std::collections::HashSet<String> = sqlx::query_scalar("SELECT participant_id FROM participant WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .collect();
+    
+        let mut kept = std::collections::HashSet::new();
+        for participant in server_participants {
+            kept.insert(participant.participant_id.clone());
+        
+            // joined_at is kept from the first time we saw the participant
+            let changed = sqlx::query(
+                "INSERT INTO participant (
+                    participant_id, user_id, username, joined_at, role, chat_id
+                ) VALUES (?, ?, ?, ?, ?, ?)
+                ON CONFLICT(participant_id) DO UPDATE SET
+                    user_id = excluded.user_id,
+                    username = excluded.username,
+                    role = excluded.role,
+                    chat_id = excluded.chat_id
+                WHERE excluded.user_id IS NOT participant.user_id
+                   OR excluded.username IS NOT participant.username
+                   OR excluded.role IS NOT participant.role
+                   OR excluded.chat_id IS NOT participant.chat_id"
+            )
+            .bind(&participant.participant_id)
+            .bind(&participant.user_id)
+            .bind(&participant.username)
+            .bind(participant.joined_at)
+            .bind(&participant.role)
+            .bind(chat_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() > 0;
+        
+            if !cached.contains(&participant.participant_id) {
+                result.added.push(participant.participant_id.clone());
+            } else if changed {
+                result.updated.push(participant.participant_id.clone());
+            }
+        }
+    
+        for participant_id in cached.difference(&kept) {
+            sqlx::query("DELETE FROM participant WHERE participant_id = ?")
+                .bind(participant_id)
+                .execute(&mut *tx)
+                .await?;
+            result.removed.push(participant_id.clone());
+        }
+    
+        tx.commit().await?;
+    
+        println!("[Database] Participant delta for chat {} applied: {} added, {} updated, {} removed",
+                 chat_id, result.added.len(), result.updated.len(), result.removed.len());
+    
+        if chat_created {
+            self.publish_chat(ChangeOp::Insert, chat_id).await;
+        }
+        self.publish_delta(ChangeTable::Participant, &result).await;
+        Ok(result)
     }
 }
 
-pub async fn delete_friend(user_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+impl SqliteRepo {
+    pub async fn health_check(&self) -> Result<(), SqlxError> {
+        // Add timeout to prevent hanging
+        let timeout_duration = std::time::Duration::from_secs(5);
     
-    sqlx::query("DELETE FROM friend WHERE user_id = ?")
-        .bind(user_id)
-        .execute(&pool)
-        .await?;
+        let pool_future = self.pool();
+        let timeout_future = tokio::time::sleep(timeout_duration);
     
-    Ok(())
-}
-
-pub async fn clear_friend_data() -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+        let pool = tokio::select! {
+            pool_result = pool_future => pool_result?,
+            _ = timeout_future => {
+                println!("[Database] Health check timeout after {} seconds", timeout_duration.as_secs());
+                return Err(SqlxError::Configuration("Database connection timeout".into()));
+            }
+        };
     
-    sqlx::query("DELETE FROM friend").execute(&pool).await?;
-    Ok(())
-}
-
-// Participant operations
-pub async fn insert_or_update_participant(participant: &Participant) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+        // Simple query to test connection with timeout
+        let query_future = sqlx::query_scalar("SELECT 1").fetch_one(&pool);
+        let query_timeout = tokio::time::sleep(timeout_duration);
     
-    sqlx::query(
-        "INSERT OR REPLACE INTO participant (
-            participant_id, user_id, username, joined_at, role, chat_id
-        ) VALUES (?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&participant.participant_id)
-    .bind(&participant.user_id)
-    .bind(&participant.username)
-    .bind(participant.joined_at)
-    .bind(&participant.role)
-    .bind(&participant.chat_id)
-    .execute(&pool)
-    .await?;
+        let _: i32 = tokio::select! {
+            result = query_future => result?,
+            _ = query_timeout => {
+                println!("[Database] Health check query timeout after {} seconds", timeout_duration.as_secs());
+                return Err(SqlxError::Configuration("Database query timeout".into()));
+            }
+        };
     
-    Ok(())
-}
+        Ok(())
+    }
 
-pub async fn get_participants_for_chat(chat_id: &str) -> Result<Vec<Participant>, SqlxError> {
-    let pool = get_pool().await?;
+    pub async fn clear_all_data(&self) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+    
+        sqlx::query("DELETE FROM user").execute(&pool).await?;
+        sqlx::query("DELETE FROM chat").execute(&pool).await?;
+        sqlx::query("DELETE FROM message").execute(&pool).await?;
+        sqlx::query("DELETE FROM friend").execute(&pool).await?;
+        sqlx::query("DELETE FROM participant").execute(&pool).await?;
+        sqlx::query("DELETE FROM user_keys").execute(&pool).await?;
+        sqlx::query("DELETE FROM presence").execute(&pool).await?;
+        sqlx::query("DELETE FROM message_receipt").execute(&pool).await?;
+    
+        self.changes.publish_all([
+            ChangeTable::User, ChangeTable::Chat, ChangeTable::Message, ChangeTable::Friend,
+            ChangeTable::Participant, ChangeTable::UserKeys, ChangeTable::Presence, ChangeTable::MessageReceipt,
+        ].map(ChangeRecord::reset));
+        Ok(())
+    }
+
+    pub async fn get_database_stats(&self) -> Result<serde_json::Value, SqlxError> {
+        let pool = self.pool().await?;
     
-    let rows = sqlx::query("SELECT * FROM participant WHERE chat_id = ? ORDER BY joined_at ASC")
-        .bind(chat_id)
-        .fetch_all(&pool)
-        .await?;
+        let total_users: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM user").fetch_one(&pool).await?;
+        let total_chats: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM chat").fetch_one(&pool).await?;
+        let total_messages: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM message").fetch_one(&pool).await?;
+        let total_friends: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM friend").fetch_one(&pool).await?;
     
-    let participants: Vec<Participant> = rows.iter().map(|row| Participant {
-        participant_id: row.get("participant_id"),
-        user_id: row.get("user_id"),
-        username: row.get("username"),
-        joined_at: row.get("joined_at"),
-        role: row.get("role"),
-        chat_id: row.get("chat_id"),
-    }).collect();
+        let stats = serde_json::json!({
+            "totalUsers": total_users,
+            "totalChats": total_chats,
+            "totalMessages": total_messages,
+            "totalFriends": total_friends
+        });
     
-    Ok(participants)
-}
+        Ok(stats)
+    }
 
-pub async fn clear_participant_data() -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    
-    sqlx::query("DELETE FROM participant").execute(&pool).await?;
-    Ok(())
-}
+    // Legacy function for compatibility
+    pub async fn ensure_database_initialized(&self) -> Result<(), SqlxError> {
+        let _pool = self.pool().await?;
+        Ok(())
+    }
+
+    // Legacy function for compatibility
+    pub async fn initialize_database_legacy(&self) -> Result<(), SqlxError> {
+        let _pool = self.pool().await?;
+        Ok(())
+    }
 
-// Additional participant operations needed by frontend
-pub async fn get_participant_by_id(participant_id: &str) -> Result<Option<Participant>, SqlxError> {
-    let pool = get_pool().await?;
+    // Secure token storage functions
+    pub async fn save_secure_token(&self, key: &str, value: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    let row = sqlx::query("SELECT * FROM participant WHERE participant_id = ?")
-        .bind(participant_id)
-        .fetch_optional(&pool)
-        .await?;
+        // Use parameterized query to prevent SQL injection
+        sqlx::query("INSERT OR REPLACE INTO secure_tokens (key_name, encrypted_value, created_at) VALUES (?, ?, ?)")
+            .bind(key)
+            .bind(value) // In production, this should be encrypted
+            .bind(Timestamp::now())
+            .execute(&pool)
+            .await?;
     
-    if let Some(row) = row {
-        Ok(Some(Participant {
-            participant_id: row.get("participant_id"),
-            user_id: row.get("user_id"),
-            username: row.get("username"),
-            joined_at: row.get("joined_at"),
-            role: row.get("role"),
-            chat_id: row.get("chat_id"),
-        }))
-    } else {
-        Ok(None)
+        Ok(())
     }
-}
 
-pub async fn get_participant_by_user_id_and_chat_id(user_id: &str, chat_id: &str) -> Result<Option<Participant>, SqlxError> {
-    let pool = get_pool().await?;
+    pub async fn load_secure_token(&self, key: &str) -> Result<Option<String>, SqlxError> {
+        let pool = self.pool().await?;
     
-    let row = sqlx::query("SELECT * FROM participant WHERE user_id = ? AND chat_id = ?")
-        .bind(user_id)
-        .bind(chat_id)
-        .fetch_optional(&pool)
-        .await?;
+        let row = sqlx::query("SELECT encrypted_value FROM secure_tokens WHERE key_name = ?")
+            .bind(key)
+            .fetch_optional(&pool)
+            .await?;
     
-    if let Some(row) = row {
-        Ok(Some(Participant {
-            participant_id: row.get("participant_id"),
-            user_id: row.get("user_id"),
-            username: row.get("username"),
-            joined_at: row.get("joined_at"),
-            role: row.get("role"),
-            chat_id: row.get("chat_id"),
-        }))
-    } else {
-        Ok(None)
+        Ok(row.map(|r| r.get::<String, _>("encrypted_value")))
     }
-}
 
-pub async fn delete_participant(participant_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    pub async fn clear_secure_token(&self, key: &str) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("DELETE FROM participant WHERE participant_id = ?")
-        .bind(participant_id)
-        .execute(&pool)
-        .await?;
+        sqlx::query("DELETE FROM secure_tokens WHERE key_name = ?")
+            .bind(key)
+            .execute(&pool)
+            .await?;
     
-    Ok(())
+        Ok(())
+    }
 }
 
-pub async fn update_participant_role(participant_id: &str, role: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    
-    sqlx::query("UPDATE participant SET role = ? WHERE participant_id = ?")
-        .bind(role)
-        .bind(participant_id)
-        .execute(&pool)
-        .await?;
-    
-    Ok(())
+// Archive import
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportConflict {
+    // "message" or "participant"
+    pub kind: String,
+    pub id: String,
+    pub reason: String,
 }
 
-// Additional friend operations needed by frontend
-pub async fn update_friend_status(user_id: &str, status: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ImportResult {
+    pub chat_id: String,
+    pub chat_created: bool,
+    pub messages_added: usize,
+    pub messages_updated: usize,
+    pub messages_unchanged: usize,
+    pub participants_added: usize,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+fn import_conflict(kind: &str, id: &str, reason: impl Into<String>) -> ImportConflict {
+    ImportConflict { kind: kind.to_string(), id: id.to_string(), reason: reason.into() }
+}
+
+impl SqliteRepo {
+    /// Merge an exported chat back into the cache in one transaction.
+    ///
+    /// Messages are matched by message_id (client_message_id when the message was never
+    /// acknowledged), so importing the same archive twice changes nothing. Rows that exist
+    /// locally with different content are reported as conflicts and the local copy wins.
+    /// A chat that is not cached yet is created like any other; if the server no longer
+    /// has it, the next delta sync keeps it as read-only and local-only.
+    pub async fn import_chat_archive(&self, chat: &Chat, participants: &[Participant], messages: &[Message]) -> Result<ImportResult, SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        let now = Timestamp::now();
+        let mut result = ImportResult { chat_id: chat.chat_id.clone(), ..Default::default() };
+    
+        result.chat_created = sqlx::query(
+            "INSERT INTO chat (
+                chat_id, name, created_at, creator_id, is_group, group_name, description,
+                unread_count, participants, imported_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, 0, ?, ?)
+            ON CONFLICT(chat_id) DO NOTHING"
+        )
+        .bind(&chat.chat_id)
+        .bind(&chat.name)
+        .bind(chat.created_at)
+        .bind(&chat.creator_id)
+        .bind(chat.is_group)
+        .bind(&chat.group_name)
+        .bind(&chat.description)
+        .bind(&chat.participants)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected() > 0;
+    
+        if !result.chat_created {
+            // Server data wins for an existing chat; only fill gaps and remember the import
+            sqlx::query(
+                "UPDATE chat SET
+                    name = COALESCE(name, ?),
+                    group_name = COALESCE(group_name, ?),
+                    description = COALESCE(description, ?),
+                    imported_at = COALESCE(imported_at, ?)
+                 WHERE chat_id = ?"
+            )
+            .bind(&chat.name)
+            .bind(&chat.group_name)
+            .bind(&chat.description)
+            .bind(now)
+            .bind(&chat.chat_id)
+            .execute(&mut *tx)
+            .await?;
+        }
     
-    sqlx::query("UPDATE friend SET status = ? WHERE user_id = ?")
-        .bind(status)
-        .bind(user_id)
-        .execute(&pool)
-        .await?;
+        // An explicit import brings back a chat the user had deleted locally
+        let undeleted = sqlx::query("DELETE FROM local_deletes WHERE chat_id = ?")
+            .bind(&chat.chat_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() > 0;
     
-    Ok(())
-}
-
-// User keys operations
-pub async fn insert_or_update_user_keys(keys: &UserKeys) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+        let mut added_participants = Vec::new();
+        let mut changed_messages = Vec::new();
+    
+        for participant in participants {
+            if participant.chat_id != chat.chat_id {
+                result.conflicts.push(import_conflict("participant", &participant.participant_id, "belongs to a different chat"));
+                continue;
+            }
+            let added = sqlx::query(
+                "INSERT INTO participant (participant_id, user_id, username, joined_at, role, chat_id)
+                 VALUES (?, ?, ?, ?, ?, ?)
+                 ON CONFLICT(participant_id) DO NOTHING"
+            )
+            .bind(&participant.participant_id)
+            .bind(&participant.user_id)
+            .bind(&participant.username)
+            .bind(participant.joined_at)
+            .bind(&participant.role)
+            .bind(&participant.chat_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() > 0;
+            if added {
+                result.participants_added += 1;
+                added_participants.push(participant.participant_id.clone());
+            }
+        }
+    
+        for message in messages {
+            let key = message.message_id.as_deref().unwrap_or(&message.client_message_id);
+            if message.chat_id != chat.chat_id {
+                result.conflicts.push(import_conflict("message", key, "belongs to a different chat"));
+                continue;
+            }
+        
+            let existing = match message.message_id.as_deref() {
+                Some(message_id) => sqlx::query_as::<_, (i64, Option<String>, String, String, String, MessageStatus)>(
+                    "SELECT id, message_id, chat_id, sender_id, content, status FROM message WHERE message_id = ? LIMIT 1"
+                )
+                .bind(message_id)
+                .fetch_optional(&mut *tx)
+                .await?,
+                None => None,
+            };
+            let existing = match existing {
+                Some(row) => Some(row),
+                None => sqlx::query_as::<_, (i64, Option<String>, String, String, String, MessageStatus)>(
+                    "SELECT id, message_id, chat_id, sender_id, content, status FROM message WHERE client_message_id = ?"
+                )
+                .bind(&message.client_message_id)
+                .fetch_optional(&mut *tx)
+                .await?,
+            };
+        
+            let Some((id, local_message_id, chat_id, sender_id, content, local_status)) = existing else {
+                let row = sqlx::query(
+                    "INSERT INTO message (
+                        message_id, client_message_id, chat_id, sender_id, content, timestamp,
+                        status, sender_username, reply_to_message_id
+                    ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    RETURNING *"
+                )
+                .bind(&message.message_id)
+                .bind(&message.client_message_id)
+                .bind(&message.chat_id)
+                .bind(&message.sender_id)
+                .bind(&message.content)
+                .bind(message.timestamp)
+                .bind(message.status)
+                .bind(&message.sender_username)
+                .bind(&message.reply_to_message_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                result.messages_added += 1;
+                changed_messages.push((ChangeOp::Insert, message_from_row(&row)));
+                continue;
+            };
+        
+            if let (Some(local), Some(archived)) = (local_message_id.as_deref(), message.message_id.as_deref()) {
+                if local != archived {
+                    result.conflicts.push(import_conflict("message", key, format!("client id already used by message {}", local)));
+                    continue;
+                }
+            }
+            if chat_id != message.chat_id {
+                result.conflicts.push(import_conflict("message", key, format!("already stored in chat {}", chat_id)));
+                continue;
+            }
+            if sender_id != message.sender_id || content != message.content {
+                result.conflicts.push(import_conflict("message", key, "differs from the local copy; kept the local copy"));
+                continue;
+            }
+        
+            // Same message: only fill in what the local row is missing
+            let status = local_status.advanced(message.status);
+            let updated = sqlx::query(
+                "UPDATE message SET
+                    message_id = COALESCE(message_id, ?),
+                    sender_username = COALESCE(sender_username, ?),
+                    reply_to_message_id = COALESCE(reply_to_message_id, ?),
+                    status = ?
+                 WHERE id = ?
+                   AND (message_id IS NULL AND ? IS NOT NULL
+                     OR sender_username IS NULL AND ? IS NOT NULL
+                     OR reply_to_message_id IS NULL AND ? IS NOT NULL
+                     OR status != ?)
+                 RETURNING *"
+            )
+            .bind(&message.message_id)
+            .bind(&message.sender_username)
+            .bind(&message.reply_to_message_id)
+            .bind(status)
+            .bind(id)
+            .bind(&message.message_id)
+            .bind(&message.sender_username)
+            .bind(&message.reply_to_message_id)
+            .bind(status)
+            .fetch_optional(&mut *tx)
+            .await?;
+            if let Some(row) = updated {
+                result.messages_updated += 1;
+                changed_messages.push((ChangeOp::Update, message_from_row(&row)));
+            } else {
+                result.messages_unchanged += 1;
+            }
+        }
     
-    sqlx::query(
-        "INSERT OR REPLACE INTO user_keys (
-            user_id, key1, key2, key3, key4, private_key1, private_key2, private_key3, private_key4
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&keys.user_id)
-    .bind(&keys.key1)
-    .bind(&keys.key2)
-    .bind(&keys.key3)
-    .bind(&keys.key4)
-    .bind(&keys.private_key1)
-    .bind(&keys.private_key2)
-    .bind(&keys.private_key3)
-    .bind(&keys.private_key4)
-    .execute(&pool)
-    .await?;
+        tx.commit().await?;
     
-    Ok(())
-}
+        println!("[Database] Imported chat {}: {} added, {} updated, {} unchanged, {} conflicts",
+                 result.chat_id, result.messages_added, result.messages_updated,
+                 result.messages_unchanged, result.conflicts.len());
+    
+        let chat_op = if result.chat_created { ChangeOp::Insert } else { ChangeOp::Update };
+        self.publish_chat(chat_op, &chat.chat_id).await;
+        if undeleted {
+            self.changes.publish(ChangeRecord::delete(ChangeTable::ChatLocalDelete, chat.chat_id.clone()));
+        }
+        for participant_id in &added_participants {
+            self.publish_participant(ChangeOp::Insert, participant_id).await;
+        }
+        for op in [ChangeOp::Insert, ChangeOp::Update] {
+            self.publish_messages(op, changed_messages.iter().filter(|(o, _)| *o == op).map(|(_, message)| message)).await;
+        }
+        Ok(result)
+    }
 
-pub async fn get_user_keys(user_id: &str) -> Result<Option<UserKeys>, SqlxError> {
-    let pool = get_pool().await?;
+    // Presence functions
+    pub async fn upsert_presence(&self, presence: &Presence) -> Result<(), SqlxError> {
+        let pool = self.pool().await?;
+        let mut tx = pool.begin().await?;
     
-    let row = sqlx::query("SELECT * FROM user_keys WHERE user_id = ?")
-        .bind(user_id)
-        .fetch_optional(&pool)
+        // last_seen never moves backwards, even if frames arrive out of order
+        sqlx::query(
+            "INSERT INTO presence (user_id, status, last_seen, updated_at) VALUES (?, ?, ?, ?)
+             ON CONFLICT(user_id) DO UPDATE SET
+                status = excluded.status,
+                last_seen = MAX(presence.last_seen, excluded.last_seen),
+                updated_at = excluded.updated_at"
+        )
+        .bind(&presence.user_id)
+        .bind(&presence.status)
+        .bind(presence.last_seen)
+        .bind(Timestamp::now())
+        .execute(&mut *tx)
         .await?;
     
-    if let Some(row) = row {
-        Ok(Some(UserKeys {
-            user_id: row.get("user_id"),
-            key1: row.get("key1"),
-            key2: row.get("key2"),
-            key3: row.get("key3"),
-            key4: row.get("key4"),
-            private_key1: row.get("private_key1"),
-            private_key2: row.get("private_key2"),
-            private_key3: row.get("private_key3"),
-            private_key4: row.get("private_key4"),
-        }))
-    } else {
-        Ok(None)
+        // Keep the cached user row in step for users we have one for
+        sqlx::query("UPDATE user SET last_seen = ? WHERE user_id = ? AND (last_seen IS NULL OR last_seen < ?)")
+            .bind(presence.last_seen)
+            .bind(&presence.user_id)
+            .bind(presence.last_seen)
+            .execute(&mut *tx)
+            .await?;
+    
+        tx.commit().await?;
+    
+        match self.get_presence_for_users(std::slice::from_ref(&presence.user_id)).await {
+            Ok(stored) => self.changes.publish_all(stored.iter()
+                .map(|stored| ChangeRecord::row(ChangeTable::Presence, ChangeOp::Upsert, &stored.user_id, stored))),
+            Err(e) => println!("[Database] Change feed: failed to read presence {}: {}", presence.user_id, e),
+        }
+        Ok(())
     }
-}
 
-// Dark mode operations
-pub async fn update_dark_mode(user_id: &str, is_dark_mode: bool) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    pub async fn get_presence_for_users(&self, user_ids: &[String]) -> Result<Vec<Presence>, SqlxError> {
+        let pool = self.pool().await?;
+        let mut presences = Vec::new();
     
-    sqlx::query("UPDATE user SET is_dark_mode = ? WHERE user_id = ?")
-        .bind(is_dark_mode)
-        .bind(user_id)
-        .execute(&pool)
-        .await?;
+        for user_id in user_ids {
+            let row = sqlx::query("SELECT user_id, status, last_seen FROM presence WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_optional(&pool)
+                .await?;
+        
+            if let Some(row) = row {
+                presences.push(Presence {
+                    user_id: row.get("user_id"),
+                    status: row.get("status"),
+                    last_seen: row.get("last_seen"),
+                });
+            }
+        }
     
-    Ok(())
+        Ok(presences)
+    }
 }
 
-pub async fn get_dark_mode(user_id: &str) -> Result<bool, SqlxError> {
-    let pool = get_pool().await?;
-    let result = sqlx::query("SELECT is_dark_mode FROM user WHERE user_id = ?")
-        .bind(user_id)
-        .fetch_optional(&pool)
-        .await?;
+// Backup and restore
+#[derive(Debug, Serialize, Clone)]
+pub struct BackupInfo {
+    pub path: String,
+    pub size_bytes: u64,
+    pub schema_version: i64,
+    pub created_at: Timestamp,
+}
 
-    Ok(result.map(|row| row.get::<bool, _>("is_dark_mode")).unwrap_or(false))
+#[derive(Debug, Serialize, Clone)]
+pub struct RestoreReport {
+    pub restored_from: String,
+    // Copy of the database as it was just before the restore (None if there was none)
+    pub safety_copy: Option<String>,
+    // Version of the backup file, and the version after migrating it
+    pub backup_version: i64,
+    pub schema_version: i64,
 }
 
-pub async fn update_color_scheme(user_id: &str, color_scheme: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    sqlx::query("UPDATE user SET color_scheme = ? WHERE user_id = ?")
-        .bind(color_scheme)
-        .bind(user_id)
-        .execute(&pool)
-        .await?;
-    Ok(())
+fn io_error(context: &str, e: std::io::Error) -> SqlxError {
+    SqlxError::Configuration(format!("{}: {}", context, e).into())
 }
 
-pub async fn get_color_scheme(user_id: &str) -> Result<String, SqlxError> {
-    let pool = get_pool().await?;
-    let result = sqlx::query("SELECT color_scheme FROM user WHERE user_id = ?")
-        .bind(user_id)
-        .fetch_optional(&pool)
+// VACUUM INTO a temporary file next to the target, then move it into place so a
+// half-written backup never replaces a good one
+async fn vacuum_into(pool: &SqlitePool, target: &std::path::Path) -> Result<(), SqlxError> {
+    let temp = target.with_extension("backup-tmp");
+    if temp.exists() {
+        std::fs::remove_file(&temp).map_err(|e| io_error("Failed to remove stale temporary backup", e))?;
+    }
+    
+    sqlx::query("VACUUM INTO ?")
+        .bind(temp.to_string_lossy().to_string())
+        .execute(pool)
         .await?;
-
-    Ok(result.map(|row| row.get::<String, _>("color_scheme")).unwrap_or_else(|| "blue".to_string()))
+    
+    std::fs::rename(&temp, target).map_err(|e| io_error("Failed to move backup into place", e))
 }
 
-pub async fn health_check() -> Result<(), SqlxError> {
-    // Add timeout to prevent hanging
-    let timeout_duration = std::time::Duration::from_secs(5);
+impl SqliteRepo {
+    // Online backup of the live database; readers and writers keep going while it runs
+    pub async fn backup_database(&self, path: &str) -> Result<BackupInfo, SqlxError> {
+        let pool = self.pool().await?;
+        let target = PathBuf::from(path);
     
-    let pool_future = get_pool();
-    let timeout_future = tokio::time::sleep(timeout_duration);
-    
-    let pool = tokio::select! {
-        pool_result = pool_future => pool_result?,
-        _ = timeout_future => {
-            println!("[Database] Health check timeout after {} seconds", timeout_duration.as_secs());
-            return Err(SqlxError::Configuration("Database connection timeout".into()));
+        if self.path.as_ref() == Some(&target) {
+            return Err(SqlxError::Configuration("Backup path is the live database".into()));
+        }
+        if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| io_error("Failed to create backup directory", e))?;
         }
-    };
     
-    // Simple query to test connection with timeout
-    let query_future = sqlx::query_scalar("SELECT 1").fetch_one(&pool);
-    let query_timeout = tokio::time::sleep(timeout_duration);
+        println!("[Database] Backing up database to: {:?}", target);
+        vacuum_into(&pool, &target).await?;
     
-    let _: i32 = tokio::select! {
-        result = query_future => result?,
-        _ = query_timeout => {
-            println!("[Database] Health check query timeout after {} seconds", timeout_duration.as_secs());
-            return Err(SqlxError::Configuration("Database query timeout".into()));
-        }
-    };
+        let size_bytes = std::fs::metadata(&target).map(|m| m.len()).unwrap_or(0);
+        let schema_version = crate::migrations::current_version(&pool).await?;
+        println!("[Database] Backup complete: {} bytes at schema version {}", size_bytes, schema_version);
     
-    Ok(())
+        Ok(BackupInfo {
+            path: target.to_string_lossy().to_string(),
+            size_bytes,
+            schema_version,
+            created_at: Timestamp::now(),
+        })
+    }
 }
 
-pub async fn clear_all_data() -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+// Open a backup read-only and check it is a database this build can use
+async fn validate_backup(path: &std::path::Path) -> Result<i64, SqlxError> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
     
-    sqlx::query("DELETE FROM user").execute(&pool).await?;
-    sqlx::query("DELETE FROM chat").execute(&pool).await?;
-    sqlx::query("DELETE FROM message").execute(&pool).await?;
-    sqlx::query("DELETE FROM friend").execute(&pool).await?;
-    sqlx::query("DELETE FROM participant").execute(&pool).await?;
-    sqlx::query("DELETE FROM user_keys").execute(&pool).await?;
+    let result = async {
+        let check: String = sqlx::query_scalar("PRAGMA quick_check").fetch_one(&pool).await?;
+        if check != "ok" {
+            return Err(SqlxError::Configuration(format!("Backup failed integrity check: {}", check).into()));
+        }
+        let version = crate::migrations::current_version(&pool).await?;
+        if version == 0 {
+            return Err(SqlxError::Configuration("Backup is not a chat database".into()));
+        }
+        if version > crate::migrations::latest_version() {
+            return Err(SqlxError::Configuration(format!(
+                "Backup is from a newer version of the app (schema {}, this build supports {})",
+                version, crate::migrations::latest_version()
+            ).into()));
+        }
+        Ok(version)
+    }.await;
     
-    Ok(())
+    pool.close().await;
+    result
 }
 
-pub async fn get_database_stats() -> Result<serde_json::Value, SqlxError> {
-    let pool = get_pool().await?;
+// Put a database file in place of the live one, dropping any WAL left from the old file
+fn replace_database_file(source: &std::path::Path, db_path: &std::path::Path) -> Result<(), SqlxError> {
+    let temp = db_path.with_extension("restore-tmp");
+    std::fs::copy(source, &temp).map_err(|e| io_error("Failed to copy database file", e))?;
+    for suffix in ["-wal", "-shm"] {
+        let sidecar = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+        if sidecar.exists() {
+            std::fs::remove_file(&sidecar).map_err(|e| io_error("Failed to remove stale WAL file", e))?;
+        }
+    }
+    std::fs::rename(&temp, db_path).map_err(|e| io_error("Failed to move database file into place", e))
+}
+
+impl SqliteRepo {
+    /// Replace the local database with a backup.
+    ///
+    /// The backup is validated first, then a safety copy of the current database is taken
+    /// next to it. The pool is closed while the file is swapped and reopened (and migrated)
+    /// afterwards. If the restored file cannot be opened the safety copy is put back.
+    /// `pool()` callers wait on the pool lock for the duration.
+    pub async fn restore_database(&self, path: &str) -> Result<RestoreReport, SqlxError> {
+        let source = PathBuf::from(path);
+        let db_path = self.file_path()?;
+        if source == db_path {
+            return Err(SqlxError::Configuration("Restore path is the live database".into()));
+        }
+    
+        println!("[Database] Validating backup: {:?}", source);
+        let backup_version = validate_backup(&source).await?;
+    
+        let mut current = self.pool.write().await;
+    
+        let safety_path = PathBuf::from(format!("{}.pre-restore-{}", db_path.display(), Timestamp::now()));
+        let safety_copy = if let Some(pool) = current.as_ref() {
+            println!("[Database] Saving pre-restore safety copy to: {:?}", safety_path);
+            vacuum_into(pool, &safety_path).await?;
+            Some(safety_path)
+        } else if db_path.exists() {
+            std::fs::copy(&db_path, &safety_path).map_err(|e| io_error("Failed to save pre-restore safety copy", e))?;
+            Some(safety_path)
+        } else {
+            None
+        };
     
-    let total_users: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM user").fetch_one(&pool).await?;
-    let total_chats: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM chat").fetch_one(&pool).await?;
-    let total_messages: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM message").fetch_one(&pool).await?;
-    let total_friends: i32 = sqlx::query_scalar("SELECT COUNT(*) FROM friend").fetch_one(&pool).await?;
+        if let Some(pool) = current.take() {
+            println!("[Database] Closing database pool for restore");
+            pool.close().await;
+        }
     
-    let stats = serde_json::json!({
-        "totalUsers": total_users,
-        "totalChats": total_chats,
-        "totalMessages": total_messages,
-        "totalFriends": total_friends
-    });
+        replace_database_file(&source, &db_path)?;
     
-    Ok(stats)
+        let reopened = async {
+            let pool = open_pool(&db_path).await?;
+            match crate::migrations::run_migrations(&pool).await {
+                Ok(report) => Ok((pool, report.to_version)),
+                Err(e) => {
+                    pool.close().await;
+                    Err(e)
+                }
+            }
+        }.await;
+    
+        match reopened {
+            Ok((pool, schema_version)) => {
+                *current = Some(pool);
+                println!("[Database] Restored database from {:?} (schema {} -> {})", source, backup_version, schema_version);
+                self.publish_reset_all();
+                Ok(RestoreReport {
+                    restored_from: source.to_string_lossy().to_string(),
+                    safety_copy: safety_copy.map(|p| p.to_string_lossy().to_string()),
+                    backup_version,
+                    schema_version,
+                })
+            }
+            Err(e) => {
+                eprintln!("[Database] Failed to open restored database: {}", e);
+                if let Some(safety_copy) = &safety_copy {
+                    println!("[Database] Putting the previous database back");
+                    replace_database_file(safety_copy, &db_path)?;
+                    *current = Some(open_pool(&db_path).await?);
+                }
+                Err(e)
+            }
+        }
+    }
 }
 
-// Legacy function for compatibility
-pub async fn ensure_database_initialized() -> Result<(), SqlxError> {
-    let _pool = get_pool().await?;
-    Ok(())
+// Maintenance
+#[derive(Debug, Serialize, Clone)]
+pub struct IntegrityReport {
+    pub mode: String,
+    pub ok: bool,
+    pub problems: Vec<String>,
 }
 
-// Legacy function for compatibility
-pub async fn initialize_database_legacy() -> Result<(), SqlxError> {
-    let _pool = get_pool().await?;
-    Ok(())
+#[derive(Debug, Serialize, Clone)]
+pub struct VacuumReport {
+    // True when the file was switched to incremental auto-vacuum by a full VACUUM
+    pub converted: bool,
+    pub freelist_pages_before: i64,
+    pub freelist_pages_after: i64,
+    pub bytes_reclaimed: i64,
 }
 
-// Clear all messages for a specific chat
-pub async fn clear_messages_for_chat(chat_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    
-    sqlx::query("DELETE FROM message WHERE chat_id = ?")
-        .bind(chat_id)
-        .execute(&pool)
-        .await?;
-    
-    Ok(())
+#[derive(Debug, Serialize, Clone)]
+pub struct CheckpointReport {
+    pub mode: String,
+    pub journal_mode: String,
+    pub busy: bool,
+    pub wal_pages: i64,
+    pub checkpointed_pages: i64,
 }
 
-// Remove all participants for a specific chat
-pub async fn remove_all_participants_for_chat(chat_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    
-    sqlx::query("DELETE FROM participant WHERE chat_id = ?")
-        .bind(chat_id)
-        .execute(&pool)
-        .await?;
-    
-    Ok(())
-} 
-
-// Secure token storage functions
-pub async fn save_secure_token(key: &str, value: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    
-    // Use parameterized query to prevent SQL injection
-    sqlx::query("INSERT OR REPLACE INTO secure_tokens (key_name, encrypted_value, created_at) VALUES (?, ?, ?)")
-        .bind(key)
-        .bind(value) // In production, this should be encrypted
-        .bind(chrono::Utc::now().timestamp())
-        .execute(&pool)
-        .await?;
-    
-    Ok(())
+#[derive(Debug, Serialize, Clone)]
+pub struct ObjectSize {
+    pub name: String,
+    pub kind: String,
+    pub bytes: i64,
 }
 
-pub async fn load_secure_token(key: &str) -> Result<Option<String>, SqlxError> {
-    let pool = get_pool().await?;
-    
-    let row = sqlx::query("SELECT encrypted_value FROM secure_tokens WHERE key_name = ?")
-        .bind(key)
-        .fetch_optional(&pool)
-        .await?;
+#[derive(Debug, Serialize, Clone)]
+pub struct ChatStorage {
+    pub chat_id: String,
+    pub name: Option<String>,
+    pub message_count: i64,
+    // Sum of the stored message values; page overhead is not included
+    pub approx_bytes: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AgeBucket {
+    pub label: String,
+    pub message_count: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StorageReport {
+    pub file_bytes: u64,
+    pub wal_bytes: u64,
+    pub page_size: i64,
+    pub page_count: i64,
+    pub freelist_pages: i64,
+    pub tables: Vec<ObjectSize>,
+    pub indexes: Vec<ObjectSize>,
+    pub chats: Vec<ChatStorage>,
+    pub message_age: Vec<AgeBucket>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TableRecovery {
+    pub table: String,
+    pub copied: i64,
+    // Rows (or rowid ranges) that could not be read from the damaged file
+    pub unreadable: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RecoveryReport {
+    pub problems: Vec<String>,
+    // Where the damaged file was moved to
+    pub damaged_copy: String,
+    pub tables: Vec<TableRecovery>,
+}
+
+impl SqliteRepo {
+    // PRAGMA integrity_check (full) or quick_check; reports at most 100 problems
+    pub async fn integrity_check(&self, full: bool) -> Result<IntegrityReport, SqlxError> {
+        let pool = self.pool().await?;
+        let mode = if full { "integrity_check" } else { "quick_check" };
+    
+        println!("[Database] Running {}", mode);
+        let rows: Vec<String> = sqlx::query_scalar(&format!("PRAGMA {}(100)", mode))
+            .fetch_all(&pool)
+            .await?;
     
-    Ok(row.map(|r| r.get::<String, _>("encrypted_value")))
+        let ok = rows.len() == 1 && rows[0] == "ok";
+        if !ok {
+            println!("[Database] {} found {} problems", mode, rows.len());
+        }
+        Ok(IntegrityReport {
+            mode: mode.to_string(),
+            ok,
+            problems: if ok { Vec::new() } else { rows },
+        })
+    }
 }
 
-pub async fn clear_secure_token(key: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    
-    sqlx::query("DELETE FROM secure_tokens WHERE key_name = ?")
-        .bind(key)
-        .execute(&pool)
-        .await?;
-    
-    Ok(())
+async fn pragma_i64(pool: &SqlitePool, pragma: &str) -> Result<i64, SqlxError> {
+    sqlx::query_scalar(&format!("PRAGMA {}", pragma)).fetch_one(pool).await
 }
 
-// MARK: - Local Deletes Management
-
-/// Add a chat to local deletes tracking
-pub async fn add_local_delete(chat_id: &str, user_id: &str, is_creator: bool) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
-    let timestamp = chrono::Utc::now().timestamp();
+impl SqliteRepo {
+    // Return free pages to the file system. Files created before incremental auto-vacuum
+    // was enabled get one full VACUUM to switch them over.
+    pub async fn incremental_vacuum(&self, max_pages: Option<i64>) -> Result<VacuumReport, SqlxError> {
+        let pool = self.pool().await?;
+        let page_size = pragma_i64(&pool, "page_size").await?;
+        let freelist_pages_before = pragma_i64(&pool, "freelist_count").await?;
     
-    sqlx::query(
-        "INSERT OR REPLACE INTO local_deletes (chat_id, deleted_at, user_id, is_creator) VALUES (?, ?, ?, ?)"
-    )
-    .bind(chat_id)
-    .bind(timestamp)
-    .bind(user_id)
-    .bind(is_creator)
-    .execute(&pool)
-    .await?;
+        // 2 = INCREMENTAL
+        let converted = pragma_i64(&pool, "auto_vacuum").await? != 2;
+        if converted {
+            println!("[Database] Switching database to incremental auto-vacuum (full VACUUM)");
+            let mut conn = pool.acquire().await?;
+            sqlx::query("PRAGMA auto_vacuum = INCREMENTAL").execute(&mut *conn).await?;
+            sqlx::query("VACUUM").execute(&mut *conn).await?;
+        } else {
+            // Every row of the pragma has to be stepped for the pages to be freed
+            sqlx::query(&format!("PRAGMA incremental_vacuum({})", max_pages.unwrap_or(0).max(0)))
+                .fetch_all(&pool)
+                .await?;
+        }
     
-    Ok(())
-}
+        let freelist_pages_after = pragma_i64(&pool, "freelist_count").await?;
+        let report = VacuumReport {
+            converted,
+            freelist_pages_before,
+            freelist_pages_after,
+            bytes_reclaimed: (freelist_pages_before - freelist_pages_after).max(0) * page_size,
+        };
+        println!("[Database] Vacuum reclaimed {} bytes", report.bytes_reclaimed);
+        Ok(report)
+    }
 
-/// Check if a chat is locally deleted
-pub async fn is_chat_locally_deleted(chat_id: &str, user_id: &str) -> Result<bool, SqlxError> {
-    let pool = get_pool().await?;
+    // Copy the WAL back into the database file; mode is passive, full, restart or truncate
+    pub async fn wal_checkpoint(&self, mode: &str) -> Result<CheckpointReport, SqlxError> {
+        let mode = mode.to_lowercase();
+        if !["passive", "full", "restart", "truncate"].contains(&mode.as_str()) {
+            return Err(SqlxError::Configuration(format!("Unknown checkpoint mode: {}", mode).into()));
+        }
+        let pool = self.pool().await?;
     
-    let result = sqlx::query("SELECT 1 FROM local_deletes WHERE chat_id = ? AND user_id = ?")
-        .bind(chat_id)
-        .bind(user_id)
-        .fetch_optional(&pool)
-        .await?;
+        let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode").fetch_one(&pool).await?;
+        let row = sqlx::query(&format!("PRAGMA wal_checkpoint({})", mode.to_uppercase()))
+            .fetch_one(&pool)
+            .await?;
     
-    Ok(result.is_some())
-}
+        let report = CheckpointReport {
+            mode,
+            journal_mode,
+            busy: row.get::<i64, _>(0) != 0,
+            wal_pages: row.get(1),
+            checkpointed_pages: row.get(2),
+        };
+        println!("[Database] WAL checkpoint ({}): {}/{} pages, busy={}",
+                 report.mode, report.checkpointed_pages, report.wal_pages, report.busy);
+        Ok(report)
+    }
 
-/// Remove a chat from local deletes tracking
-pub async fn remove_local_delete(chat_id: &str, user_id: &str) -> Result<(), SqlxError> {
-    let pool = get_pool().await?;
+    pub async fn storage_report(&self) -> Result<StorageReport, SqlxError> {
+        let pool = self.pool().await?;
     
-    sqlx::query("DELETE FROM local_deletes WHERE chat_id = ? AND user_id = ?")
-        .bind(chat_id)
-        .bind(user_id)
-        .execute(&pool)
-        .await?;
+        // An in-memory database has no file to measure
+        let (file_bytes, wal_bytes) = match &self.path {
+            Some(db_path) => (
+                std::fs::metadata(db_path).map(|m| m.len()).unwrap_or(0),
+                std::fs::metadata(format!("{}-wal", db_path.display())).map(|m| m.len()).unwrap_or(0),
+            ),
+            None => (0, 0),
+        };
     
-    Ok(())
+        // Page usage per table and index, from the dbstat virtual table
+        let rows = sqlx::query(
+            "SELECT s.name AS name, COALESCE(m.type, 'internal') AS kind, SUM(s.pgsize) AS bytes
+             FROM dbstat s
+             LEFT JOIN sqlite_master m ON m.name = s.name
+             GROUP BY s.name
+             ORDER BY bytes DESC"
+        )
+        .fetch_all(&pool)
+        .await?;
+        let (indexes, tables): (Vec<ObjectSize>, Vec<ObjectSize>) = rows.iter()
+            .map(|row| ObjectSize {
+                name: row.get("name"),
+                kind: row.get("kind"),
+                bytes: row.get("bytes"),
+            })
+            .partition(|object| object.kind == "index");
+    
+        let chats = sqlx::query(
+            "SELECT c.chat_id, COALESCE(c.group_name, c.name) AS name,
+                    COUNT(m.id) AS message_count,
+                    COALESCE(SUM(
+                        LENGTH(CAST(m.content AS BLOB)) + LENGTH(m.client_message_id) + LENGTH(m.sender_id)
+                        + COALESCE(LENGTH(m.message_id), 0) + COALESCE(LENGTH(m.sender_username), 0)
+                        + COALESCE(LENGTH(m.reply_to_message_id), 0) + 40
+                    ), 0) AS approx_bytes
+             FROM chat c
+             LEFT JOIN message m ON m.chat_id = c.chat_id
+             GROUP BY c.chat_id
+             ORDER BY approx_bytes DESC"
+        )
+        .fetch_all(&pool)
+        .await?
+        .iter()
+        .map(|row| ChatStorage {
+            chat_id: row.get("chat_id"),
+            name: row.get("name"),
+            message_count: row.get("message_count"),
+            approx_bytes: row.get("approx_bytes"),
+        })
+        .collect();
+    
+        const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+        let age = sqlx::query(&format!(
+            "SELECT
+                COALESCE(SUM(CASE WHEN age < {day} THEN 1 ELSE 0 END), 0) AS day,
+                COALESCE(SUM(CASE WHEN age >= {day} AND age < {week} THEN 1 ELSE 0 END), 0) AS week,
+                COALESCE(SUM(CASE WHEN age >= {week} AND age < {month} THEN 1 ELSE 0 END), 0) AS month,
+                COALESCE(SUM(CASE WHEN age >= {month} AND age < {year} THEN 1 ELSE 0 END), 0) AS year,
+                COALESCE(SUM(CASE WHEN age >= {year} THEN 1 ELSE 0 END), 0) AS older
+             FROM (SELECT ? - timestamp AS age FROM message)",
+            day = DAY_MS, week = 7 * DAY_MS, month = 30 * DAY_MS, year = 365 * DAY_MS
+        ))
+        .bind(Timestamp::now())
+        .fetch_one(&pool)
+        .await?;
+        let message_age = [
+            ("last_24_hours", "day"),
+            ("last_7_days", "week"),
+            ("last_30_days", "month"),
+            ("last_year", "year"),
+            ("older", "older"),
+        ]
+        .iter()
+        .map(|(label, column)| AgeBucket {
+            label: label.to_string(),
+            message_count: age.get(*column),
+        })
+        .collect();
+    
+        Ok(StorageReport {
+            file_bytes,
+            wal_bytes,
+            page_size: pragma_i64(&pool, "page_size").await?,
+            page_count: pragma_i64(&pool, "page_count").await?,
+            freelist_pages: pragma_i64(&pool, "freelist_count").await?,
+            tables,
+            indexes,
+            chats,
+            message_age,
+        })
+    }
 }
 
-/// Clean up local deletes for chats that no longer exist on server
-pub async fn cleanup_local_deletes(server_chat_ids: &[String], user_id: &str) -> Result<(), SqlxError> {
-    if server_chat_ids.is_empty() {
-        return Ok(());
+// Copy what can still be read from damaged.<table> into main.<table>. A corrupt page
+// aborts the whole INSERT ... SELECT, so fall back to rowid ranges and then single rows.
+async fn salvage_table(conn: &mut sqlx::SqliteConnection, table: &str, columns: &[String]) -> TableRecovery {
+    const CHUNK: i64 = 256;
+    let column_list = columns.iter().map(|c| format!("\"{}\"", c)).collect::<Vec<_>>().join(", ");
+    let copy_sql = format!(
+        "INSERT OR IGNORE INTO main.\"{table}\" ({cols}) SELECT {cols} FROM damaged.\"{table}\"",
+        table = table, cols = column_list
+    );
+    let mut report = TableRecovery { table: table.to_string(), copied: 0, unreadable: 0 };
+    
+    if let Ok(result) = sqlx::query(&copy_sql).execute(&mut *conn).await {
+        report.copied = result.rows_affected() as i64;
+        return report;
     }
+    println!("[Database] Table {} is damaged, salvaging row ranges", table);
+    
+    let bounds = sqlx::query_as::<_, (Option<i64>, Option<i64>)>(&format!("SELECT MIN(rowid), MAX(rowid) FROM damaged.\"{}\"", table))
+        .fetch_one(&mut *conn)
+        .await;
+    let Ok((Some(min), Some(max))) = bounds else {
+        println!("[Database] Could not read row range of {}, skipping it", table);
+        report.unreadable = 1;
+        return report;
+    };
     
-    let pool = get_pool().await?;
-    
-    // Create placeholders for the IN clause
-    let placeholders = server_chat_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-    let query = format!("DELETE FROM local_deletes WHERE user_id = ? AND chat_id NOT IN ({})", placeholders);
-    
-    let mut query_builder = sqlx::query(&query).bind(user_id);
-    
-    // Bind all server chat IDs
-    for chat_id in server_chat_ids {
-        query_builder = query_builder.bind(chat_id);
+    let range_sql = format!("{} WHERE rowid BETWEEN ? AND ?", copy_sql);
+    let mut start = min;
+    while start <= max {
+        let end = (start + CHUNK - 1).min(max);
+        match sqlx::query(&range_sql).bind(start).bind(end).execute(&mut *conn).await {
+            Ok(result) => report.copied += result.rows_affected() as i64,
+            Err(_) => {
+                for rowid in start..=end {
+                    match sqlx::query(&range_sql).bind(rowid).bind(rowid).execute(&mut *conn).await {
+                        Ok(result) => report.copied += result.rows_affected() as i64,
+                        Err(_) => report.unreadable += 1,
+                    }
+                }
+            }
+        }
+        start = end + 1;
     }
-    
-    query_builder.execute(&pool).await?;
-    
-    Ok(())
+    report
 }
 
-/// Get all locally deleted chat IDs for a user
-pub async fn get_local_deleted_chat_ids(user_id: &str) -> Result<Vec<String>, SqlxError> {
-    let pool = get_pool().await?;
+impl SqliteRepo {
+    /// Rebuild a damaged database: the readable rows are copied into a freshly migrated
+    /// file, which then replaces the live one. The damaged file is kept next to it.
+    pub async fn recover_database(&self, problems: Vec<String>) -> Result<RecoveryReport, SqlxError> {
+        let db_path = self.file_path()?;
+        let recovered_path = PathBuf::from(format!("{}.recovered-tmp", db_path.display()));
+        if recovered_path.exists() {
+            std::fs::remove_file(&recovered_path).map_err(|e| io_error("Failed to remove stale recovery file", e))?;
+        }
+        println!("[Database] Recovering database into: {:?}", recovered_path);
+    
+        // Foreign keys stay off while copying so table order does not matter; orphans are dropped after
+        let options = SqliteConnectOptions::new()
+            .filename(&recovered_path)
+            .create_if_missing(true)
+            .foreign_keys(false);
+        let fresh = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
     
-    let rows = sqlx::query("SELECT chat_id FROM local_deletes WHERE user_id = ?")
-        .bind(user_id)
-        .fetch_all(&pool)
-        .await?;
+        let salvaged = async {
+            crate::migrations::run_migrations(&fresh).await?;
+            let mut conn = fresh.acquire().await?;
+            sqlx::query("ATTACH DATABASE ? AS damaged")
+                .bind(db_path.to_string_lossy().to_string())
+                .execute(&mut *conn)
+                .await?;
+        
+            let tables: Vec<String> = sqlx::query_scalar(
+                "SELECT name FROM main.sqlite_master
+                 WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+                   AND name NOT LIKE 'message_fts%' AND name != 'schema_version'"
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+        
+            let mut reports = Vec::new();
+            for table in tables {
+                let columns: Vec<String> = sqlx::query_scalar(
+                    "SELECT name FROM pragma_table_info(?, 'main')
+                     WHERE name IN (SELECT name FROM pragma_table_info(?, 'damaged'))"
+                )
+                .bind(&table)
+                .bind(&table)
+                .fetch_all(&mut *conn)
+                .await
+                .unwrap_or_default();
+                if columns.is_empty() {
+                    println!("[Database] Table {} not readable in damaged file, skipping", table);
+                    continue;
+                }
+                let report = salvage_table(&mut conn, &table, &columns).await;
+                println!("[Database] Recovered {} rows from {} ({} unreadable)", report.copied, report.table, report.unreadable);
+                reports.push(report);
+            }
+        
+            sqlx::query("DETACH DATABASE damaged").execute(&mut *conn).await?;
+            sqlx::query("DELETE FROM message WHERE chat_id NOT IN (SELECT chat_id FROM chat)").execute(&mut *conn).await?;
+            sqlx::query("DELETE FROM participant WHERE chat_id NOT IN (SELECT chat_id FROM chat)").execute(&mut *conn).await?;
+            sqlx::query("INSERT INTO message_fts(message_fts) VALUES ('rebuild')").execute(&mut *conn).await?;
+            Ok::<_, SqlxError>(reports)
+        }.await;
+        fresh.close().await;
+        let tables = salvaged?;
+    
+        // Swap the rebuilt file in while nobody can get at the pool
+        let mut current = self.pool.write().await;
+        if let Some(pool) = current.take() {
+            pool.close().await;
+        }
+        let damaged_copy = PathBuf::from(format!("{}.damaged-{}", db_path.display(), Timestamp::now().as_millis()));
+        std::fs::rename(&db_path, &damaged_copy).map_err(|e| io_error("Failed to move damaged database aside", e))?;
+        for suffix in ["-wal", "-shm"] {
+            let sidecar = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+            if sidecar.exists() {
+                std::fs::rename(&sidecar, format!("{}{}", damaged_copy.display(), suffix))
+                    .map_err(|e| io_error("Failed to move damaged WAL aside", e))?;
+            }
+        }
+        std::fs::rename(&recovered_path, &db_path).map_err(|e| io_error("Failed to move recovered database into place", e))?;
+        *current = Some(open_pool(&db_path).await?);
+    
+        println!("[Database] Recovery complete, damaged file kept at {:?}", damaged_copy);
+        self.publish_reset_all();
+        Ok(RecoveryReport {
+            problems,
+            damaged_copy: damaged_copy.to_string_lossy().to_string(),
+            tables,
+        })
+    }
+
+    // Startup check: recover only if quick_check finds damage
+    pub async fn recover_if_corrupt(&self) -> Result<Option<RecoveryReport>, SqlxError> {
+        let report = self.integrity_check(false).await?;
+        if report.ok {
+            return Ok(None);
+        }
+        eprintln!("[Database] Database is damaged: {:?}", report.problems);
+        self.recover_database(report.problems).await.map(Some)
+    }
+
+    // Schema version functions
+    pub async fn get_schema_status(&self) -> Result<serde_json::Value, SqlxError> {
+        let pool = self.pool().await?;
+        let current = crate::migrations::current_version(&pool).await?;
+        let pending = crate::migrations::pending_migrations(&pool).await?;
     
-    let chat_ids: Vec<String> = rows.iter().map(|row| row.get("chat_id")).collect();
-    Ok(chat_ids)
-} 
\ No newline at end of file
+        Ok(serde_json::json!({
+            "currentVersion": current,
+            "latestVersion": crate::migrations::latest_version(),
+            "pending": pending
+        }))
+    }
+
+    pub async fn verify_schema(&self) -> Result<crate::migrations::SchemaVerification, SqlxError> {
+        let pool = self.pool().await?;
+        crate::migrations::verify_schema(&pool).await
+    }
+}
@@ -1,14 +1,28 @@
 pub mod modules;
 pub mod database_async;
+pub mod migrations;
+pub mod changes;
+pub mod repo;
+pub mod timestamp;
+pub mod message_status;
 
+use modules::archive::*;
 use modules::auth::*;
 use modules::chat::*;
 use modules::database::*;
+use modules::diagnostics::*;
 use modules::friend::*;
 use modules::participant::*;
+use modules::presence::*;
+use modules::profiles::*;
+use modules::receipts::*;
+use modules::retention::*;
+use modules::typing::*;
 use modules::websocket::*;
 use modules::window::*;
+use repo::SqliteRepo;
 use std::collections::HashMap;
+use tauri::{Emitter, Manager};
 use std::sync::Mutex;
 use std::sync::Arc;
 use tokio::sync::Mutex as TokioMutex;
@@ -32,6 +46,11 @@ pub fn run() {
         .manage(Mutex::new(HashMap::<String, String>::new())) // Manage token storage
         .manage(Arc::new(TokioMutex::new(WebSocketState::default()))) // Manage WebSocket state
         .manage(Arc::new(SocketTx(TokioMutex::new(None)))) // Manage SocketTx for WebSocket
+        .manage(Arc::new(TypingState::default())) // Manage typing indicators
+        .manage(Arc::new(PresenceState::default())) // Manage presence tracking
+        .manage(Arc::new(ReceiptState::default())) // Manage outgoing read receipts
+        .manage(Arc::new(ProfileState::default())) // Manage sender name backfill
+        .manage(SqliteRepo::new(database_async::get_db_path())) // Manage the local database
         .invoke_handler(tauri::generate_handler![
             // Auth commands
             login,
@@ -52,6 +71,15 @@ pub fn run() {
             db_get_stats,
             db_reset_database,
             db_check_ready,
+            db_get_schema_version,
+            db_verify_migrations,
+            db_backup,
+            db_restore,
+            db_integrity_check,
+            db_incremental_vacuum,
+            db_wal_checkpoint,
+            db_storage_report,
+            db_recover,
             
             // User commands
             db_insert_user,
@@ -64,6 +92,8 @@ pub fn run() {
             db_get_dark_mode,
             db_update_color_scheme,
             db_get_color_scheme,
+            db_update_send_read_receipts,
+            db_get_send_read_receipts,
             
             // Chat commands
             db_insert_chat,
@@ -71,8 +101,6 @@ pub fn run() {
             db_get_chat_by_id,
             db_get_all_chats,
             db_get_cached_chats_only,
-            db_update_chat_unread_count,
-            db_update_chat_last_message,
             db_delete_chat_by_id,
             db_clear_chat_data,
             
@@ -85,6 +113,7 @@ pub fn run() {
             db_get_messages_before_timestamp,
             db_get_last_message,
             db_update_message_sent_status,
+            db_update_message_status,
             db_update_message_sent_status_by_server_id,
             db_mark_message_delivered_by_server_id,
             db_mark_message_read_by_server_id,
@@ -136,6 +165,13 @@ pub fn run() {
             get_cached_chats_for_current_user,
             get_cached_chats_for_current_user_filtered,
             get_cached_messages_for_chat,
+            search_messages,
+            get_message_page,
+            get_message_read_state,
+            set_chat_message_ttl,
+            get_chat_message_ttl,
+            export_chat,
+            import_chat_archive,
             fetch_all_chats_and_save,
             delete_chat,
             leave_chat_with_token,
@@ -172,8 +208,16 @@ pub fn run() {
             send_socket_binary_message,
             send_socket_ping,
             get_websocket_status,
+            get_websocket_diagnostics,
             reconnect_socket,
             
+            // Typing commands
+            set_typing,
+            
+            // Presence commands
+            report_user_activity,
+            get_presence,
+            
             // Window commands
             window_show_main_window,
             window_hide_main_window,
@@ -196,13 +240,25 @@ pub fn run() {
             println!("[App] Architecture: {}", std::env::consts::ARCH);
             
             // Initialize database
-            tauri::async_runtime::spawn(async {
-                match database_async::initialize_database().await {
+            let app_handle = app.handle().clone();
+            let repo = app.state::<SqliteRepo>().inner().clone();
+            // Forward repository writes to the frontend as batched db-changed events
+            changes::spawn_forwarder(app.handle().clone(), repo.changes());
+            tauri::async_runtime::spawn(async move {
+                match repo.initialize().await {
                     Ok(_) => {
                         println!("[App] Database initialized successfully");
+                        // Salvage what we can if the file was damaged (e.g. by a crash mid-write)
+                        match repo.recover_if_corrupt().await {
+                            Ok(Some(report)) => { app_handle.emit("database-recovered", &report).ok(); },
+                            Ok(None) => {},
+                            Err(e) => eprintln!("[App] Database integrity check failed: {}", e),
+                        }
                         // Add a longer delay to ensure database is fully ready for frontend access
                         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
                         println!("[App] Database ready for frontend access");
+                        // Purge messages from chats with a retention timer
+                        tauri::async_runtime::spawn(run_retention_task(app_handle));
                     },
                     Err(e) => eprintln!("[App] Database initialization failed: {}", e),
                 }
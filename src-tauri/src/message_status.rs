@@ -0,0 +1,143 @@
+// Delivery state of a message, stored in the single message.status column.
+//
+//   Pending -> Sent -> Delivered -> Read
+//      |
+//      +----> Failed -> Pending (retry), or straight to Sent or later if the server
+//                       acknowledges the message after all
+//
+// A status only ever moves forward. Every write goes through `advance`, so a late
+// "sent" ack can never undo "read". The is_* helpers give the flags the message
+// table used to store, for callers and payloads that still expect them.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    sqlite::{Sqlite, SqliteTypeInfo, SqliteValueRef},
+    Decode, Encode, Type,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageStatus {
+    #[default]
+    Pending,
+    Sent,
+    Delivered,
+    Read,
+    Failed,
+}
+
+impl MessageStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MessageStatus::Pending => "pending",
+            MessageStatus::Sent => "sent",
+            MessageStatus::Delivered => "delivered",
+            MessageStatus::Read => "read",
+            MessageStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(MessageStatus::Pending),
+            "sent" => Some(MessageStatus::Sent),
+            "delivered" => Some(MessageStatus::Delivered),
+            "read" => Some(MessageStatus::Read),
+            "failed" => Some(MessageStatus::Failed),
+            _ => None,
+        }
+    }
+
+    // Position on the Pending -> Read line; Failed sits with Pending
+    fn rank(self) -> u8 {
+        match self {
+            MessageStatus::Pending | MessageStatus::Failed => 0,
+            MessageStatus::Sent => 1,
+            MessageStatus::Delivered => 2,
+            MessageStatus::Read => 3,
+        }
+    }
+
+    /// The status after a transition to `to`, or None if the transition would move
+    /// the message backwards. Moving to the current status is allowed and a no-op.
+    pub fn advance(self, to: MessageStatus) -> Option<MessageStatus> {
+        match (self, to) {
+            (from, to) if from == to => Some(to),
+            // Retry of a failed send
+            (MessageStatus::Failed, MessageStatus::Pending) => Some(to),
+            // Only a message the server has not accepted can fail
+            (MessageStatus::Pending, MessageStatus::Failed) => Some(to),
+            (_, MessageStatus::Failed) => None,
+            (from, to) if to.rank() > from.rank() => Some(to),
+            _ => None,
+        }
+    }
+
+    /// Advance if allowed, otherwise stay put
+    pub fn advanced(self, to: MessageStatus) -> MessageStatus {
+        self.advance(to).unwrap_or(self)
+    }
+
+    // The furthest state the legacy flags describe
+    pub fn from_flags(is_sent: bool, is_delivered: bool, is_read: bool, is_failed: bool) -> Self {
+        if is_read {
+            MessageStatus::Read
+        } else if is_delivered {
+            MessageStatus::Delivered
+        } else if is_sent {
+            MessageStatus::Sent
+        } else if is_failed {
+            MessageStatus::Failed
+        } else {
+            MessageStatus::Pending
+        }
+    }
+
+    pub fn is_sent(self) -> bool {
+        self.rank() >= MessageStatus::Sent.rank()
+    }
+
+    pub fn is_delivered(self) -> bool {
+        self.rank() >= MessageStatus::Delivered.rank()
+    }
+
+    pub fn is_read(self) -> bool {
+        self == MessageStatus::Read
+    }
+
+    pub fn is_failed(self) -> bool {
+        self == MessageStatus::Failed
+    }
+}
+
+impl std::fmt::Display for MessageStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Stored as TEXT
+impl Type<Sqlite> for MessageStatus {
+    fn type_info() -> SqliteTypeInfo {
+        <&str as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <&str as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for MessageStatus {
+    fn encode_by_ref(&self, buf: &mut <Sqlite as sqlx::Database>::ArgumentBuffer<'q>) -> Result<IsNull, BoxDynError> {
+        <&str as Encode<Sqlite>>::encode_by_ref(&self.as_str(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for MessageStatus {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let text = <&str as Decode<Sqlite>>::decode(value)?;
+        MessageStatus::parse(text).ok_or_else(|| format!("unknown message status: {}", text).into())
+    }
+}
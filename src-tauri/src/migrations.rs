@@ -0,0 +1,404 @@
+// Versioned, forward-only schema migrations for the local database.
+//
+// Each migration has a number and is applied once; the highest applied number is
+// the schema version. Pending migrations run in a single transaction at startup, so
+// a database is either fully upgraded or left as it was.
+//
+// Databases created before migrations existed have no schema_version table. They
+// are treated as version 1 and the later migrations are written to tolerate objects
+// that some of those installs already have.
+
+use sqlx::{sqlite::{SqliteConnection, SqlitePool, SqlitePoolOptions}, Row, Error as SqlxError};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::timestamp::Timestamp;
+
+// One step of a migration
+pub enum Step {
+    // Plain SQL, may contain several statements
+    Sql(&'static str),
+    // ALTER TABLE ... ADD COLUMN, skipped if the column is already there
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+}
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+// Append new migrations at the end; never edit or renumber one that has shipped
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial schema",
+        steps: &[Step::Sql(include_str!("../sql/migrations/001_initial_schema.sql"))],
+    },
+    Migration {
+        version: 2,
+        name: "message.is_failed",
+        steps: &[Step::AddColumn {
+            table: "message",
+            column: "is_failed",
+            definition: "INTEGER NOT NULL DEFAULT 0",
+        }],
+    },
+    Migration {
+        version: 3,
+        name: "local_deletes table",
+        steps: &[Step::Sql(include_str!("../sql/migrations/003_local_deletes.sql"))],
+    },
+    Migration {
+        version: 4,
+        name: "presence table",
+        steps: &[Step::Sql(include_str!("../sql/migrations/004_presence.sql"))],
+    },
+    Migration {
+        version: 5,
+        name: "message full-text search",
+        steps: &[Step::Sql(include_str!("../sql/migrations/005_message_search.sql"))],
+    },
+    Migration {
+        version: 6,
+        name: "chat.message_ttl_secs",
+        // NULL means messages in the chat never expire
+        steps: &[Step::AddColumn {
+            table: "chat",
+            column: "message_ttl_secs",
+            definition: "INTEGER",
+        }],
+    },
+    Migration {
+        version: 7,
+        name: "chat foreign keys",
+        steps: &[Step::Sql(include_str!("../sql/migrations/007_chat_foreign_keys.sql"))],
+    },
+    Migration {
+        version: 8,
+        name: "chat import flags",
+        // imported_at marks chats restored from an archive; those are kept (read-only,
+        // local-only) instead of deleted when the server stops listing them
+        steps: &[
+            Step::AddColumn {
+                table: "chat",
+                column: "imported_at",
+                definition: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "chat",
+                column: "is_local_only",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            Step::AddColumn {
+                table: "chat",
+                column: "is_read_only",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+        ],
+    },
+    Migration {
+        version: 9,
+        name: "millisecond timestamps",
+        steps: &[Step::Sql(include_str!("../sql/migrations/009_normalize_timestamps.sql"))],
+    },
+    Migration {
+        version: 10,
+        name: "message page index",
+        steps: &[Step::Sql(include_str!("../sql/migrations/010_message_page_index.sql"))],
+    },
+    Migration {
+        version: 11,
+        name: "message receipts",
+        steps: &[Step::Sql(include_str!("../sql/migrations/011_message_receipts.sql"))],
+    },
+    Migration {
+        version: 12,
+        name: "message status column",
+        steps: &[Step::Sql(include_str!("../sql/migrations/012_message_status.sql"))],
+    },
+    Migration {
+        version: 13,
+        name: "user.send_read_receipts",
+        // Privacy setting; delivery receipts are always sent
+        steps: &[Step::AddColumn {
+            table: "user",
+            column: "send_read_receipts",
+            definition: "INTEGER NOT NULL DEFAULT 1",
+        }],
+    },
+    Migration {
+        version: 14,
+        name: "chat summary triggers",
+        // is_own_account marks accounts that have signed in on this device; unlike
+        // token_hash it survives logout, so the triggers can tell our own messages apart
+        // from cached profiles of other users
+        steps: &[
+            Step::AddColumn {
+                table: "user",
+                column: "is_own_account",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            Step::Sql("UPDATE user SET is_own_account = 1 WHERE token_hash IS NOT NULL AND token_hash != ''"),
+            Step::Sql(include_str!("../sql/migrations/014_chat_summary.sql")),
+        ],
+    },
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingMigration {
+    pub version: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub from_version: i64,
+    pub to_version: i64,
+    pub latest_version: i64,
+    pub applied: Vec<PendingMigration>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaVerification {
+    pub current_version: i64,
+    pub latest_version: i64,
+    pub pending: Vec<PendingMigration>,
+    // Result of applying the pending migrations in a rolled-back transaction
+    pub dry_run_error: Option<String>,
+    // Objects a freshly migrated database has that this one lacks (after the dry run)
+    pub missing_tables: Vec<String>,
+    pub missing_columns: Vec<String>,
+    pub ok: bool,
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+async fn ensure_version_table(conn: &mut SqliteConnection) -> Result<(), SqlxError> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        )"
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+async fn table_exists(conn: &mut SqliteConnection, table: &str) -> Result<bool, SqlxError> {
+    let row = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .bind(table)
+        .fetch_optional(&mut *conn)
+        .await?;
+    Ok(row.is_some())
+}
+
+async fn column_exists(conn: &mut SqliteConnection, table: &str, column: &str) -> Result<bool, SqlxError> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_one(&mut *conn)
+        .await?;
+    Ok(count > 0)
+}
+
+// Version recorded in schema_version. A database from before migrations (user table
+// but no version rows) counts as version 1; an empty database is version 0.
+async fn read_version(conn: &mut SqliteConnection) -> Result<i64, SqlxError> {
+    if table_exists(conn, "schema_version").await? {
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+            .fetch_one(&mut *conn)
+            .await?;
+        if let Some(version) = version {
+            return Ok(version);
+        }
+    }
+    if table_exists(conn, "user").await? {
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+pub async fn current_version(pool: &SqlitePool) -> Result<i64, SqlxError> {
+    let mut conn = pool.acquire().await?;
+    read_version(&mut conn).await
+}
+
+pub async fn pending_migrations(pool: &SqlitePool) -> Result<Vec<PendingMigration>, SqlxError> {
+    let current = current_version(pool).await?;
+    Ok(pending_after(current))
+}
+
+fn pending_after(version: i64) -> Vec<PendingMigration> {
+    MIGRATIONS.iter()
+        .filter(|m| m.version > version)
+        .map(|m| PendingMigration { version: m.version, name: m.name.to_string() })
+        .collect()
+}
+
+async fn apply_steps(conn: &mut SqliteConnection, migration: &Migration) -> Result<(), SqlxError> {
+    for step in migration.steps {
+        match step {
+            Step::Sql(sql) => {
+                sqlx::query(sql).execute(&mut *conn).await?;
+            }
+            Step::AddColumn { table, column, definition } => {
+                if column_exists(conn, table, column).await? {
+                    println!("[Migrations] {}.{} already exists, skipping", table, column);
+                    continue;
+                }
+                sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                    .execute(&mut *conn)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Apply pending migrations inside one transaction. With dry_run the transaction is
+// rolled back, which proves the migrations would apply without changing anything.
+async fn migrate(pool: &SqlitePool, dry_run: bool) -> Result<MigrationReport, SqlxError> {
+    let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+
+    let from_version = read_version(&mut tx).await?;
+    ensure_version_table(&mut tx).await?;
+
+    let now = Timestamp::now();
+    if from_version == 1 && !dry_run {
+        // Record the baseline for databases that predate migrations
+        sqlx::query("INSERT OR IGNORE INTO schema_version (version, name, applied_at) VALUES (1, ?, ?)")
+            .bind(MIGRATIONS[0].name)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let mut applied = Vec::new();
+    let mut to_version = from_version;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > from_version) {
+        println!("[Migrations] Applying migration {} ({}){}", migration.version, migration.name,
+                 if dry_run { " [dry run]" } else { "" });
+        apply_steps(&mut tx, migration).await?;
+        sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES (?, ?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        applied.push(PendingMigration { version: migration.version, name: migration.name.to_string() });
+        to_version = migration.version;
+    }
+
+    if dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+
+    Ok(MigrationReport {
+        from_version,
+        to_version,
+        latest_version: latest_version(),
+        applied,
+        dry_run,
+    })
+}
+
+pub async fn run_migrations(pool: &SqlitePool) -> Result<MigrationReport, SqlxError> {
+    let report = migrate(pool, false).await?;
+    if report.applied.is_empty() {
+        println!("[Migrations] Schema is up to date at version {}", report.to_version);
+    } else {
+        println!("[Migrations] Upgraded schema from version {} to {}", report.from_version, report.to_version);
+    }
+    Ok(report)
+}
+
+pub async fn dry_run_migrations(pool: &SqlitePool) -> Result<MigrationReport, SqlxError> {
+    migrate(pool, true).await
+}
+
+// table -> columns, for every ordinary table
+async fn schema_columns(conn: &mut SqliteConnection) -> Result<BTreeMap<String, Vec<String>>, SqlxError> {
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'"
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut columns = BTreeMap::new();
+    for table in tables {
+        let rows = sqlx::query("SELECT name FROM pragma_table_info(?)")
+            .bind(&table)
+            .fetch_all(&mut *conn)
+            .await?;
+        columns.insert(table, rows.iter().map(|row| row.get::<String, _>("name")).collect());
+    }
+    Ok(columns)
+}
+
+// Dry-run the pending migrations and compare the result with a fresh database
+// migrated to the latest version. Nothing in the database is changed.
+pub async fn verify_schema(pool: &SqlitePool) -> Result<SchemaVerification, SqlxError> {
+    let current = current_version(pool).await?;
+    let pending = pending_after(current);
+
+    let reference_pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await?;
+    migrate(&reference_pool, false).await?;
+    let expected = {
+        let mut conn = reference_pool.acquire().await?;
+        schema_columns(&mut conn).await?
+    };
+    reference_pool.close().await;
+
+    // Apply the pending migrations, inspect the result, then roll everything back
+    let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+    let mut dry_run_error = None;
+    ensure_version_table(&mut tx).await?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        if let Err(e) = apply_steps(&mut tx, migration).await {
+            dry_run_error = Some(format!("migration {} ({}) failed: {}", migration.version, migration.name, e));
+            break;
+        }
+    }
+    let actual = schema_columns(&mut tx).await?;
+    tx.rollback().await?;
+
+    let mut missing_tables = Vec::new();
+    let mut missing_columns = Vec::new();
+    for (table, columns) in &expected {
+        match actual.get(table) {
+            None => missing_tables.push(table.clone()),
+            Some(actual_columns) => {
+                for column in columns {
+                    if !actual_columns.contains(column) {
+                        missing_columns.push(format!("{}.{}", table, column));
+                    }
+                }
+            }
+        }
+    }
+
+    let ok = dry_run_error.is_none() && missing_tables.is_empty() && missing_columns.is_empty();
+    Ok(SchemaVerification {
+        current_version: current,
+        latest_version: latest_version(),
+        pending,
+        dry_run_error,
+        missing_tables,
+        missing_columns,
+        ok,
+    })
+}
@@ -0,0 +1,326 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use crate::database_async::{Chat, ImportResult, Message, Participant};
+use crate::repo::{ChatRepo, FriendRepo, MessageRepo, ParticipantRepo, SqliteRepo, UserRepo};
+use crate::timestamp::Timestamp;
+
+// Identifies our JSON archives; bump ARCHIVE_VERSION when the layout changes
+pub const ARCHIVE_FORMAT: &str = "terracrypt-chat-archive";
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    #[serde(alias = "md")]
+    Markdown,
+    Html,
+}
+
+// JSON export layout, read back by the importer
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatArchive {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: Timestamp,
+    pub chat: Chat,
+    pub participants: Vec<Participant>,
+    // Stored rows as-is, with sender_username filled in where it could be resolved
+    pub messages: Vec<Message>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ExportSummary {
+    pub path: String,
+    pub format: ExportFormat,
+    pub message_count: usize,
+    pub bytes: usize,
+}
+
+// ======== HELPERS ========
+
+fn format_timestamp(timestamp: Timestamp) -> String {
+    timestamp.to_datetime()
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn chat_title(chat: &Chat) -> String {
+    chat.group_name.clone()
+        .or_else(|| chat.name.clone())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| chat.chat_id.clone())
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn preview(content: &str) -> String {
+    let line = content.lines().next().unwrap_or("");
+    let mut short: String = line.chars().take(80).collect();
+    if short.len() < line.len() || content.lines().nth(1).is_some() {
+        short.push('…');
+    }
+    short
+}
+
+// Stored username, then chat participants, friends and finally local users; the id as last resort
+async fn resolve_usernames(repo: &SqliteRepo, messages: &mut [Message], participants: &[Participant]) {
+    let mut names: HashMap<String, String> = participants.iter()
+        .map(|p| (p.user_id.clone(), p.username.clone()))
+        .collect();
+
+    let unknown: HashSet<String> = messages.iter()
+        .filter(|m| m.sender_username.as_deref().unwrap_or("").is_empty())
+        .map(|m| m.sender_id.clone())
+        .filter(|id| !names.contains_key(id))
+        .collect();
+    for user_id in unknown {
+        if let Ok(Some(friend)) = repo.get_friend_by_id(&user_id).await {
+            names.insert(user_id, friend.username);
+        } else if let Ok(Some(user)) = repo.get_user_by_id(&user_id).await {
+            names.insert(user_id, user.username);
+        }
+    }
+
+    for message in messages.iter_mut() {
+        if message.sender_username.as_deref().unwrap_or("").is_empty() {
+            message.sender_username = Some(names.get(&message.sender_id).cloned().unwrap_or_else(|| message.sender_id.clone()));
+        }
+    }
+}
+
+fn sender_name(message: &Message) -> &str {
+    message.sender_username.as_deref().unwrap_or(&message.sender_id)
+}
+
+// reply_to_message_id may hold either the server or the client id of the parent
+fn index_by_id(messages: &[Message]) -> HashMap<&str, usize> {
+    let mut index = HashMap::new();
+    for (i, message) in messages.iter().enumerate() {
+        index.insert(message.client_message_id.as_str(), i);
+        if let Some(id) = message.message_id.as_deref() {
+            index.insert(id, i);
+        }
+    }
+    index
+}
+
+// ======== RENDERERS ========
+
+fn render_json(chat: &Chat, participants: &[Participant], messages: &[Message]) -> Result<String, String> {
+    let archive = ChatArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        exported_at: Timestamp::now(),
+        chat: chat.clone(),
+        participants: participants.to_vec(),
+        messages: messages.to_vec(),
+    };
+    serde_json::to_string_pretty(&archive).map_err(|e| format!("Failed to serialize archive: {}", e))
+}
+
+fn render_markdown(chat: &Chat, participants: &[Participant], messages: &[Message]) -> String {
+    let index = index_by_id(messages);
+    let mut out = format!("# {}\n\n", chat_title(chat));
+    out.push_str(&format!("- Chat ID: `{}`\n", chat.chat_id));
+    out.push_str(&format!("- Exported: {}\n", chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")));
+    out.push_str(&format!("- Messages: {}\n", messages.len()));
+    if !participants.is_empty() {
+        let list: Vec<String> = participants.iter().map(|p| format!("{} ({})", p.username, p.role)).collect();
+        out.push_str(&format!("- Participants: {}\n", list.join(", ")));
+    }
+    out.push_str("\n---\n");
+
+    for message in messages {
+        out.push_str(&format!("\n**{}** · {}\n\n", sender_name(message), format_timestamp(message.timestamp)));
+        if let Some(parent_id) = message.reply_to_message_id.as_deref() {
+            match index.get(parent_id).map(|&i| &messages[i]) {
+                Some(parent) => out.push_str(&format!("> ↪ Replying to **{}**: {}\n\n", sender_name(parent), preview(&parent.content))),
+                None => out.push_str("> ↪ Replying to a message that is not in this export\n\n"),
+            }
+        }
+        out.push_str(&message.content);
+        out.push('\n');
+    }
+    out
+}
+
+const HTML_STYLE: &str = "body{font-family:-apple-system,Segoe UI,Roboto,sans-serif;max-width:820px;margin:2em auto;padding:0 1em;color:#1f2933;background:#f7f9fb}\
+header{border-bottom:1px solid #d9e2ec;margin-bottom:1.5em}h1{margin-bottom:.2em}.meta{color:#627d98;font-size:.9em}\
+.msg{background:#fff;border:1px solid #d9e2ec;border-radius:8px;padding:.6em .9em;margin:.6em 0}\
+.msg .head{font-size:.85em;color:#627d98;margin-bottom:.3em}.msg .head b{color:#102a43}\
+.msg .body{white-space:pre-wrap;word-wrap:break-word}.replies{margin-left:1.2em;padding-left:.8em;border-left:2px solid #bcccdc}\
+.parent{font-size:.85em;color:#829ab1;margin-bottom:.3em}.parent a{color:inherit}";
+
+fn render_html_message(out: &mut String, message: &Message, parent: Option<&Message>, orphan_reply: bool) {
+    out.push_str(&format!("<div class=\"msg\" id=\"msg-{}\">", escape_html(&message.client_message_id)));
+    out.push_str(&format!(
+        "<div class=\"head\"><b>{}</b> · {}</div>",
+        escape_html(sender_name(message)),
+        escape_html(&format_timestamp(message.timestamp))
+    ));
+    if let Some(parent) = parent {
+        out.push_str(&format!(
+            "<div class=\"parent\">↪ <a href=\"#msg-{}\">{}: {}</a></div>",
+            escape_html(&parent.client_message_id),
+            escape_html(sender_name(parent)),
+            escape_html(&preview(&parent.content))
+        ));
+    } else if orphan_reply {
+        out.push_str("<div class=\"parent\">↪ Reply to a message that is not in this export</div>");
+    }
+    out.push_str(&format!("<div class=\"body\">{}</div>", escape_html(&message.content)));
+}
+
+// Replies are nested under their parent. Walks the tree with an explicit stack so long
+// reply chains cannot overflow, and renders anything left unvisited (cycles) at top level.
+fn render_html(chat: &Chat, participants: &[Participant], messages: &[Message]) -> String {
+    let index = index_by_id(messages);
+    let parents: Vec<Option<usize>> = messages.iter().enumerate()
+        .map(|(i, m)| m.reply_to_message_id.as_deref().and_then(|id| index.get(id).copied()).filter(|&p| p != i))
+        .collect();
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); messages.len()];
+    for (i, parent) in parents.iter().enumerate() {
+        if let Some(p) = parent {
+            children[*p].push(i);
+        }
+    }
+
+    let title = escape_html(&chat_title(chat));
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<header>\n<h1>{}</h1>\n",
+        title, HTML_STYLE, title
+    );
+    out.push_str(&format!(
+        "<p class=\"meta\">Chat ID {} · Exported {} · {} messages</p>\n",
+        escape_html(&chat.chat_id),
+        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+        messages.len()
+    ));
+    if !participants.is_empty() {
+        let list: Vec<String> = participants.iter()
+            .map(|p| format!("{} ({})", escape_html(&p.username), escape_html(&p.role)))
+            .collect();
+        out.push_str(&format!("<p class=\"meta\">Participants: {}</p>\n", list.join(", ")));
+    }
+    out.push_str("</header>\n<main>\n");
+
+    enum Step { Open(usize), Close }
+    let mut visited = vec![false; messages.len()];
+    let roots: Vec<usize> = (0..messages.len()).filter(|&i| parents[i].is_none()).collect();
+    let leftovers = (0..messages.len()).collect::<Vec<_>>();
+    for root in roots.into_iter().chain(leftovers) {
+        if visited[root] {
+            continue;
+        }
+        let mut stack = vec![Step::Open(root)];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Open(i) => {
+                    if visited[i] {
+                        continue;
+                    }
+                    visited[i] = true;
+                    let message = &messages[i];
+                    let orphan_reply = message.reply_to_message_id.is_some() && parents[i].is_none();
+                    render_html_message(&mut out, message, parents[i].map(|p| &messages[p]), orphan_reply);
+                    let pending: Vec<usize> = children[i].iter().copied().filter(|&c| !visited[c]).collect();
+                    if pending.is_empty() {
+                        out.push_str("</div>\n");
+                    } else {
+                        out.push_str("\n<div class=\"replies\">\n");
+                        stack.push(Step::Close);
+                        stack.extend(pending.into_iter().rev().map(Step::Open));
+                    }
+                }
+                Step::Close => out.push_str("</div>\n</div>\n"),
+            }
+        }
+    }
+
+    out.push_str("</main>\n</body>\n</html>\n");
+    out
+}
+
+// ======== COMMANDS ========
+
+// Write a chat's history to `path` as a JSON archive, a Markdown transcript or a single HTML page
+#[tauri::command]
+pub async fn export_chat(repo: State<'_, SqliteRepo>, chat_id: String, format: ExportFormat, path: String) -> Result<ExportSummary, String> {
+    println!("[Archive] Exporting chat {} as {:?} to {}", chat_id, format, path);
+
+    let chat = repo.get_chat_by_id(&chat_id).await
+        .map_err(|e| format!("Failed to load chat: {}", e))?
+        .ok_or_else(|| format!("Chat not found: {}", chat_id))?;
+    let participants = repo.get_participants_for_chat(&chat_id).await
+        .map_err(|e| format!("Failed to load participants: {}", e))?;
+    let mut messages = repo.get_messages_for_chat(&chat_id).await
+        .map_err(|e| format!("Failed to load messages: {}", e))?;
+
+    messages.sort_by_key(|m| (m.timestamp, m.id));
+    resolve_usernames(&repo, &mut messages, &participants).await;
+
+    let contents = match format {
+        ExportFormat::Json => render_json(&chat, &participants, &messages)?,
+        ExportFormat::Markdown => render_markdown(&chat, &participants, &messages),
+        ExportFormat::Html => render_html(&chat, &participants, &messages),
+    };
+
+    if let Some(parent) = Path::new(&path).parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create export directory: {}", e))?;
+    }
+    std::fs::write(&path, &contents).map_err(|e| format!("Failed to write export: {}", e))?;
+
+    println!("[Archive] Exported {} messages ({} bytes)", messages.len(), contents.len());
+    Ok(ExportSummary {
+        path,
+        format,
+        message_count: messages.len(),
+        bytes: contents.len(),
+    })
+}
+
+// Load a JSON archive written by export_chat and merge it into the local database
+#[tauri::command]
+pub async fn import_chat_archive(app: AppHandle, repo: State<'_, SqliteRepo>, path: String) -> Result<ImportResult, String> {
+    println!("[Archive] Importing chat archive from {}", path);
+
+    let contents = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read archive: {}", e))?;
+    let value: serde_json::Value = serde_json::from_str(&contents)
+        .map_err(|e| format!("Archive is not valid JSON: {}", e))?;
+
+    // Check the header first so a newer archive gets a clear error instead of a parse failure
+    if value.get("format").and_then(|f| f.as_str()) != Some(ARCHIVE_FORMAT) {
+        return Err("Not a chat archive".to_string());
+    }
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version == 0 || version > ARCHIVE_VERSION as u64 {
+        return Err(format!("Unsupported archive version {} (supported: 1 to {})", version, ARCHIVE_VERSION));
+    }
+
+    let archive: ChatArchive = serde_json::from_value(value)
+        .map_err(|e| format!("Malformed archive: {}", e))?;
+    let result = repo.import_chat_archive(&archive.chat, &archive.participants, &archive.messages).await
+        .map_err(|e| format!("Failed to import archive: {}", e))?;
+
+    for conflict in &result.conflicts {
+        println!("[Archive] Conflict on {} {}: {}", conflict.kind, conflict.id, conflict.reason);
+    }
+    app.emit("chat-imported", &result).ok();
+    Ok(result)
+}
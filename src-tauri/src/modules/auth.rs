@@ -3,7 +3,7 @@ use std::sync::Mutex;
 use serde::{Deserialize, Serialize};
 use reqwest;
 use tauri::State;
-use crate::database_async;
+use crate::repo::SqliteRepo;
 
 #[derive(Debug, Serialize)]
 struct LoginRequest {
@@ -141,29 +141,32 @@ pub async fn register(username: String, email: String, password: String) -> Resu
 
 #[tauri::command]
 pub async fn save_secure_token(
+    repo: State<'_, SqliteRepo>,
     key: String,
     value: String,
 ) -> Result<(), String> {
     // Store token in secure database instead of memory
-    database_async::save_secure_token(&key, &value).await
+    repo.save_secure_token(&key, &value).await
         .map_err(|e| format!("Failed to save token: {e}"))
 }
 
 #[tauri::command]
 pub async fn load_secure_token(
+    repo: State<'_, SqliteRepo>,
     key: String,
 ) -> Result<Option<String>, String> {
     // Load token from secure database
-    database_async::load_secure_token(&key).await
+    repo.load_secure_token(&key).await
         .map_err(|e| format!("Failed to load token: {e}"))
 }
 
 #[tauri::command]
 pub async fn clear_secure_token(
+    repo: State<'_, SqliteRepo>,
     key: String,
 ) -> Result<(), String> {
     // Clear token from secure database
-    database_async::clear_secure_token(&key).await
+    repo.clear_secure_token(&key).await
         .map_err(|e| format!("Failed to clear token: {e}"))
 }
 
@@ -1,13 +1,15 @@
 use reqwest;
 use serde_json;
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 use std::collections::HashMap;
 use std::sync::Mutex;
 use std::cmp::min;
 use crate::database_async::{self as db_async};
-use crate::modules::participant::sync_participants_with_api_token;
+use crate::message_status::MessageStatus;
+use crate::repo::{ChatRepo, MessageRepo, ParticipantRepo, SqliteRepo, UserRepo};
+use crate::timestamp::Timestamp;
+use crate::modules::participant::sync_participants_for_chat;
 use crate::modules::auth::login;
-use chrono;
 
 // ======== CHAT STRUCTURES ========
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -22,10 +24,11 @@ pub struct Message {
     pub chat_id: String,
     pub sender_id: String,
     pub content: String,
-    pub timestamp: i64,
+    pub timestamp: Timestamp,
     pub is_read: bool,
     pub is_sent: bool,
     pub is_delivered: bool,
+    pub status: MessageStatus,
     pub sender_username: Option<String>,
     pub reply_to_message_id: Option<String>,
 }
@@ -34,11 +37,19 @@ pub struct Message {
 pub struct Chat {
     pub chat_id: String,
     pub name: Option<String>, // Changed from String to Option<String> to match database
-    pub created_at: i64,
+    pub created_at: Timestamp,
     pub creator_id: String,
     pub is_group: bool,
     pub participants: Vec<String>,
     pub unread_count: i32,
+    // Maintained locally from cached messages; None for chats straight from the server
+    #[serde(default)]
+    pub last_message_content: Option<String>,
+    #[serde(default)]
+    pub last_message_timestamp: Option<Timestamp>,
+    // Imported chats the server no longer has can be read but not sent to
+    #[serde(default)]
+    pub is_read_only: bool,
 }
 
 #[derive(serde::Deserialize)]
@@ -66,7 +77,7 @@ pub struct SendMessageRequest {
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct SendMessageResponse {
     pub message_id: String,
-    pub timestamp: i64,
+    pub timestamp: Timestamp,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -75,11 +86,16 @@ pub struct ChatMessage {
     pub chat_id: String,
     pub sender_id: String,
     pub content: String,
-    pub timestamp: i64,
+    pub timestamp: Timestamp,
     pub sender_username: String,
     pub reply_to_message_id: Option<String>,
 }
 
+#[derive(serde::Deserialize)]
+struct MessagesResponse {
+    data: Vec<ChatMessage>,
+}
+
 // ======== CHAT COMMANDS ========
 #[derive(serde::Deserialize)]
 struct CreateChatResponse {
@@ -91,16 +107,16 @@ struct CurrentUserResponse {
     pub user_id: String,
 }
 
-async fn get_current_user_id_from_token(token: &str) -> Result<String, String> {
+async fn get_current_user_id_from_token(repo: &SqliteRepo, token: &str) -> Result<String, String> {
     // First try to get user by token from database
-    match db_async::get_user_by_token(token).await {
+    match repo.get_user_by_token(token).await {
         Ok(Some(user)) => {
             println!("Found current user in database: {}", user.user_id);
             Ok(user.user_id)
         },
         Ok(None) => {
             // If not found in database, try to get most recent user
-            match db_async::get_most_recent_user().await {
+            match repo.get_most_recent_user().await {
                 Ok(Some(user)) => {
                     println!("Found most recent user in database: {}", user.user_id);
                     Ok(user.user_id)
@@ -137,6 +153,7 @@ async fn get_current_user_id_from_token(token: &str) -> Result<String, String> {
 
 #[tauri::command]
 pub async fn create_chat(
+    repo: State<'_, SqliteRepo>,
     token: String,
     name: String,
     members: Vec<ParticipantSimple>,
@@ -145,7 +162,7 @@ pub async fn create_chat(
     println!("Members count: {}", members.len());
     
     // First, get the current user ID from the token
-    let current_user_id = match get_current_user_id_from_token(&token).await {
+    let current_user_id = match get_current_user_id_from_token(&repo, &token).await {
         Ok(user_id) => user_id,
         Err(e) => {
             println!("Failed to get current user ID from token: {}", e);
@@ -317,7 +334,7 @@ pub async fn create_chat(
         println!("Successfully created chat with ID: {}", response.chat_id);
         
         // Save the newly created chat to local database
-        let current_timestamp = chrono::Utc::now().timestamp();
+        let current_timestamp = Timestamp::now();
         
         // FIXED: For 1-on-1 chats, determine the proper chat name from other participant
         let mut chat_name = if is_group { Some(name.clone()) } else { None };
@@ -330,7 +347,7 @@ pub async fn create_chat(
             
             if let Some(other_id) = other_participant_id {
                 // Try to get the other participant's user info from database
-                if let Ok(other_user) = db_async::get_user_by_id(other_id).await {
+                if let Ok(other_user) = repo.get_user_by_id(other_id).await {
                     if let Some(other_user) = other_user {
                         chat_name = Some(other_user.username.clone());
                         println!("1-on-1 chat name set to other participant username: {}", other_user.username);
@@ -359,10 +376,12 @@ pub async fn create_chat(
             description: None,
             last_message_content: None,
             last_message_timestamp: None,
+            is_local_only: false,
+            is_read_only: false,
         };
         
         // Insert the chat into local database
-        if let Err(e) = db_async::insert_or_update_chat(&db_chat).await {
+        if let Err(e) = repo.insert_or_update_chat(&db_chat).await {
             println!("Warning: Failed to save chat to local database: {}", e);
             // Don't fail the entire operation if local save fails
         } else {
@@ -375,12 +394,13 @@ pub async fn create_chat(
             let is_admin = member["is_admin"].as_bool().unwrap_or(false);
             
             if let Ok(participant) = crate::modules::participant::create_participant_from_user_id(
+                repo.clone(),
                 user_id.to_string(),
                 response.chat_id.clone(),
                 is_admin,
                 current_timestamp
             ).await {
-                if let Err(e) = db_async::insert_or_update_participant(&participant).await {
+                if let Err(e) = repo.insert_or_update_participant(&participant).await {
                     println!("Warning: Failed to save participant {}: {}", user_id, e);
                 }
             }
@@ -451,7 +471,7 @@ pub async fn save_message(
 }
 
 #[tauri::command]
-pub async fn get_chats(state: State<'_, Mutex<HashMap<String, String>>>) -> Result<Vec<Chat>, String> {
+pub async fn get_chats(state: State<'_, Mutex<HashMap<String, String>>>, repo: State<'_, SqliteRepo>) -> Result<Vec<Chat>, String> {
     let token = {
         let store = state.lock().unwrap();
         store.get("access_token")
@@ -459,11 +479,11 @@ pub async fn get_chats(state: State<'_, Mutex<HashMap<String, String>>>) -> Resu
             .clone()
     };
     
-    get_chats_with_token(token).await
+    get_chats_with_token(repo, token).await
 }
 
 #[tauri::command]
-pub async fn get_chats_with_token(token: String) -> Result<Vec<Chat>, String> {
+pub async fn get_chats_with_token(repo: State<'_, SqliteRepo>, token: String) -> Result<Vec<Chat>, String> {
     println!("Getting chats with token");
     
     let client = reqwest::Client::new();
@@ -507,13 +527,17 @@ pub async fn get_chats_with_token(token: String) -> Result<Vec<Chat>, String> {
                 let chat = Chat {
                     chat_id: api_chat.chat_id,
                     name: api_chat.name, // Now this is Option<String> which matches the struct
-                    created_at: api_chat.created_at
-                        .and_then(|s| s.parse::<i64>().ok())
-                        .unwrap_or_else(|| chrono::Utc::now().timestamp()),
+                    created_at: api_chat.created_at.as_deref()
+                        .and_then(|s| Timestamp::parse_rfc3339(s).ok()
+                            .or_else(|| s.parse::<i64>().ok().map(Timestamp::from_any)))
+                        .unwrap_or_else(Timestamp::now),
                     creator_id: api_chat.creator_id.unwrap_or_else(|| "unknown".to_string()),
                     is_group: api_chat.is_group.unwrap_or(false),
                     participants: api_chat.participants.unwrap_or_default(),
                     unread_count: 0,
+                    last_message_content: None,
+                    last_message_timestamp: None,
+                    is_read_only: false,
                 };
                 chats.push(chat);
             }
@@ -524,7 +548,7 @@ pub async fn get_chats_with_token(token: String) -> Result<Vec<Chat>, String> {
     } else if status.as_u16() == 401 {
         println!("Token expired (401 Unauthorized), attempting silent relogin...");
         // Try silent relogin and retry the request
-        match attempt_silent_relogin_and_retry_chats().await {
+        match attempt_silent_relogin_and_retry_chats(&repo).await {
             Ok(chats) => {
                 println!("Silent relogin successful, retrieved {} chats", chats.len());
                 Ok(chats)
@@ -543,6 +567,7 @@ pub async fn get_chats_with_token(token: String) -> Result<Vec<Chat>, String> {
 #[tauri::command]
 pub async fn send_message(
     state: State<'_, Mutex<HashMap<String, String>>>,
+    repo: State<'_, SqliteRepo>,
     content: String,
     chat_id: String,
     reply_to_message_id: Option<String>,
@@ -554,6 +579,12 @@ pub async fn send_message(
             .clone()
     };
     
+    if let Ok(Some(chat)) = repo.get_chat_by_id(&chat_id).await {
+        if chat.is_read_only {
+            return Err(format!("Chat {} is read-only", chat_id));
+        }
+    }
+    
     println!("Sending message to chat: {}", chat_id);
     println!("Message content: {}", content);
     
@@ -583,7 +614,7 @@ pub async fn send_message(
         #[derive(serde::Deserialize)]
         struct BackendMessageResponse {
             message_id: String,
-            timestamp: i64,
+            timestamp: Timestamp,
         }
         
         let backend_response: BackendMessageResponse = serde_json::from_str(&text)
@@ -621,11 +652,6 @@ pub async fn get_messages(token: String, chat_id: String) -> Result<Vec<ChatMess
     println!("Get messages response body: {}", text);
 
     if status.is_success() {
-        #[derive(serde::Deserialize)]
-        struct MessagesResponse {
-            data: Vec<ChatMessage>,
-        }
-        
         let messages_response: MessagesResponse = serde_json::from_str(&text)
             .map_err(|e| format!("Invalid JSON response: {e}"))?;
         
@@ -638,7 +664,7 @@ pub async fn get_messages(token: String, chat_id: String) -> Result<Vec<ChatMess
 }
 
 #[tauri::command]
-pub async fn get_cached_chats_with_delta(state: State<'_, Mutex<HashMap<String, String>>>) -> Result<Vec<Chat>, String> {
+pub async fn get_cached_chats_with_delta(state: State<'_, Mutex<HashMap<String, String>>>, repo: State<'_, SqliteRepo>) -> Result<Vec<Chat>, String> {
     let token = {
         let store = state.lock().unwrap();
         store.get("access_token")
@@ -649,10 +675,10 @@ pub async fn get_cached_chats_with_delta(state: State<'_, Mutex<HashMap<String,
     println!("Getting cached chats with delta update");
     
     // First get cached chats
-    let cached_chats = get_cached_chats_only().await?;
+    let cached_chats = get_cached_chats_only(repo.clone()).await?;
     
     // Then try to fetch fresh data
-    match get_chats_with_token(token).await {
+    match get_chats_with_token(repo, token).await {
         Ok(fresh_chats) => {
             println!("Successfully updated chats from server");
             Ok(fresh_chats)
@@ -665,10 +691,10 @@ pub async fn get_cached_chats_with_delta(state: State<'_, Mutex<HashMap<String,
 }
 
 #[tauri::command]
-pub async fn get_cached_chats_only() -> Result<Vec<Chat>, String> {
+pub async fn get_cached_chats_only(repo: State<'_, SqliteRepo>) -> Result<Vec<Chat>, String> {
     println!("Getting cached chats only");
     
-    let chats = db_async::get_all_chats().await
+    let chats = repo.get_all_chats().await
         .map_err(|e| format!("Database error: {e}"))?;
     
     let converted_chats: Vec<Chat> = chats.into_iter().map(|db_chat| Chat {
@@ -682,6 +708,9 @@ pub async fn get_cached_chats_only() -> Result<Vec<Chat>, String> {
             .and_then(|p| serde_json::from_str::<Vec<String>>(p).ok())
             .unwrap_or_default(),
         unread_count: db_chat.unread_count,
+        last_message_content: db_chat.last_message_content,
+        last_message_timestamp: db_chat.last_message_timestamp,
+        is_read_only: db_chat.is_read_only,
     }).collect();
     
     println!("Retrieved {} cached chats", converted_chats.len());
@@ -689,17 +718,17 @@ pub async fn get_cached_chats_only() -> Result<Vec<Chat>, String> {
 }
 
 /// Get cached chats only, filtering out locally deleted ones for a specific user
-pub async fn get_cached_chats_only_for_user(user_id: &str) -> Result<Vec<Chat>, String> {
+pub async fn get_cached_chats_only_for_user(repo: &SqliteRepo, user_id: &str) -> Result<Vec<Chat>, String> {
     println!("Getting cached chats only for user: {}", user_id);
     
-    let chats = db_async::get_all_chats().await
+    let chats = repo.get_all_chats().await
         .map_err(|e| format!("Database error: {e}"))?;
     
     let mut filtered_chats = Vec::new();
     
     for db_chat in chats {
         // Check if this chat is locally deleted for this user
-        let is_locally_deleted = db_async::is_chat_locally_deleted(&db_chat.chat_id, user_id).await
+        let is_locally_deleted = repo.is_chat_locally_deleted(&db_chat.chat_id, user_id).await
             .unwrap_or(false);
         
         if !is_locally_deleted {
@@ -714,6 +743,9 @@ pub async fn get_cached_chats_only_for_user(user_id: &str) -> Result<Vec<Chat>,
                     .and_then(|p| serde_json::from_str::<Vec<String>>(p).ok())
                     .unwrap_or_default(),
                 unread_count: db_chat.unread_count,
+                last_message_content: db_chat.last_message_content,
+                last_message_timestamp: db_chat.last_message_timestamp,
+                is_read_only: db_chat.is_read_only,
             };
             filtered_chats.push(chat);
         } else {
@@ -726,10 +758,10 @@ pub async fn get_cached_chats_only_for_user(user_id: &str) -> Result<Vec<Chat>,
 }
 
 #[tauri::command]
-pub async fn get_cached_messages_for_chat(chat_id: String) -> Result<Vec<Message>, String> {
+pub async fn get_cached_messages_for_chat(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<Vec<Message>, String> {
     println!("Getting cached messages for chat: {}", chat_id);
     
-    let messages = db_async::get_messages_for_chat(&chat_id).await
+    let messages = repo.get_messages_for_chat(&chat_id).await
         .map_err(|e| format!("Database error: {e}"))?;
     
     let converted_messages: Vec<Message> = messages.into_iter().map(|db_message| Message {
@@ -738,9 +770,10 @@ pub async fn get_cached_messages_for_chat(chat_id: String) -> Result<Vec<Message
         sender_id: db_message.sender_id,
         content: db_message.content,
         timestamp: db_message.timestamp,
-        is_read: db_message.is_read,
-        is_sent: db_message.is_sent,
-        is_delivered: db_message.is_delivered,
+        is_read: db_message.status.is_read(),
+        is_sent: db_message.status.is_sent(),
+        is_delivered: db_message.status.is_delivered(),
+        status: db_message.status,
         sender_username: db_message.sender_username,
         reply_to_message_id: db_message.reply_to_message_id,
     }).collect();
@@ -749,11 +782,147 @@ pub async fn get_cached_messages_for_chat(chat_id: String) -> Result<Vec<Message
     Ok(converted_messages)
 }
 
+// Full-text search over cached messages, best matches first
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn search_messages(
+    repo: State<'_, SqliteRepo>,
+    query: String,
+    chat_id: Option<String>,
+    sender_id: Option<String>,
+    from: Option<Timestamp>,
+    to: Option<Timestamp>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+) -> Result<db_async::MessageSearchPage, String> {
+    println!("Searching messages: {}", query);
+    
+    let filter = db_async::MessageSearchFilter { chat_id, sender_id, from, to };
+    let limit = limit.unwrap_or(20).clamp(1, 100);
+    
+    repo.search_messages(&query, &filter, limit, cursor.as_deref()).await
+        .map_err(|e| format!("Database error: {e}"))
+}
+
+// One page of a chat's history. Older pages that run short of local history are
+// topped up from the server when a token is given, so callers never see the seam.
 #[tauri::command]
-pub async fn fetch_all_chats_and_save(token: String) -> Result<Vec<Chat>, String> {
+pub async fn get_message_page(
+    repo: State<'_, SqliteRepo>,
+    chat_id: String,
+    cursor: Option<String>,
+    direction: Option<db_async::PageDirection>,
+    limit: Option<i64>,
+    token: Option<String>,
+) -> Result<db_async::MessagePage, String> {
+    let direction = direction.unwrap_or(db_async::PageDirection::Older);
+    let limit = limit.unwrap_or(50).clamp(1, 200);
+
+    let page = repo.get_message_page(&chat_id, cursor.as_deref(), direction, limit).await
+        .map_err(|e| format!("Database error: {e}"))?;
+
+    let token = match token {
+        Some(token) if direction == db_async::PageDirection::Older && !page.has_more => token,
+        _ => return Ok(page),
+    };
+
+    if page.messages.len() as i64 == limit {
+        // Local history ends exactly here; the server may still have more
+        return Ok(db_async::MessagePage { has_more: true, ..page });
+    }
+
+    // Anchor the remote fetch on the oldest message the server knows about; an unsent
+    // outbox row would leave `before` empty and re-fetch the newest page forever
+    let before = repo.get_oldest_server_message_id(&chat_id).await
+        .map_err(|e| format!("Database error: {e}"))?;
+
+    let fetched = match fetch_remote_history(&repo, &token, &chat_id, before.as_deref(), limit).await {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            println!("[Chat] Remote history fetch failed, serving local page: {}", e);
+            return Ok(page);
+        }
+    };
+
+    let mut page = repo.get_message_page(&chat_id, cursor.as_deref(), direction, limit).await
+        .map_err(|e| format!("Database error: {e}"))?;
+    page.has_more = page.has_more || fetched as i64 == limit;
+    Ok(page)
+}
+
+// Fetch up to `limit` messages older than `before` and cache them; returns how many came back
+async fn fetch_remote_history(
+    repo: &SqliteRepo,
+    token: &str,
+    chat_id: &str,
+    before: Option<&str>,
+    limit: i64,
+) -> Result<usize, String> {
+    let mut query = vec![("limit", limit.to_string())];
+    if let Some(before) = before {
+        query.push(("before", before.to_string()));
+    }
+
+    let client = reqwest::Client::new();
+    let res = client
+        .get(format!("https://dev.v1.terracrypt.cc/api/v1/chats/{}/messages", chat_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .query(&query)
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {e}"))?;
+
+    let status = res.status();
+    let text = res.text().await.unwrap_or_else(|_| "<no body>".into());
+    if !status.is_success() {
+        return Err(format!("Failed to get messages: {} - {}", status, text));
+    }
+
+    let messages_response: MessagesResponse = serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid JSON response: {e}"))?;
+    let own_user_id = get_current_user_id_from_token(repo, token).await.ok();
+
+    for message in &messages_response.data {
+        // Backfilled history should not inflate unread counts, but our own messages
+        // must not claim the recipient read them
+        let status = if own_user_id.as_deref() == Some(message.sender_id.as_str()) {
+            MessageStatus::Delivered
+        } else {
+            MessageStatus::Read
+        };
+        let db_message = db_async::Message {
+            id: None,
+            message_id: Some(message.message_id.clone()),
+            client_message_id: message.message_id.clone(),
+            chat_id: chat_id.to_string(),
+            sender_id: message.sender_id.clone(),
+            content: message.content.clone(),
+            timestamp: message.timestamp,
+            status,
+            sender_username: Some(message.sender_username.clone()),
+            reply_to_message_id: message.reply_to_message_id.clone(),
+        };
+        repo.reconcile_incoming_message(&db_message).await
+            .map_err(|e| format!("Database error: {e}"))?;
+    }
+
+    println!("[Chat] Cached {} older messages for chat {}", messages_response.data.len(), chat_id);
+    Ok(messages_response.data.len())
+}
+
+// Who has received and read a message we sent: "read by N of M" plus the readers
+#[tauri::command]
+pub async fn get_message_read_state(repo: State<'_, SqliteRepo>, message_id: String) -> Result<db_async::MessageReadState, String> {
+    repo.get_message_read_state(&message_id).await
+        .map_err(|e| format!("Database error: {e}"))?
+        .ok_or_else(|| format!("Message not found: {}", message_id))
+}
+
+#[tauri::command]
+pub async fn fetch_all_chats_and_save(repo: State<'_, SqliteRepo>, token: String) -> Result<Vec<Chat>, String> {
     println!("Fetching all chats and saving to database");
     
-    let chats = get_chats_with_token(token.clone()).await?;
+    let chats = get_chats_with_token(repo.clone(), token.clone()).await?;
     
     for chat in &chats {
         // Save chat to database
@@ -769,20 +938,22 @@ pub async fn fetch_all_chats_and_save(token: String) -> Result<Vec<Chat>, String
             description: None,
             last_message_content: None,
             last_message_timestamp: None,
+            is_local_only: false,
+            is_read_only: false,
         };
         
-        if let Err(e) = db_async::insert_or_update_chat(&db_chat).await {
+        if let Err(e) = repo.insert_or_update_chat(&db_chat).await {
             println!("Failed to save chat {}: {}", chat.chat_id, e);
         }
         
         // Fetch and save participants for this chat
-        match sync_participants_with_api_token(token.clone(), chat.chat_id.clone()).await {
+        match sync_participants_for_chat(&repo, token.clone(), chat.chat_id.clone()).await {
             Ok(_) => {
                 println!("Successfully synced participants for chat {}", chat.chat_id);
                 
                 // Generate and save chat name based on participants
                 // This is especially important for 1-on-1 chats that might not have names from server
-                if let Err(e) = generate_and_save_chat_name(token.clone(), chat.chat_id.clone()).await {
+                if let Err(e) = generate_and_save_chat_name(repo.clone(), token.clone(), chat.chat_id.clone()).await {
                     println!("Failed to generate chat name for chat {}: {}", chat.chat_id, e);
                 }
             }
@@ -797,186 +968,82 @@ pub async fn fetch_all_chats_and_save(token: String) -> Result<Vec<Chat>, String
 }
 
 #[tauri::command]
-pub async fn chats_delta_update(token: String) -> Result<Vec<Chat>, String> {
+pub async fn chats_delta_update(app: AppHandle, repo: State<'_, SqliteRepo>, token: String) -> Result<db_async::DeltaResult, String> {
     println!("Performing chats delta update for current user");
     
     // Get current user ID from token to check local deletes
-    let current_user_id = get_current_user_id_from_token(&token).await?;
-    
-    // Get current cached chats
-    let cached_chats = get_cached_chats_only().await?;
-    let cached_chat_ids: std::collections::HashSet<String> = cached_chats.iter()
-        .map(|chat| chat.chat_id.clone())
-        .collect();
-    
-    println!("Found {} cached chats", cached_chat_ids.len());
+    let current_user_id = get_current_user_id_from_token(&repo, &token).await?;
     
     // Fetch fresh chats from server
-    let server_chats = get_chats_with_token(token.clone()).await?;
-    let server_chat_ids: std::collections::HashSet<String> = server_chats.iter()
-        .map(|chat| chat.chat_id.clone())
-        .collect();
-    
-    println!("Found {} server chats", server_chat_ids.len());
-    
-    // IMPORTANT: Filter out locally deleted chats from server response
-    // This prevents re-adding chats that were just deleted locally
-    let mut filtered_server_chats = Vec::new();
-    for server_chat in &server_chats {
-        let is_locally_deleted = db_async::is_chat_locally_deleted(&server_chat.chat_id, &current_user_id).await
-            .unwrap_or(false);
-        
-        if !is_locally_deleted {
-            filtered_server_chats.push(server_chat.clone());
-        } else {
-            println!("Skipping locally deleted chat: {}", server_chat.chat_id);
-        }
-    }
-    
-    let filtered_server_chat_ids: std::collections::HashSet<String> = filtered_server_chats.iter()
-        .map(|chat| chat.chat_id.clone())
-        .collect();
-    
-    println!("After filtering locally deleted: {} server chats", filtered_server_chat_ids.len());
-    
-    // Find chats that exist on server but not locally (new chats)
-    let new_chat_ids: Vec<String> = filtered_server_chat_ids.difference(&cached_chat_ids)
-        .cloned()
-        .collect();
-    
-    // Find chats that exist locally but not on server (deleted chats)
-    let deleted_chat_ids: Vec<String> = cached_chat_ids.difference(&filtered_server_chat_ids)
-        .cloned()
-        .collect();
-    
-    println!("New chats to add: {:?}", new_chat_ids);
-    println!("Chats to delete: {:?}", deleted_chat_ids);
+    let server_chats = get_chats_with_token(repo.clone(), token.clone()).await?;
+    println!("Found {} server chats", server_chats.len());
+    
+    let db_chats: Vec<db_async::Chat> = server_chats.iter().map(|server_chat| db_async::Chat {
+        chat_id: server_chat.chat_id.clone(),
+        name: server_chat.name.clone(), // Keep the server-provided name if available
+        created_at: server_chat.created_at,
+        creator_id: Some(server_chat.creator_id.clone()),
+        is_group: server_chat.is_group,
+        participants: Some(serde_json::to_string(&server_chat.participants).unwrap_or_default()),
+        unread_count: server_chat.unread_count,
+        group_name: if server_chat.is_group { server_chat.name.clone() } else { None }, // Set group_name for group chats
+        description: None,
+        last_message_content: None,
+        last_message_timestamp: None,
+        is_local_only: false,
+        is_read_only: false,
+    }).collect();
     
-    // Delete chats that no longer exist on server
-    for chat_id in &deleted_chat_ids {
-        println!("Removing deleted chat from database: {}", chat_id);
-        
-        // Clear messages for this chat
-        if let Err(e) = db_async::clear_messages_for_chat(chat_id).await {
-            println!("Failed to clear messages for chat {}: {}", chat_id, e);
-        }
-        
-        // Remove all participants for this chat
-        if let Err(e) = db_async::remove_all_participants_for_chat(chat_id).await {
-            println!("Failed to remove participants for chat {}: {}", chat_id, e);
-        }
-        
-        // Delete the chat itself
-        if let Err(e) = db_async::delete_chat(chat_id).await {
-            println!("Failed to delete chat {}: {}", chat_id, e);
-        }
-    }
+    // Adds, updates, removals and local delete cleanup all land in one transaction
+    let delta = repo.apply_chat_delta(&db_chats, &current_user_id).await
+        .map_err(|e| format!("Database error: {e}"))?;
     
-    // Add new chats from server (ONLY if they don't exist locally and weren't locally deleted)
-    for server_chat in &filtered_server_chats {
-        if new_chat_ids.contains(&server_chat.chat_id) {
-            println!("Adding new chat to database: {}", server_chat.chat_id);
-            
-            // Convert to database format and insert
-            let db_chat = db_async::Chat {
-                chat_id: server_chat.chat_id.clone(),
-                name: server_chat.name.clone(), // Keep the server-provided name if available
-                created_at: server_chat.created_at,
-                creator_id: Some(server_chat.creator_id.clone()),
-                is_group: server_chat.is_group,
-                participants: Some(serde_json::to_string(&server_chat.participants).unwrap_or_default()),
-                unread_count: server_chat.unread_count,
-                group_name: if server_chat.is_group { server_chat.name.clone() } else { None }, // Set group_name for group chats
-                description: None,
-                last_message_content: None,
-                last_message_timestamp: None,
-            };
-            
-            if let Err(e) = db_async::insert_or_update_chat(&db_chat).await {
-                println!("Failed to insert new chat {}: {}", server_chat.chat_id, e);
-            } else {
-                // After successfully inserting the chat, generate and save the proper chat name
-                // This is especially important for 1-on-1 chats that might not have names from server
-                if let Err(e) = generate_and_save_chat_name(token.clone(), server_chat.chat_id.clone()).await {
-                    println!("Failed to generate chat name for new chat {}: {}", server_chat.chat_id, e);
-                }
-            }
-        }
-    }
+    println!("New chats: {:?}", delta.added);
+    println!("Updated chats: {:?}", delta.updated);
+    println!("Removed chats: {:?}", delta.removed);
     
-    // Update existing chats with any changes from server (ONLY if they exist locally)
-    for server_chat in &filtered_server_chats {
-        if cached_chat_ids.contains(&server_chat.chat_id) {
-            println!("Updating existing chat: {}", server_chat.chat_id);
-            
-            let db_chat = db_async::Chat {
-                chat_id: server_chat.chat_id.clone(),
-                name: server_chat.name.clone(),
-                created_at: server_chat.created_at,
-                creator_id: Some(server_chat.creator_id.clone()),
-                is_group: server_chat.is_group,
-                participants: Some(serde_json::to_string(&server_chat.participants).unwrap_or_default()),
-                unread_count: server_chat.unread_count,
-                group_name: None,
-                description: None,
-                last_message_content: None,
-                last_message_timestamp: None,
-            };
-            
-            if let Err(e) = db_async::insert_or_update_chat(&db_chat).await {
-                println!("Failed to update chat {}: {}", server_chat.chat_id, e);
-            }
+    // Generate names for new chats, especially 1-on-1 chats that might not have names from server
+    for chat_id in &delta.added {
+        if let Err(e) = generate_and_save_chat_name(repo.clone(), token.clone(), chat_id.clone()).await {
+            println!("Failed to generate chat name for new chat {}: {}", chat_id, e);
         }
     }
     
-    // Clean up local deletes for chats that no longer exist on server
-    let server_chat_ids_vec: Vec<String> = filtered_server_chat_ids.into_iter().collect();
-    if let Err(e) = db_async::cleanup_local_deletes(&server_chat_ids_vec, &current_user_id).await {
-        println!("Failed to cleanup local deletes: {}", e);
+    if !delta.is_empty() {
+        app.emit("chats-delta", &delta).ok();
     }
-    
-    // Return the updated chat list (filtered for local deletes)
-    get_cached_chats_only_for_user(&current_user_id).await
+    Ok(delta)
 }
 
 #[tauri::command]
-pub async fn get_cached_chats_for_current_user() -> Result<Vec<Chat>, String> {
+pub async fn get_cached_chats_for_current_user(repo: State<'_, SqliteRepo>) -> Result<Vec<Chat>, String> {
     println!("Getting cached chats for current user");
-    get_cached_chats_only().await
+    get_cached_chats_only(repo).await
 }
 
 #[tauri::command]
-pub async fn get_cached_chats_for_current_user_filtered(token: String) -> Result<Vec<Chat>, String> {
+pub async fn get_cached_chats_for_current_user_filtered(repo: State<'_, SqliteRepo>, token: String) -> Result<Vec<Chat>, String> {
     println!("Getting filtered cached chats for current user");
     
     // Get current user ID from token to filter local deletes
-    let current_user_id = get_current_user_id_from_token(&token).await?;
+    let current_user_id = get_current_user_id_from_token(&repo, &token).await?;
     
-    get_cached_chats_only_for_user(&current_user_id).await
+    get_cached_chats_only_for_user(&repo, &current_user_id).await
 }
 
 #[tauri::command]
-pub async fn delete_chat_from_database(chat_id: String) -> Result<(), String> {
+pub async fn delete_chat_from_database(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<(), String> {
     println!("Deleting chat from database: {}", chat_id);
     
-    // First clear messages for this chat
-    if let Err(e) = db_async::clear_messages_for_chat(&chat_id).await {
-        println!("Warning: Failed to clear messages for chat {}: {}", chat_id, e);
-    }
-    
-    // Then clear participants for this chat
-    if let Err(e) = db_async::remove_all_participants_for_chat(&chat_id).await {
-        println!("Warning: Failed to clear participants for chat {}: {}", chat_id, e);
-    }
-    
-    // Finally delete the chat itself
-    db_async::delete_chat(&chat_id).await
+    // Messages and participants go with the chat (ON DELETE CASCADE)
+    repo.delete_chat(&chat_id).await
         .map_err(|e| format!("Database error: {e}"))
 }
 
 #[tauri::command]
 pub async fn leave_chat(
     state: State<'_, Mutex<HashMap<String, String>>>,
+    repo: State<'_, SqliteRepo>,
     chat_id: String,
 ) -> Result<(), String> {
     let token = {
@@ -986,18 +1053,18 @@ pub async fn leave_chat(
             .clone()
     };
 
-    leave_chat_with_token(token, chat_id).await
+    leave_chat_with_token(repo, token, chat_id).await
 }
 
 #[tauri::command]
-pub async fn delete_chat(token: String, chat_id: String) -> Result<(), String> {
+pub async fn delete_chat(repo: State<'_, SqliteRepo>, token: String, chat_id: String) -> Result<(), String> {
     println!("Smart deleting chat {} from server...", chat_id);
     
     // Get current user ID to check if they're the creator
-    let current_user_id = get_current_user_id_from_token(&token).await?;
+    let current_user_id = get_current_user_id_from_token(&repo, &token).await?;
     
     // Check if user is the creator of this chat
-    let cached_chats = get_cached_chats_only().await?;
+    let cached_chats = get_cached_chats_only(repo.clone()).await?;
     let is_creator = cached_chats.iter()
         .find(|chat| chat.chat_id == chat_id)
         .map(|chat| chat.creator_id == current_user_id)
@@ -1006,7 +1073,7 @@ pub async fn delete_chat(token: String, chat_id: String) -> Result<(), String> {
     println!("User {} is creator of chat {}: {}", current_user_id, chat_id, is_creator);
     
     // Add to local deletes immediately (before server response!) - Swift pattern
-    if let Err(e) = db_async::add_local_delete(&chat_id, &current_user_id, is_creator).await {
+    if let Err(e) = repo.add_local_delete(&chat_id, &current_user_id, is_creator).await {
         println!("Warning: Failed to add local delete tracking: {}", e);
     }
     
@@ -1015,18 +1082,8 @@ pub async fn delete_chat(token: String, chat_id: String) -> Result<(), String> {
     let perform_local_cleanup = || async {
         println!("Performing local cleanup for chat: {}", chat_id_clone);
         
-        // Clear messages for this chat
-        if let Err(e) = db_async::clear_messages_for_chat(&chat_id_clone).await {
-            println!("Failed to clear messages for chat {}: {}", chat_id_clone, e);
-        }
-        
-        // Remove all participants for this chat
-        if let Err(e) = db_async::remove_all_participants_for_chat(&chat_id_clone).await {
-            println!("Failed to remove participants for chat {}: {}", chat_id_clone, e);
-        }
-        
-        // Delete the chat itself
-        if let Err(e) = db_async::delete_chat(&chat_id_clone).await {
+        // Messages and participants go with the chat (ON DELETE CASCADE)
+        if let Err(e) = repo.delete_chat(&chat_id_clone).await {
             println!("Failed to delete chat {}: {}", chat_id_clone, e);
         }
     };
@@ -1077,7 +1134,7 @@ pub async fn delete_chat(token: String, chat_id: String) -> Result<(), String> {
         // User is not creator - try to leave the chat instead
         println!("User is not creator, attempting to leave chat {} instead", chat_id);
         
-        match leave_chat_with_token(token, chat_id.clone()).await {
+        match leave_chat_with_token(repo.clone(), token, chat_id.clone()).await {
             Ok(_) => {
                 println!("Successfully left chat {}", chat_id);
                 perform_local_cleanup().await;
@@ -1094,7 +1151,7 @@ pub async fn delete_chat(token: String, chat_id: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn leave_chat_with_token(token: String, chat_id: String) -> Result<(), String> {
+pub async fn leave_chat_with_token(repo: State<'_, SqliteRepo>, token: String, chat_id: String) -> Result<(), String> {
     println!("Leaving chat: {}", chat_id);
     println!("[Chat] DEBUG: Received parameters: token = '{}...', chat_id = '{}'", &token[..min(20, token.len())], chat_id);
     
@@ -1120,7 +1177,7 @@ pub async fn leave_chat_with_token(token: String, chat_id: String) -> Result<(),
         }
         
         // Remove from database with proper cleanup
-        if let Err(e) = delete_chat_from_database(chat_id.clone()).await {
+        if let Err(e) = delete_chat_from_database(repo, chat_id.clone()).await {
             println!("Failed to delete chat from database: {}", e);
         }
         
@@ -1134,6 +1191,7 @@ pub async fn leave_chat_with_token(token: String, chat_id: String) -> Result<(),
 #[tauri::command]
 pub async fn refresh_direct_chat_names(
     state: State<'_, Mutex<HashMap<String, String>>>,
+    repo: State<'_, SqliteRepo>,
 ) -> Result<(), String> {
     let _token = {
         let store = state.lock().unwrap();
@@ -1142,7 +1200,7 @@ pub async fn refresh_direct_chat_names(
             .clone()
     };
 
-    let chats = get_cached_chats_only().await?;
+    let chats = get_cached_chats_only(repo).await?;
     
     for chat in chats {
         if !chat.is_group {
@@ -1157,11 +1215,11 @@ pub async fn refresh_direct_chat_names(
 
 // Generate and save chat name based on participants
 #[tauri::command]
-pub async fn generate_and_save_chat_name(token: String, chat_id: String) -> Result<(), String> {
+pub async fn generate_and_save_chat_name(repo: State<'_, SqliteRepo>, token: String, chat_id: String) -> Result<(), String> {
     println!("Generating chat name for chat: {}", chat_id);
     
     // Get the chat from database
-    let chat = db_async::get_chat_by_id(&chat_id).await
+    let chat = repo.get_chat_by_id(&chat_id).await
         .map_err(|e| format!("Database error: {e}"))?;
     
     if let Some(mut chat) = chat {
@@ -1178,7 +1236,7 @@ pub async fn generate_and_save_chat_name(token: String, chat_id: String) -> Resu
             }
             
             // Get participants to generate group name
-            let participants = db_async::get_participants_for_chat(&chat_id).await
+            let participants = repo.get_participants_for_chat(&chat_id).await
                 .map_err(|e| format!("Database error: {e}"))?;
             
             println!("Found {} participants for group chat {}", participants.len(), chat_id);
@@ -1204,7 +1262,7 @@ pub async fn generate_and_save_chat_name(token: String, chat_id: String) -> Resu
                 // Update chat name in database
                 chat.name = Some(group_name.clone());
                 
-                if let Err(e) = db_async::insert_or_update_chat(&chat).await {
+                if let Err(e) = repo.insert_or_update_chat(&chat).await {
                     println!("Failed to update group chat name: {}", e);
                     return Err(format!("Database update failed: {}", e));
                 } else {
@@ -1215,7 +1273,7 @@ pub async fn generate_and_save_chat_name(token: String, chat_id: String) -> Resu
             }
         } else {
             // For direct chats, get the other participant's name
-            let participants = db_async::get_participants_for_chat(&chat_id).await
+            let participants = repo.get_participants_for_chat(&chat_id).await
                 .map_err(|e| format!("Database error: {e}"))?;
             
             println!("Found {} participants for direct chat {}", participants.len(), chat_id);
@@ -1224,7 +1282,7 @@ pub async fn generate_and_save_chat_name(token: String, chat_id: String) -> Resu
                 // For direct chats, we need to identify which participant is the current user
                 // and use the other participant's username as the chat name
                 // We'll get the current user ID from the database (like the session manager does)
-                let current_user = db_async::get_most_recent_user().await
+                let current_user = repo.get_most_recent_user().await
                     .map_err(|e| format!("Database error: {e}"))?;
                 
                 if let Some(current_user) = current_user {
@@ -1240,7 +1298,7 @@ pub async fn generate_and_save_chat_name(token: String, chat_id: String) -> Resu
                         let real_username = if !token.is_empty() {
                             // Import the get_username_for_user_id function from participant module
                             use crate::modules::participant::get_username_for_user_id;
-                            match get_username_for_user_id(&token, &other_participant.user_id).await {
+                            match get_username_for_user_id(&repo, &token, &other_participant.user_id).await {
                                 Ok(username) => {
                                     if username != other_participant.user_id && !username.starts_with("user_") {
                                         println!("Retrieved real username from API: {}", username);
@@ -1265,7 +1323,7 @@ pub async fn generate_and_save_chat_name(token: String, chat_id: String) -> Resu
                         // Update chat name in database
                         chat.name = Some(real_username.clone());
                         
-                        if let Err(e) = db_async::insert_or_update_chat(&chat).await {
+                        if let Err(e) = repo.insert_or_update_chat(&chat).await {
                             println!("Failed to update direct chat name: {}", e);
                             return Err(format!("Database update failed: {}", e));
                         } else {
@@ -1298,11 +1356,11 @@ async fn attempt_silent_relogin_and_retry_delete_chat(_chat_id: String) -> Resul
     Err("Authentication expired. Please log in again.".to_string())
 }
 
-async fn attempt_silent_relogin_and_retry_chats() -> Result<Vec<Chat>, String> {
+async fn attempt_silent_relogin_and_retry_chats(repo: &SqliteRepo) -> Result<Vec<Chat>, String> {
     println!("Attempting silent relogin...");
     
     // Get stored credentials from database
-    let stored_user = db_async::get_most_recent_user().await
+    let stored_user = repo.get_most_recent_user().await
         .map_err(|e| format!("Database error: {e}"))?;
     
     let stored_user = match stored_user {
@@ -1336,11 +1394,11 @@ async fn attempt_silent_relogin_and_retry_chats() -> Result<Vec<Chat>, String> {
 
 // Refresh all chat names in the database
 #[tauri::command]
-pub async fn refresh_all_chat_names(token: String) -> Result<(), String> {
+pub async fn refresh_all_chat_names(repo: State<'_, SqliteRepo>, token: String) -> Result<(), String> {
     println!("Refreshing all chat names with token...");
     
     // Get all chats from database
-    let chats = db_async::get_all_chats().await
+    let chats = repo.get_all_chats().await
         .map_err(|e| format!("Database error: {e}"))?;
     
     println!("Found {} chats to refresh names for", chats.len());
@@ -1352,7 +1410,7 @@ pub async fn refresh_all_chat_names(token: String) -> Result<(), String> {
         let chat_id = chat.chat_id.clone();
         println!("Processing chat {} (name: {:?}, is_group: {})", chat_id, chat.name, chat.is_group);
         
-        match generate_and_save_chat_name(token.clone(), chat_id.clone()).await {
+        match generate_and_save_chat_name(repo.clone(), token.clone(), chat_id.clone()).await {
             Ok(_) => {
                 success_count += 1;
                 println!("Successfully refreshed chat name for {}", chat_id);
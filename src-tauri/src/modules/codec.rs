@@ -0,0 +1,129 @@
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+// Subprotocols offered during the handshake, most preferred first
+pub const MSGPACK_SUBPROTOCOL: &str = "chat.msgpack";
+pub const JSON_SUBPROTOCOL: &str = "chat";
+
+// Encoding negotiated for the current connection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireEncoding {
+    Json,
+    MessagePack,
+}
+
+impl WireEncoding {
+    // Pick the encoding from the subprotocol the server accepted; anything else means JSON
+    pub fn from_subprotocol(protocol: Option<&str>) -> Self {
+        match protocol.map(|p| p.trim()) {
+            Some(MSGPACK_SUBPROTOCOL) => WireEncoding::MessagePack,
+            _ => WireEncoding::Json,
+        }
+    }
+
+    pub fn offered_subprotocols() -> String {
+        format!("{}, {}", MSGPACK_SUBPROTOCOL, JSON_SUBPROTOCOL)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WireEncoding::Json => "json",
+            WireEncoding::MessagePack => "msgpack",
+        }
+    }
+}
+
+// Event we send to the server: {"type": ..., "message": {...}}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(default)]
+    pub message: serde_json::Value,
+    // Any other top-level fields, carried through unchanged
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+// Event the server sends us, same envelope as ClientEvent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(default)]
+    pub message: serde_json::Value,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+// MessagePack form of an event. The ciphertext in message.content is lifted out
+// as raw bytes instead of travelling as base64 text.
+#[derive(Serialize, Deserialize)]
+struct PackedEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    #[serde(default)]
+    message: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content: Option<ByteBuf>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+// Encode a JSON client event for a binary frame. Returns None if the text is not
+// an event envelope, in which case it is sent as a text frame unchanged.
+pub fn encode_client_event(text: &str) -> Option<Vec<u8>> {
+    let mut event: ClientEvent = serde_json::from_str(text).ok()?;
+
+    let content = event.message.get("content")
+        .and_then(|c| c.as_str())
+        .and_then(|c| general_purpose::STANDARD.decode(c).ok());
+    if content.is_some() {
+        if let Some(message) = event.message.as_object_mut() {
+            message.remove("content");
+        }
+    }
+
+    let packed = PackedEvent {
+        event_type: event.event_type,
+        message: event.message,
+        content: content.map(ByteBuf::from),
+        extra: event.extra,
+    };
+    match rmp_serde::to_vec_named(&packed) {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            println!("[Codec] Failed to encode client event as MessagePack: {}", e);
+            None
+        }
+    }
+}
+
+// Decode a binary frame from the server into the JSON text the rest of the
+// pipeline understands. Servers without MessagePack may still send JSON as binary.
+pub fn decode_server_event(bytes: &[u8]) -> Result<String, String> {
+    if let Ok(packed) = rmp_serde::from_slice::<PackedEvent>(bytes) {
+        let mut event = ServerEvent {
+            event_type: packed.event_type,
+            message: packed.message,
+            extra: packed.extra,
+        };
+        if let Some(content) = packed.content {
+            if let Some(message) = event.message.as_object_mut() {
+                message.insert(
+                    "content".to_string(),
+                    serde_json::Value::String(general_purpose::STANDARD.encode(content.as_ref())),
+                );
+            }
+        }
+        return serde_json::to_string(&event)
+            .map_err(|e| format!("Failed to re-encode server event: {}", e));
+    }
+
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| "Binary frame is neither MessagePack nor UTF-8".to_string())?;
+    serde_json::from_str::<ServerEvent>(text)
+        .map_err(|e| format!("Binary frame is not a server event: {}", e))?;
+    Ok(text.to_string())
+}
@@ -1,4 +1,8 @@
 use crate::database_async;
+use crate::message_status::MessageStatus;
+use crate::repo::{ChatRepo, FriendRepo, MessageRepo, ParticipantRepo, SqliteRepo, UserRepo};
+use crate::timestamp::Timestamp;
+use tauri::{AppHandle, Emitter, State};
 use std::sync::Arc;
 use tokio::sync::Mutex as TokioMutex;
 
@@ -8,16 +12,16 @@ pub struct DatabaseState(pub Arc<TokioMutex<()>>);
 // ======== DATABASE COMMANDS ========
 
 #[tauri::command]
-pub async fn db_initialize_database() -> Result<(), String> {
+pub async fn db_initialize_database(repo: State<'_, SqliteRepo>) -> Result<(), String> {
     println!("[Database] Initializing database...");
-    let _pool = database_async::initialize_database().await.map_err(|e| e.to_string())?;
+    let _pool = repo.initialize().await.map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[tauri::command]
-pub async fn db_ensure_initialized() -> Result<(), String> {
+pub async fn db_ensure_initialized(repo: State<'_, SqliteRepo>) -> Result<(), String> {
     println!("[Database] Ensuring database is initialized...");
-    let _pool = database_async::initialize_database().await.map_err(|e| e.to_string())?;
+    let _pool = repo.initialize().await.map_err(|e| e.to_string())?;
     Ok(())
 }
 
@@ -28,30 +32,30 @@ pub async fn db_reset_initialization() -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn db_clear_all_data() -> Result<(), String> {
+pub async fn db_clear_all_data(repo: State<'_, SqliteRepo>) -> Result<(), String> {
     println!("[Database] Clearing all data...");
-    database_async::clear_all_data().await.map_err(|e| e.to_string())
+    repo.clear_all_data().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_health_check() -> Result<bool, String> {
+pub async fn db_health_check(repo: State<'_, SqliteRepo>) -> Result<bool, String> {
     println!("[Database] Performing health check...");
-    match database_async::health_check().await {
+    match repo.health_check().await {
         Ok(_) => Ok(true),
         Err(e) => Err(e.to_string())
     }
 }
 
 #[tauri::command]
-pub async fn db_get_stats() -> Result<serde_json::Value, String> {
+pub async fn db_get_stats(repo: State<'_, SqliteRepo>) -> Result<serde_json::Value, String> {
     println!("[Database] Getting database stats...");
-    let stats = database_async::get_database_stats().await.map_err(|e| e.to_string())?;
+    let stats = repo.get_database_stats().await.map_err(|e| e.to_string())?;
     Ok(serde_json::json!(stats))
 }
 
 #[tauri::command]
-pub async fn db_check_ready() -> Result<bool, String> {
-    match database_async::check_database_ready().await {
+pub async fn db_check_ready(repo: State<'_, SqliteRepo>) -> Result<bool, String> {
+    match repo.check_database_ready().await {
         Ok(_) => Ok(true),
         Err(e) => Err(format!("Database not ready: {}", e))
     }
@@ -60,363 +64,438 @@ pub async fn db_check_ready() -> Result<bool, String> {
 // ======== USER COMMANDS ========
 
 #[tauri::command]
-pub async fn db_insert_user(user: database_async::User) -> Result<(), String> {
+pub async fn db_insert_user(repo: State<'_, SqliteRepo>, user: database_async::User) -> Result<(), String> {
     println!("[Database] Inserting user...");
-    database_async::insert_or_update_user(&user).await.map_err(|e| e.to_string())
+    repo.insert_or_update_user(&user).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_user_by_id(user_id: String) -> Result<Option<database_async::User>, String> {
+pub async fn db_get_user_by_id(repo: State<'_, SqliteRepo>, user_id: String) -> Result<Option<database_async::User>, String> {
     println!("[Database] Getting user by ID: {}", user_id);
-    database_async::get_user_by_id(&user_id).await.map_err(|e| e.to_string())
+    repo.get_user_by_id(&user_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_user_by_token(token: String) -> Result<Option<database_async::User>, String> {
+pub async fn db_get_user_by_token(repo: State<'_, SqliteRepo>, token: String) -> Result<Option<database_async::User>, String> {
     println!("[Database] Getting user by token...");
-    database_async::get_user_by_token(&token).await.map_err(|e| e.to_string())
+    repo.get_user_by_token(&token).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_update_user_token(user_id: String, token: String) -> Result<(), String> {
+pub async fn db_update_user_token(repo: State<'_, SqliteRepo>, user_id: String, token: String) -> Result<(), String> {
     println!("[Database] Updating user token...");
-    database_async::update_user_token(&user_id, &token).await.map_err(|e| e.to_string())
+    repo.update_user_token(&user_id, &token).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_clear_user_data() -> Result<(), String> {
+pub async fn db_clear_user_data(repo: State<'_, SqliteRepo>) -> Result<(), String> {
     println!("[Database] Clearing user data...");
-    database_async::clear_user_data().await.map_err(|e| e.to_string())
+    repo.clear_user_data().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_most_recent_user() -> Result<Option<database_async::User>, String> {
+pub async fn db_get_most_recent_user(repo: State<'_, SqliteRepo>) -> Result<Option<database_async::User>, String> {
     println!("[Database] Getting most recent user...");
-    database_async::get_most_recent_user().await.map_err(|e| e.to_string())
+    repo.get_most_recent_user().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_update_dark_mode(user_id: String, is_dark_mode: bool) -> Result<(), String> {
+pub async fn db_update_dark_mode(repo: State<'_, SqliteRepo>, user_id: String, is_dark_mode: bool) -> Result<(), String> {
     println!("[Database] Updating dark mode...");
-    database_async::update_dark_mode(&user_id, is_dark_mode).await.map_err(|e| e.to_string())
+    repo.update_dark_mode(&user_id, is_dark_mode).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_dark_mode(user_id: String) -> Result<bool, String> {
+pub async fn db_get_dark_mode(repo: State<'_, SqliteRepo>, user_id: String) -> Result<bool, String> {
     println!("[Database] Getting dark mode...");
-    database_async::get_dark_mode(&user_id).await.map_err(|e| e.to_string())
+    repo.get_dark_mode(&user_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_update_color_scheme(user_id: String, color_scheme: String) -> Result<(), String> {
+pub async fn db_update_send_read_receipts(repo: State<'_, SqliteRepo>, user_id: String, enabled: bool) -> Result<(), String> {
+    println!("[Database] Updating read receipt setting...");
+    repo.update_send_read_receipts(&user_id, enabled).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn db_get_send_read_receipts(repo: State<'_, SqliteRepo>, user_id: String) -> Result<bool, String> {
+    println!("[Database] Getting read receipt setting...");
+    repo.get_send_read_receipts(&user_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn db_update_color_scheme(repo: State<'_, SqliteRepo>, user_id: String, color_scheme: String) -> Result<(), String> {
     println!("[Database] Updating color scheme...");
-    database_async::update_color_scheme(&user_id, &color_scheme).await.map_err(|e| e.to_string())
+    repo.update_color_scheme(&user_id, &color_scheme).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_color_scheme(user_id: String) -> Result<String, String> {
+pub async fn db_get_color_scheme(repo: State<'_, SqliteRepo>, user_id: String) -> Result<String, String> {
     println!("[Database] Getting color scheme...");
-    database_async::get_color_scheme(&user_id).await.map_err(|e| e.to_string())
+    repo.get_color_scheme(&user_id).await.map_err(|e| e.to_string())
 }
 
 // ======== CHAT COMMANDS ========
 
 #[tauri::command]
-pub async fn db_insert_chat(chat: database_async::Chat) -> Result<(), String> {
+pub async fn db_insert_chat(repo: State<'_, SqliteRepo>, chat: database_async::Chat) -> Result<(), String> {
     println!("[Database] Inserting chat...");
-    database_async::insert_or_update_chat(&chat).await.map_err(|e| e.to_string())
+    repo.insert_or_update_chat(&chat).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_insert_or_update_chat(chat: database_async::Chat) -> Result<(), String> {
+pub async fn db_insert_or_update_chat(repo: State<'_, SqliteRepo>, chat: database_async::Chat) -> Result<(), String> {
     println!("[Database] Inserting/updating chat...");
-    database_async::insert_or_update_chat(&chat).await.map_err(|e| e.to_string())
+    repo.insert_or_update_chat(&chat).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_chat_by_id(chat_id: String) -> Result<Option<database_async::Chat>, String> {
+pub async fn db_get_chat_by_id(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<Option<database_async::Chat>, String> {
     println!("[Database] Getting chat by ID: {}", chat_id);
-    database_async::get_chat_by_id(&chat_id).await.map_err(|e| e.to_string())
+    repo.get_chat_by_id(&chat_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_all_chats() -> Result<Vec<database_async::Chat>, String> {
+pub async fn db_get_all_chats(repo: State<'_, SqliteRepo>) -> Result<Vec<database_async::Chat>, String> {
     println!("[Database] Getting all chats...");
-    database_async::get_all_chats().await.map_err(|e| e.to_string())
+    repo.get_all_chats().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_cached_chats_only() -> Result<Vec<database_async::Chat>, String> {
+pub async fn db_get_cached_chats_only(repo: State<'_, SqliteRepo>) -> Result<Vec<database_async::Chat>, String> {
     println!("[Database] Getting cached chats only...");
-    database_async::get_all_chats().await.map_err(|e| e.to_string())
-}
-
-#[tauri::command]
-pub async fn db_update_chat_unread_count(chat_id: String, unread_count: i32) -> Result<(), String> {
-    println!("[Database] Updating chat unread count...");
-    database_async::update_chat_unread_count(&chat_id, unread_count).await.map_err(|e| e.to_string())
-}
-
-#[tauri::command]
-pub async fn db_update_chat_last_message(chat_id: String, content: Option<String>, timestamp: Option<i64>) -> Result<(), String> {
-    println!("[Database] Updating chat last message...");
-    database_async::update_chat_last_message(&chat_id, content.as_deref(), timestamp).await.map_err(|e| e.to_string())
+    repo.get_all_chats().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_delete_chat_by_id(chat_id: String) -> Result<(), String> {
+pub async fn db_delete_chat_by_id(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<(), String> {
     println!("[Database] Deleting chat by ID: {}", chat_id);
-    database_async::delete_chat(&chat_id).await.map_err(|e| e.to_string())
+    repo.delete_chat(&chat_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_clear_chat_data() -> Result<(), String> {
+pub async fn db_clear_chat_data(repo: State<'_, SqliteRepo>) -> Result<(), String> {
     println!("[Database] Clearing chat data...");
-    database_async::clear_chat_data().await.map_err(|e| e.to_string())
+    repo.clear_chat_data().await.map_err(|e| e.to_string())
 }
 
 // ======== MESSAGE COMMANDS ========
 
 #[tauri::command]
-pub async fn db_insert_message(message: database_async::Message) -> Result<(), String> {
+pub async fn db_insert_message(repo: State<'_, SqliteRepo>, message: database_async::Message) -> Result<(), String> {
     println!("[Database] Inserting message...");
-    database_async::insert_or_update_message(&message).await.map_err(|e| e.to_string())
+    repo.insert_or_update_message(&message).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_insert_messages(messages: Vec<database_async::Message>) -> Result<(), String> {
+pub async fn db_insert_messages(repo: State<'_, SqliteRepo>, messages: Vec<database_async::Message>) -> Result<(), String> {
     println!("[Database] Inserting messages...");
-    database_async::insert_messages(&messages).await.map_err(|e| e.to_string())
+    repo.insert_messages(&messages).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_message_by_id(message_id: String) -> Result<Option<database_async::Message>, String> {
+pub async fn db_get_message_by_id(repo: State<'_, SqliteRepo>, message_id: String) -> Result<Option<database_async::Message>, String> {
     println!("[Database] Getting message by ID: {}", message_id);
-    database_async::get_message_by_id(&message_id).await.map_err(|e| e.to_string())
+    repo.get_message_by_id(&message_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_message_by_client_id(client_message_id: String) -> Result<Option<database_async::Message>, String> {
+pub async fn db_get_message_by_client_id(repo: State<'_, SqliteRepo>, client_message_id: String) -> Result<Option<database_async::Message>, String> {
     println!("[Database] Getting message by client ID: {}", client_message_id);
-    database_async::get_message_by_client_id(&client_message_id).await.map_err(|e| e.to_string())
+    repo.get_message_by_client_id(&client_message_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_messages_for_chat(chat_id: String) -> Result<Vec<database_async::Message>, String> {
+pub async fn db_get_messages_for_chat(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<Vec<database_async::Message>, String> {
     println!("[Database] Getting messages for chat: {}", chat_id);
-    database_async::get_messages_for_chat(&chat_id).await.map_err(|e| e.to_string())
+    repo.get_messages_for_chat(&chat_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_messages_before_timestamp(chat_id: String, before_timestamp: i64, limit: i32) -> Result<Vec<database_async::Message>, String> {
+pub async fn db_get_messages_before_timestamp(repo: State<'_, SqliteRepo>, chat_id: String, before_timestamp: Timestamp, limit: i32) -> Result<Vec<database_async::Message>, String> {
     println!("[Database] Getting messages before timestamp...");
-    database_async::get_messages_before_timestamp(&chat_id, before_timestamp, limit).await.map_err(|e| e.to_string())
+    repo.get_messages_before_timestamp(&chat_id, before_timestamp, limit).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_last_message(chat_id: String) -> Result<Option<database_async::Message>, String> {
+pub async fn db_get_last_message(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<Option<database_async::Message>, String> {
     println!("[Database] Getting last message for chat: {}", chat_id);
-    database_async::get_last_message(&chat_id).await.map_err(|e| e.to_string())
+    repo.get_last_message(&chat_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_update_message_sent_status(client_message_id: String, is_sent: bool) -> Result<(), String> {
+pub async fn db_update_message_sent_status(repo: State<'_, SqliteRepo>, client_message_id: String, is_sent: bool) -> Result<(), String> {
     println!("[Database] Updating message sent status...");
-    database_async::update_message_sent_status(&client_message_id, is_sent).await.map_err(|e| e.to_string())
+    repo.update_message_sent_status(&client_message_id, is_sent).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_update_message_sent_status_by_server_id(server_id: String, is_sent: bool) -> Result<(), String> {
+pub async fn db_update_message_status(repo: State<'_, SqliteRepo>, client_message_id: String, status: MessageStatus) -> Result<Option<database_async::Message>, String> {
+    println!("[Database] Updating message status: {} -> {}", client_message_id, status);
+    repo.update_message_status(&client_message_id, status).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn db_update_message_sent_status_by_server_id(repo: State<'_, SqliteRepo>, server_id: String, is_sent: bool) -> Result<(), String> {
     println!("[Database] Updating message sent status by server ID...");
-    database_async::update_message_sent_status_by_server_id(&server_id, is_sent).await.map_err(|e| e.to_string())
+    repo.update_message_sent_status_by_server_id(&server_id, is_sent).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_mark_message_delivered_by_server_id(server_id: String) -> Result<(), String> {
+pub async fn db_mark_message_delivered_by_server_id(repo: State<'_, SqliteRepo>, server_id: String) -> Result<(), String> {
     println!("[Database] Marking message as delivered...");
-    database_async::mark_message_delivered_by_server_id_new(&server_id).await.map_err(|e| e.to_string())
+    repo.mark_message_delivered_by_server_id_new(&server_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_mark_message_read_by_server_id(server_id: String) -> Result<(), String> {
+pub async fn db_mark_message_read_by_server_id(repo: State<'_, SqliteRepo>, server_id: String) -> Result<(), String> {
     println!("[Database] Marking message as read...");
-    database_async::mark_message_read_by_server_id_new(&server_id).await.map_err(|e| e.to_string())
+    repo.mark_message_read_by_server_id_new(&server_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_mark_messages_read_by_server_ids(message_ids: Vec<String>) -> Result<(), String> {
+pub async fn db_mark_messages_read_by_server_ids(repo: State<'_, SqliteRepo>, message_ids: Vec<String>) -> Result<(), String> {
     println!("[Database] Marking messages as read...");
-    database_async::mark_messages_read_by_server_ids(&message_ids).await.map_err(|e| e.to_string())
+    repo.mark_messages_read_by_server_ids(&message_ids).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_unread_messages(chat_id: String) -> Result<Vec<database_async::Message>, String> {
+pub async fn db_get_unread_messages(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<Vec<database_async::Message>, String> {
     println!("[Database] Getting unread messages for chat: {}", chat_id);
-    database_async::get_unread_messages(&chat_id).await.map_err(|e| e.to_string())
+    repo.get_unread_messages(&chat_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_count_unread_messages(chat_id: String) -> Result<i32, String> {
+pub async fn db_count_unread_messages(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<i32, String> {
     println!("[Database] Counting unread messages for chat: {}", chat_id);
-    database_async::count_unread_messages(&chat_id).await.map_err(|e| e.to_string())
+    repo.count_unread_messages(&chat_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_mark_messages_as_read(chat_id: String) -> Result<(), String> {
+pub async fn db_mark_messages_as_read(repo: State<'_, SqliteRepo>, chat_id: String, app: AppHandle) -> Result<(), String> {
     println!("[Database] Marking messages as read for chat: {}", chat_id);
-    database_async::mark_messages_as_read(&chat_id).await.map_err(|e| e.to_string())
+    let read = repo.mark_messages_as_read(&chat_id).await.map_err(|e| e.to_string())?;
+    crate::modules::receipts::queue_read_receipts(&app, &read).await;
+    Ok(())
 }
 
 #[tauri::command]
-pub async fn db_update_message_id_by_client(client_message_id: String, server_id: String) -> Result<(), String> {
+pub async fn db_update_message_id_by_client(repo: State<'_, SqliteRepo>, client_message_id: String, server_id: String) -> Result<(), String> {
     println!("[Database] Updating message ID by client...");
-    database_async::update_message_id_by_client(&client_message_id, &server_id).await.map_err(|e| e.to_string())
+    repo.update_message_id_by_client(&client_message_id, &server_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_delete_message_by_id(message_id: String) -> Result<(), String> {
+pub async fn db_delete_message_by_id(repo: State<'_, SqliteRepo>, message_id: String) -> Result<(), String> {
     println!("[Database] Deleting message by ID: {}", message_id);
-    database_async::delete_message_by_id(&message_id).await.map_err(|e| e.to_string())
+    repo.delete_message_by_id(&message_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_delete_message_by_client_id(client_message_id: String) -> Result<(), String> {
+pub async fn db_delete_message_by_client_id(repo: State<'_, SqliteRepo>, client_message_id: String) -> Result<(), String> {
     println!("[Database] Deleting message by client ID: {}", client_message_id);
-    database_async::delete_message_by_client_id(&client_message_id).await.map_err(|e| e.to_string())
+    repo.delete_message_by_client_id(&client_message_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_clear_message_data() -> Result<(), String> {
+pub async fn db_clear_message_data(repo: State<'_, SqliteRepo>) -> Result<(), String> {
     println!("[Database] Clearing message data...");
-    database_async::clear_message_data().await.map_err(|e| e.to_string())
+    repo.clear_message_data().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_clear_messages_for_chat(chat_id: String) -> Result<(), String> {
+pub async fn db_clear_messages_for_chat(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<(), String> {
     println!("[Database] Clearing messages for chat: {}", chat_id);
-    database_async::clear_messages_for_chat(&chat_id).await.map_err(|e| e.to_string())
+    repo.clear_messages_for_chat(&chat_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_remove_all_participants_for_chat(chat_id: String) -> Result<(), String> {
+pub async fn db_remove_all_participants_for_chat(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<(), String> {
     println!("[Database] Removing all participants for chat: {}", chat_id);
-    database_async::remove_all_participants_for_chat(&chat_id).await.map_err(|e| e.to_string())
+    repo.remove_all_participants_for_chat(&chat_id).await.map_err(|e| e.to_string())
 }
 
 // ======== FRIEND COMMANDS ========
 
 #[tauri::command]
-pub async fn db_insert_friend(friend: database_async::Friend) -> Result<(), String> {
+pub async fn db_insert_friend(repo: State<'_, SqliteRepo>, friend: database_async::Friend) -> Result<(), String> {
     println!("[Database] Inserting friend...");
-    database_async::insert_or_update_friend(&friend).await.map_err(|e| e.to_string())
+    repo.insert_or_update_friend(&friend).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_insert_or_update_friend(friend: database_async::Friend) -> Result<(), String> {
+pub async fn db_insert_or_update_friend(repo: State<'_, SqliteRepo>, friend: database_async::Friend) -> Result<(), String> {
     println!("[Database] Inserting/updating friend...");
-    database_async::insert_or_update_friend(&friend).await.map_err(|e| e.to_string())
+    repo.insert_or_update_friend(&friend).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_all_friends() -> Result<Vec<database_async::Friend>, String> {
+pub async fn db_get_all_friends(repo: State<'_, SqliteRepo>) -> Result<Vec<database_async::Friend>, String> {
     println!("[Database] Getting all friends...");
-    database_async::get_all_friends().await.map_err(|e| e.to_string())
+    repo.get_all_friends().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_cached_friends_only() -> Result<Vec<database_async::Friend>, String> {
+pub async fn db_get_cached_friends_only(repo: State<'_, SqliteRepo>) -> Result<Vec<database_async::Friend>, String> {
     println!("[Database] Getting cached friends only...");
-    database_async::get_all_friends().await.map_err(|e| e.to_string())
+    repo.get_all_friends().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_friend_by_id(friend_id: String) -> Result<Option<database_async::Friend>, String> {
+pub async fn db_get_friend_by_id(repo: State<'_, SqliteRepo>, friend_id: String) -> Result<Option<database_async::Friend>, String> {
     println!("[Database] Getting friend by ID: {}", friend_id);
-    database_async::get_friend_by_id(&friend_id).await.map_err(|e| e.to_string())
+    repo.get_friend_by_id(&friend_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_delete_friend(user_id: String) -> Result<(), String> {
+pub async fn db_delete_friend(repo: State<'_, SqliteRepo>, user_id: String) -> Result<(), String> {
     println!("[Database] Deleting friend: {}", user_id);
-    database_async::delete_friend(&user_id).await.map_err(|e| e.to_string())
+    repo.delete_friend(&user_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_update_friend_status(friend_id: String, status: String) -> Result<(), String> {
+pub async fn db_update_friend_status(repo: State<'_, SqliteRepo>, friend_id: String, status: String) -> Result<(), String> {
     println!("[Database] Updating friend status...");
-    database_async::update_friend_status(&friend_id, &status).await.map_err(|e| e.to_string())
+    repo.update_friend_status(&friend_id, &status).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_clear_friend_data() -> Result<(), String> {
+pub async fn db_clear_friend_data(repo: State<'_, SqliteRepo>) -> Result<(), String> {
     println!("[Database] Clearing friend data...");
-    database_async::clear_friend_data().await.map_err(|e| e.to_string())
+    repo.clear_friend_data().await.map_err(|e| e.to_string())
 }
 
 // ======== PARTICIPANT COMMANDS ========
 
 #[tauri::command]
-pub async fn db_insert_participant(participant: database_async::Participant) -> Result<(), String> {
+pub async fn db_insert_participant(repo: State<'_, SqliteRepo>, participant: database_async::Participant) -> Result<(), String> {
     println!("[Database] Inserting participant...");
-    database_async::insert_or_update_participant(&participant).await.map_err(|e| e.to_string())
+    repo.insert_or_update_participant(&participant).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_participants_for_chat(chat_id: String) -> Result<Vec<database_async::Participant>, String> {
+pub async fn db_get_participants_for_chat(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<Vec<database_async::Participant>, String> {
     println!("[Database] Getting participants for chat: {}", chat_id);
-    database_async::get_participants_for_chat(&chat_id).await.map_err(|e| e.to_string())
+    repo.get_participants_for_chat(&chat_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_participant_by_id(participant_id: String) -> Result<Option<database_async::Participant>, String> {
+pub async fn db_get_participant_by_id(repo: State<'_, SqliteRepo>, participant_id: String) -> Result<Option<database_async::Participant>, String> {
     println!("[Database] Getting participant by ID: {}", participant_id);
-    database_async::get_participant_by_id(&participant_id).await.map_err(|e| e.to_string())
+    repo.get_participant_by_id(&participant_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_participant_by_user_id_and_chat_id(user_id: String, chat_id: String) -> Result<Option<database_async::Participant>, String> {
+pub async fn db_get_participant_by_user_id_and_chat_id(repo: State<'_, SqliteRepo>, user_id: String, chat_id: String) -> Result<Option<database_async::Participant>, String> {
     println!("[Database] Getting participant by user ID and chat ID...");
-    database_async::get_participant_by_user_id_and_chat_id(&user_id, &chat_id).await.map_err(|e| e.to_string())
+    repo.get_participant_by_user_id_and_chat_id(&user_id, &chat_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_update_participant_role(participant_id: String, role: String) -> Result<(), String> {
+pub async fn db_update_participant_role(repo: State<'_, SqliteRepo>, participant_id: String, role: String) -> Result<(), String> {
     println!("[Database] Updating participant role...");
-    database_async::update_participant_role(&participant_id, &role).await.map_err(|e| e.to_string())
+    repo.update_participant_role(&participant_id, &role).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_delete_participant(participant_id: String) -> Result<(), String> {
+pub async fn db_delete_participant(repo: State<'_, SqliteRepo>, participant_id: String) -> Result<(), String> {
     println!("[Database] Deleting participant: {}", participant_id);
-    database_async::delete_participant(&participant_id).await.map_err(|e| e.to_string())
+    repo.delete_participant(&participant_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_clear_participant_data() -> Result<(), String> {
+pub async fn db_clear_participant_data(repo: State<'_, SqliteRepo>) -> Result<(), String> {
     println!("[Database] Clearing participant data...");
-    database_async::clear_participant_data().await.map_err(|e| e.to_string())
+    repo.clear_participant_data().await.map_err(|e| e.to_string())
 }
 
 // ======== USER KEYS COMMANDS ========
 
 #[tauri::command]
-pub async fn db_insert_user_keys(keys: database_async::UserKeys) -> Result<(), String> {
+pub async fn db_insert_user_keys(repo: State<'_, SqliteRepo>, keys: database_async::UserKeys) -> Result<(), String> {
     println!("[Database] Inserting user keys...");
-    database_async::insert_or_update_user_keys(&keys).await.map_err(|e| e.to_string())
+    repo.insert_or_update_user_keys(&keys).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn db_get_user_keys(user_id: String) -> Result<Option<database_async::UserKeys>, String> {
+pub async fn db_get_user_keys(repo: State<'_, SqliteRepo>, user_id: String) -> Result<Option<database_async::UserKeys>, String> {
     println!("[Database] Getting user keys for user: {}", user_id);
-    database_async::get_user_keys(&user_id).await.map_err(|e| e.to_string())
+    repo.get_user_keys(&user_id).await.map_err(|e| e.to_string())
 }
 
 // ======== UTILITY COMMANDS ========
 
 #[tauri::command]
-pub async fn db_reset_database() -> Result<(), String> {
+pub async fn db_reset_database(repo: State<'_, SqliteRepo>) -> Result<(), String> {
     println!("[Database] Resetting database...");
-    database_async::clear_all_data().await.map_err(|e| e.to_string())
+    repo.clear_all_data().await.map_err(|e| e.to_string())
+}
+
+// ======== BACKUP COMMANDS ========
+
+// Copy the live database to path without closing it
+#[tauri::command]
+pub async fn db_backup(repo: State<'_, SqliteRepo>, path: String) -> Result<database_async::BackupInfo, String> {
+    println!("[Database] Backing up database to {}...", path);
+    repo.backup_database(&path).await.map_err(|e| e.to_string())
+}
+
+// Replace the database with a backup; the previous database is kept as a safety copy
+#[tauri::command]
+pub async fn db_restore(repo: State<'_, SqliteRepo>, app: tauri::AppHandle, path: String) -> Result<database_async::RestoreReport, String> {
+    println!("[Database] Restoring database from {}...", path);
+    let report = repo.restore_database(&path).await.map_err(|e| e.to_string())?;
+    // Everything cached in the frontend now describes the old database
+    app.emit("database-restored", &report).ok();
+    Ok(report)
 }
 
- 
\ No newline at end of file
+// ======== MAINTENANCE COMMANDS ========
+
+// quick_check by default; full runs the slower integrity_check
+#[tauri::command]
+pub async fn db_integrity_check(repo: State<'_, SqliteRepo>, full: Option<bool>) -> Result<database_async::IntegrityReport, String> {
+    repo.integrity_check(full.unwrap_or(false)).await.map_err(|e| e.to_string())
+}
+
+// Frees up to `pages` free pages (all when omitted)
+#[tauri::command]
+pub async fn db_incremental_vacuum(repo: State<'_, SqliteRepo>, pages: Option<i64>) -> Result<database_async::VacuumReport, String> {
+    repo.incremental_vacuum(pages).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn db_wal_checkpoint(repo: State<'_, SqliteRepo>, mode: Option<String>) -> Result<database_async::CheckpointReport, String> {
+    repo.wal_checkpoint(mode.as_deref().unwrap_or("passive")).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn db_storage_report(repo: State<'_, SqliteRepo>) -> Result<database_async::StorageReport, String> {
+    println!("[Database] Building storage report...");
+    repo.storage_report().await.map_err(|e| e.to_string())
+}
+
+// Rebuild the database from its readable rows; refuses to touch a healthy database
+#[tauri::command]
+pub async fn db_recover(repo: State<'_, SqliteRepo>, app: tauri::AppHandle) -> Result<database_async::RecoveryReport, String> {
+    let report = repo.recover_if_corrupt().await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Database passed the integrity check, nothing to recover".to_string())?;
+    app.emit("database-recovered", &report).ok();
+    Ok(report)
+}
+
+// ======== SCHEMA COMMANDS ========
+
+#[tauri::command]
+pub async fn db_get_schema_version(repo: State<'_, SqliteRepo>) -> Result<serde_json::Value, String> {
+    println!("[Database] Getting schema version...");
+    repo.get_schema_status().await.map_err(|e| e.to_string())
+}
+
+// Dry-runs pending migrations and checks the schema against the latest version; changes nothing
+#[tauri::command]
+pub async fn db_verify_migrations(repo: State<'_, SqliteRepo>) -> Result<crate::migrations::SchemaVerification, String> {
+    println!("[Database] Verifying schema migrations...");
+    repo.verify_schema().await.map_err(|e| e.to_string())
+}
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::State;
+use tokio::sync::Mutex as TokioMutex;
+use serde::Serialize;
+use serde_json::json;
+use crate::modules::websocket::{ConnectionState, WebSocketState};
+
+// Number of round-trip samples kept for the min/avg/max figures
+const RTT_SAMPLE_LIMIT: usize = 50;
+// Number of finished connections kept in the history
+const HISTORY_LIMIT: usize = 30;
+// Pings without a pong after this long are forgotten
+const PING_EXPIRY: Duration = Duration::from_secs(120);
+
+// Why an inbound frame could not be handled. Only Decode counts as a parse failure;
+// a well-formed frame whose handler failed (e.g. a database error) is counted separately.
+#[derive(Debug)]
+pub enum FrameError {
+    Decode(String),
+    Handler(String),
+}
+
+impl From<String> for FrameError {
+    fn from(message: String) -> Self {
+        FrameError::Decode(message)
+    }
+}
+
+impl From<&str> for FrameError {
+    fn from(message: &str) -> Self {
+        FrameError::Decode(message.to_string())
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Decode(message) | FrameError::Handler(message) => f.write_str(message),
+        }
+    }
+}
+
+// One finished (or failed) connection attempt
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionRecord {
+    pub generation: u64,
+    pub connected_at: Option<i64>, // Unix milliseconds; None if the handshake failed
+    pub ended_at: i64,
+    pub uptime_secs: Option<u64>,
+    pub reason: String,
+    pub close_code: Option<u16>,
+    pub close_reason: Option<String>,
+}
+
+struct OpenConnection {
+    generation: u64,
+    started: Instant,
+    connected_at: i64,
+}
+
+#[derive(Default)]
+struct DiagnosticsLog {
+    pending_pings: HashMap<u64, Instant>,
+    rtt_samples: VecDeque<Duration>,
+    unmatched_pongs: u64,
+    parse_failures: HashMap<String, u64>,
+    handler_failures: HashMap<String, u64>,
+    current: Option<OpenConnection>,
+    history: VecDeque<ConnectionRecord>,
+}
+
+// Counters and history shared by the connection tasks and get_websocket_diagnostics.
+// Unlike QueueStats these survive reconnects, so flaky periods stay visible.
+#[derive(Default)]
+pub struct WsDiagnostics {
+    frames_in: AtomicU64,
+    bytes_in: AtomicU64,
+    frames_out: AtomicU64,
+    bytes_out: AtomicU64,
+    ping_seq: AtomicU64,
+    log: Mutex<DiagnosticsLog>,
+}
+
+impl WsDiagnostics {
+    pub fn record_in(&self, bytes: usize) {
+        self.frames_in.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_out(&self, bytes: usize) {
+        self.frames_out.fetch_add(1, Ordering::Relaxed);
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    // Payload for the next ping; the server echoes it in the pong so we can time the round trip
+    pub fn next_ping_payload(&self) -> Vec<u8> {
+        let seq = self.ping_seq.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        let mut log = self.log.lock().unwrap();
+        log.pending_pings.retain(|_, sent| now.duration_since(*sent) < PING_EXPIRY);
+        log.pending_pings.insert(seq, now);
+        seq.to_be_bytes().to_vec()
+    }
+
+    pub fn record_pong(&self, payload: &[u8]) {
+        let mut log = self.log.lock().unwrap();
+        let sent = <[u8; 8]>::try_from(payload)
+            .ok()
+            .and_then(|seq| log.pending_pings.remove(&u64::from_be_bytes(seq)));
+        match sent {
+            Some(sent) => {
+                if log.rtt_samples.len() == RTT_SAMPLE_LIMIT {
+                    log.rtt_samples.pop_front();
+                }
+                log.rtt_samples.push_back(sent.elapsed());
+            }
+            // Unsolicited pong, or a reply to a ping from before a reconnect
+            None => log.unmatched_pongs += 1,
+        }
+    }
+
+    pub fn record_parse_failure(&self, frame_type: &str) {
+        let mut log = self.log.lock().unwrap();
+        *log.parse_failures.entry(frame_type.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn record_handler_failure(&self, frame_type: &str) {
+        let mut log = self.log.lock().unwrap();
+        *log.handler_failures.entry(frame_type.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn record_frame_error(&self, frame_type: &str, error: &FrameError) {
+        match error {
+            FrameError::Decode(_) => self.record_parse_failure(frame_type),
+            FrameError::Handler(_) => self.record_handler_failure(frame_type),
+        }
+    }
+
+    pub fn connection_opened(&self, generation: u64) {
+        let mut log = self.log.lock().unwrap();
+        log.pending_pings.clear();
+        log.current = Some(OpenConnection {
+            generation,
+            started: Instant::now(),
+            connected_at: chrono::Utc::now().timestamp_millis(),
+        });
+    }
+
+    // Close out the record for this connection. The first caller wins, so the
+    // task that noticed the problem gets to name the reason.
+    pub fn connection_closed(&self, generation: u64, reason: &str, close_code: Option<u16>, close_reason: Option<String>) {
+        let mut log = self.log.lock().unwrap();
+        if log.current.as_ref().map(|c| c.generation) != Some(generation) {
+            return;
+        }
+        let Some(open) = log.current.take() else {
+            return;
+        };
+        println!("[WebSocket] Connection {} ended after {}s: {}", generation, open.started.elapsed().as_secs(), reason);
+        push_record(&mut log, ConnectionRecord {
+            generation,
+            connected_at: Some(open.connected_at),
+            ended_at: chrono::Utc::now().timestamp_millis(),
+            uptime_secs: Some(open.started.elapsed().as_secs()),
+            reason: reason.to_string(),
+            close_code,
+            close_reason,
+        });
+    }
+
+    pub fn connect_failed(&self, generation: u64, reason: &str) {
+        let mut log = self.log.lock().unwrap();
+        push_record(&mut log, ConnectionRecord {
+            generation,
+            connected_at: None,
+            ended_at: chrono::Utc::now().timestamp_millis(),
+            uptime_secs: None,
+            reason: reason.to_string(),
+            close_code: None,
+            close_reason: None,
+        });
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        let log = self.log.lock().unwrap();
+        let samples: Vec<f64> = log.rtt_samples.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        let rtt = if samples.is_empty() {
+            serde_json::Value::Null
+        } else {
+            json!({
+                "last_ms": samples.last(),
+                "min_ms": samples.iter().cloned().fold(f64::INFINITY, f64::min),
+                "max_ms": samples.iter().cloned().fold(0.0, f64::max),
+                "avg_ms": samples.iter().sum::<f64>() / samples.len() as f64,
+                "samples": samples.len()
+            })
+        };
+        let current = log.current.as_ref().map(|c| json!({
+            "generation": c.generation,
+            "connected_at": c.connected_at,
+            "uptime_secs": c.started.elapsed().as_secs()
+        }));
+
+        json!({
+            "current_connection": current,
+            "rtt": rtt,
+            "pings_awaiting_pong": log.pending_pings.len(),
+            "unmatched_pongs": log.unmatched_pongs,
+            "frames_in": self.frames_in.load(Ordering::Relaxed),
+            "bytes_in": self.bytes_in.load(Ordering::Relaxed),
+            "frames_out": self.frames_out.load(Ordering::Relaxed),
+            "bytes_out": self.bytes_out.load(Ordering::Relaxed),
+            "parse_failures": log.parse_failures,
+            "handler_failures": log.handler_failures,
+            "connection_history": log.history
+        })
+    }
+}
+
+fn push_record(log: &mut DiagnosticsLog, record: ConnectionRecord) {
+    if log.history.len() == HISTORY_LIMIT {
+        log.history.pop_front();
+    }
+    log.history.push_back(record);
+}
+
+#[tauri::command]
+pub async fn get_websocket_diagnostics(
+    ws_state: State<'_, Arc<TokioMutex<WebSocketState>>>
+) -> Result<serde_json::Value, String> {
+    let state = ws_state.lock().await;
+    let mut diagnostics = state.diagnostics.snapshot();
+    if let Some(object) = diagnostics.as_object_mut() {
+        let connection_state = match state.connection_state {
+            ConnectionState::Connected => "connected",
+            ConnectionState::Disconnected => "disconnected",
+            ConnectionState::Connecting => "connecting",
+        };
+        object.insert("connection_state".to_string(), json!(connection_state));
+        object.insert("generation".to_string(), json!(state.generation));
+        object.insert("encoding".to_string(), json!(state.encoding.as_str()));
+        object.insert("reconnect_attempts".to_string(), json!(state.reconnect_attempts));
+        object.insert("seconds_since_last_frame".to_string(), json!(state.last_heartbeat.elapsed().as_secs()));
+    }
+    Ok(diagnostics)
+}
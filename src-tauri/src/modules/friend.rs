@@ -1,10 +1,12 @@
 use reqwest;
 use serde_json;
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 use std::collections::HashMap;
 use std::sync::Mutex;
 use crate::database_async::{self as db_async};
+use crate::repo::{FriendRepo, SqliteRepo};
 use crate::modules::auth::get_current_user_with_token;
+use crate::timestamp::Timestamp;
 
 // ======== FRIEND STRUCTURES ========
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -262,10 +264,10 @@ pub async fn decline_friend_request(token: String, user_id: String) -> Result<()
 }
 
 #[tauri::command]
-pub async fn get_cached_friends_only() -> Result<Vec<Friend>, String> {
+pub async fn get_cached_friends_only(repo: State<'_, SqliteRepo>) -> Result<Vec<Friend>, String> {
     println!("Getting cached friends only");
     
-    let friends = db_async::get_all_friends().await
+    let friends = repo.get_all_friends().await
         .map_err(|e| format!("Database error: {e}"))?;
     
     let converted_friends: Vec<Friend> = friends.into_iter().map(|db_friend| Friend {
@@ -282,7 +284,7 @@ pub async fn get_cached_friends_only() -> Result<Vec<Friend>, String> {
 }
 
 #[tauri::command]
-pub async fn fetch_all_friends_and_save(token: String) -> Result<Vec<Friend>, String> {
+pub async fn fetch_all_friends_and_save(repo: State<'_, SqliteRepo>, token: String) -> Result<Vec<Friend>, String> {
     println!("Fetching all friends and saving to database");
     
     let friends = get_friends_with_token(token).await?;
@@ -295,12 +297,12 @@ pub async fn fetch_all_friends_and_save(token: String) -> Result<Vec<Friend>, St
             email: friend.email.clone(),
             picture: friend.picture.clone(),
             is_favorite: friend.is_favorite.unwrap_or(false),
-            created_at: Some(chrono::Utc::now().timestamp()),
-            updated_at: Some(chrono::Utc::now().timestamp()),
+            created_at: Some(Timestamp::now()),
+            updated_at: Some(Timestamp::now()),
             status: Some(String::new()),
         };
         
-        if let Err(e) = db_async::insert_or_update_friend(&db_friend).await {
+        if let Err(e) = repo.insert_or_update_friend(&db_friend).await {
             println!("Failed to save friend {}: {}", friend.user_id, e);
         }
     }
@@ -310,7 +312,7 @@ pub async fn fetch_all_friends_and_save(token: String) -> Result<Vec<Friend>, St
 }
 
 #[tauri::command]
-pub async fn get_cached_friends_with_delta(state: State<'_, Mutex<HashMap<String, String>>>) -> Result<Vec<Friend>, String> {
+pub async fn get_cached_friends_with_delta(state: State<'_, Mutex<HashMap<String, String>>>, repo: State<'_, SqliteRepo>) -> Result<Vec<Friend>, String> {
     let token = {
         let store = state.lock().unwrap();
         store.get("access_token")
@@ -321,7 +323,7 @@ pub async fn get_cached_friends_with_delta(state: State<'_, Mutex<HashMap<String
     println!("Getting cached friends with delta update");
     
     // First get cached friends
-    let cached_friends = get_cached_friends_only().await?;
+    let cached_friends = get_cached_friends_only(repo).await?;
     
     // Then try to fetch fresh data
     match get_friends_with_token(token).await {
@@ -339,107 +341,49 @@ pub async fn get_cached_friends_with_delta(state: State<'_, Mutex<HashMap<String
 
 
 #[tauri::command]
-pub async fn friends_delta_update(token: String) -> Result<Vec<Friend>, String> {
+pub async fn friends_delta_update(app: AppHandle, repo: State<'_, SqliteRepo>, token: String) -> Result<db_async::DeltaResult, String> {
     println!("Performing friends delta update for current user");
     
-    // Get current cached friends
-    let cached_friends = get_cached_friends_only().await?;
-    let cached_friend_ids: std::collections::HashSet<String> = cached_friends.iter()
-        .map(|friend| friend.user_id.clone())
-        .collect();
-    
-    println!("Found {} cached friends", cached_friend_ids.len());
-    
     // Fetch fresh friends from server
     let server_friends = get_friends_with_token(token.clone()).await?;
-    let server_friend_ids: std::collections::HashSet<String> = server_friends.iter()
-        .map(|friend| friend.user_id.clone())
-        .collect();
-    
-    println!("Found {} server friends", server_friend_ids.len());
-    
-    // Find friends that exist on server but not locally (new friends)
-    let new_friend_ids: Vec<String> = server_friend_ids.difference(&cached_friend_ids)
-        .cloned()
-        .collect();
+    println!("Found {} server friends", server_friends.len());
+    
+    let now = Timestamp::now();
+    let db_friends: Vec<db_async::Friend> = server_friends.iter().map(|server_friend| db_async::Friend {
+        user_id: server_friend.user_id.clone(),
+        username: server_friend.username.clone(),
+        name: server_friend.name.clone(),
+        email: server_friend.email.clone(),
+        picture: server_friend.picture.clone(),
+        is_favorite: server_friend.is_favorite.unwrap_or(false),
+        created_at: Some(now),
+        updated_at: Some(now),
+        status: Some("accepted".to_string()),
+    }).collect();
     
-    // Find friends that exist locally but not on server (deleted friends)
-    let deleted_friend_ids: Vec<String> = cached_friend_ids.difference(&server_friend_ids)
-        .cloned()
-        .collect();
+    let delta = repo.apply_friend_delta(&db_friends).await
+        .map_err(|e| format!("Database error: {e}"))?;
     
-    println!("New friends to add: {:?}", new_friend_ids);
-    println!("Friends to delete: {:?}", deleted_friend_ids);
+    println!("New friends: {:?}", delta.added);
+    println!("Updated friends: {:?}", delta.updated);
+    println!("Removed friends: {:?}", delta.removed);
     
-    // Delete friends that no longer exist on server
-    for user_id in &deleted_friend_ids {
-        println!("Removing deleted friend from database: {}", user_id);
-        
-        if let Err(e) = db_async::delete_friend(user_id).await {
-            println!("Failed to delete friend {}: {}", user_id, e);
-        }
+    if !delta.is_empty() {
+        app.emit("friends-delta", &delta).ok();
     }
-    
-    // Add new friends from server
-    for server_friend in &server_friends {
-        if new_friend_ids.contains(&server_friend.user_id) {
-            println!("Adding new friend to database: {}", server_friend.user_id);
-            
-            let db_friend = db_async::Friend {
-                user_id: server_friend.user_id.clone(),
-                username: server_friend.username.clone(),
-                name: server_friend.name.clone(),
-                email: server_friend.email.clone(),
-                picture: server_friend.picture.clone(),
-                is_favorite: server_friend.is_favorite.unwrap_or(false),
-                created_at: Some(chrono::Utc::now().timestamp()),
-                updated_at: Some(chrono::Utc::now().timestamp()),
-                status: Some("accepted".to_string()),
-            };
-            
-            if let Err(e) = db_async::insert_or_update_friend(&db_friend).await {
-                println!("Failed to insert new friend {}: {}", server_friend.user_id, e);
-            }
-        }
-    }
-    
-    // Update existing friends with any changes from server
-    for server_friend in &server_friends {
-        if cached_friend_ids.contains(&server_friend.user_id) {
-            println!("Updating existing friend: {}", server_friend.user_id);
-            
-            let db_friend = db_async::Friend {
-                user_id: server_friend.user_id.clone(),
-                username: server_friend.username.clone(),
-                name: server_friend.name.clone(),
-                email: server_friend.email.clone(),
-                picture: server_friend.picture.clone(),
-                is_favorite: server_friend.is_favorite.unwrap_or(false),
-                created_at: Some(chrono::Utc::now().timestamp()),
-                updated_at: Some(chrono::Utc::now().timestamp()),
-                status: Some("accepted".to_string()),
-            };
-            
-            if let Err(e) = db_async::insert_or_update_friend(&db_friend).await {
-                println!("Failed to update friend {}: {}", server_friend.user_id, e);
-            }
-        }
-    }
-    
-    // Return updated friends list
-    get_cached_friends_only().await
+    Ok(delta)
 }
 
 #[tauri::command]
-pub async fn get_cached_friends_for_current_user() -> Result<Vec<Friend>, String> {
+pub async fn get_cached_friends_for_current_user(repo: State<'_, SqliteRepo>) -> Result<Vec<Friend>, String> {
     println!("Getting cached friends for current user");
-    get_cached_friends_only().await
+    get_cached_friends_only(repo).await
 }
 
 #[tauri::command]
-pub async fn delete_friend_from_database(user_id: String) -> Result<(), String> {
+pub async fn delete_friend_from_database(repo: State<'_, SqliteRepo>, user_id: String) -> Result<(), String> {
     println!("Deleting friend from database: {}", user_id);
     
-    db_async::delete_friend(&user_id).await
+    repo.delete_friend(&user_id).await
         .map_err(|e| format!("Database error: {e}"))
 } 
\ No newline at end of file
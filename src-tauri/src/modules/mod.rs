@@ -1,7 +1,15 @@
+pub mod archive;
 pub mod auth;
 pub mod chat;
+pub mod codec;
 pub mod database;
+pub mod diagnostics;
 pub mod friend;
 pub mod participant;
+pub mod presence;
+pub mod profiles;
+pub mod receipts;
+pub mod retention;
+pub mod typing;
 pub mod websocket;
 pub mod window; 
\ No newline at end of file
@@ -1,10 +1,12 @@
 use reqwest;
 use serde_json;
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 use std::collections::HashMap;
 use std::sync::Mutex;
 use chrono;
 use crate::database_async::{self as db_async};
+use crate::repo::{FriendRepo, ParticipantRepo, SqliteRepo, UserRepo};
+use crate::timestamp::Timestamp;
 
 // ======== PARTICIPANT STRUCTURES ========
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -53,15 +55,16 @@ pub struct ParticipantSimple {
 
 #[tauri::command]
 pub async fn create_participant_from_user_id(
+    repo: State<'_, SqliteRepo>,
     user_id: String,
     chat_id: String,
     is_admin: bool,
-    joined_at: i64,
+    joined_at: Timestamp,
 ) -> Result<db_async::Participant, String> {
     println!("Creating participant for user_id: {} in chat: {}", user_id, chat_id);
     
     // Try to get the actual username from local database or friends list only (no API call without token)
-    let username = get_username_for_user_id_local_only(&user_id).await.unwrap_or_else(|e| {
+    let username = get_username_for_user_id_local_only(&repo, &user_id).await.unwrap_or_else(|e| {
         println!("Failed to get username for user_id {}: {}, using fallback", user_id, e);
         // Fallback: use a placeholder that indicates we need to resolve the username
         format!("user_{}", user_id.chars().take(8).collect::<String>())
@@ -85,6 +88,7 @@ pub async fn create_participant_from_user_id(
 #[tauri::command]
 pub async fn add_participants(
     state: State<'_, Mutex<HashMap<String, String>>>,
+    repo: State<'_, SqliteRepo>,
     chat_id: String,
     participant_ids: Vec<String>,
     admin_ids: Vec<String>,
@@ -96,11 +100,12 @@ pub async fn add_participants(
             .clone()
     };
 
-    add_participants_with_token(token, chat_id, participant_ids, admin_ids).await
+    add_participants_with_token(repo, token, chat_id, participant_ids, admin_ids).await
 }
 
 #[tauri::command]
 pub async fn add_participants_with_token(
+    repo: State<'_, SqliteRepo>,
     token: String,
     chat_id: String,
     participant_ids: Vec<String>,
@@ -109,7 +114,7 @@ pub async fn add_participants_with_token(
     println!("Adding participants to chat: {}", chat_id);
     
     // Filter out participants that are already in the chat
-    let existing_participants = get_cached_participants_for_chat(chat_id.clone()).await?;
+    let existing_participants = get_cached_participants_for_chat(repo.clone(), chat_id.clone()).await?;
     let existing_user_ids: std::collections::HashSet<String> = existing_participants
         .iter()
         .map(|p| p.user_id.clone())
@@ -156,19 +161,19 @@ pub async fn add_participants_with_token(
         
         // Save participants to database
         for user_id in &filtered {
-            let username = get_username_for_user_id(&token, user_id).await?;
+            let username = get_username_for_user_id(&repo, &token, user_id).await?;
             let is_admin = admin_ids.contains(user_id);
             
             let db_participant = db_async::Participant {
                 participant_id: format!("{}_{}", chat_id, user_id),
                 user_id: user_id.clone(),
                 username,
-                joined_at: chrono::Utc::now().timestamp(),
+                joined_at: Timestamp::now(),
                 role: if is_admin { "admin".to_string() } else { "member".to_string() },
                 chat_id: chat_id.clone(),
             };
             
-            if let Err(e) = db_async::insert_or_update_participant(&db_participant).await {
+            if let Err(e) = repo.insert_or_update_participant(&db_participant).await {
                 println!("Failed to save participant {}: {}", user_id, e);
             }
         }
@@ -183,6 +188,7 @@ pub async fn add_participants_with_token(
 #[tauri::command]
 pub async fn remove_participant(
     state: State<'_, Mutex<HashMap<String, String>>>,
+    repo: State<'_, SqliteRepo>,
     chat_id: String,
     user_id: String,
 ) -> Result<(), String> {
@@ -193,11 +199,12 @@ pub async fn remove_participant(
             .clone()
     };
 
-    remove_participant_with_token(token, chat_id, user_id).await
+    remove_participant_with_token(repo, token, chat_id, user_id).await
 }
 
 #[tauri::command]
 pub async fn remove_participant_with_token(
+    repo: State<'_, SqliteRepo>,
     token: String,
     chat_id: String,
     user_id: String,
@@ -223,7 +230,7 @@ pub async fn remove_participant_with_token(
         
         // Remove from database
         let participant_id = format!("{}_{}", chat_id, user_id);
-        if let Err(e) = db_async::delete_participant(&participant_id).await {
+        if let Err(e) = repo.delete_participant(&participant_id).await {
             println!("Failed to remove participant from database: {}", e);
         }
         
@@ -237,6 +244,7 @@ pub async fn remove_participant_with_token(
 #[tauri::command]
 pub async fn update_participant_role(
     state: State<'_, Mutex<HashMap<String, String>>>,
+    repo: State<'_, SqliteRepo>,
     chat_id: String,
     user_id: String,
     new_role: String,
@@ -248,11 +256,12 @@ pub async fn update_participant_role(
             .clone()
     };
 
-    update_participant_role_with_token(token, chat_id, user_id, new_role).await
+    update_participant_role_with_token(repo, token, chat_id, user_id, new_role).await
 }
 
 #[tauri::command]
 pub async fn update_participant_role_with_token(
+    repo: State<'_, SqliteRepo>,
     token: String,
     chat_id: String,
     user_id: String,
@@ -261,15 +270,15 @@ pub async fn update_participant_role_with_token(
     println!("Updating participant {} role to {} in chat: {}", user_id, new_role, chat_id);
     
     // First remove the participant
-    remove_participant_with_token(token.clone(), chat_id.clone(), user_id.clone()).await?;
+    remove_participant_with_token(repo.clone(), token.clone(), chat_id.clone(), user_id.clone()).await?;
     
     // Then add them back with the new role
     let admin_ids = if new_role == "admin" { vec![user_id.clone()] } else { vec![] };
-    add_participants_with_token(token, chat_id.clone(), vec![user_id.clone()], admin_ids).await?;
+    add_participants_with_token(repo.clone(), token, chat_id.clone(), vec![user_id.clone()], admin_ids).await?;
     
     // Update the database role
     let participant_id = format!("{}_{}", chat_id, user_id);
-    db_async::update_participant_role(&participant_id, &new_role).await
+    repo.update_participant_role(&participant_id, &new_role).await
         .map_err(|e| format!("Database error: {e}"))?;
     
     println!("Successfully updated participant role");
@@ -278,9 +287,11 @@ pub async fn update_participant_role_with_token(
 
 #[tauri::command]
 pub async fn sync_participants_with_api(
+    app: AppHandle,
     state: State<'_, Mutex<HashMap<String, String>>>,
+    repo: State<'_, SqliteRepo>,
     chat_id: String,
-) -> Result<(), String> {
+) -> Result<db_async::DeltaResult, String> {
     let token = {
         let store = state.lock().unwrap();
         store.get("access_token")
@@ -288,22 +299,42 @@ pub async fn sync_participants_with_api(
             .clone()
     };
 
-    sync_participants_with_api_token(token, chat_id).await
+    sync_participants_with_api_token(app, repo, token, chat_id).await
 }
 
 #[tauri::command]
 pub async fn sync_participants_with_api_token(
+    app: AppHandle,
+    repo: State<'_, SqliteRepo>,
     token: String,
     chat_id: String,
-) -> Result<(), String> {
+) -> Result<db_async::DeltaResult, String> {
+    let delta = sync_participants_for_chat(&repo, token, chat_id.clone()).await?;
+    if !delta.is_empty() {
+        app.emit("participants-delta", serde_json::json!({
+            "chat_id": chat_id,
+            "added": delta.added,
+            "updated": delta.updated,
+            "removed": delta.removed
+        })).ok();
+    }
+    Ok(delta)
+}
+
+// Fetch the members of a chat and apply them to the cache in one transaction
+pub async fn sync_participants_for_chat(
+    repo: &SqliteRepo,
+    token: String,
+    chat_id: String,
+) -> Result<db_async::DeltaResult, String> {
     println!("Syncing participants with API for chat: {}", chat_id);
     
     // Check if we already have participants for this chat and they're recent
-    let existing_participants = db_async::get_participants_for_chat(&chat_id).await;
+    let existing_participants = repo.get_participants_for_chat(&chat_id).await;
     if let Ok(participants) = existing_participants {
         if !participants.is_empty() {
             println!("Found {} existing participants for chat {}, skipping API call", participants.len(), chat_id);
-            return Ok(());
+            return Ok(db_async::DeltaResult::default());
         }
     }
     
@@ -327,47 +358,28 @@ pub async fn sync_participants_with_api_token(
         
         println!("Successfully retrieved {} participants from API", participants_response.data.len());
         
-        // Only clear if we have new data
-        if !participants_response.data.is_empty() {
-            let participant_count = participants_response.data.len();
-            
-            // Clear existing participants for this chat
-            let existing_participants = db_async::get_participants_for_chat(&chat_id).await
-                .map_err(|e| format!("Database error: {e}"))?;
-            
-            for participant in existing_participants {
-                if let Err(e) = db_async::delete_participant(&participant.participant_id).await {
-                    println!("Failed to delete existing participant: {}", e);
-                }
-            }
-            
-            // Save new participants
-            for api_participant in &participants_response.data {
-                let user_id = api_participant.user.user_id.clone();
-                let username = api_participant.user.username.clone();
-                
-                println!("Saving participant: user_id={}, username={}", user_id, username);
-                
-                let db_participant = db_async::Participant {
-                    participant_id: format!("{}_{}", chat_id, user_id),
-                    user_id: user_id,
-                    username: username,
-                    joined_at: chrono::Utc::now().timestamp(),
-                    role: if api_participant.is_admin { "admin".to_string() } else { "member".to_string() },
-                    chat_id: chat_id.clone(),
-                };
-                
-                if let Err(e) = db_async::insert_or_update_participant(&db_participant).await {
-                    println!("Failed to save participant {}: {}", api_participant.user.user_id, e);
-                } else {
-                    println!("Successfully saved participant: {}", api_participant.user.user_id);
-                }
-            }
-            
-            println!("Successfully synced {} participants for chat {}", participant_count, chat_id);
+        // Only replace if we have new data
+        if participants_response.data.is_empty() {
+            return Ok(db_async::DeltaResult::default());
         }
         
-        Ok(())
+        let db_participants: Vec<db_async::Participant> = participants_response.data.iter().map(|api_participant| {
+            let user_id = api_participant.user.user_id.clone();
+            db_async::Participant {
+                participant_id: format!("{}_{}", chat_id, user_id),
+                user_id,
+                username: api_participant.user.username.clone(),
+                joined_at: Timestamp::parse_rfc3339(&api_participant.joined_at).unwrap_or_else(|_| Timestamp::now()),
+                role: if api_participant.is_admin { "admin".to_string() } else { "member".to_string() },
+                chat_id: chat_id.clone(),
+            }
+        }).collect();
+        
+        let delta = repo.apply_participant_delta(&chat_id, &db_participants).await
+            .map_err(|e| format!("Database error: {e}"))?;
+        
+        println!("Successfully synced {} participants for chat {}", db_participants.len(), chat_id);
+        Ok(delta)
     } else {
         println!("Failed to sync participants with status: {}", status);
         Err(format!("Failed to sync participants: {} - {}", status, text))
@@ -375,10 +387,10 @@ pub async fn sync_participants_with_api_token(
 }
 
 #[tauri::command]
-pub async fn get_cached_participants_for_chat(chat_id: String) -> Result<Vec<Participant>, String> {
+pub async fn get_cached_participants_for_chat(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<Vec<Participant>, String> {
     println!("Getting cached participants for chat: {}", chat_id);
     
-    let participants = db_async::get_participants_for_chat(&chat_id).await
+    let participants = repo.get_participants_for_chat(&chat_id).await
         .map_err(|e| format!("Database error: {e}"))?;
     
     println!("Found {} participants in database for chat {}", participants.len(), chat_id);
@@ -405,32 +417,32 @@ pub async fn get_cached_participants_for_chat(chat_id: String) -> Result<Vec<Par
 }
 
 #[tauri::command]
-pub async fn get_participant_by_user_id(chat_id: String, user_id: String) -> Result<Option<Participant>, String> {
+pub async fn get_participant_by_user_id(repo: State<'_, SqliteRepo>, chat_id: String, user_id: String) -> Result<Option<Participant>, String> {
     println!("Getting participant {} for chat: {}", user_id, chat_id);
     
-    let participants = get_cached_participants_for_chat(chat_id).await?;
+    let participants = get_cached_participants_for_chat(repo, chat_id).await?;
     let participant = participants.into_iter().find(|p| p.user_id == user_id);
     
     Ok(participant)
 }
 
 #[tauri::command]
-pub async fn is_participant_in_chat(chat_id: String, user_id: String) -> Result<bool, String> {
+pub async fn is_participant_in_chat(repo: State<'_, SqliteRepo>, chat_id: String, user_id: String) -> Result<bool, String> {
     println!("Checking if participant {} is in chat: {}", user_id, chat_id);
     
-    let participant = get_participant_by_user_id(chat_id, user_id).await?;
+    let participant = get_participant_by_user_id(repo, chat_id, user_id).await?;
     Ok(participant.is_some())
 }
 
 #[tauri::command]
-pub async fn clear_cached_participants(chat_id: String) -> Result<(), String> {
+pub async fn clear_cached_participants(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<(), String> {
     println!("Clearing cached participants for chat: {}", chat_id);
     
-    let participants = db_async::get_participants_for_chat(&chat_id).await
+    let participants = repo.get_participants_for_chat(&chat_id).await
         .map_err(|e| format!("Database error: {e}"))?;
     
     for participant in participants {
-        if let Err(e) = db_async::delete_participant(&participant.participant_id).await {
+        if let Err(e) = repo.delete_participant(&participant.participant_id).await {
             println!("Failed to delete participant: {}", e);
         }
     }
@@ -440,15 +452,15 @@ pub async fn clear_cached_participants(chat_id: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn get_username_for_user_id_command(token: String, user_id: String) -> Result<String, String> {
-    get_username_for_user_id(&token, &user_id).await
+pub async fn get_username_for_user_id_command(repo: State<'_, SqliteRepo>, token: String, user_id: String) -> Result<String, String> {
+    get_username_for_user_id(&repo, &token, &user_id).await
 }
 
-pub async fn get_username_for_user_id_local_only(user_id: &str) -> Result<String, String> {
+pub async fn get_username_for_user_id_local_only(repo: &SqliteRepo, user_id: &str) -> Result<String, String> {
     println!("Getting username for user_id (local only): {}", user_id);
     
     // First try to get from local database if we have user data
-    match db_async::get_user_by_id(user_id).await {
+    match repo.get_user_by_id(user_id).await {
         Ok(Some(user)) => {
             if !user.username.is_empty() && user.username != user_id {
                 println!("Found username in local user database: {}", user.username);
@@ -466,7 +478,7 @@ pub async fn get_username_for_user_id_local_only(user_id: &str) -> Result<String
     }
     
     // If no local user data or username is missing, try to get from friends list
-    match db_async::get_friend_by_id(user_id).await {
+    match repo.get_friend_by_id(user_id).await {
         Ok(Some(friend)) => {
             if !friend.username.is_empty() && friend.username != user_id {
                 println!("Found username in friends list: {}", friend.username);
@@ -494,11 +506,11 @@ pub async fn get_username_for_user_id_local_only(user_id: &str) -> Result<String
     Ok(truncated_id)
 }
 
-pub async fn get_username_for_user_id(token: &str, user_id: &str) -> Result<String, String> {
+pub async fn get_username_for_user_id(repo: &SqliteRepo, token: &str, user_id: &str) -> Result<String, String> {
     println!("Getting username for user_id: {}", user_id);
     
     // First try to get from local database if we have user data
-    match db_async::get_user_by_id(user_id).await {
+    match repo.get_user_by_id(user_id).await {
         Ok(Some(user)) => {
             if !user.username.is_empty() && user.username != user_id {
                 println!("Found username in local user database: {}", user.username);
@@ -516,7 +528,7 @@ pub async fn get_username_for_user_id(token: &str, user_id: &str) -> Result<Stri
     }
     
     // If no local user data or username is missing, try to get from friends list
-    match db_async::get_friend_by_id(user_id).await {
+    match repo.get_friend_by_id(user_id).await {
         Ok(Some(friend)) => {
             if !friend.username.is_empty() && friend.username != user_id {
                 println!("Found username in friends list: {}", friend.username);
@@ -550,9 +562,9 @@ pub async fn get_username_for_user_id(token: &str, user_id: &str) -> Result<Stri
                         if !username.is_empty() && username != user_id {
                             println!("Found username from API: {}", username);
                             // Cache this username in the local database for future use
-                            if let Ok(Some(mut user)) = db_async::get_user_by_id(user_id).await {
+                            if let Ok(Some(mut user)) = repo.get_user_by_id(user_id).await {
                                 user.username = username.to_string();
-                                if let Err(e) = db_async::insert_or_update_user(&user).await {
+                                if let Err(e) = repo.insert_or_update_user(&user).await {
                                     println!("Failed to cache username for user {}: {}", user_id, e);
                                 } else {
                                     println!("Successfully cached username for user {}", user_id);
@@ -569,14 +581,14 @@ pub async fn get_username_for_user_id(token: &str, user_id: &str) -> Result<Stri
                                     role: user_data["role"].as_str().map(|s| s.to_string()),
                                     token_hash: None,
                                     verified: user_data["verified"].as_bool().unwrap_or(false),
-                                    created_at: chrono::Utc::now().timestamp(),
-                                    updated_at: chrono::Utc::now().timestamp(),
+                                    created_at: Timestamp::now(),
+                                    updated_at: Timestamp::now(),
                                     deleted_at: None,
                                     is_dark_mode: false,
-                                    last_seen: chrono::Utc::now().timestamp(),
+                                    last_seen: Timestamp::now(),
                                     color_scheme: Some("blue".to_string()),
                                 };
-                                if let Err(e) = db_async::insert_or_update_user(&new_user).await {
+                                if let Err(e) = repo.insert_or_update_user(&new_user).await {
                                     println!("Failed to create user entry for {}: {}", user_id, e);
                                 } else {
                                     println!("Successfully created new user entry for {}", user_id);
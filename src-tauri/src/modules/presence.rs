@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::{mpsc, Mutex as TokioMutex};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::database_async;
+use crate::repo::SqliteRepo;
+use crate::timestamp::Timestamp;
+use crate::modules::websocket::{enqueue_outbound, OutboundFrame, OverflowPolicy, QueueStats, SocketTx, WebSocketState};
+
+// We report ourselves as away after this long without user activity
+const AWAY_AFTER: Duration = Duration::from_secs(5 * 60);
+// How often the presence task checks for idleness
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+impl PresenceStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PresenceStatus::Online => "online",
+            PresenceStatus::Away => "away",
+            PresenceStatus::Offline => "offline",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "online" => Some(PresenceStatus::Online),
+            "away" | "idle" => Some(PresenceStatus::Away),
+            "offline" => Some(PresenceStatus::Offline),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PresenceInfo {
+    pub user_id: String,
+    pub status: PresenceStatus,
+    pub last_seen: Option<Timestamp>,
+}
+
+pub struct PresenceTracker {
+    // Live statuses received on the current connection
+    remote: HashMap<String, PresenceInfo>,
+    last_activity: Instant,
+    // What the server last heard from us on this connection
+    sent_status: Option<PresenceStatus>,
+}
+
+pub struct PresenceState(pub TokioMutex<PresenceTracker>);
+
+impl Default for PresenceState {
+    fn default() -> Self {
+        Self(TokioMutex::new(PresenceTracker {
+            remote: HashMap::new(),
+            last_activity: Instant::now(),
+            sent_status: None,
+        }))
+    }
+}
+
+impl PresenceTracker {
+    fn own_status(&self) -> PresenceStatus {
+        if self.last_activity.elapsed() >= AWAY_AFTER {
+            PresenceStatus::Away
+        } else {
+            PresenceStatus::Online
+        }
+    }
+}
+
+async fn send_presence(
+    tx: &mpsc::Sender<OutboundFrame>,
+    status: PresenceStatus,
+    stats: &QueueStats,
+) -> Result<bool, String> {
+    let frame = json!({
+        "type": "presence",
+        "message": {
+            "status": status.as_str()
+        }
+    });
+    // Ok(false) means the frame was dropped; callers leave sent_status alone so the
+    // next idle check sends it again
+    enqueue_outbound(tx, frame.to_string(), OverflowPolicy::DropNewest, stats).await
+}
+
+// Called by the frontend on user input; flips us back to online if we were away
+#[tauri::command]
+pub async fn report_user_activity(
+    socket_tx: State<'_, Arc<SocketTx>>,
+    ws_state: State<'_, Arc<TokioMutex<WebSocketState>>>,
+    presence_state: State<'_, Arc<PresenceState>>,
+) -> Result<(), String> {
+    // connect_socket locks ws_state before the presence state, so never the other way round
+    let queue_stats = Arc::clone(&ws_state.lock().await.queue_stats);
+    let tx = socket_tx.0.lock().await.clone();
+
+    let mut tracker = presence_state.0.lock().await;
+    tracker.last_activity = Instant::now();
+    if tracker.sent_status == Some(PresenceStatus::Online) {
+        return Ok(());
+    }
+    if let Some(tx) = tx {
+        match send_presence(&tx, PresenceStatus::Online, &queue_stats).await {
+            Ok(true) => {
+                println!("[Presence] User active again, reported online");
+                tracker.sent_status = Some(PresenceStatus::Online);
+            }
+            Ok(false) => println!("[Presence] Online status dropped, send queue is full"),
+            Err(e) => println!("[Presence] Failed to report online status: {}", e),
+        }
+    }
+    Ok(())
+}
+
+// Current presence for the given users: live status if we have one, otherwise the stored last_seen
+#[tauri::command]
+pub async fn get_presence(
+    user_ids: Vec<String>,
+    presence_state: State<'_, Arc<PresenceState>>,
+    repo: State<'_, SqliteRepo>,
+) -> Result<Vec<PresenceInfo>, String> {
+    let live: HashMap<String, PresenceInfo> = {
+        let tracker = presence_state.0.lock().await;
+        user_ids.iter()
+            .filter_map(|id| tracker.remote.get(id).map(|info| (id.clone(), info.clone())))
+            .collect()
+    };
+
+    let missing: Vec<String> = user_ids.iter()
+        .filter(|id| !live.contains_key(*id))
+        .cloned()
+        .collect();
+    let stored: HashMap<String, Timestamp> = repo.get_presence_for_users(&missing).await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|presence| (presence.user_id, presence.last_seen))
+        .collect();
+
+    Ok(user_ids.into_iter()
+        .map(|user_id| match live.get(&user_id) {
+            Some(info) => info.clone(),
+            None => PresenceInfo {
+                last_seen: stored.get(&user_id).copied(),
+                user_id,
+                status: PresenceStatus::Offline,
+            },
+        })
+        .collect())
+}
+
+// Handle a presence frame from the server
+pub async fn handle_presence_frame(app: &AppHandle, message_text: &str) -> Result<(), String> {
+    let Some(presence_state) = app.try_state::<Arc<PresenceState>>() else {
+        return Ok(());
+    };
+
+    let value: serde_json::Value = serde_json::from_str(message_text)
+        .map_err(|e| format!("Failed to parse presence frame: {}", e))?;
+    let message = value.get("message").unwrap_or(&value);
+    let Some(user_id) = message.get("user_id").and_then(|v| v.as_str()) else {
+        return Err("Presence frame missing user_id".to_string());
+    };
+    let Some(status) = message.get("status").and_then(|v| v.as_str()).and_then(PresenceStatus::parse) else {
+        return Err(format!("Presence frame with unknown status: {}", message_text));
+    };
+
+    // Servers send last_seen either as Unix seconds or as an RFC 3339 string
+    let now = Timestamp::now();
+    let last_seen = match message.get("last_seen") {
+        Some(serde_json::Value::Number(n)) => n.as_i64().map(Timestamp::from_any),
+        Some(serde_json::Value::String(s)) => Timestamp::parse_rfc3339(s).ok(),
+        _ => None,
+    };
+    let last_seen = match status {
+        // Someone online is being seen right now
+        PresenceStatus::Online => now,
+        _ => last_seen.unwrap_or(now),
+    };
+
+    let info = PresenceInfo {
+        user_id: user_id.to_string(),
+        status,
+        last_seen: Some(last_seen),
+    };
+
+    let changed = {
+        let mut tracker = presence_state.0.lock().await;
+        let previous = tracker.remote.insert(user_id.to_string(), info.clone());
+        previous.map(|p| p.status) != Some(status)
+    };
+
+    if let Err(e) = app.state::<SqliteRepo>().upsert_presence(&database_async::Presence {
+        user_id: user_id.to_string(),
+        status: status.as_str().to_string(),
+        last_seen,
+    }).await {
+        println!("[Presence] Failed to store presence for {}: {}", user_id, e);
+    }
+
+    if changed {
+        println!("[Presence] {} is now {}", user_id, status.as_str());
+        app.emit("presence-changed", &info).ok();
+    }
+    Ok(())
+}
+
+// Forget live statuses from the previous connection; everyone is offline until told otherwise
+pub async fn reset_presence(app: &AppHandle) {
+    let Some(presence_state) = app.try_state::<Arc<PresenceState>>() else {
+        return;
+    };
+    let mut tracker = presence_state.0.lock().await;
+    tracker.sent_status = None;
+    for (_, mut info) in tracker.remote.drain() {
+        if info.status != PresenceStatus::Offline {
+            info.status = PresenceStatus::Offline;
+            app.emit("presence-changed", &info).ok();
+        }
+    }
+}
+
+// Connection task: announce ourselves, then report away/online as the user goes idle or returns
+pub async fn run_presence_task(
+    app: AppHandle,
+    tx: mpsc::Sender<OutboundFrame>,
+    queue_stats: Arc<QueueStats>,
+    cancel: CancellationToken,
+) {
+    let Some(presence_state) = app.try_state::<Arc<PresenceState>>() else {
+        return;
+    };
+    let presence_state = Arc::clone(presence_state.inner());
+
+    loop {
+        {
+            let mut tracker = presence_state.0.lock().await;
+            let status = tracker.own_status();
+            if tracker.sent_status != Some(status) {
+                match send_presence(&tx, status, &queue_stats).await {
+                    Ok(true) => {
+                        println!("[Presence] Reported own status: {}", status.as_str());
+                        tracker.sent_status = Some(status);
+                    }
+                    Ok(false) => println!("[Presence] Own status dropped, retrying on the next check"),
+                    Err(e) => println!("[Presence] Failed to report own status: {}", e),
+                }
+            }
+        }
+
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = sleep(IDLE_CHECK_INTERVAL) => {}
+        }
+    }
+    println!("[Presence] Presence task ended");
+}
@@ -0,0 +1,114 @@
+use futures::future::join_all;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Mutex as TokioMutex;
+use tokio::time::sleep;
+use crate::repo::{MessageRepo, SqliteRepo, UserRepo};
+use crate::modules::participant::get_username_for_user_id;
+use crate::modules::websocket::{message_updated_payload, WebSocketState};
+
+// Messages arriving within this window share one backfill
+const BACKFILL_DELAY: Duration = Duration::from_secs(1);
+// Most unknown senders looked up on the server per backfill
+const MAX_REMOTE_LOOKUPS: usize = 20;
+
+// Sender name backfill; at most one is waiting or running at a time
+#[derive(Default)]
+pub struct BackfillState {
+    // A backfill task is waiting or running
+    active: bool,
+    // More messages came in while it was running; it goes round once more
+    requested: bool,
+}
+
+#[derive(Default)]
+pub struct ProfileState(pub TokioMutex<BackfillState>);
+
+// The sender's name from the local users, friends and participants, without going to the network
+pub async fn cached_username(app: &AppHandle, user_id: &str) -> Option<String> {
+    let repo = app.state::<SqliteRepo>();
+    match repo.get_usernames(&[user_id.to_string()]).await {
+        Ok(mut names) => names.remove(user_id),
+        Err(e) => {
+            println!("[Profiles] Failed to look up username for {}: {}", user_id, e);
+            None
+        }
+    }
+}
+
+// Fill in missing sender names shortly, together with any others that come in meanwhile
+pub async fn schedule_sender_backfill(app: &AppHandle) {
+    let Some(profile_state) = app.try_state::<Arc<ProfileState>>() else {
+        return;
+    };
+    let profile_state = Arc::clone(profile_state.inner());
+    {
+        let mut backfill = profile_state.0.lock().await;
+        if backfill.active {
+            backfill.requested = true;
+            return;
+        }
+        backfill.active = true;
+    }
+
+    let app = app.clone();
+    tokio::spawn(async move {
+        loop {
+            sleep(BACKFILL_DELAY).await;
+            backfill_sender_usernames(&app).await;
+
+            // Only now can another backfill start, so two never overlap
+            let mut backfill = profile_state.0.lock().await;
+            if !backfill.requested {
+                backfill.active = false;
+                break;
+            }
+            backfill.requested = false;
+        }
+    });
+}
+
+// Senders nobody local can name are fetched from the server, which caches them as users;
+// every unnamed message is then filled from the cache in one update and reported as updated.
+// The API only looks up one user per request (users/{id}), so the lookups cannot be
+// batched into one call; they run concurrently instead of one after another.
+pub async fn backfill_sender_usernames(app: &AppHandle) {
+    let repo = app.state::<SqliteRepo>();
+
+    let token = match app.try_state::<Arc<TokioMutex<WebSocketState>>>() {
+        Some(ws_state) => ws_state.lock().await.auth_token.clone(),
+        None => None,
+    };
+    if let Some(token) = token {
+        match repo.get_unresolved_sender_ids().await {
+            Ok(sender_ids) => {
+                let lookups = sender_ids.iter().take(MAX_REMOTE_LOOKUPS).map(|sender_id| {
+                    let repo = &repo;
+                    let token = &token;
+                    async move {
+                        if let Err(e) = get_username_for_user_id(repo, token, sender_id).await {
+                            println!("[Profiles] Failed to fetch username for {}: {}", sender_id, e);
+                        }
+                    }
+                });
+                join_all(lookups).await;
+            }
+            Err(e) => println!("[Profiles] Failed to list unresolved senders: {}", e),
+        }
+    }
+
+    let updated = match repo.fill_sender_usernames().await {
+        Ok(updated) => updated,
+        Err(e) => {
+            println!("[Profiles] Failed to fill in sender usernames: {}", e);
+            return;
+        }
+    };
+    if !updated.is_empty() {
+        println!("[Profiles] Resolved sender usernames for {} messages", updated.len());
+    }
+    for message in &updated {
+        app.emit("message-updated", message_updated_payload(message)).ok();
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::{mpsc, Mutex as TokioMutex};
+use tokio::time::sleep;
+use serde_json::json;
+use crate::database_async::Message;
+use crate::repo::{SqliteRepo, UserRepo};
+use crate::modules::websocket::{enqueue_outbound, OutboundFrame, OverflowPolicy, QueueStats, SocketTx, WebSocketState, OUTBOUND_SEND_TIMEOUT};
+
+// Receipts queued within this window go out together, and at most this often
+const READ_RECEIPT_INTERVAL: Duration = Duration::from_secs(2);
+// Most message ids in one receipt frame
+const MAX_READ_BATCH: usize = 100;
+// Longest wait between retries of receipts that could not be sent
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+// Receipts waiting for the next flush
+#[derive(Default)]
+pub struct ReceiptTracker {
+    // chat_id -> server message ids, in the order they were read
+    pending_read: HashMap<String, Vec<String>>,
+    // chat_id -> server message ids, in the order they arrived
+    pending_delivered: HashMap<String, Vec<String>>,
+    // A flush task is already waiting
+    flush_scheduled: bool,
+    // Flushes in a row that left receipts unsent; sets the retry backoff
+    failed_flushes: u32,
+    // The signed-in user, looked up once per connection
+    own_user_id: Option<String>,
+}
+
+impl ReceiptTracker {
+    fn is_empty(&self) -> bool {
+        self.pending_read.is_empty() && self.pending_delivered.is_empty()
+    }
+}
+
+pub struct ReceiptState(pub TokioMutex<ReceiptTracker>);
+
+impl Default for ReceiptState {
+    fn default() -> Self {
+        Self(TokioMutex::new(ReceiptTracker::default()))
+    }
+}
+
+async fn outbound(app: &AppHandle) -> Option<(mpsc::Sender<OutboundFrame>, Arc<QueueStats>)> {
+    let ws_state = app.try_state::<Arc<TokioMutex<WebSocketState>>>()?;
+    let queue_stats = Arc::clone(&ws_state.lock().await.queue_stats);
+    let tx = app.try_state::<Arc<SocketTx>>()?.0.lock().await.clone()?;
+    Some((tx, queue_stats))
+}
+
+// The signed-in user: the one the socket authenticated as, else the most recent login.
+// Cached until the next connection, since every incoming message needs it.
+async fn own_user_id(app: &AppHandle, receipt_state: &ReceiptState) -> Option<String> {
+    if let Some(user_id) = receipt_state.0.lock().await.own_user_id.clone() {
+        return Some(user_id);
+    }
+
+    let token = match app.try_state::<Arc<TokioMutex<WebSocketState>>>() {
+        Some(ws_state) => ws_state.lock().await.auth_token.clone(),
+        None => None,
+    };
+    let repo = app.state::<SqliteRepo>();
+    let mut user = None;
+    if let Some(token) = token {
+        user = repo.get_user_by_token(&token).await.ok().flatten();
+    }
+    if user.is_none() {
+        user = repo.get_most_recent_user().await.ok().flatten();
+    }
+    let user_id = user.map(|user| user.user_id)?;
+    receipt_state.0.lock().await.own_user_id = Some(user_id.clone());
+    Some(user_id)
+}
+
+// Forget the cached user and send whatever was queued while offline; called when a
+// new connection is established
+pub async fn reset_receipts(app: &AppHandle) {
+    let Some(receipt_state) = app.try_state::<Arc<ReceiptState>>() else {
+        return;
+    };
+    let receipt_state = Arc::clone(receipt_state.inner());
+    let mut tracker = receipt_state.0.lock().await;
+    tracker.own_user_id = None;
+    tracker.failed_flushes = 0;
+    if tracker.is_empty() || tracker.flush_scheduled {
+        return;
+    }
+    tracker.flush_scheduled = true;
+    schedule_flush(app.clone(), Arc::clone(&receipt_state), READ_RECEIPT_INTERVAL);
+}
+
+// Add message ids to a per-chat queue, skipping ones already waiting
+fn enqueue_ids(queue: &mut HashMap<String, Vec<String>>, chat_id: &str, message_ids: impl IntoIterator<Item = String>) {
+    let pending = queue.entry(chat_id.to_string()).or_default();
+    for message_id in message_ids {
+        if !pending.contains(&message_id) {
+            pending.push(message_id);
+        }
+    }
+}
+
+fn status_frame(status: &str, chat_id: &str, message_ids: &[String], own_user_id: Option<&str>) -> String {
+    json!({
+        "type": "message-status",
+        "message": {
+            "chat_id": chat_id,
+            "status": status,
+            "message_ids": message_ids,
+            "sender_id": own_user_id,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        }
+    }).to_string()
+}
+
+// Tell the sender a message reached us. Queued once per newly stored message and sent
+// with the next batch, so a full send queue delays the receipt instead of losing it.
+pub async fn send_delivered_receipt(app: &AppHandle, message: &Message) {
+    let Some(message_id) = message.message_id.clone() else {
+        return;
+    };
+    queue_receipts(app, |tracker, own_user_id| {
+        if own_user_id == Some(message.sender_id.as_str()) {
+            return;
+        }
+        enqueue_ids(&mut tracker.pending_delivered, &message.chat_id, [message_id]);
+    }).await;
+}
+
+// Queue read receipts for messages the user just read. Receipts go out per chat in
+// batches, at most once per READ_RECEIPT_INTERVAL.
+pub async fn queue_read_receipts(app: &AppHandle, messages: &[Message]) {
+    queue_receipts(app, |tracker, own_user_id| {
+        // Our own messages in the chat were read by us, which is nothing to report
+        for message in messages {
+            if own_user_id == Some(message.sender_id.as_str()) {
+                continue;
+            }
+            if let Some(message_id) = &message.message_id {
+                enqueue_ids(&mut tracker.pending_read, &message.chat_id, [message_id.clone()]);
+            }
+        }
+    }).await;
+}
+
+// Let `add` queue receipts, then make sure a flush is on its way
+async fn queue_receipts(app: &AppHandle, add: impl FnOnce(&mut ReceiptTracker, Option<&str>)) {
+    let Some(receipt_state) = app.try_state::<Arc<ReceiptState>>() else {
+        return;
+    };
+    let receipt_state = Arc::clone(receipt_state.inner());
+    let own_user_id = own_user_id(app, &receipt_state).await;
+
+    let mut tracker = receipt_state.0.lock().await;
+    add(&mut tracker, own_user_id.as_deref());
+    if tracker.is_empty() || tracker.flush_scheduled {
+        return;
+    }
+    tracker.flush_scheduled = true;
+    schedule_flush(app.clone(), Arc::clone(&receipt_state), READ_RECEIPT_INTERVAL);
+}
+
+fn schedule_flush(app: AppHandle, receipt_state: Arc<ReceiptState>, delay: Duration) {
+    tokio::spawn(async move {
+        sleep(delay).await;
+        let (delivered, read) = {
+            let mut tracker = receipt_state.0.lock().await;
+            tracker.flush_scheduled = false;
+            (std::mem::take(&mut tracker.pending_delivered), std::mem::take(&mut tracker.pending_read))
+        };
+        flush_receipts(&app, &receipt_state, delivered, read).await;
+    });
+}
+
+async fn flush_receipts(
+    app: &AppHandle,
+    receipt_state: &Arc<ReceiptState>,
+    delivered: HashMap<String, Vec<String>>,
+    mut read: HashMap<String, Vec<String>>,
+) {
+    let own_user_id = own_user_id(app, receipt_state).await;
+
+    // Checked at send time so turning the setting off also drops what is queued.
+    // Delivery receipts are always sent.
+    if let (Some(user_id), false) = (own_user_id.as_deref(), read.is_empty()) {
+        match app.state::<SqliteRepo>().get_send_read_receipts(user_id).await {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("[Receipts] Read receipts are turned off, dropping {} queued chats", read.len());
+                read.clear();
+            }
+            Err(e) => {
+                println!("[Receipts] Failed to read the read receipt setting, not sending: {}", e);
+                read.clear();
+            }
+        }
+    }
+
+    let (delivered, read) = match outbound(app).await {
+        Some((tx, queue_stats)) => (
+            send_receipt_batches(&tx, &queue_stats, "delivered", delivered, own_user_id.as_deref()).await,
+            send_receipt_batches(&tx, &queue_stats, "read", read, own_user_id.as_deref()).await,
+        ),
+        None => {
+            println!("[Receipts] WebSocket not connected, keeping receipts for {} chats", delivered.len() + read.len());
+            (delivered, read)
+        }
+    };
+
+    let mut tracker = receipt_state.0.lock().await;
+    if delivered.is_empty() && read.is_empty() {
+        tracker.failed_flushes = 0;
+        return;
+    }
+
+    // Keep what did not go out and try again later, backing off while it keeps failing
+    for (chat_id, message_ids) in delivered {
+        enqueue_ids(&mut tracker.pending_delivered, &chat_id, message_ids);
+    }
+    for (chat_id, message_ids) in read {
+        enqueue_ids(&mut tracker.pending_read, &chat_id, message_ids);
+    }
+    tracker.failed_flushes = tracker.failed_flushes.saturating_add(1);
+    if tracker.flush_scheduled {
+        return;
+    }
+    tracker.flush_scheduled = true;
+    let delay = READ_RECEIPT_INTERVAL
+        .saturating_mul(1 << tracker.failed_flushes.min(5))
+        .min(MAX_RETRY_DELAY);
+    println!("[Receipts] Retrying unsent receipts in {:?}", delay);
+    schedule_flush(app.clone(), Arc::clone(receipt_state), delay);
+}
+
+// Send one kind of receipt in per-chat batches. Stops at the first batch the writer
+// will not take and returns it together with everything after it.
+async fn send_receipt_batches(
+    tx: &mpsc::Sender<OutboundFrame>,
+    queue_stats: &QueueStats,
+    status: &str,
+    pending: HashMap<String, Vec<String>>,
+    own_user_id: Option<&str>,
+) -> HashMap<String, Vec<String>> {
+    let mut unsent = HashMap::new();
+    for (chat_id, message_ids) in pending {
+        if !unsent.is_empty() {
+            unsent.insert(chat_id, message_ids);
+            continue;
+        }
+        for (index, batch) in message_ids.chunks(MAX_READ_BATCH).enumerate() {
+            let frame = status_frame(status, &chat_id, batch, own_user_id);
+            if let Err(e) = enqueue_outbound(tx, frame, OverflowPolicy::Timeout(OUTBOUND_SEND_TIMEOUT), queue_stats).await {
+                println!("[Receipts] {} receipts for chat {} not sent, keeping them: {}", status, chat_id, e);
+                unsent.insert(chat_id.clone(), message_ids[index * MAX_READ_BATCH..].to_vec());
+                break;
+            }
+            println!("[Receipts] Sent {} receipts for {} messages in chat {}", status, batch.len(), chat_id);
+        }
+    }
+    unsent
+}
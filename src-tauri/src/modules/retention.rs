@@ -0,0 +1,66 @@
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::time::sleep;
+use crate::repo::{ChatRepo, MessageRepo, SqliteRepo};
+use crate::timestamp::Timestamp;
+
+// How often expired messages are purged
+const PURGE_INTERVAL: Duration = Duration::from_secs(30);
+
+// Delete expired messages and tell the frontend which ones went away
+async fn purge_and_notify(app: &AppHandle) -> Result<usize, String> {
+    let expired = app.state::<SqliteRepo>().purge_expired_messages(Timestamp::now()).await
+        .map_err(|e| format!("Failed to purge expired messages: {}", e))?;
+
+    let mut removed = 0;
+    for chat in &expired {
+        removed += chat.client_message_ids.len();
+        println!("[Retention] {} messages expired in chat {}", chat.client_message_ids.len(), chat.chat_id);
+        app.emit("messages-expired", chat).ok();
+    }
+    Ok(removed)
+}
+
+// Set how long messages in a chat are kept, in seconds; None (or 0) turns expiry off
+#[tauri::command]
+pub async fn set_chat_message_ttl(
+    app: AppHandle,
+    repo: State<'_, SqliteRepo>,
+    chat_id: String,
+    ttl_seconds: Option<i64>,
+) -> Result<(), String> {
+    let ttl_seconds = match ttl_seconds {
+        Some(ttl) if ttl < 0 => return Err(format!("Invalid message TTL: {}", ttl)),
+        Some(0) | None => None,
+        Some(ttl) => Some(ttl),
+    };
+
+    let updated = repo.set_chat_message_ttl(&chat_id, ttl_seconds).await
+        .map_err(|e| e.to_string())?;
+    if !updated {
+        return Err(format!("Chat not found: {}", chat_id));
+    }
+    println!("[Retention] Message TTL for chat {} set to {:?}", chat_id, ttl_seconds);
+
+    // Apply a shorter TTL straight away instead of on the next tick
+    if ttl_seconds.is_some() {
+        purge_and_notify(&app).await?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_chat_message_ttl(repo: State<'_, SqliteRepo>, chat_id: String) -> Result<Option<i64>, String> {
+    repo.get_chat_message_ttl(&chat_id).await.map_err(|e| e.to_string())
+}
+
+// Background task started once the database is ready
+pub async fn run_retention_task(app: AppHandle) {
+    println!("[Retention] Retention task started");
+    loop {
+        if let Err(e) = purge_and_notify(&app).await {
+            println!("[Retention] {}", e);
+        }
+        sleep(PURGE_INTERVAL).await;
+    }
+}
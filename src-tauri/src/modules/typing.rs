@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Mutex as TokioMutex;
+use tokio::time::sleep;
+use serde_json::json;
+use crate::modules::websocket::{enqueue_outbound, OverflowPolicy, SocketTx, WebSocketState};
+
+// A "still typing" frame is re-sent at most this often while the user keeps typing
+const TYPING_RESEND_INTERVAL: Duration = Duration::from_secs(3);
+// A remote user counts as typing for this long after their last frame
+const TYPING_EXPIRY: Duration = Duration::from_secs(6);
+
+// Who is typing in each chat, plus what we last told the server about ourselves
+#[derive(Default)]
+pub struct TypingTracker {
+    // chat_id -> user_id -> (username, expires_at)
+    remote: HashMap<String, HashMap<String, (Option<String>, Instant)>>,
+    // chat_id -> (last sent state, when it was sent)
+    sent: HashMap<String, (bool, Instant)>,
+    // Set when the server rejects typing frames; cleared on the next connection
+    unsupported: bool,
+}
+
+pub struct TypingState(pub TokioMutex<TypingTracker>);
+
+impl Default for TypingState {
+    fn default() -> Self {
+        Self(TokioMutex::new(TypingTracker::default()))
+    }
+}
+
+impl TypingTracker {
+    // Whether a frame for this state should go out now, given the throttle
+    fn should_send(&self, chat_id: &str, is_typing: bool) -> bool {
+        match self.sent.get(chat_id) {
+            // "Stopped" is only worth sending if the server last heard "typing"
+            None => is_typing,
+            Some((last, sent_at)) => {
+                if *last != is_typing {
+                    true
+                } else {
+                    is_typing && sent_at.elapsed() >= TYPING_RESEND_INTERVAL
+                }
+            }
+        }
+    }
+
+    fn typing_users(&self, chat_id: &str) -> Vec<serde_json::Value> {
+        let now = Instant::now();
+        self.remote.get(chat_id)
+            .map(|users| users.iter()
+                .filter(|(_, (_, expires_at))| *expires_at > now)
+                .map(|(user_id, (username, _))| json!({ "user_id": user_id, "username": username }))
+                .collect())
+            .unwrap_or_default()
+    }
+
+    // Drop expired entries for a chat; returns true if anything was removed
+    fn prune(&mut self, chat_id: &str) -> bool {
+        let now = Instant::now();
+        let Some(users) = self.remote.get_mut(chat_id) else {
+            return false;
+        };
+        let before = users.len();
+        users.retain(|_, (_, expires_at)| *expires_at > now);
+        let changed = users.len() != before;
+        if users.is_empty() {
+            self.remote.remove(chat_id);
+        }
+        changed
+    }
+}
+
+fn emit_typing_update(app: &AppHandle, tracker: &TypingTracker, chat_id: &str) {
+    app.emit("typing-update", json!({
+        "chat_id": chat_id,
+        "typing": tracker.typing_users(chat_id)
+    })).ok();
+}
+
+// Send our typing state for a chat. Returns whether a frame was actually queued;
+// throttled, disconnected or unsupported cases are not errors.
+#[tauri::command]
+pub async fn set_typing(
+    chat_id: String,
+    is_typing: bool,
+    socket_tx: State<'_, Arc<SocketTx>>,
+    ws_state: State<'_, Arc<TokioMutex<WebSocketState>>>,
+    typing_state: State<'_, Arc<TypingState>>,
+) -> Result<bool, String> {
+    // Take the socket first; connect_socket locks ws_state before the typing state
+    let queue_stats = Arc::clone(&ws_state.lock().await.queue_stats);
+    let Some(tx) = socket_tx.0.lock().await.clone() else {
+        println!("[Typing] WebSocket not connected, not sending typing state for chat {}", chat_id);
+        return Ok(false);
+    };
+
+    let mut tracker = typing_state.0.lock().await;
+    if tracker.unsupported {
+        return Ok(false);
+    }
+    if !tracker.should_send(&chat_id, is_typing) {
+        return Ok(false);
+    }
+
+    let frame = json!({
+        "type": "typing",
+        "message": {
+            "chat_id": chat_id,
+            "is_typing": is_typing
+        }
+    });
+
+    // Typing frames are disposable; never wait for room in the queue
+    match enqueue_outbound(&tx, frame.to_string(), OverflowPolicy::DropNewest, &queue_stats).await {
+        Ok(true) => {}
+        // Not recorded as sent, so the next keystroke tries again
+        Ok(false) => return Ok(false),
+        Err(e) => {
+            println!("[Typing] Typing frame for chat {} not sent: {}", chat_id, e);
+            return Ok(false);
+        }
+    }
+
+    tracker.sent.insert(chat_id, (is_typing, Instant::now()));
+    Ok(true)
+}
+
+// Handle a typing frame from the server
+pub async fn handle_typing_frame(app: &AppHandle, message_text: &str) -> Result<(), String> {
+    let Some(typing_state) = app.try_state::<Arc<TypingState>>() else {
+        return Ok(());
+    };
+    let typing_state = Arc::clone(typing_state.inner());
+
+    let value: serde_json::Value = serde_json::from_str(message_text)
+        .map_err(|e| format!("Failed to parse typing frame: {}", e))?;
+    let message = value.get("message").unwrap_or(&value);
+    let chat_id = message.get("chat_id").and_then(|v| v.as_str());
+    let user_id = message.get("user_id")
+        .or_else(|| message.get("sender_id"))
+        .and_then(|v| v.as_str());
+    let (Some(chat_id), Some(user_id)) = (chat_id, user_id) else {
+        return Err("Typing frame missing chat_id or user_id".to_string());
+    };
+    let is_typing = message.get("is_typing").and_then(|v| v.as_bool()).unwrap_or(true);
+    let username = message.get("username")
+        .or_else(|| message.get("sender_username"))
+        .and_then(|v| v.as_str())
+        .map(|name| name.to_string());
+
+    let mut tracker = typing_state.0.lock().await;
+    if is_typing {
+        let expires_at = Instant::now() + TYPING_EXPIRY;
+        tracker.remote.entry(chat_id.to_string())
+            .or_default()
+            .insert(user_id.to_string(), (username, expires_at));
+        schedule_expiry(app.clone(), Arc::clone(&typing_state), chat_id.to_string());
+    } else {
+        let removed = tracker.remote.get_mut(chat_id)
+            .map(|users| users.remove(user_id).is_some())
+            .unwrap_or(false);
+        if !removed {
+            return Ok(());
+        }
+    }
+    tracker.prune(chat_id);
+    emit_typing_update(app, &tracker, chat_id);
+    Ok(())
+}
+
+// A user who just sent a message has stopped typing
+pub async fn clear_typing_user(app: &AppHandle, chat_id: &str, user_id: &str) {
+    let Some(typing_state) = app.try_state::<Arc<TypingState>>() else {
+        return;
+    };
+    let mut tracker = typing_state.0.lock().await;
+    let removed = tracker.remote.get_mut(chat_id)
+        .map(|users| users.remove(user_id).is_some())
+        .unwrap_or(false);
+    if removed {
+        tracker.prune(chat_id);
+        emit_typing_update(app, &tracker, chat_id);
+    }
+}
+
+// The server answered a typing frame with an error; stop sending them on this connection
+pub async fn mark_typing_unsupported(app: &AppHandle) {
+    let Some(typing_state) = app.try_state::<Arc<TypingState>>() else {
+        return;
+    };
+    let mut tracker = typing_state.0.lock().await;
+    if !tracker.unsupported {
+        println!("[Typing] Server does not accept typing frames, disabling them for this connection");
+        tracker.unsupported = true;
+    }
+}
+
+// Forget everything tied to the previous connection
+pub async fn reset_typing(app: &AppHandle) {
+    let Some(typing_state) = app.try_state::<Arc<TypingState>>() else {
+        return;
+    };
+    let mut tracker = typing_state.0.lock().await;
+    let chats: Vec<String> = tracker.remote.keys().cloned().collect();
+    tracker.remote.clear();
+    tracker.sent.clear();
+    tracker.unsupported = false;
+    for chat_id in chats {
+        emit_typing_update(app, &tracker, &chat_id);
+    }
+}
+
+// Re-check the chat once the newest entry could have expired
+fn schedule_expiry(app: AppHandle, typing_state: Arc<TypingState>, chat_id: String) {
+    tokio::spawn(async move {
+        sleep(TYPING_EXPIRY).await;
+        let mut tracker = typing_state.0.lock().await;
+        if tracker.prune(&chat_id) {
+            emit_typing_update(&app, &tracker, &chat_id);
+        }
+    });
+}
@@ -1,15 +1,26 @@
 use futures::{SinkExt as FuturesSinkExt, StreamExt as FuturesStreamExt};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
-use tauri::{AppHandle, State};
+use tauri::{AppHandle, Manager, State};
 use tauri::Emitter;
 use tokio::sync::{mpsc, Mutex as TokioMutex};
-use tokio::time::sleep;
+use tokio::sync::mpsc::error::{SendTimeoutError, TrySendError};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
 use tokio_tungstenite::{connect_async_with_config, tungstenite::Message, tungstenite::client::IntoClientRequest};
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, frame::coding::CloseCode};
+use tokio_util::sync::CancellationToken;
 use url::Url;
 use serde_json::json;
 use serde::Deserialize;
 use base64::{Engine as _, engine::general_purpose};
+use crate::database_async::{IncomingMessageOutcome, ReceiptStatus};
+use crate::repo::{MessageRepo, SqliteRepo};
+use crate::modules::codec::{decode_server_event, encode_client_event, WireEncoding};
+use crate::modules::diagnostics::{FrameError, WsDiagnostics};
+use crate::timestamp::Timestamp;
 
 // Encryption key - must match the frontend exactly
 const INTERNAL_KEY: &str = "hardcoded_key";
@@ -33,8 +44,249 @@ fn decrypt_message_content(encrypted_content: &str) -> Result<String, String> {
 }
 
 
+// Outgoing frames waiting for the writer task
+const OUTBOUND_QUEUE_CAPACITY: usize = 256;
+// Incoming frames buffered per chat before the reader is made to wait
+const INBOUND_CHAT_QUEUE_CAPACITY: usize = 64;
+// How long an outgoing message may wait for room in a full queue
+pub const OUTBOUND_SEND_TIMEOUT: Duration = Duration::from_secs(5);
+// Per-chat workers shut down after this long without frames
+const INBOUND_WORKER_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+// How long the reader waits for the server to answer our Close frame
+const CLOSE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+// How long disconnect waits for each connection task before aborting it
+const TASK_JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A frame waiting for the writer task
+#[derive(Debug)]
+pub enum OutboundFrame {
+    // JSON event; sent as MessagePack when the connection negotiated it
+    Text(String),
+    // Raw bytes, sent as a binary frame unchanged
+    Binary(Vec<u8>),
+    // Native WebSocket ping
+    Ping,
+}
+
+impl From<String> for OutboundFrame {
+    fn from(text: String) -> Self {
+        OutboundFrame::Text(text)
+    }
+}
+
+#[derive(Default)]
+pub struct SocketTx(pub TokioMutex<Option<mpsc::Sender<OutboundFrame>>>);
+
+// What to do when a bounded queue is full
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    // Wait for room; used for incoming frames so the socket reader slows down instead of losing data
+    Block,
+    // Wait up to the given duration, then fail the send
+    Timeout(Duration),
+    // Drop the new frame immediately; used for frames that are safe to lose such as pings
+    DropNewest,
+}
+
+// Queue counters shared between the connection tasks and get_websocket_status
 #[derive(Default)]
-pub struct SocketTx(pub TokioMutex<Option<mpsc::UnboundedSender<String>>>);
+pub struct QueueStats {
+    pub inbound_depth: AtomicUsize,
+    pub inbound_high_water: AtomicUsize,
+    pub inbound_workers: AtomicUsize,
+    pub inbound_backpressure_events: AtomicU64,
+    pub outbound_dropped: AtomicU64,
+    pub outbound_timeouts: AtomicU64,
+}
+
+impl QueueStats {
+    // Depth and worker counts are left alone: workers from a previous connection may still be draining
+    fn reset(&self) {
+        self.inbound_high_water.store(0, Ordering::Relaxed);
+        self.inbound_backpressure_events.store(0, Ordering::Relaxed);
+        self.outbound_dropped.store(0, Ordering::Relaxed);
+        self.outbound_timeouts.store(0, Ordering::Relaxed);
+    }
+}
+
+// Queue a frame for the writer task according to the given overflow policy.
+// Returns false when DropNewest discarded the frame because the queue was full.
+pub async fn enqueue_outbound(
+    tx: &mpsc::Sender<OutboundFrame>,
+    message: impl Into<OutboundFrame>,
+    policy: OverflowPolicy,
+    stats: &QueueStats,
+) -> Result<bool, String> {
+    let message = message.into();
+    match policy {
+        OverflowPolicy::Block => tx.send(message).await
+            .map(|()| true)
+            .map_err(|_| "WebSocket writer has shut down".to_string()),
+        OverflowPolicy::Timeout(wait) => match tx.send_timeout(message, wait).await {
+            Ok(()) => Ok(true),
+            Err(SendTimeoutError::Timeout(_)) => {
+                stats.outbound_timeouts.fetch_add(1, Ordering::Relaxed);
+                println!("[WebSocket] Outbound queue full for {} seconds, rejecting message", wait.as_secs());
+                Err("WebSocket send queue is full".to_string())
+            }
+            Err(SendTimeoutError::Closed(_)) => Err("WebSocket writer has shut down".to_string()),
+        },
+        OverflowPolicy::DropNewest => match tx.try_send(message) {
+            Ok(()) => Ok(true),
+            Err(TrySendError::Full(_)) => {
+                stats.outbound_dropped.fetch_add(1, Ordering::Relaxed);
+                println!("[WebSocket] Outbound queue full, dropping frame");
+                Ok(false)
+            }
+            Err(TrySendError::Closed(_)) => Err("WebSocket writer has shut down".to_string()),
+        },
+    }
+}
+
+// Incoming frames that need database work, routed to the worker for their chat
+enum InboundFrame {
+    Chat(String),
+    Status(String),
+}
+
+// Per-chat worker queues; a worker removes its own entry when it goes idle
+type ChatWorkers = HashMap<String, mpsc::Sender<InboundFrame>>;
+
+// Routes incoming frames so that each chat is processed in order by its own worker,
+// while different chats run in parallel
+struct InboundRouter {
+    // Every send happens with this lock held, so an idle worker can check its queue
+    // and deregister without a frame slipping in between
+    workers: Arc<TokioMutex<ChatWorkers>>,
+    stats: Arc<QueueStats>,
+    diagnostics: Arc<WsDiagnostics>,
+    cancel: CancellationToken,
+    app: AppHandle,
+}
+
+impl InboundRouter {
+    fn new(app: AppHandle, stats: Arc<QueueStats>, diagnostics: Arc<WsDiagnostics>, cancel: CancellationToken) -> Self {
+        Self { workers: Arc::new(TokioMutex::new(HashMap::new())), stats, diagnostics, cancel, app }
+    }
+
+    async fn dispatch(&mut self, chat_id: String, frame: InboundFrame) {
+        let depth = self.stats.inbound_depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.stats.inbound_high_water.fetch_max(depth, Ordering::Relaxed);
+
+        let mut workers = self.workers.lock().await;
+        let mut frame = frame;
+        loop {
+            let tx = match workers.get(&chat_id) {
+                Some(tx) => tx.clone(),
+                None => {
+                    let tx = spawn_chat_worker(
+                        chat_id.clone(),
+                        self.app.clone(),
+                        Arc::clone(&self.stats),
+                        Arc::clone(&self.diagnostics),
+                        Arc::downgrade(&self.workers),
+                    );
+                    workers.insert(chat_id.clone(), tx.clone());
+                    tx
+                }
+            };
+
+            match tx.try_send(frame) {
+                Ok(()) => return,
+                Err(TrySendError::Full(pending)) => {
+                    // OverflowPolicy::Block - stop reading from the socket until this chat catches up
+                    self.stats.inbound_backpressure_events.fetch_add(1, Ordering::Relaxed);
+                    println!("[WebSocket] Inbound queue for chat {} is full, applying backpressure", chat_id);
+                    tokio::select! {
+                        _ = self.cancel.cancelled() => {
+                            println!("[WebSocket] Connection cancelled while waiting on chat {}, dropping frame", chat_id);
+                            self.stats.inbound_depth.fetch_sub(1, Ordering::Relaxed);
+                            return;
+                        }
+                        sent = tx.send(pending) => match sent {
+                            Ok(()) => return,
+                            Err(mpsc::error::SendError(pending)) => {
+                                workers.remove(&chat_id);
+                                frame = pending;
+                            }
+                        },
+                    }
+                }
+                // Only reachable if the worker task died; idle workers deregister themselves first
+                Err(TrySendError::Closed(pending)) => {
+                    workers.remove(&chat_id);
+                    frame = pending;
+                }
+            }
+        }
+    }
+}
+
+fn spawn_chat_worker(
+    chat_id: String,
+    app: AppHandle,
+    stats: Arc<QueueStats>,
+    diagnostics: Arc<WsDiagnostics>,
+    workers: Weak<TokioMutex<ChatWorkers>>,
+) -> mpsc::Sender<InboundFrame> {
+    let (tx, mut rx) = mpsc::channel::<InboundFrame>(INBOUND_CHAT_QUEUE_CAPACITY);
+    stats.inbound_workers.fetch_add(1, Ordering::Relaxed);
+    println!("[WebSocket] Starting inbound worker for chat {}", chat_id);
+
+    tokio::spawn(async move {
+        loop {
+            match timeout(INBOUND_WORKER_IDLE_TIMEOUT, rx.recv()).await {
+                Ok(Some(frame)) => process_inbound_frame(frame, &app, &stats, &diagnostics).await,
+                // The router is gone and everything it queued has been handled
+                Ok(None) => break,
+                Err(_) => {
+                    // Deregister while holding the router's lock, so no frame can arrive
+                    // and no replacement worker can start until this one has exited
+                    let Some(workers) = workers.upgrade() else { continue };
+                    let mut workers = workers.lock().await;
+                    if rx.is_empty() {
+                        workers.remove(&chat_id);
+                        rx.close();
+                        break;
+                    }
+                }
+            }
+        }
+        stats.inbound_workers.fetch_sub(1, Ordering::Relaxed);
+        println!("[WebSocket] Inbound worker for chat {} stopped", chat_id);
+    });
+
+    tx
+}
+
+async fn process_inbound_frame(frame: InboundFrame, app: &AppHandle, stats: &QueueStats, diagnostics: &WsDiagnostics) {
+    match frame {
+        InboundFrame::Chat(text) => {
+            if let Err(e) = handle_chat_message(&text, app.clone()).await {
+                println!("[WebSocket] Error handling chat message: {}", e);
+                diagnostics.record_frame_error("chat", &e);
+            }
+        }
+        InboundFrame::Status(text) => {
+            if let Err(e) = handle_message_status(&text, app.clone()).await {
+                println!("[WebSocket] Error handling message status: {}", e);
+                diagnostics.record_parse_failure("message-status");
+            }
+        }
+    }
+    stats.inbound_depth.fetch_sub(1, Ordering::Relaxed);
+}
+
+// Extract message.chat_id so frames can be routed to the right worker
+fn frame_chat_id(text: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(text)
+        .ok()
+        .and_then(|value| value.get("message")
+            .and_then(|m| m.get("chat_id"))
+            .and_then(|c| c.as_str())
+            .map(|c| c.to_string()))
+        .unwrap_or_default()
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ConnectionState {
@@ -43,6 +295,32 @@ pub enum ConnectionState {
     Connecting,
 }
 
+// The reader, writer and heartbeat tasks of one live connection
+pub struct ConnectionHandle {
+    pub generation: u64,
+    cancel: CancellationToken,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl ConnectionHandle {
+    // Cancel the connection and wait for its tasks; the writer sends a Close frame on its way out
+    async fn shutdown(mut self) {
+        println!("[WebSocket] Cancelling connection {}", self.generation);
+        self.cancel.cancel();
+        for mut task in self.tasks.drain(..) {
+            match timeout(TASK_JOIN_TIMEOUT, &mut task).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => println!("[WebSocket] Connection task failed: {}", e),
+                Err(_) => {
+                    println!("[WebSocket] Connection task did not stop within {} seconds, aborting it", TASK_JOIN_TIMEOUT.as_secs());
+                    task.abort();
+                }
+            }
+        }
+        println!("[WebSocket] Connection {} shut down", self.generation);
+    }
+}
+
 pub struct WebSocketState {
     pub connection_state: ConnectionState,
     pub last_heartbeat: Instant,
@@ -51,6 +329,13 @@ pub struct WebSocketState {
     pub reconnect_delay: Duration,
     pub heartbeat_interval: Duration,
     pub auth_token: Option<String>, // Store the auth token for reconnection
+    pub queue_stats: Arc<QueueStats>,
+    pub diagnostics: Arc<WsDiagnostics>,
+    // Bumped on every connect and disconnect so tasks of a replaced connection can tell they are stale
+    pub generation: u64,
+    pub connection: Option<ConnectionHandle>,
+    // Negotiated during the handshake; JSON unless the server accepted MessagePack
+    pub encoding: WireEncoding,
 }
 
 impl Default for WebSocketState {
@@ -63,6 +348,11 @@ impl Default for WebSocketState {
             reconnect_delay: Duration::from_secs(2),
             heartbeat_interval: Duration::from_secs(30),
             auth_token: None,
+            queue_stats: Arc::new(QueueStats::default()),
+            diagnostics: Arc::new(WsDiagnostics::default()),
+            generation: 0,
+            connection: None,
+            encoding: WireEncoding::Json,
         }
     }
 }
@@ -101,26 +391,25 @@ async fn handle_message_status(message_text: &str, app: AppHandle) -> Result<(),
     
     // Update message status using server ID
     if let Some(ref server_id) = server_message_id {
+        let repo = app.state::<SqliteRepo>();
         match status_type.as_str() {
             "sent" => {
-                if let Err(e) = crate::database_async::update_message_sent_status_by_server_id(server_id, true).await {
+                if let Err(e) = repo.update_message_sent_status_by_server_id(server_id, true).await {
                     println!("[WebSocket] Failed to mark message as sent: {}", e);
                 } else {
                     println!("[WebSocket] Successfully marked message {} as sent", server_id);
                 }
             }
-            "delivered" => {
-                if let Err(e) = crate::database_async::mark_message_delivered_by_server_id_new(server_id).await {
-                    println!("[WebSocket] Failed to mark message as delivered: {}", e);
-                } else {
-                    println!("[WebSocket] Successfully marked message {} as delivered", server_id);
-                }
-            }
-            "read" => {
-                if let Err(e) = crate::database_async::mark_message_read_by_server_id_new(server_id).await {
-                    println!("[WebSocket] Failed to mark message as read: {}", e);
-                } else {
-                    println!("[WebSocket] Successfully marked message {} as read", server_id);
+            "delivered" | "read" => {
+                // The frame's sender is the recipient reporting the receipt
+                let receipt_status = ReceiptStatus::parse(&status_type).unwrap_or(ReceiptStatus::Delivered);
+                let at = Timestamp::parse_rfc3339(&status.timestamp)
+                    .ok()
+                    .or_else(|| status.timestamp.parse().ok().map(Timestamp::from_any))
+                    .unwrap_or_else(Timestamp::now);
+                match repo.record_message_receipt(server_id, &status.sender_id, receipt_status, at).await {
+                    Ok(_) => println!("[WebSocket] Recorded {} receipt for message {} from {}", status_type, server_id, status.sender_id),
+                    Err(e) => println!("[WebSocket] Failed to record {} receipt: {}", status_type, e),
                 }
             }
             _ => {
@@ -158,7 +447,16 @@ pub async fn connect_socket(
     
     ws_state_guard.connection_state = ConnectionState::Connecting;
     ws_state_guard.auth_token = Some(token.clone()); // Store the token
+    ws_state_guard.generation += 1;
+    let generation = ws_state_guard.generation;
+    let previous = ws_state_guard.connection.take();
     drop(ws_state_guard);
+    
+    // Make sure the tasks of an earlier connection are gone before starting new ones
+    if let Some(previous) = previous {
+        println!("[WebSocket] Shutting down previous connection {}", previous.generation);
+        previous.shutdown().await;
+    }
 
             println!("[WebSocket] Attempting WebSocket connection to wss://dev.v1.terracrypt.cc/api/v1/ws");
     println!("[WebSocket] Using Bearer token: {}...", &token[..std::cmp::min(16, token.len())]);
@@ -199,10 +497,10 @@ pub async fn connect_socket(
             .map_err(|e| format!("Failed to parse User-Agent header: {}", e))?
     );
     
-    // Add Sec-WebSocket-Protocol header if needed
+    // Offer MessagePack first; servers that don't know it pick plain "chat" (JSON)
     request.headers_mut().insert(
         "Sec-WebSocket-Protocol",
-        "chat".parse()
+        WireEncoding::offered_subprotocols().parse()
             .map_err(|e| format!("Failed to parse Sec-WebSocket-Protocol header: {}", e))?
     );
     
@@ -210,7 +508,7 @@ pub async fn connect_socket(
     println!("[WebSocket] Request headers: {:?}", request.headers());
     
     // Use connect_async_with_config with default config
-    let (ws_stream, _response) = match connect_async_with_config(request, None, false).await {
+    let (ws_stream, response) = match connect_async_with_config(request, None, false).await {
         Ok((ws_stream, response)) => {
             println!("[WebSocket] Connection established successfully!");
             println!("[WebSocket] Response status: {}", response.status());
@@ -220,27 +518,53 @@ pub async fn connect_socket(
         Err(e) => {
             println!("[WebSocket] Connection failed: {}", e);
             let mut ws_state_guard = ws_state.lock().await;
-            ws_state_guard.connection_state = ConnectionState::Disconnected;
+            ws_state_guard.diagnostics.connect_failed(generation, &format!("handshake failed: {}", e));
+            if ws_state_guard.generation == generation {
+                ws_state_guard.connection_state = ConnectionState::Disconnected;
+            }
             return Err(format!("Failed to connect: {}", e));
         }
     };
 
+    let encoding = WireEncoding::from_subprotocol(
+        response.headers().get("Sec-WebSocket-Protocol").and_then(|v| v.to_str().ok())
+    );
+    println!("[WebSocket] Negotiated wire encoding: {}", encoding.as_str());
+
     println!("[WebSocket] Splitting WebSocket stream into read/write parts...");
     let (write, mut read) = ws_stream.split();
-    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let (tx, mut rx) = mpsc::channel::<OutboundFrame>(OUTBOUND_QUEUE_CAPACITY);
+    let write = Arc::new(TokioMutex::new(write));
+    let cancel = CancellationToken::new();
     
     // Update state
     println!("[WebSocket] Updating connection state...");
     let mut ws_state_guard = ws_state.lock().await;
+    if ws_state_guard.generation != generation {
+        // disconnect_socket (or a newer connect) ran while the handshake was in flight
+        drop(ws_state_guard);
+        println!("[WebSocket] Connection {} was superseded during handshake, closing it", generation);
+        write.lock().await.send(Message::Close(Some(client_close_frame()))).await.ok();
+        return Err("Connection attempt was cancelled".to_string());
+    }
     ws_state_guard.connection_state = ConnectionState::Connected;
     ws_state_guard.last_heartbeat = Instant::now();
     ws_state_guard.reconnect_attempts = 0;
-    drop(ws_state_guard);
+    ws_state_guard.encoding = encoding;
+    let heartbeat_interval = ws_state_guard.heartbeat_interval;
+    let queue_stats = Arc::clone(&ws_state_guard.queue_stats);
+    queue_stats.reset();
+    let diagnostics = Arc::clone(&ws_state_guard.diagnostics);
+    diagnostics.connection_opened(generation);
     
+    let presence_tx = tx.clone();
     *state.0.lock().await = Some(tx);
-    println!("[WebSocket] WebSocket connection fully established and ready!");
-
-    let write = Arc::new(TokioMutex::new(write));
+    println!("[WebSocket] WebSocket connection {} fully established and ready!", generation);
+    crate::modules::typing::reset_typing(&app).await;
+    crate::modules::presence::reset_presence(&app).await;
+    crate::modules::receipts::reset_receipts(&app).await;
+    // Catch up on names left unresolved while we were offline
+    crate::modules::profiles::schedule_sender_backfill(&app).await;
 
     // Emit connection status
     println!("[WebSocket] Emitting 'connected' status to frontend...");
@@ -258,17 +582,37 @@ pub async fn connect_socket(
     // Task to handle incoming messages
     println!("[WebSocket] Starting message reader task...");
     let app_clone = app.clone();
-    let ws_state_clone = Arc::clone(&ws_state.inner());
-    tokio::spawn(async move {
+    let ws_state_clone = Arc::clone(ws_state.inner());
+    let socket_tx_clone = Arc::clone(state.inner());
+    let reader_cancel = cancel.clone();
+    let mut router = InboundRouter::new(app.clone(), Arc::clone(&queue_stats), Arc::clone(&diagnostics), cancel.clone());
+    let reader_diagnostics = Arc::clone(&diagnostics);
+    let reader = tokio::spawn(async move {
         println!("[WebSocket] Message reader task started, waiting for messages...");
         let mut message_count = 0;
-        while let Some(msg_result) = read.next().await {
+        let mut closed_locally = false;
+        // Why the server side ended, for the connection history
+        let mut end_reason = "stream ended".to_string();
+        let mut close_code = None;
+        let mut close_reason = None;
+        loop {
+            let msg_result = tokio::select! {
+                _ = reader_cancel.cancelled() => {
+                    closed_locally = true;
+                    break;
+                }
+                next = read.next() => match next {
+                    Some(msg_result) => msg_result,
+                    None => break,
+                },
+            };
             message_count += 1;
             println!("[WebSocket] Message #{} received", message_count);
             
             match msg_result {
                 Ok(msg) => {
                     println!("[WebSocket] Message type: {:?}", msg);
+                    reader_diagnostics.record_in(msg.len());
                     match msg {
                         Message::Text(text) => {
                             // Handle text messages
@@ -279,40 +623,9 @@ pub async fn connect_socket(
                             
                             println!("[WebSocket] Received text message: {}", text);
                             
-                            // Clone text for background task
-                            let text_for_background = text.clone();
-                            
-                            // Check if it's a chat message and log it prominently
-                            if text.contains("\"type\":\"chat\"") {
-                                println!("[WebSocket]  CHAT MESSAGE RECEIVED: {}", text);
-                                
-                                // Handle chat message in background task
-                                let app_clone_for_task = app_clone.clone();
-                                tokio::spawn(async move {
-                                    if let Err(e) = handle_chat_message(&text_for_background, app_clone_for_task).await {
-                                        println!("[WebSocket] Error handling chat message: {}", e);
-                                    }
-                                });
-                            }
-                            // Check if it's a message status update (ACK) - handle multiple possible formats
-                            else if text.contains("\"type\":\"message-status\"") || 
-                                    text.contains("\"type\":\"status\"") ||
-                                    (text.contains("\"status\"") && text.contains("\"message_id\"")) {
-                                println!("[WebSocket]  MESSAGE STATUS UPDATE RECEIVED: {}", text);
-                                
-                                // Handle status update in background task
-                                let app_clone_for_task = app_clone.clone();
-                                tokio::spawn(async move {
-                                    if let Err(e) = handle_message_status(&text_for_background, app_clone_for_task).await {
-                                        println!("[WebSocket] Error handling message status: {}", e);
-                                    }
-                                });
-                            }
+                            route_text_frame(&text, &mut router, &app_clone).await;
                             
-                            // Update heartbeat timestamp
-                            let mut ws_state_guard = ws_state_clone.lock().await;
-                            ws_state_guard.last_heartbeat = Instant::now();
-                            drop(ws_state_guard);
+                            touch_heartbeat(&ws_state_clone, generation).await;
                             
                             // Emit message to frontend
                             app_clone.emit("message", text).ok();
@@ -323,81 +636,82 @@ pub async fn connect_socket(
                             println!("[WebSocket] Close frame code: {:?}", close_frame.as_ref().map(|f| f.code));
                             if let Some(frame) = close_frame.as_ref() {
                                 println!("[WebSocket] Close frame reason: {:?}", frame.reason);
+                                close_code = Some(u16::from(frame.code));
+                                close_reason = Some(frame.reason.to_string());
                             }
+                            end_reason = "closed by server".to_string();
                             println!("[WebSocket] Server is closing the connection");
                             break;
                         }
                         Message::Ping(data) => {
                             // Handle ping frames
                             println!("[WebSocket] Received ping frame with data: {:?}", data);
-                            // Update heartbeat timestamp
-                            let mut ws_state_guard = ws_state_clone.lock().await;
-                            ws_state_guard.last_heartbeat = Instant::now();
-                            drop(ws_state_guard);
+                            touch_heartbeat(&ws_state_clone, generation).await;
                         }
                         Message::Pong(data) => {
                             // Handle pong frames
                             println!("[WebSocket] Received pong frame with data: {:?}", data);
-                            // Update heartbeat timestamp
-                            let mut ws_state_guard = ws_state_clone.lock().await;
-                            ws_state_guard.last_heartbeat = Instant::now();
-                            drop(ws_state_guard);
+                            reader_diagnostics.record_pong(&data);
+                            touch_heartbeat(&ws_state_clone, generation).await;
                         }
                         Message::Binary(data) => {
-                            // Handle binary messages
+                            // MessagePack events (or JSON sent as binary) take the same path as text frames
                             println!("[WebSocket] Received binary message with {} bytes", data.len());
-                            // Update heartbeat timestamp
-                            let mut ws_state_guard = ws_state_clone.lock().await;
-                            ws_state_guard.last_heartbeat = Instant::now();
-                            drop(ws_state_guard);
+                            touch_heartbeat(&ws_state_clone, generation).await;
+                            match decode_server_event(&data) {
+                                Ok(text) => {
+                                    println!("[WebSocket] Decoded binary message: {}", text);
+                                    route_text_frame(&text, &mut router, &app_clone).await;
+                                    app_clone.emit("message", text).ok();
+                                }
+                                Err(e) => {
+                                    println!("[WebSocket] Failed to decode binary message: {}", e);
+                                    reader_diagnostics.record_parse_failure("binary");
+                                }
+                            }
                         }
                         Message::Frame(frame) => {
                             // Handle raw frames
                             println!("[WebSocket] Received raw frame: {:?}", frame);
-                            // Update heartbeat timestamp
-                            let mut ws_state_guard = ws_state_clone.lock().await;
-                            ws_state_guard.last_heartbeat = Instant::now();
-                            drop(ws_state_guard);
+                            touch_heartbeat(&ws_state_clone, generation).await;
                         }
                     }
                 }
                 Err(e) => {
                     println!("[WebSocket] Read error: {}", e);
                     println!("[WebSocket] Read error type: {:?}", e);
+                    end_reason = format!("read error: {}", e);
                     break;
                 }
             }
         }
         
-        // Connection closed
-        println!("[WebSocket] Message reader task ending - connection closed");
-        println!("[WebSocket] Total messages received: {}", message_count);
-        println!("[WebSocket] This could be due to server closing connection or network issue");
-        
-        // Only mark as disconnected if we haven't received any messages recently
-        let mut ws_state_guard = ws_state_clone.lock().await;
-        let time_since_last_message = ws_state_guard.last_heartbeat.elapsed();
-        if time_since_last_message > Duration::from_secs(30) { // 30 second timeout
-            println!("[WebSocket] No recent messages, marking as disconnected");
-            ws_state_guard.connection_state = ConnectionState::Disconnected;
-            ws_state_guard.auth_token = None; // Clear the token on disconnect
-            drop(ws_state_guard);
-            
-            app_clone.emit("websocket-status", json!({
-                "connection_state": "disconnected",
-                "is_connected": false,
-                "is_connecting": false,
-                "reconnect_attempts": 0,
-                "last_heartbeat": 0,
-                "max_reconnect_attempts": 5,
-                "heartbeat_interval": 30
-            })).ok();
-            println!("[WebSocket] Disconnected status emitted to frontend");
+        if closed_locally {
+            // The writer sends our Close frame; wait briefly for the server to acknowledge it
+            println!("[WebSocket] Reader {} cancelled, waiting for close acknowledgement...", generation);
+            let acknowledged = timeout(CLOSE_HANDSHAKE_TIMEOUT, async {
+                while let Some(Ok(msg)) = read.next().await {
+                    if let Message::Close(_) = msg {
+                        break;
+                    }
+                }
+            }).await;
+            if acknowledged.is_err() {
+                println!("[WebSocket] Server did not acknowledge close within {} seconds", CLOSE_HANDSHAKE_TIMEOUT.as_secs());
+            }
         } else {
-            println!("[WebSocket] Recent messages detected, keeping connection state as active");
-            drop(ws_state_guard);
+            // Connection closed by the server or the network
+            println!("[WebSocket] Message reader task ending - connection closed");
+            println!("[WebSocket] Total messages received: {}", message_count);
+            println!("[WebSocket] This could be due to server closing connection or network issue");
+            
+            // Stop the writer and heartbeat for this connection
+            reader_diagnostics.connection_closed(generation, &end_reason, close_code, close_reason);
+            reader_cancel.cancel();
+            mark_disconnected_if_current(&ws_state_clone, &socket_tx_clone, generation, &app_clone).await;
         }
         
+        println!("[WebSocket] Message reader task {} ended", generation);
         // Note: Automatic reconnection removed due to type complexity
         // The frontend can handle reconnection through the UI
     });
@@ -405,30 +719,60 @@ pub async fn connect_socket(
     // Task to handle outgoing messages
     println!("[WebSocket] Starting message writer task...");
     let write_clone = Arc::clone(&write);
-    tokio::spawn(async move {
+    let writer_cancel = cancel.clone();
+    let writer_diagnostics = Arc::clone(&diagnostics);
+    let writer = tokio::spawn(async move {
         println!("[WebSocket] Message writer task started, ready to send messages...");
-        while let Some(msg) = rx.recv().await {
-            println!("[WebSocket] Processing message: {}", msg);
-            
+        loop {
+            let msg = tokio::select! {
+                _ = writer_cancel.cancelled() => break,
+                msg = rx.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+            };
             let mut write_guard = write_clone.lock().await;
             
-            // Handle special PING message
-            if msg == "PING" {
-                println!("[WebSocket] Sending native WebSocket ping frame");
-                if let Err(e) = write_guard.send(Message::Ping(vec![])).await {
-                    println!("[WebSocket] Error sending ping frame: {}", e);
-                    break;
+            let frame = match msg {
+                OutboundFrame::Ping => {
+                    println!("[WebSocket] Sending native WebSocket ping frame");
+                    Message::Ping(writer_diagnostics.next_ping_payload())
                 }
-                println!("[WebSocket] Ping frame sent successfully");
-            } else {
-                // Send as text message (like iOS WebSocketEngine)
-                println!("[WebSocket] Sending text message: {}", msg);
-                if let Err(e) = write_guard.send(Message::Text(msg)).await {
-                    println!("[WebSocket] Error sending text message: {}", e);
-                    break;
+                OutboundFrame::Binary(data) => {
+                    println!("[WebSocket] Sending binary message: {} bytes", data.len());
+                    Message::Binary(data)
                 }
-                println!("[WebSocket] Text message sent successfully");
+                OutboundFrame::Text(text) => match encoding {
+                    WireEncoding::MessagePack => match encode_client_event(&text) {
+                        Some(data) => {
+                            println!("[WebSocket] Sending MessagePack event: {} bytes (JSON was {})", data.len(), text.len());
+                            Message::Binary(data)
+                        }
+                        None => Message::Text(text),
+                    },
+                    // Send as text message (like iOS WebSocketEngine)
+                    WireEncoding::Json => {
+                        println!("[WebSocket] Sending text message: {}", text);
+                        Message::Text(text)
+                    }
+                },
+            };
+            
+            let frame_len = frame.len();
+            if let Err(e) = write_guard.send(frame).await {
+                println!("[WebSocket] Error sending frame: {}", e);
+                writer_diagnostics.connection_closed(generation, &format!("write error: {}", e), None, None);
+                writer_cancel.cancel();
+                break;
             }
+            writer_diagnostics.record_out(frame_len);
+        }
+        
+        // Say goodbye properly; fails harmlessly if the server already closed the socket
+        let mut write_guard = write_clone.lock().await;
+        match write_guard.send(Message::Close(Some(client_close_frame()))).await {
+            Ok(_) => println!("[WebSocket] Close frame sent for connection {}", generation),
+            Err(e) => println!("[WebSocket] Could not send close frame for connection {}: {}", generation, e),
         }
         println!("[WebSocket] Message writer task ending");
     });
@@ -436,82 +780,175 @@ pub async fn connect_socket(
     // Heartbeat task with native WebSocket ping (like Swift)
     println!("[WebSocket] Starting heartbeat task...");
     let write_clone = Arc::clone(&write);
-    let ws_state_clone = Arc::clone(&ws_state.inner());
+    let ws_state_clone = Arc::clone(ws_state.inner());
+    let socket_tx_clone = Arc::clone(state.inner());
+    let heartbeat_cancel = cancel.clone();
+    let heartbeat_diagnostics = Arc::clone(&diagnostics);
     let app_clone = app.clone();
-    tokio::spawn(async move {
-        let heartbeat_interval = {
-            let state = ws_state_clone.lock().await;
-            state.heartbeat_interval
-        };
-        
+    let heartbeat = tokio::spawn(async move {
         println!("[WebSocket] Heartbeat task started, will send ping every {} seconds", heartbeat_interval.as_secs());
+        let mut connection_lost = false;
         loop {
-            sleep(heartbeat_interval).await;
-            
-            // Check if connection is still active before sending ping
-            let is_connected = {
-                let state = ws_state_clone.lock().await;
-                state.connection_state == ConnectionState::Connected
-            };
-            
-            if !is_connected {
-                println!("[WebSocket] Connection no longer active, stopping heartbeat");
-                break;
+            tokio::select! {
+                _ = heartbeat_cancel.cancelled() => break,
+                _ = sleep(heartbeat_interval) => {}
             }
             
             println!("[WebSocket] Sending heartbeat ping...");
             
             // Send native WebSocket ping frame (like iOS implementation)
             let mut write_guard = write_clone.lock().await;
-            if let Err(e) = write_guard.send(Message::Ping(vec![])).await {
+            let ping = Message::Ping(heartbeat_diagnostics.next_ping_payload());
+            let ping_len = ping.len();
+            if let Err(e) = write_guard.send(ping).await {
                 println!("[WebSocket] Error sending heartbeat ping: {}", e);
+                heartbeat_diagnostics.connection_closed(generation, &format!("heartbeat ping failed: {}", e), None, None);
+                connection_lost = true;
                 break;
             }
+            drop(write_guard);
+            heartbeat_diagnostics.record_out(ping_len);
             println!("[WebSocket] Heartbeat ping sent successfully");
             
             // Check if we've received anything recently (3x heartbeat interval timeout for more tolerance)
             let ws_state_guard = ws_state_clone.lock().await;
+            if ws_state_guard.generation != generation {
+                println!("[WebSocket] Heartbeat {} belongs to a replaced connection, stopping", generation);
+                break;
+            }
             let elapsed = ws_state_guard.last_heartbeat.elapsed();
             let timeout = heartbeat_interval * 3; // More tolerant timeout
             if elapsed > timeout {
                 println!("[WebSocket] Heartbeat timeout - last message received {} seconds ago", elapsed.as_secs());
-                drop(ws_state_guard);
+                heartbeat_diagnostics.connection_closed(
+                    generation,
+                    &format!("heartbeat timeout after {}s of silence", elapsed.as_secs()),
+                    None,
+                    None,
+                );
+                connection_lost = true;
                 break;
             }
             println!("[WebSocket] Heartbeat check passed, last message received {} seconds ago", elapsed.as_secs());
         }
         
-        // Only set disconnected if we're actually disconnected
-        // Check if the WebSocket is still functional before marking as disconnected
-        println!("[WebSocket] Heartbeat task ending - checking actual connection status");
-        let mut ws_state_guard = ws_state_clone.lock().await;
-        
-        // Only mark as disconnected if we haven't received any messages recently
-        let time_since_last_message = ws_state_guard.last_heartbeat.elapsed();
-        if time_since_last_message > Duration::from_secs(60) { // 1 minute timeout
-            println!("[WebSocket] No messages received for 60 seconds, marking as disconnected");
-            ws_state_guard.connection_state = ConnectionState::Disconnected;
-            drop(ws_state_guard);
-            
-            app_clone.emit("websocket-status", json!({
-                "connection_state": "disconnected",
-                "is_connected": false,
-                "is_connecting": false,
-                "reconnect_attempts": 0,
-                "last_heartbeat": 0,
-                "max_reconnect_attempts": 5,
-                "heartbeat_interval": 30
-            })).ok();
+        if connection_lost {
+            // Tear down the reader and writer too; the writer sends the Close frame
+            heartbeat_cancel.cancel();
+            mark_disconnected_if_current(&ws_state_clone, &socket_tx_clone, generation, &app_clone).await;
             println!("[WebSocket] Heartbeat timeout status emitted to frontend");
-        } else {
-            println!("[WebSocket] Messages still flowing, keeping connection as active");
-            drop(ws_state_guard);
         }
+        println!("[WebSocket] Heartbeat task {} ended", generation);
     });
 
+    // Presence task reports our online/away status for the lifetime of the connection
+    let presence = tokio::spawn(crate::modules::presence::run_presence_task(
+        app.clone(),
+        presence_tx,
+        Arc::clone(&queue_stats),
+        cancel.clone(),
+    ));
+
+    ws_state_guard.connection = Some(ConnectionHandle {
+        generation,
+        cancel,
+        tasks: vec![reader, writer, heartbeat, presence],
+    });
+    drop(ws_state_guard);
+
     Ok(())
 }
 
+// Route a decoded JSON frame to the handler for its type
+async fn route_text_frame(text: &str, router: &mut InboundRouter, app: &AppHandle) {
+    // Check if it's a chat message and log it prominently
+    if text.contains("\"type\":\"chat\"") {
+        println!("[WebSocket]  CHAT MESSAGE RECEIVED: {}", text);
+        
+        // Queue for the chat's worker so messages are stored in arrival order
+        router.dispatch(frame_chat_id(text), InboundFrame::Chat(text.to_string())).await;
+    }
+    // Typing frames are ephemeral and never touch the database
+    else if text.contains("\"type\":\"typing\"") {
+        if let Err(e) = crate::modules::typing::handle_typing_frame(app, text).await {
+            println!("[WebSocket] Error handling typing frame: {}", e);
+            router.diagnostics.record_parse_failure("typing");
+        }
+    }
+    else if text.contains("\"type\":\"presence\"") {
+        if let Err(e) = crate::modules::presence::handle_presence_frame(app, text).await {
+            println!("[WebSocket] Error handling presence frame: {}", e);
+            router.diagnostics.record_parse_failure("presence");
+        }
+    }
+    // An error about typing means this server does not support the frame type
+    else if text.contains("\"type\":\"error\"") && text.contains("typing") {
+        println!("[WebSocket] Server rejected typing frame: {}", text);
+        crate::modules::typing::mark_typing_unsupported(app).await;
+    }
+    // Check if it's a message status update (ACK) - handle multiple possible formats
+    else if text.contains("\"type\":\"message-status\"") || 
+            text.contains("\"type\":\"status\"") ||
+            (text.contains("\"status\"") && text.contains("\"message_id\"")) {
+        println!("[WebSocket]  MESSAGE STATUS UPDATE RECEIVED: {}", text);
+        
+        // Same worker as the chat's messages so an ACK never overtakes its message
+        router.dispatch(frame_chat_id(text), InboundFrame::Status(text.to_string())).await;
+    }
+    else if serde_json::from_str::<serde_json::Value>(text).is_err() {
+        println!("[WebSocket] Received frame that is not valid JSON");
+        router.diagnostics.record_parse_failure("invalid-json");
+    }
+}
+
+// Update last_heartbeat, unless this task's connection has been replaced
+async fn touch_heartbeat(ws_state: &TokioMutex<WebSocketState>, generation: u64) {
+    let mut ws_state_guard = ws_state.lock().await;
+    if ws_state_guard.generation == generation {
+        ws_state_guard.last_heartbeat = Instant::now();
+    }
+}
+
+// Called by a connection task that noticed its socket died. Does nothing if the
+// connection has already been replaced or disconnected on purpose.
+async fn mark_disconnected_if_current(
+    ws_state: &TokioMutex<WebSocketState>,
+    socket_tx: &SocketTx,
+    generation: u64,
+    app: &AppHandle,
+) {
+    let mut ws_state_guard = ws_state.lock().await;
+    if ws_state_guard.generation != generation || ws_state_guard.connection_state == ConnectionState::Disconnected {
+        println!("[WebSocket] Connection {} is stale, leaving state untouched", generation);
+        return;
+    }
+    println!("[WebSocket] Connection {} lost, marking as disconnected", generation);
+    ws_state_guard.connection_state = ConnectionState::Disconnected;
+    *socket_tx.0.lock().await = None;
+    drop(ws_state_guard);
+    emit_disconnected(app);
+}
+
+fn emit_disconnected(app: &AppHandle) {
+    app.emit("websocket-status", json!({
+        "connection_state": "disconnected",
+        "is_connected": false,
+        "is_connecting": false,
+        "reconnect_attempts": 0,
+        "last_heartbeat": 0,
+        "max_reconnect_attempts": 5,
+        "heartbeat_interval": 30
+    })).ok();
+    println!("[WebSocket] Disconnected status emitted to frontend");
+}
+
+fn client_close_frame() -> CloseFrame<'static> {
+    CloseFrame {
+        code: CloseCode::Normal,
+        reason: "client disconnect".into(),
+    }
+}
+
 #[tauri::command]
 pub async fn disconnect_socket(
     state: State<'_, Arc<SocketTx>>,
@@ -522,21 +959,21 @@ pub async fn disconnect_socket(
     let mut ws_state_guard = ws_state.lock().await;
     ws_state_guard.connection_state = ConnectionState::Disconnected;
     ws_state_guard.auth_token = None; // Clear the token on disconnect
+    // Any task or handshake still running for the old connection is now stale
+    ws_state_guard.generation += 1;
+    let connection = ws_state_guard.connection.take();
+    *state.0.lock().await = None;
+    let diagnostics = Arc::clone(&ws_state_guard.diagnostics);
     drop(ws_state_guard);
     
-    *state.0.lock().await = None;
+    if let Some(connection) = connection {
+        let frame = client_close_frame();
+        diagnostics.connection_closed(connection.generation, "client disconnect", Some(u16::from(frame.code)), Some(frame.reason.to_string()));
+        connection.shutdown().await;
+    }
     println!("[WebSocket] WebSocket disconnected successfully");
     
-    app.emit("websocket-status", json!({
-        "connection_state": "disconnected",
-        "is_connected": false,
-        "is_connecting": false,
-        "reconnect_attempts": 0,
-        "last_heartbeat": 0,
-        "max_reconnect_attempts": 5,
-        "heartbeat_interval": 30
-    })).ok();
-    println!("[WebSocket] Disconnected status emitted to frontend");
+    emit_disconnected(&app);
     
     Ok(())
 }
@@ -544,12 +981,15 @@ pub async fn disconnect_socket(
 #[tauri::command]
 pub async fn send_socket_message(
     state: State<'_, Arc<SocketTx>>, 
+    ws_state: State<'_, Arc<TokioMutex<WebSocketState>>>,
     message: String
 ) -> Result<(), String> {
     println!("[WebSocket] Attempting to send message: {}", message);
-    let tx_option = state.0.lock().await;
-    if let Some(tx) = &*tx_option {
-        tx.send(message).map_err(|e| format!("Failed to send message: {}", e))?;
+    let queue_stats = Arc::clone(&ws_state.lock().await.queue_stats);
+    let tx_option = state.0.lock().await.clone();
+    if let Some(tx) = tx_option {
+        enqueue_outbound(&tx, message, OverflowPolicy::Timeout(OUTBOUND_SEND_TIMEOUT), &queue_stats).await
+            .map_err(|e| format!("Failed to send message: {}", e))?;
         println!("[WebSocket] Message queued for sending successfully");
         Ok(())
     } else {
@@ -561,27 +1001,19 @@ pub async fn send_socket_message(
 #[tauri::command]
 pub async fn send_socket_binary_message(
     state: State<'_, Arc<SocketTx>>, 
+    ws_state: State<'_, Arc<TokioMutex<WebSocketState>>>,
     message: Vec<u8>
 ) -> Result<(), String> {
     println!("[WebSocket] Attempting to send binary message: {} bytes", message.len());
     println!("[WebSocket] Binary data preview: {:?}", &message[..std::cmp::min(message.len(), 100)]);
     
-    // Try to decode as string for debugging
-    if let Ok(text) = String::from_utf8(message.clone()) {
-        println!("[WebSocket] Binary data as string: {}", text);
-    }
-    
-    let tx_option = state.0.lock().await;
-    if let Some(tx) = &*tx_option {
-        // Convert binary to text message for compatibility
-        if let Ok(text) = String::from_utf8(message) {
-            tx.send(text).map_err(|e| format!("Failed to send binary message: {}", e))?;
-            println!("[WebSocket] Binary message converted to text and queued for sending successfully");
-            Ok(())
-        } else {
-            println!("[WebSocket] Failed to convert binary message to text");
-            Err("Failed to convert binary message to text".to_string())
-        }
+    let queue_stats = Arc::clone(&ws_state.lock().await.queue_stats);
+    let tx_option = state.0.lock().await.clone();
+    if let Some(tx) = tx_option {
+        enqueue_outbound(&tx, OutboundFrame::Binary(message), OverflowPolicy::Timeout(OUTBOUND_SEND_TIMEOUT), &queue_stats).await
+            .map_err(|e| format!("Failed to send binary message: {}", e))?;
+        println!("[WebSocket] Binary message queued for sending successfully");
+        Ok(())
     } else {
         println!("[WebSocket] Failed to send binary message - WebSocket not connected");
         Err("WebSocket not connected".to_string())
@@ -590,15 +1022,20 @@ pub async fn send_socket_binary_message(
 
 #[tauri::command]
 pub async fn send_socket_ping(
-    state: State<'_, Arc<SocketTx>>
+    state: State<'_, Arc<SocketTx>>,
+    ws_state: State<'_, Arc<TokioMutex<WebSocketState>>>,
 ) -> Result<(), String> {
     println!("[WebSocket] Attempting to send ping message");
-    let tx_option = state.0.lock().await;
-    if let Some(tx) = &*tx_option {
+    let queue_stats = Arc::clone(&ws_state.lock().await.queue_stats);
+    let tx_option = state.0.lock().await.clone();
+    if let Some(tx) = tx_option {
         // Send native WebSocket ping frame (like iOS implementation)
-        // Note: This will be converted to Message::Ping in the message writer task
-        tx.send("PING".to_string()).map_err(|e| format!("Failed to send ping: {}", e))?;
-        println!("[WebSocket] Ping message queued for sending successfully");
+        // A ping that cannot be queued is dropped; the heartbeat task sends its own.
+        let queued = enqueue_outbound(&tx, OutboundFrame::Ping, OverflowPolicy::DropNewest, &queue_stats).await
+            .map_err(|e| format!("Failed to send ping: {}", e))?;
+        if queued {
+            println!("[WebSocket] Ping message queued for sending successfully");
+        }
         Ok(())
     } else {
         println!("[WebSocket] Failed to send ping - WebSocket not connected");
@@ -612,9 +1049,14 @@ pub async fn send_socket_ping(
 
 #[tauri::command]
 pub async fn get_websocket_status(
+    socket_tx: State<'_, Arc<SocketTx>>,
     ws_state: State<'_, Arc<TokioMutex<WebSocketState>>>
 ) -> Result<serde_json::Value, String> {
+    let outbound_depth = socket_tx.0.lock().await.as_ref()
+        .map(|tx| tx.max_capacity() - tx.capacity())
+        .unwrap_or(0);
     let state = ws_state.lock().await;
+    let stats = &state.queue_stats;
     let connection_state_str = match state.connection_state {
         ConnectionState::Connected => "connected",
         ConnectionState::Disconnected => "disconnected",
@@ -628,7 +1070,17 @@ pub async fn get_websocket_status(
         "reconnect_attempts": state.reconnect_attempts,
         "last_heartbeat": state.last_heartbeat.elapsed().as_secs(),
         "max_reconnect_attempts": state.max_reconnect_attempts,
-        "heartbeat_interval": state.heartbeat_interval.as_secs()
+        "heartbeat_interval": state.heartbeat_interval.as_secs(),
+        "encoding": state.encoding.as_str(),
+        "outbound_queue_depth": outbound_depth,
+        "outbound_queue_capacity": OUTBOUND_QUEUE_CAPACITY,
+        "outbound_dropped": stats.outbound_dropped.load(Ordering::Relaxed),
+        "outbound_timeouts": stats.outbound_timeouts.load(Ordering::Relaxed),
+        "inbound_queue_depth": stats.inbound_depth.load(Ordering::Relaxed),
+        "inbound_queue_high_water": stats.inbound_high_water.load(Ordering::Relaxed),
+        "inbound_chat_queue_capacity": INBOUND_CHAT_QUEUE_CAPACITY,
+        "inbound_chat_workers": stats.inbound_workers.load(Ordering::Relaxed),
+        "inbound_backpressure_events": stats.inbound_backpressure_events.load(Ordering::Relaxed)
     });
     println!("[WebSocket] Status requested: {:?}", status);
     Ok(status)
@@ -656,6 +1108,7 @@ pub async fn reconnect_socket(
     // Attempt reconnection with retry logic (like Swift)
     for attempt in 1..=max_attempts {
         println!("[WebSocket] Reconnection attempt {}/{}", attempt, max_attempts);
+        ws_state.lock().await.reconnect_attempts = attempt;
         
         match connect_socket(token.clone(), state.clone(), ws_state.clone(), app.clone()).await {
             Ok(_) => {
@@ -676,8 +1129,27 @@ pub async fn reconnect_socket(
     Err("Failed to reconnect after maximum attempts".to_string())
 }
 
+// Body of the message-updated event: the stored row plus the legacy status flags
+pub fn message_updated_payload(message: &crate::database_async::Message) -> serde_json::Value {
+    json!({
+        "message_id": message.message_id,
+        "client_message_id": message.client_message_id,
+        "chat_id": message.chat_id,
+        "sender_id": message.sender_id,
+        "content": message.content,
+        "timestamp": message.timestamp,
+        "status": message.status,
+        "is_read": message.status.is_read(),
+        "is_sent": message.status.is_sent(),
+        "is_delivered": message.status.is_delivered(),
+        "is_failed": message.status.is_failed(),
+        "sender_username": message.sender_username,
+        "reply_to_message_id": message.reply_to_message_id
+    })
+}
+
 // Handle chat message in background task
-async fn handle_chat_message(message_text: &str, app: AppHandle) -> Result<(), String> {
+async fn handle_chat_message(message_text: &str, app: AppHandle) -> Result<(), FrameError> {
     println!("[WebSocket] Processing chat message in background task");
     
     // Parse the message
@@ -716,61 +1188,67 @@ async fn handle_chat_message(message_text: &str, app: AppHandle) -> Result<(), S
         .and_then(|v| v.as_str())
         .ok_or("No sent_at in message")?;
     
-    // Parse timestamp with nanosecond precision for proper message ordering
-    let parsed_datetime = chrono::DateTime::parse_from_rfc3339(sent_at)
+    // Stored in milliseconds like every other timestamp; ties are ordered by row id
+    let timestamp = Timestamp::parse_rfc3339(sent_at)
         .map_err(|e| format!("Failed to parse timestamp: {}", e))?;
     
-    // Convert to nanoseconds since epoch for maximum precision
-    let timestamp = parsed_datetime.timestamp_nanos_opt()
-        .unwrap_or_else(|| parsed_datetime.timestamp() * 1_000_000_000);
+    println!("[WebSocket] Parsed timestamp: {} -> {} (milliseconds)", sent_at, timestamp);
+    
+    // Present when this is the echo of a message we sent ourselves
+    let client_message_id = message.get("client_message_id")
+        .and_then(|v| v.as_str())
+        .filter(|id| !id.is_empty())
+        .unwrap_or(message_id);
     
-    println!("[WebSocket] Parsed timestamp: {} -> {} (nanoseconds)", sent_at, timestamp);
+    let reply_to_message_id = message.get("reply_to_message_id")
+        .and_then(|v| v.as_str())
+        .map(|id| id.to_string());
     
     println!("[WebSocket] Saving decrypted message to database: {}", message_id);
     
-    // Save decrypted message to database
+    // Named from the local profile cache when we can; otherwise a backfill fills it in later
+    let sender_username = crate::modules::profiles::cached_username(&app, sender_id).await;
+    
+    // Save decrypted message to database, merging with any row we already have for it
     let db_message = crate::database_async::Message {
         id: None,
         message_id: Some(message_id.to_string()),
-        client_message_id: message_id.to_string(), // Use server ID as client ID for incoming messages
+        client_message_id: client_message_id.to_string(),
         chat_id: chat_id.to_string(),
         sender_id: sender_id.to_string(),
         content: decrypted_content.clone(), // Store decrypted content in database
         timestamp,
-        is_read: false,
-        is_sent: true,
-        is_delivered: true,
-        is_failed: false,
-        sender_username: None,
-        reply_to_message_id: None,
+        status: crate::message_status::MessageStatus::Delivered,
+        sender_username,
+        reply_to_message_id,
     };
     
-    if let Err(e) = crate::database_async::insert_or_update_message(&db_message).await {
-        println!("[WebSocket] Failed to save message to database: {}", e);
-        return Err(format!("Database error: {}", e));
-    }
-    
-    println!("[WebSocket] Decrypted message saved to database successfully");
-    
-    // Emit message saved event to frontend (with decrypted content)
-    println!("[WebSocket] Emitting message-saved event to frontend");
-    app.emit("message-saved", json!({
-        "message_id": message_id,
-        "chat_id": chat_id,
-        "sender_id": sender_id,
-        "content": decrypted_content, // Send decrypted content - no need for frontend to decrypt
-        "timestamp": timestamp
-    })).ok();
-    
-    println!("[WebSocket] Message-saved event emitted successfully");
+    let (outcome, stored) = match app.state::<SqliteRepo>().reconcile_incoming_message(&db_message).await {
+        Ok(result) => result,
+        Err(e) => {
+            println!("[WebSocket] Failed to save message to database: {}", e);
+            return Err(FrameError::Handler(format!("Database error: {}", e)));
+        }
+    };
     
-    // Also emit a simple test event to verify event system
-    println!("[WebSocket] Emitting test event to verify event system");
-    app.emit("test-event", json!({
-        "test": "event_system_working",
-        "timestamp": timestamp
-    })).ok();
-    println!("[WebSocket] Test event emitted");
+    match outcome {
+        IncomingMessageOutcome::Inserted => {
+            // The frontend learns about the new row from the db-changed feed
+            println!("[WebSocket] Decrypted message saved to database successfully");
+            crate::modules::typing::clear_typing_user(&app, chat_id, sender_id).await;
+            crate::modules::receipts::send_delivered_receipt(&app, &stored).await;
+        }
+        IncomingMessageOutcome::Merged => {
+            println!("[WebSocket] Message {} merged into existing row {}, emitting message-updated", message_id, stored.client_message_id);
+            app.emit("message-updated", message_updated_payload(&stored)).ok();
+        }
+        IncomingMessageOutcome::Unchanged => {
+            println!("[WebSocket] Message {} is a redelivery, no event emitted", message_id);
+        }
+    }
+    if stored.sender_username.as_deref().unwrap_or("").is_empty() {
+        crate::modules::profiles::schedule_sender_backfill(&app).await;
+    }
     
     Ok(())
 }
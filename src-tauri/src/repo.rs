@@ -0,0 +1,113 @@
+// Storage interfaces over the local cache. Commands and sync code go through these
+// rather than a particular database; `SqliteRepo` implements them over chat.db, or over
+// a private in-memory database for tests.
+
+use std::collections::HashMap;
+use std::future::Future;
+use sqlx::Error as SqlxError;
+use crate::database_async::{
+    Chat, DeltaResult, ExpiredMessages, Friend, IncomingMessageOutcome, Message, MessagePage,
+    MessageReadState, MessageSearchFilter, MessageSearchPage, PageDirection, Participant,
+    ReceiptStatus, User, UserKeys,
+};
+use crate::message_status::MessageStatus;
+use crate::timestamp::Timestamp;
+
+pub use crate::database_async::SqliteRepo;
+
+// Cached chats, their retention settings and local-delete markers
+pub trait ChatRepo: Send + Sync {
+    fn insert_or_update_chat(&self, chat: &Chat) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_chat_by_id(&self, chat_id: &str) -> impl Future<Output = Result<Option<Chat>, SqlxError>> + Send;
+    fn get_all_chats(&self) -> impl Future<Output = Result<Vec<Chat>, SqlxError>> + Send;
+    fn set_chat_message_ttl(&self, chat_id: &str, ttl_secs: Option<i64>) -> impl Future<Output = Result<bool, SqlxError>> + Send;
+    fn get_chat_message_ttl(&self, chat_id: &str) -> impl Future<Output = Result<Option<i64>, SqlxError>> + Send;
+    fn delete_chat(&self, chat_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn clear_chat_data(&self) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn add_local_delete(&self, chat_id: &str, user_id: &str, is_creator: bool) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn is_chat_locally_deleted(&self, chat_id: &str, user_id: &str) -> impl Future<Output = Result<bool, SqlxError>> + Send;
+    fn remove_local_delete(&self, chat_id: &str, user_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn cleanup_local_deletes(&self, server_chat_ids: &[String], user_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_local_deleted_chat_ids(&self, user_id: &str) -> impl Future<Output = Result<Vec<String>, SqlxError>> + Send;
+    fn apply_chat_delta(&self, server_chats: &[Chat], user_id: &str) -> impl Future<Output = Result<DeltaResult, SqlxError>> + Send;
+}
+
+// Cached messages, search and retention purges
+pub trait MessageRepo: Send + Sync {
+    fn insert_or_update_message(&self, message: &Message) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn insert_messages(&self, messages: &[Message]) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn reconcile_incoming_message(&self, message: &Message) -> impl Future<Output = Result<(IncomingMessageOutcome, Message), SqlxError>> + Send;
+    fn get_messages_for_chat(&self, chat_id: &str) -> impl Future<Output = Result<Vec<Message>, SqlxError>> + Send;
+    fn get_messages_before_timestamp(&self, chat_id: &str, before_timestamp: Timestamp, limit: i32) -> impl Future<Output = Result<Vec<Message>, SqlxError>> + Send;
+    fn get_message_page(&self, chat_id: &str, cursor: Option<&str>, direction: PageDirection, limit: i64) -> impl Future<Output = Result<MessagePage, SqlxError>> + Send;
+    fn get_last_message(&self, chat_id: &str) -> impl Future<Output = Result<Option<Message>, SqlxError>> + Send;
+    // Server id of the oldest message the server has confirmed; unsent outbox rows are skipped
+    fn get_oldest_server_message_id(&self, chat_id: &str) -> impl Future<Output = Result<Option<String>, SqlxError>> + Send;
+    fn get_message_by_id(&self, message_id: &str) -> impl Future<Output = Result<Option<Message>, SqlxError>> + Send;
+    fn get_message_by_client_id(&self, client_message_id: &str) -> impl Future<Output = Result<Option<Message>, SqlxError>> + Send;
+    fn update_message_status(&self, client_message_id: &str, status: MessageStatus) -> impl Future<Output = Result<Option<Message>, SqlxError>> + Send;
+    fn update_message_sent_status(&self, client_message_id: &str, is_sent: bool) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn update_message_sent_status_by_server_id(&self, server_id: &str, is_sent: bool) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn mark_message_delivered_by_server_id_new(&self, server_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn mark_message_read_by_server_id_new(&self, server_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn mark_messages_read_by_server_ids(&self, message_ids: &[String]) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn record_message_receipt(&self, message_id: &str, user_id: &str, status: ReceiptStatus, at: Timestamp) -> impl Future<Output = Result<bool, SqlxError>> + Send;
+    fn get_message_read_state(&self, message_id: &str) -> impl Future<Output = Result<Option<MessageReadState>, SqlxError>> + Send;
+    fn count_unread_messages(&self, chat_id: &str) -> impl Future<Output = Result<i32, SqlxError>> + Send;
+    fn get_unread_messages(&self, chat_id: &str) -> impl Future<Output = Result<Vec<Message>, SqlxError>> + Send;
+    fn mark_messages_as_read(&self, chat_id: &str) -> impl Future<Output = Result<Vec<Message>, SqlxError>> + Send;
+    fn update_message_id_by_client(&self, client_message_id: &str, server_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn delete_message_by_id(&self, message_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn delete_message_by_client_id(&self, client_message_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn clear_message_data(&self) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn search_messages(&self, query: &str, filter: &MessageSearchFilter, limit: i64, cursor: Option<&str>) -> impl Future<Output = Result<MessageSearchPage, SqlxError>> + Send;
+    fn clear_messages_for_chat(&self, chat_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn purge_expired_messages(&self, now: Timestamp) -> impl Future<Output = Result<Vec<ExpiredMessages>, SqlxError>> + Send;
+    // Senders of messages stored without a name that no local profile can name either
+    fn get_unresolved_sender_ids(&self) -> impl Future<Output = Result<Vec<String>, SqlxError>> + Send;
+    fn fill_sender_usernames(&self) -> impl Future<Output = Result<Vec<Message>, SqlxError>> + Send;
+}
+
+// Cached friend list
+pub trait FriendRepo: Send + Sync {
+    fn insert_or_update_friend(&self, friend: &Friend) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_all_friends(&self) -> impl Future<Output = Result<Vec<Friend>, SqlxError>> + Send;
+    fn get_friend_by_id(&self, user_id: &str) -> impl Future<Output = Result<Option<Friend>, SqlxError>> + Send;
+    fn delete_friend(&self, user_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn clear_friend_data(&self) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn update_friend_status(&self, user_id: &str, status: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn apply_friend_delta(&self, server_friends: &[Friend]) -> impl Future<Output = Result<DeltaResult, SqlxError>> + Send;
+}
+
+// Cached chat participants
+pub trait ParticipantRepo: Send + Sync {
+    fn insert_or_update_participant(&self, participant: &Participant) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_participants_for_chat(&self, chat_id: &str) -> impl Future<Output = Result<Vec<Participant>, SqlxError>> + Send;
+    fn clear_participant_data(&self) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_participant_by_id(&self, participant_id: &str) -> impl Future<Output = Result<Option<Participant>, SqlxError>> + Send;
+    fn get_participant_by_user_id_and_chat_id(&self, user_id: &str, chat_id: &str) -> impl Future<Output = Result<Option<Participant>, SqlxError>> + Send;
+    fn delete_participant(&self, participant_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn update_participant_role(&self, participant_id: &str, role: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn remove_all_participants_for_chat(&self, chat_id: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn apply_participant_delta(&self, chat_id: &str, server_participants: &[Participant]) -> impl Future<Output = Result<DeltaResult, SqlxError>> + Send;
+}
+
+// Signed-in users, their keys and appearance settings
+pub trait UserRepo: Send + Sync {
+    fn insert_or_update_user(&self, user: &User) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_user_by_id(&self, user_id: &str) -> impl Future<Output = Result<Option<User>, SqlxError>> + Send;
+    fn get_user_by_token(&self, token: &str) -> impl Future<Output = Result<Option<User>, SqlxError>> + Send;
+    fn get_most_recent_user(&self) -> impl Future<Output = Result<Option<User>, SqlxError>> + Send;
+    fn update_user_token(&self, user_id: &str, token: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn clear_user_data(&self) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn insert_or_update_user_keys(&self, keys: &UserKeys) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_user_keys(&self, user_id: &str) -> impl Future<Output = Result<Option<UserKeys>, SqlxError>> + Send;
+    fn update_dark_mode(&self, user_id: &str, is_dark_mode: bool) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_dark_mode(&self, user_id: &str) -> impl Future<Output = Result<bool, SqlxError>> + Send;
+    fn update_send_read_receipts(&self, user_id: &str, enabled: bool) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_send_read_receipts(&self, user_id: &str) -> impl Future<Output = Result<bool, SqlxError>> + Send;
+    fn update_color_scheme(&self, user_id: &str, color_scheme: &str) -> impl Future<Output = Result<(), SqlxError>> + Send;
+    fn get_color_scheme(&self, user_id: &str) -> impl Future<Output = Result<String, SqlxError>> + Send;
+    // Locally known usernames for any users, friends or chat participants among `user_ids`
+    fn get_usernames(&self, user_ids: &[String]) -> impl Future<Output = Result<HashMap<String, String>, SqlxError>> + Send;
+}
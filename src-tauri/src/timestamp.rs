@@ -0,0 +1,102 @@
+// Canonical timestamp for everything in the local database: milliseconds since the
+// Unix epoch, UTC.
+//
+// Older rows and some server payloads carry seconds or nanoseconds. Values coming in
+// through serde or out of SQLite are normalized by magnitude (see `from_any`), and
+// only milliseconds are ever written back. Migration 9 applies the same bands
+// to existing rows.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    sqlite::{Sqlite, SqliteTypeInfo, SqliteValueRef},
+    Decode, Encode, Type,
+};
+
+// Upper bounds of each unit; every band covers 1973 to roughly the year 5000
+const SECONDS_BELOW: i64 = 100_000_000_000;
+const MILLIS_BELOW: i64 = 100_000_000_000_000;
+const MICROS_BELOW: i64 = 100_000_000_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp(i64);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp(Utc::now().timestamp_millis())
+    }
+
+    pub fn from_millis(millis: i64) -> Self {
+        Timestamp(millis)
+    }
+
+    pub fn from_secs(secs: i64) -> Self {
+        Timestamp(secs.saturating_mul(1000))
+    }
+
+    /// Interpret a raw value of unknown unit (s, ms, µs or ns) by its magnitude
+    pub fn from_any(raw: i64) -> Self {
+        match raw {
+            i64::MIN..=0 => Timestamp(raw),
+            1..SECONDS_BELOW => Timestamp(raw * 1000),
+            SECONDS_BELOW..MILLIS_BELOW => Timestamp(raw),
+            MILLIS_BELOW..MICROS_BELOW => Timestamp(raw / 1000),
+            _ => Timestamp(raw / 1_000_000),
+        }
+    }
+
+    pub fn parse_rfc3339(value: &str) -> Result<Self, chrono::ParseError> {
+        DateTime::parse_from_rfc3339(value).map(|dt| Timestamp(dt.timestamp_millis()))
+    }
+
+    pub fn as_millis(self) -> i64 {
+        self.0
+    }
+
+    pub fn to_datetime(self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp_millis(self.0)
+    }
+}
+
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Plain number on the wire, so the frontend keeps seeing milliseconds
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        i64::deserialize(deserializer).map(Timestamp::from_any)
+    }
+}
+
+impl Type<Sqlite> for Timestamp {
+    fn type_info() -> SqliteTypeInfo {
+        <i64 as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <i64 as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for Timestamp {
+    fn encode_by_ref(&self, buf: &mut <Sqlite as sqlx::Database>::ArgumentBuffer<'q>) -> Result<IsNull, BoxDynError> {
+        <i64 as Encode<Sqlite>>::encode_by_ref(&self.0, buf)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for Timestamp {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        <i64 as Decode<Sqlite>>::decode(value).map(Timestamp::from_any)
+    }
+}
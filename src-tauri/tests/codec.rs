@@ -0,0 +1,40 @@
+use app_lib::modules::codec::{decode_server_event, encode_client_event};
+use serde_json::{json, Value};
+
+fn parse(text: &str) -> Value {
+    serde_json::from_str(text).unwrap()
+}
+
+#[test]
+fn messagepack_round_trip_matches_json() {
+    let event = json!({
+        "type": "chat",
+        "message": {
+            "chat_id": "c1",
+            "content": "aGVsbG8=",
+            "client_message_id": "local-1"
+        },
+        "request_id": "r-42",
+        "version": 2
+    });
+    let text = event.to_string();
+
+    let packed = encode_client_event(&text).unwrap();
+    assert_eq!(parse(&decode_server_event(&packed).unwrap()), event);
+    // JSON sent in a binary frame comes out the same way
+    assert_eq!(parse(&decode_server_event(text.as_bytes()).unwrap()), event);
+}
+
+#[test]
+fn non_base64_content_stays_in_the_message() {
+    let event = json!({ "type": "chat", "message": { "content": "not base64!" } });
+
+    let packed = encode_client_event(&event.to_string()).unwrap();
+    assert_eq!(parse(&decode_server_event(&packed).unwrap()), event);
+}
+
+#[test]
+fn frames_that_are_not_events_are_rejected() {
+    assert!(encode_client_event("plain text").is_none());
+    assert!(decode_server_event(&[0xc1, 0xff, 0x00]).is_err());
+}
@@ -0,0 +1,268 @@
+use app_lib::migrations;
+use app_lib::timestamp::Timestamp;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+
+// Single connection so every query sees the same in-memory database
+async fn memory_pool() -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("open in-memory database")
+}
+
+async fn v1_pool() -> SqlitePool {
+    let pool = memory_pool().await;
+    sqlx::query(include_str!("fixtures/v1_database.sql"))
+        .execute(&pool)
+        .await
+        .expect("load v1 fixture");
+    pool
+}
+
+async fn has_column(pool: &SqlitePool, table: &str, column: &str) -> bool {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    count > 0
+}
+
+async fn has_table(pool: &SqlitePool, table: &str) -> bool {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .bind(table)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    count > 0
+}
+
+#[tokio::test]
+async fn v1_fixture_is_detected_as_version_1() {
+    let pool = v1_pool().await;
+    assert_eq!(migrations::current_version(&pool).await.unwrap(), 1);
+    assert!(!has_table(&pool, "schema_version").await);
+}
+
+#[tokio::test]
+async fn upgrades_v1_database_to_latest() {
+    let pool = v1_pool().await;
+
+    let report = migrations::run_migrations(&pool).await.unwrap();
+    assert_eq!(report.from_version, 1);
+    assert_eq!(report.to_version, migrations::latest_version());
+    assert!(report.applied.iter().all(|m| m.version > 1));
+
+    assert_eq!(migrations::current_version(&pool).await.unwrap(), migrations::latest_version());
+    assert!(has_column(&pool, "message", "status").await);
+    assert!(!has_column(&pool, "message", "is_failed").await);
+    assert!(has_table(&pool, "local_deletes").await);
+    assert!(has_table(&pool, "presence").await);
+    assert!(has_column(&pool, "chat", "message_ttl_secs").await);
+    assert!(has_column(&pool, "chat", "imported_at").await);
+    assert!(has_column(&pool, "chat", "is_local_only").await);
+    assert!(has_column(&pool, "chat", "is_read_only").await);
+
+    // Existing rows survive with their flags folded into a status
+    let rows: Vec<(String, String)> = sqlx::query_as("SELECT client_message_id, status FROM message ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(rows, vec![
+        ("client-1".to_string(), "read".to_string()),
+        ("client-2".to_string(), "delivered".to_string()),
+    ]);
+
+    let chats: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chat").fetch_one(&pool).await.unwrap();
+    assert_eq!(chats, 1);
+
+    // Every version, including the v1 baseline, is recorded
+    let versions: Vec<i64> = sqlx::query_scalar("SELECT version FROM schema_version ORDER BY version")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(versions, (1..=migrations::latest_version()).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn running_twice_is_a_no_op() {
+    let pool = v1_pool().await;
+    migrations::run_migrations(&pool).await.unwrap();
+
+    let report = migrations::run_migrations(&pool).await.unwrap();
+    assert!(report.applied.is_empty());
+    assert_eq!(report.to_version, migrations::latest_version());
+}
+
+#[tokio::test]
+async fn tolerates_legacy_install_that_already_has_newer_objects() {
+    // Some pre-migration installs were created from a schema that already had these
+    let pool = v1_pool().await;
+    sqlx::query("ALTER TABLE message ADD COLUMN is_failed INTEGER NOT NULL DEFAULT 0")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("CREATE TABLE local_deletes (chat_id TEXT PRIMARY KEY, deleted_at INTEGER NOT NULL, user_id TEXT NOT NULL, is_creator INTEGER NOT NULL DEFAULT 0)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let report = migrations::run_migrations(&pool).await.unwrap();
+    assert_eq!(report.to_version, migrations::latest_version());
+}
+
+#[tokio::test]
+async fn fresh_database_gets_full_schema() {
+    let pool = memory_pool().await;
+    assert_eq!(migrations::current_version(&pool).await.unwrap(), 0);
+
+    let report = migrations::run_migrations(&pool).await.unwrap();
+    assert_eq!(report.from_version, 0);
+    assert_eq!(report.applied.len(), migrations::MIGRATIONS.len());
+
+    let verification = migrations::verify_schema(&pool).await.unwrap();
+    assert!(verification.ok, "{:?}", verification);
+}
+
+#[tokio::test]
+async fn dry_run_and_verify_leave_database_untouched() {
+    let pool = v1_pool().await;
+
+    let report = migrations::dry_run_migrations(&pool).await.unwrap();
+    assert!(report.dry_run);
+    assert_eq!(report.to_version, migrations::latest_version());
+
+    let verification = migrations::verify_schema(&pool).await.unwrap();
+    assert_eq!(verification.current_version, 1);
+    assert!(!verification.pending.is_empty());
+    assert!(verification.ok, "{:?}", verification);
+
+    assert_eq!(migrations::current_version(&pool).await.unwrap(), 1);
+    assert!(!has_column(&pool, "message", "is_failed").await);
+    assert!(!has_table(&pool, "schema_version").await);
+}
+
+#[tokio::test]
+async fn search_index_is_backfilled_and_kept_in_sync() {
+    let pool = v1_pool().await;
+    migrations::run_migrations(&pool).await.unwrap();
+
+    async fn matches(pool: &SqlitePool, query: &str) -> Vec<String> {
+        sqlx::query_scalar(
+            "SELECT m.client_message_id FROM message_fts JOIN message m ON m.id = message_fts.rowid
+             WHERE message_fts MATCH ? ORDER BY m.id"
+        )
+        .bind(query)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+
+    // History from before the migration is searchable
+    assert_eq!(matches(&pool, "there").await, vec!["client-2".to_string()]);
+
+    sqlx::query("UPDATE message SET content = 'goodbye' WHERE client_message_id = 'client-1'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(matches(&pool, "hello").await.is_empty());
+    assert_eq!(matches(&pool, "goodbye").await, vec!["client-1".to_string()]);
+
+    sqlx::query("DELETE FROM message WHERE client_message_id = 'client-2'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(matches(&pool, "there").await.is_empty());
+}
+
+#[tokio::test]
+async fn foreign_keys_cascade_and_orphans_are_dropped() {
+    let pool = v1_pool().await;
+    // Left behind by an earlier delete that failed halfway
+    sqlx::query(
+        "INSERT INTO message (message_id, client_message_id, chat_id, sender_id, content, timestamp)
+         VALUES ('srv-x', 'client-x', 'gone', 'user-1', 'orphan', 1700000002000000000)"
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("INSERT INTO participant (participant_id, user_id, username, joined_at, role, chat_id) VALUES ('part-x', 'user-1', 'alice', 0, 'member', 'gone')")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    migrations::run_migrations(&pool).await.unwrap();
+
+    let orphans: i64 = sqlx::query_scalar(
+        "SELECT (SELECT COUNT(*) FROM message WHERE chat_id = 'gone') + (SELECT COUNT(*) FROM participant WHERE chat_id = 'gone')"
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(orphans, 0);
+
+    // Message ids survive the rebuild, so the search index still points at the right rows
+    let hits: Vec<String> = sqlx::query_scalar(
+        "SELECT m.client_message_id FROM message_fts JOIN message m ON m.id = message_fts.rowid WHERE message_fts MATCH 'hello OR orphan'"
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(hits, vec!["client-1".to_string()]);
+
+    sqlx::query("DELETE FROM chat WHERE chat_id = 'chat-1'").execute(&pool).await.unwrap();
+    let remaining: i64 = sqlx::query_scalar("SELECT (SELECT COUNT(*) FROM message) + (SELECT COUNT(*) FROM participant)")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+
+    // A message for a chat that does not exist is rejected
+    let insert = sqlx::query(
+        "INSERT INTO message (client_message_id, chat_id, sender_id, content, timestamp) VALUES ('client-y', 'missing', 'user-1', 'x', 0)"
+    )
+    .execute(&pool)
+    .await;
+    assert!(insert.is_err());
+}
+
+#[tokio::test]
+async fn timestamps_are_normalized_to_milliseconds() {
+    let pool = v1_pool().await;
+    // Microseconds, as written by an old build
+    sqlx::query(
+        "INSERT INTO message (message_id, client_message_id, chat_id, sender_id, content, timestamp)
+         VALUES ('srv-3', 'client-3', 'chat-1', 'user-1', 'micros', 1700000002000000)"
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let raw: Vec<i64> = sqlx::query_scalar("SELECT timestamp FROM message ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+    migrations::run_migrations(&pool).await.unwrap();
+
+    let messages: Vec<i64> = sqlx::query_scalar("SELECT timestamp FROM message ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(messages, vec![1_700_000_000_000, 1_700_000_001_000, 1_700_000_002_000]);
+    // The migration and Timestamp::from_any agree on every unit
+    let normalized: Vec<i64> = raw.into_iter().map(|t| Timestamp::from_any(t).as_millis()).collect();
+    assert_eq!(normalized, messages);
+
+    let (created_at, joined_at): (i64, i64) = sqlx::query_as(
+        "SELECT c.created_at, p.joined_at FROM chat c JOIN participant p ON p.chat_id = c.chat_id"
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((created_at, joined_at), (1_700_000_000_000, 1_700_000_000_000));
+
+    // Already-millisecond values are left alone
+    assert_eq!(Timestamp::from_any(1_700_000_000_000).as_millis(), 1_700_000_000_000);
+}
@@ -0,0 +1,589 @@
+use app_lib::changes::{coalesce, ChangeOp, ChangeRecord, ChangeTable};
+use app_lib::database_async::{Chat, Friend, IncomingMessageOutcome, Message, MessageSearchFilter, PageDirection, Participant, ReceiptStatus, User};
+use app_lib::message_status::MessageStatus;
+use app_lib::repo::{ChatRepo, FriendRepo, MessageRepo, ParticipantRepo, SqliteRepo, UserRepo};
+use app_lib::timestamp::Timestamp;
+
+fn chat(chat_id: &str) -> Chat {
+    Chat {
+        chat_id: chat_id.to_string(),
+        name: Some(format!("Chat {}", chat_id)),
+        created_at: Timestamp::from_millis(1_700_000_000_000),
+        creator_id: Some("alice".to_string()),
+        is_group: false,
+        group_name: None,
+        description: None,
+        unread_count: 0,
+        last_message_content: None,
+        last_message_timestamp: None,
+        participants: None,
+        is_local_only: false,
+        is_read_only: false,
+    }
+}
+
+fn message(chat_id: &str, client_id: &str, server_id: Option<&str>, millis: i64) -> Message {
+    Message {
+        id: None,
+        message_id: server_id.map(str::to_string),
+        client_message_id: client_id.to_string(),
+        chat_id: chat_id.to_string(),
+        sender_id: "alice".to_string(),
+        content: format!("message {}", client_id),
+        timestamp: Timestamp::from_millis(millis),
+        status: if server_id.is_some() { MessageStatus::Sent } else { MessageStatus::Pending },
+        sender_username: None,
+        reply_to_message_id: None,
+    }
+}
+
+fn friend(user_id: &str, username: &str) -> Friend {
+    Friend {
+        user_id: user_id.to_string(),
+        username: username.to_string(),
+        email: format!("{}@example.com", username),
+        name: username.to_string(),
+        picture: None,
+        created_at: None,
+        updated_at: None,
+        status: Some("accepted".to_string()),
+        is_favorite: false,
+    }
+}
+
+fn participant(chat_id: &str, user_id: &str, role: &str) -> Participant {
+    Participant {
+        participant_id: format!("{}_{}", chat_id, user_id),
+        user_id: user_id.to_string(),
+        username: user_id.to_string(),
+        joined_at: Timestamp::from_millis(1_700_000_000_000),
+        role: role.to_string(),
+        chat_id: chat_id.to_string(),
+    }
+}
+
+// Written against the traits only, so any implementation can be passed in
+async fn seed_chat<R: ChatRepo + MessageRepo>(repo: &R, chat_id: &str, messages: usize) {
+    repo.insert_or_update_chat(&chat(chat_id)).await.unwrap();
+    for i in 0..messages {
+        let client_id = format!("{}-{}", chat_id, i);
+        // Seeded history reads as received, so it counts as unread
+        let message = Message {
+            status: MessageStatus::Delivered,
+            ..message(chat_id, &client_id, None, 1_700_000_000_000 + i as i64)
+        };
+        repo.insert_or_update_message(&message).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn in_memory_repos_are_isolated_and_shared_between_clones() {
+    let first = SqliteRepo::in_memory().await.unwrap();
+    let second = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&first, "c1", 0).await;
+
+    assert_eq!(first.get_all_chats().await.unwrap().len(), 1);
+    assert!(second.get_all_chats().await.unwrap().is_empty());
+
+    let clone = first.clone();
+    assert!(clone.get_chat_by_id("c1").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn deleting_a_chat_removes_its_messages_and_participants() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 3).await;
+    seed_chat(&repo, "c2", 1).await;
+    repo.insert_or_update_participant(&participant("c1", "bob", "member")).await.unwrap();
+
+    assert_eq!(repo.get_messages_for_chat("c1").await.unwrap().len(), 3);
+    repo.delete_chat("c1").await.unwrap();
+
+    assert!(repo.get_chat_by_id("c1").await.unwrap().is_none());
+    assert!(repo.get_messages_for_chat("c1").await.unwrap().is_empty());
+    assert!(repo.get_participants_for_chat("c1").await.unwrap().is_empty());
+    assert_eq!(repo.get_messages_for_chat("c2").await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn incoming_message_merges_into_pending_row() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 0).await;
+    repo.insert_or_update_message(&message("c1", "local-1", None, 1_700_000_000_000)).await.unwrap();
+
+    let echo = message("c1", "local-1", Some("server-1"), 1_700_000_000_500);
+    let (outcome, stored) = repo.reconcile_incoming_message(&echo).await.unwrap();
+    assert_eq!(outcome, IncomingMessageOutcome::Merged);
+    assert_eq!(stored.message_id.as_deref(), Some("server-1"));
+
+    let (outcome, _) = repo.reconcile_incoming_message(&echo).await.unwrap();
+    assert_eq!(outcome, IncomingMessageOutcome::Unchanged);
+    assert_eq!(repo.get_messages_for_chat("c1").await.unwrap().len(), 1);
+    assert!(repo.get_message_by_id("server-1").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn incoming_message_without_client_id_skips_ambiguous_pending_rows() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 0).await;
+    for client_id in ["local-1", "local-2"] {
+        let pending = Message { content: "ok".to_string(), ..message("c1", client_id, None, 1_700_000_000_000) };
+        repo.insert_or_update_message(&pending).await.unwrap();
+    }
+
+    let echo = Message { content: "ok".to_string(), ..message("c1", "unknown", Some("server-1"), 1_700_000_000_500) };
+    let (outcome, _) = repo.reconcile_incoming_message(&echo).await.unwrap();
+    assert_eq!(outcome, IncomingMessageOutcome::Inserted);
+    let messages = repo.get_messages_for_chat("c1").await.unwrap();
+    assert_eq!(messages.iter().filter(|m| m.status == MessageStatus::Pending).count(), 2);
+}
+
+#[tokio::test]
+async fn message_pages_walk_history_in_both_directions() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 5).await;
+    // Same timestamp as c1-4, ordered after it by id
+    repo.insert_or_update_message(&message("c1", "c1-5", None, 1_700_000_000_004)).await.unwrap();
+
+    let newest = repo.get_message_page("c1", None, PageDirection::Older, 4).await.unwrap();
+    let ids: Vec<_> = newest.messages.iter().map(|m| m.client_message_id.as_str()).collect();
+    assert_eq!(ids, ["c1-2", "c1-3", "c1-4", "c1-5"]);
+    assert!(newest.has_more);
+
+    let cursor = newest.next_cursor.unwrap();
+    let older = repo.get_message_page("c1", Some(&cursor), PageDirection::Older, 4).await.unwrap();
+    let ids: Vec<_> = older.messages.iter().map(|m| m.client_message_id.as_str()).collect();
+    assert_eq!(ids, ["c1-0", "c1-1"]);
+    assert!(!older.has_more);
+
+    let cursor = older.next_cursor.unwrap();
+    let newer = repo.get_message_page("c1", Some(&cursor), PageDirection::Newer, 3).await.unwrap();
+    let ids: Vec<_> = newer.messages.iter().map(|m| m.client_message_id.as_str()).collect();
+    assert_eq!(ids, ["c1-1", "c1-2", "c1-3"]);
+    assert!(newer.has_more);
+
+    assert!(repo.get_message_page("c1", Some("not-a-cursor"), PageDirection::Older, 4).await.is_err());
+}
+
+#[tokio::test]
+async fn receipts_aggregate_into_read_state() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "g1", 0).await;
+    for user in ["bob", "carol", "dave"] {
+        repo.insert_or_update_participant(&participant("g1", user, "member")).await.unwrap();
+    }
+    repo.insert_or_update_message(&message("g1", "local-1", Some("m1"), 1_700_000_000_000)).await.unwrap();
+
+    let at = Timestamp::from_millis(1_700_000_001_000);
+    assert!(repo.record_message_receipt("m1", "bob", ReceiptStatus::Delivered, at).await.unwrap());
+    assert!(repo.record_message_receipt("m1", "carol", ReceiptStatus::Read, at).await.unwrap());
+    assert!(repo.record_message_receipt("m1", "bob", ReceiptStatus::Read, at).await.unwrap());
+    // A late delivery never downgrades a read
+    assert!(!repo.record_message_receipt("m1", "carol", ReceiptStatus::Delivered, at).await.unwrap());
+    // The sender's other devices do not count
+    repo.record_message_receipt("m1", "alice", ReceiptStatus::Read, at).await.unwrap();
+
+    let state = repo.get_message_read_state("m1").await.unwrap().unwrap();
+    assert_eq!((state.read_count, state.delivered_count, state.recipient_count), (2, 2, 3));
+    let readers: Vec<_> = state.readers.iter().map(|r| r.username.as_deref()).collect();
+    assert_eq!(readers, [Some("bob"), Some("carol")]);
+
+    let stored = repo.get_message_by_id("m1").await.unwrap().unwrap();
+    assert_eq!(stored.status, MessageStatus::Read);
+
+    repo.delete_message_by_id("m1").await.unwrap();
+    assert!(repo.get_message_read_state("m1").await.unwrap().is_none());
+    repo.insert_or_update_message(&message("g1", "local-1", Some("m1"), 1_700_000_000_000)).await.unwrap();
+    assert_eq!(repo.get_message_read_state("m1").await.unwrap().unwrap().delivered_count, 0);
+}
+
+#[tokio::test]
+async fn message_status_only_moves_forward() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 0).await;
+    repo.insert_or_update_message(&message("c1", "local-1", None, 1_700_000_000_000)).await.unwrap();
+
+    // Acks may arrive out of order; the furthest one wins
+    repo.update_message_id_by_client("local-1", "m1").await.unwrap();
+    repo.mark_message_read_by_server_id_new("m1").await.unwrap();
+    repo.mark_message_delivered_by_server_id_new("m1").await.unwrap();
+    repo.update_message_sent_status("local-1", true).await.unwrap();
+    assert_eq!(repo.get_message_by_client_id("local-1").await.unwrap().unwrap().status, MessageStatus::Read);
+
+    // A message the server has accepted can no longer fail, and a stale copy does not rewind it
+    assert!(repo.update_message_status("local-1", MessageStatus::Failed).await.unwrap().is_none());
+    repo.insert_or_update_message(&message("c1", "local-1", Some("m1"), 1_700_000_000_000)).await.unwrap();
+    assert_eq!(repo.get_message_by_client_id("local-1").await.unwrap().unwrap().status, MessageStatus::Read);
+
+    // A failed send can be retried
+    repo.insert_or_update_message(&message("c1", "local-2", None, 1_700_000_000_001)).await.unwrap();
+    assert_eq!(repo.update_message_status("local-2", MessageStatus::Failed).await.unwrap().unwrap().status, MessageStatus::Failed);
+    assert_eq!(repo.update_message_status("local-2", MessageStatus::Pending).await.unwrap().unwrap().status, MessageStatus::Pending);
+
+    // The wire format still carries the legacy flags
+    let wire = serde_json::to_value(repo.get_message_by_client_id("local-1").await.unwrap().unwrap()).unwrap();
+    assert_eq!((wire["status"].as_str(), wire["is_sent"].as_bool(), wire["is_failed"].as_bool()), (Some("read"), Some(true), Some(false)));
+}
+
+#[tokio::test]
+async fn chat_summaries_follow_message_writes() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    let me = User {
+        user_id: "alice".to_string(),
+        username: "alice".to_string(),
+        email: None,
+        name: None,
+        password: None,
+        picture: None,
+        role: None,
+        token_hash: Some("token-1".to_string()),
+        verified: true,
+        created_at: Timestamp::from_millis(1_700_000_000_000),
+        updated_at: Timestamp::from_millis(1_700_000_000_000),
+        deleted_at: None,
+        is_dark_mode: false,
+        last_seen: Timestamp::from_millis(1_700_000_000_000),
+        color_scheme: None,
+    };
+    repo.insert_or_update_user(&me).await.unwrap();
+    seed_chat(&repo, "c1", 0).await;
+    seed_chat(&repo, "c2", 0).await;
+
+    // Our own message is not unread, a received one from bob is
+    repo.insert_or_update_message(&message("c1", "mine", Some("m1"), 1_700_000_000_000)).await.unwrap();
+    let from_bob = Message { sender_id: "bob".to_string(), status: MessageStatus::Delivered, ..message("c1", "theirs", Some("m2"), 1_700_000_000_001) };
+    repo.insert_or_update_message(&from_bob).await.unwrap();
+    let summary = repo.get_chat_by_id("c1").await.unwrap().unwrap();
+    assert_eq!(summary.unread_count, 1);
+    assert_eq!(summary.last_message_content.as_deref(), Some("message theirs"));
+    assert_eq!(summary.last_message_timestamp, Some(Timestamp::from_millis(1_700_000_000_001)));
+
+    // The server's chat list carries no summary, so syncing it must not wipe ours
+    repo.insert_or_update_chat(&chat("c1")).await.unwrap();
+    repo.apply_chat_delta(&[chat("c1"), chat("c2")], "alice").await.unwrap();
+    let summary = repo.get_chat_by_id("c1").await.unwrap().unwrap();
+    assert_eq!((summary.unread_count, summary.last_message_content.as_deref()), (1, Some("message theirs")));
+
+    // The most recently active chat comes first
+    repo.insert_or_update_message(&message("c2", "later", Some("m3"), 1_700_000_000_002)).await.unwrap();
+    let order: Vec<_> = repo.get_all_chats().await.unwrap().into_iter().map(|c| c.chat_id).collect();
+    assert_eq!(order, ["c2", "c1"]);
+
+    assert_eq!(repo.count_unread_messages("c1").await.unwrap(), 1);
+    let read = repo.mark_messages_as_read("c1").await.unwrap();
+    assert_eq!(read.iter().map(|m| m.client_message_id.as_str()).collect::<Vec<_>>(), ["theirs"]);
+    assert_eq!(repo.get_chat_by_id("c1").await.unwrap().unwrap().unread_count, 0);
+    // Opening the chat says nothing about whether bob has read ours
+    assert_eq!(repo.get_message_by_client_id("mine").await.unwrap().unwrap().status, MessageStatus::Sent);
+
+    // Deleting the newest message falls back to the one before it
+    repo.delete_message_by_client_id("theirs").await.unwrap();
+    let summary = repo.get_chat_by_id("c1").await.unwrap().unwrap();
+    assert_eq!(summary.last_message_content.as_deref(), Some("message mine"));
+
+    // Signing out keeps our messages our own
+    let again = Message { sender_id: "bob".to_string(), status: MessageStatus::Delivered, ..message("c1", "again", Some("m4"), 1_700_000_000_003) };
+    repo.insert_or_update_message(&again).await.unwrap();
+    repo.update_user_token("alice", "").await.unwrap();
+    assert_eq!(repo.get_chat_by_id("c1").await.unwrap().unwrap().unread_count, 1);
+    assert_eq!(repo.count_unread_messages("c1").await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn retention_purge_removes_expired_messages_and_refreshes_the_summary() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 0).await;
+    seed_chat(&repo, "c2", 0).await;
+    assert!(repo.set_chat_message_ttl("c1", Some(60)).await.unwrap());
+    assert!(repo.set_chat_message_ttl("c2", Some(60)).await.unwrap());
+
+    let now = Timestamp::from_millis(1_700_000_100_000);
+    for (chat_id, client_id, content, millis) in [
+        ("c1", "old-1", "expired note", 1_700_000_000_000),
+        ("c1", "old-2", "expired note", 1_700_000_040_000),
+        ("c1", "new-1", "kept note", 1_700_000_050_000),
+        ("c1", "new-2", "newest note", 1_700_000_090_000),
+        ("c2", "gone", "expired note", 1_700_000_010_000),
+    ] {
+        let row = Message { content: content.to_string(), ..message(chat_id, client_id, Some(client_id), millis) };
+        repo.insert_or_update_message(&row).await.unwrap();
+    }
+    // Without a TTL nothing expires, however old
+    seed_chat(&repo, "c3", 0).await;
+    repo.insert_or_update_message(&message("c3", "ancient", Some("ancient"), 1_600_000_000_000)).await.unwrap();
+
+    let mut expired = repo.purge_expired_messages(now).await.unwrap();
+    expired.sort_by(|a, b| a.chat_id.cmp(&b.chat_id));
+    let purged: Vec<_> = expired.iter().map(|e| (e.chat_id.as_str(), e.client_message_ids.len())).collect();
+    assert_eq!(purged, [("c1", 2), ("c2", 1)]);
+
+    let remaining: Vec<_> = repo.get_messages_for_chat("c1").await.unwrap().into_iter().map(|m| m.client_message_id).collect();
+    assert_eq!(remaining, ["new-1", "new-2"]);
+    assert!(repo.get_messages_for_chat("c2").await.unwrap().is_empty());
+    assert_eq!(repo.get_messages_for_chat("c3").await.unwrap().len(), 1);
+
+    let summary = repo.get_chat_by_id("c1").await.unwrap().unwrap();
+    assert_eq!(summary.last_message_content.as_deref(), Some("newest note"));
+    assert_eq!(summary.last_message_timestamp, Some(Timestamp::from_millis(1_700_000_090_000)));
+    assert_eq!(expired[0].last_message_content.as_deref(), Some("newest note"));
+    // The search index forgets the purged rows too
+    let hits = repo.search_messages("expired", &MessageSearchFilter::default(), 10, None).await.unwrap();
+    assert!(hits.hits.is_empty());
+
+    // A second pass at the same time finds nothing left to do
+    assert!(repo.purge_expired_messages(now).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn remote_history_anchor_skips_unsent_rows() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 0).await;
+    assert_eq!(repo.get_oldest_server_message_id("c1").await.unwrap(), None);
+
+    // The outbox row is older than anything the server has confirmed
+    repo.insert_or_update_message(&message("c1", "local-1", None, 1_700_000_000_000)).await.unwrap();
+    repo.insert_or_update_message(&message("c1", "m1", Some("m1"), 1_700_000_000_001)).await.unwrap();
+    repo.insert_or_update_message(&message("c1", "m2", Some("m2"), 1_700_000_000_002)).await.unwrap();
+    assert_eq!(repo.get_oldest_server_message_id("c1").await.unwrap().as_deref(), Some("m1"));
+}
+
+#[tokio::test]
+async fn chat_delta_syncs_group_description() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    repo.apply_chat_delta(&[chat("c1")], "alice").await.unwrap();
+
+    let described = Chat { description: Some("Weekend plans".to_string()), ..chat("c1") };
+    let result = repo.apply_chat_delta(&[described], "alice").await.unwrap();
+    assert_eq!(result.updated, ["c1"]);
+    let stored = repo.get_chat_by_id("c1").await.unwrap().unwrap();
+    assert_eq!(stored.description.as_deref(), Some("Weekend plans"));
+
+    // A server copy without a description leaves ours alone
+    let result = repo.apply_chat_delta(&[chat("c1")], "alice").await.unwrap();
+    assert!(result.is_empty());
+}
+
+#[tokio::test]
+async fn importing_an_archive_twice_changes_nothing() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    let participants = [participant("c1", "bob", "member")];
+    let messages = [
+        message("c1", "local-1", Some("m1"), 1_700_000_000_000),
+        message("c1", "local-2", Some("m2"), 1_700_000_000_001),
+    ];
+
+    let first = repo.import_chat_archive(&chat("c1"), &participants, &messages).await.unwrap();
+    assert!(first.chat_created);
+    assert_eq!((first.messages_added, first.participants_added), (2, 1));
+    assert!(first.conflicts.is_empty());
+    // Whether the server still has the chat is for the next delta sync to decide
+    let stored = repo.get_chat_by_id("c1").await.unwrap().unwrap();
+    assert!(!stored.is_read_only && !stored.is_local_only);
+
+    let second = repo.import_chat_archive(&chat("c1"), &participants, &messages).await.unwrap();
+    assert!(!second.chat_created);
+    assert_eq!((second.messages_added, second.messages_updated, second.messages_unchanged), (0, 0, 2));
+    assert_eq!(second.participants_added, 0);
+    assert!(second.conflicts.is_empty());
+    assert_eq!(repo.get_messages_for_chat("c1").await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn import_reports_conflicts_and_keeps_the_local_copy() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 0).await;
+    repo.insert_or_update_message(&message("c1", "local-1", Some("m1"), 1_700_000_000_000)).await.unwrap();
+
+    let edited = Message { content: "edited".to_string(), ..message("c1", "local-1", Some("m1"), 1_700_000_000_000) };
+    let elsewhere = message("c2", "local-9", Some("m9"), 1_700_000_000_001);
+    let result = repo.import_chat_archive(&chat("c1"), &[participant("c2", "bob", "member")], &[edited, elsewhere]).await.unwrap();
+
+    let conflicts: Vec<_> = result.conflicts.iter().map(|c| (c.kind.as_str(), c.id.as_str())).collect();
+    assert_eq!(conflicts, [("participant", "c2_bob"), ("message", "m1"), ("message", "m9")]);
+    assert_eq!(result.messages_added, 0);
+    let local = repo.get_message_by_client_id("local-1").await.unwrap().unwrap();
+    assert_eq!(local.content, "message local-1");
+}
+
+#[tokio::test]
+async fn chat_delta_keeps_imported_chats_read_only() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    let history = [message("c1", "local-1", Some("m1"), 1_700_000_000_000)];
+    repo.import_chat_archive(&chat("c1"), &[], &history).await.unwrap();
+    seed_chat(&repo, "c2", 0).await;
+
+    // The server lists neither chat: the imported one stays, read-only and local-only
+    let result = repo.apply_chat_delta(&[], "alice").await.unwrap();
+    assert_eq!(result.updated, ["c1"]);
+    assert_eq!(result.removed, ["c2"]);
+    let stored = repo.get_chat_by_id("c1").await.unwrap().unwrap();
+    assert!(stored.is_read_only && stored.is_local_only);
+    assert_eq!(repo.get_messages_for_chat("c1").await.unwrap().len(), 1);
+
+    // Once the server lists it again it can be used normally
+    repo.apply_chat_delta(&[chat("c1")], "alice").await.unwrap();
+    let stored = repo.get_chat_by_id("c1").await.unwrap().unwrap();
+    assert!(!stored.is_read_only && !stored.is_local_only);
+}
+
+#[tokio::test]
+async fn sender_usernames_are_filled_from_known_profiles() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 0).await;
+    for (client_id, sender_id) in [("m1", "bob"), ("m2", "carol"), ("m3", "dave")] {
+        let message = Message { sender_id: sender_id.to_string(), ..message("c1", client_id, None, 1_700_000_000_000) };
+        repo.insert_or_update_message(&message).await.unwrap();
+    }
+    assert_eq!(repo.get_unresolved_sender_ids().await.unwrap(), ["bob", "carol", "dave"]);
+
+    // A friend's name beats the participant entry; a name that is just the id does not count
+    repo.insert_or_update_participant(&participant("c1", "bob", "member")).await.unwrap();
+    repo.insert_or_update_participant(&Participant { username: "Carol".to_string(), ..participant("c1", "carol", "member") }).await.unwrap();
+    repo.insert_or_update_friend(&friend("carol", "carol_friend")).await.unwrap();
+    let names = repo.get_usernames(&["bob".to_string(), "carol".to_string()]).await.unwrap();
+    assert_eq!(names.get("carol").map(String::as_str), Some("carol_friend"));
+    assert!(!names.contains_key("bob"));
+
+    let filled = repo.fill_sender_usernames().await.unwrap();
+    assert_eq!(filled.len(), 1);
+    assert_eq!(repo.get_message_by_client_id("m2").await.unwrap().unwrap().sender_username.as_deref(), Some("carol_friend"));
+    assert_eq!(repo.get_unresolved_sender_ids().await.unwrap(), ["bob", "dave"]);
+    // Nothing left that can be resolved
+    assert!(repo.fill_sender_usernames().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn writes_publish_change_records() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    let mut changes = repo.changes().subscribe();
+    seed_chat(&repo, "c1", 1).await;
+    assert_eq!(repo.mark_messages_as_read("c1").await.unwrap().len(), 1);
+    repo.delete_chat("c1").await.unwrap();
+
+    let mut records = Vec::new();
+    while let Ok(record) = changes.try_recv() {
+        records.push(record);
+    }
+    let summary: Vec<_> = records.iter().map(|r| (r.table, r.op, r.key.as_deref())).collect();
+    assert_eq!(summary, [
+        (ChangeTable::Chat, ChangeOp::Upsert, Some("c1")),
+        (ChangeTable::Message, ChangeOp::Upsert, Some("c1-0")),
+        // The summary triggers changed the chat as well
+        (ChangeTable::Chat, ChangeOp::Update, Some("c1")),
+        (ChangeTable::Message, ChangeOp::Update, Some("c1-0")),
+        (ChangeTable::Chat, ChangeOp::Update, Some("c1")),
+        (ChangeTable::Chat, ChangeOp::Delete, Some("c1")),
+    ]);
+    assert_eq!(records[2].row.as_ref().unwrap()["unread_count"], 1);
+    assert_eq!(records[3].row.as_ref().unwrap()["is_read"], true);
+    assert_eq!(records[4].row.as_ref().unwrap()["unread_count"], 0);
+}
+
+#[test]
+fn coalescing_keeps_one_record_per_row() {
+    let row = serde_json::json!({});
+    let coalesced = coalesce(vec![
+        ChangeRecord::row(ChangeTable::Message, ChangeOp::Insert, "m1", &row),
+        ChangeRecord::row(ChangeTable::Chat, ChangeOp::Update, "c1", &row),
+        ChangeRecord::row(ChangeTable::Message, ChangeOp::Update, "m1", &row),
+        ChangeRecord::row(ChangeTable::Friend, ChangeOp::Upsert, "f1", &row),
+        ChangeRecord::reset(ChangeTable::Friend),
+        ChangeRecord::delete(ChangeTable::Chat, "c1"),
+    ]);
+
+    let summary: Vec<_> = coalesced.iter().map(|r| (r.table, r.op, r.key.as_deref())).collect();
+    assert_eq!(summary, [
+        (ChangeTable::Message, ChangeOp::Insert, Some("m1")),
+        (ChangeTable::Chat, ChangeOp::Delete, Some("c1")),
+        (ChangeTable::Friend, ChangeOp::Reset, None),
+    ]);
+}
+
+#[tokio::test]
+async fn friend_delta_reports_changes() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    repo.insert_or_update_friend(&friend("u1", "bob")).await.unwrap();
+    repo.insert_or_update_friend(&friend("u2", "carol")).await.unwrap();
+
+    let delta = repo.apply_friend_delta(&[friend("u1", "bobby"), friend("u3", "dave")]).await.unwrap();
+    assert_eq!(delta.added, vec!["u3".to_string()]);
+    assert_eq!(delta.updated, vec!["u1".to_string()]);
+    assert_eq!(delta.removed, vec!["u2".to_string()]);
+    assert_eq!(repo.get_friend_by_id("u1").await.unwrap().unwrap().username, "bobby");
+}
+
+#[tokio::test]
+async fn participant_delta_replaces_chat_members() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    seed_chat(&repo, "c1", 0).await;
+    repo.insert_or_update_participant(&participant("c1", "bob", "member")).await.unwrap();
+
+    let delta = repo
+        .apply_participant_delta("c1", &[participant("c1", "bob", "admin"), participant("c1", "carol", "member")])
+        .await
+        .unwrap();
+    assert_eq!(delta.added.len(), 1);
+    assert_eq!(delta.updated.len(), 1);
+
+    let bob = repo.get_participant_by_user_id_and_chat_id("bob", "c1").await.unwrap().unwrap();
+    assert_eq!(bob.role, "admin");
+}
+
+#[tokio::test]
+async fn users_are_found_by_token() {
+    let repo = SqliteRepo::in_memory().await.unwrap();
+    let user = User {
+        user_id: "u1".to_string(),
+        username: "alice".to_string(),
+        email: None,
+        name: None,
+        password: None,
+        picture: None,
+        role: None,
+        token_hash: Some("token-1".to_string()),
+        verified: true,
+        created_at: Timestamp::from_millis(1_700_000_000_000),
+        updated_at: Timestamp::from_millis(1_700_000_000_000),
+        deleted_at: None,
+        is_dark_mode: false,
+        last_seen: Timestamp::from_millis(1_700_000_000_000),
+        color_scheme: None,
+    };
+    let mut changes = repo.changes().subscribe();
+    repo.insert_or_update_user(&user).await.unwrap();
+    // Credentials never reach the change feed
+    let published = changes.try_recv().unwrap().row.unwrap();
+    assert!(published["token_hash"].is_null());
+
+    assert_eq!(repo.get_user_by_token("token-1").await.unwrap().unwrap().user_id, "u1");
+    repo.update_dark_mode("u1", true).await.unwrap();
+    assert!(repo.get_dark_mode("u1").await.unwrap());
+
+    // The read receipt setting defaults on and survives the user being saved again
+    assert!(repo.get_send_read_receipts("u1").await.unwrap());
+    repo.update_send_read_receipts("u1", false).await.unwrap();
+    repo.insert_or_update_user(&user).await.unwrap();
+    assert!(!repo.get_send_read_receipts("u1").await.unwrap());
+}
+
+#[tokio::test]
+async fn maintenance_pragmas_run_on_a_file_backed_repo() {
+    let path = std::env::temp_dir().join(format!("repo-maintenance-{}.db", uuid::Uuid::new_v4()));
+    let repo = SqliteRepo::new(path.clone());
+    seed_chat(&repo, "c1", 20).await;
+    repo.delete_chat("c1").await.unwrap();
+
+    let checkpoint = repo.wal_checkpoint("passive").await.unwrap();
+    assert_eq!(checkpoint.journal_mode, "wal");
+    assert!(!checkpoint.busy);
+
+    // New files already use incremental auto-vacuum, so this runs the pragma itself
+    let vacuum = repo.incremental_vacuum(None).await.unwrap();
+    assert!(!vacuum.converted);
+    assert_eq!(vacuum.freelist_pages_after, 0);
+
+    drop(repo);
+    for suffix in ["", "-wal", "-shm"] {
+        std::fs::remove_file(format!("{}{}", path.display(), suffix)).ok();
+    }
+}
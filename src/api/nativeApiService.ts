@@ -99,14 +99,6 @@ export class NativeApiService {
     return await invoke('get_cached_chats_for_current_user_filtered', { token });
   }
 
-  async updateChatUnreadCount(chat_id: string, unread_count: number): Promise<void> {
-    return await invoke('db_update_chat_unread_count', { chat_id, unread_count });
-  }
-
-  async updateChatLastMessage(chat_id: string, content?: string, timestamp?: number): Promise<void> {
-    return await invoke('db_update_chat_last_message', { chat_id, content, timestamp });
-  }
-
   async deleteChat(chat_id: string): Promise<void> {
     return await invoke('db_delete_chat_by_id', { chat_id });
   }
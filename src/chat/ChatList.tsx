@@ -13,6 +13,7 @@ import { Chat } from '../models/models';
 import { chatService } from '../services/chatService';
 import { sessionManager } from '../utils/sessionManager';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { backgroundSyncManager } from '../services/backgroundSyncManager';
 
 // Add CSS for spinner animation
@@ -623,6 +624,41 @@ const ChatList: React.FC<ChatListProps> = ({ onSelect, onOpenChatOptions, onTogg
     };
   }, [loadChats, user?.user_id]);
 
+  // Reload once a chat delta sync has changed the cached chats
+  useEffect(() => {
+    const unlisten = listen('chats-delta', () => {
+      loadChats();
+    });
+    
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [loadChats]);
+
+  // An imported archive may have added a chat
+  useEffect(() => {
+    const unlisten = listen('chat-imported', () => {
+      loadChats();
+    });
+    
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [loadChats]);
+
+  // Expired messages may have been the last message of a chat
+  useEffect(() => {
+    const handleMessagesExpired = () => {
+      loadChats();
+    };
+
+    window.addEventListener('messages-expired', handleMessagesExpired);
+    
+    return () => {
+      window.removeEventListener('messages-expired', handleMessagesExpired);
+    };
+  }, [loadChats]);
+
   // Trigger background sync when the ChatList is opened
   useEffect(() => {
     const triggerSync = async () => {
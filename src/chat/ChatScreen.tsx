@@ -353,6 +353,7 @@ export const ChatScreen: React.FC<ChatScreenProps> = ({ chatId, onClose }) => {
           console.log(`[ChatScreen] Chat found, resolving data...`);
           const chatData = await resolveChatData(chat, chatId);
           setChatData(chatData);
+          setIsReadOnly(!!chat.is_read_only);
           console.log(`[ChatScreen] Chat data resolved: ${chatData.chatName}`);
         } else {
           console.warn("[ChatScreen] No chat found for chatId:", chatId);
@@ -442,6 +443,7 @@ export const ChatScreen: React.FC<ChatScreenProps> = ({ chatId, onClose }) => {
     participantNames: []
   });
   const [currentUserId, setCurrentUserId] = useState<string>('');
+  const [isReadOnly, setIsReadOnly] = useState(false);
 
   // Group messages by date for better organization
   const groupMessagesByDate = useMemo(() => {
@@ -572,6 +574,22 @@ export const ChatScreen: React.FC<ChatScreenProps> = ({ chatId, onClose }) => {
     };
   }, [refreshMessagesFromDatabase]);
 
+  // Drop disappearing messages once the retention task has deleted them
+  useEffect(() => {
+    const handleMessagesExpired = (event: Event) => {
+      const { chatId: expiredChatId, messageIds, clientMessageIds } = (event as CustomEvent).detail;
+      if (expiredChatId !== chatId) return;
+      const expired = new Set<string>([...messageIds, ...clientMessageIds]);
+      setMessages(prev => prev.filter(m => !(m.message_id && expired.has(m.message_id)) && !expired.has(m.client_message_id)));
+    };
+
+    window.addEventListener('messages-expired', handleMessagesExpired);
+    
+    return () => {
+      window.removeEventListener('messages-expired', handleMessagesExpired);
+    };
+  }, [chatId]);
+
   if (!chatId) {
     return (
       <div style={{
@@ -891,6 +909,16 @@ export const ChatScreen: React.FC<ChatScreenProps> = ({ chatId, onClose }) => {
           position: 'sticky',
           bottom: 0
         }}>
+          {isReadOnly ? (
+          <div style={{
+            padding: '6px 0',
+            textAlign: 'center',
+            color: theme.textSecondary,
+            fontSize: '13px'
+          }}>
+            This chat was imported from an archive and is read-only
+          </div>
+          ) : (
           <div style={{
             display: 'flex',
             alignItems: 'flex-end',
@@ -954,6 +982,7 @@ export const ChatScreen: React.FC<ChatScreenProps> = ({ chatId, onClose }) => {
               </svg>
             </button>
           </div>
+          )}
         </div>
       </div>
     </div>
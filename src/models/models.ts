@@ -33,6 +33,8 @@ export interface MessageEntity {
   is_sent: boolean;
   is_delivered: boolean;
   is_failed: boolean;
+  // Delivery state stored by the backend; the is_* flags are derived from it
+  status?: MessageSendStatus;
   sender_username?: string;
   reply_to_message_id?: string;
   // UI-only fields
@@ -44,6 +46,7 @@ export interface MessageEntity {
 
 // Message send status enum
 export enum MessageSendStatus {
+  PENDING = "pending",
   SENT = "sent",
   DELIVERED = "delivered", 
   READ = "read",
@@ -222,6 +225,9 @@ export interface Chat {
   unread_count: number;
   last_message_content?: string;
   last_message_timestamp?: number;
+  // Imported chats the server no longer has
+  is_local_only?: boolean;
+  is_read_only?: boolean;
 }
 
 export interface Friend {
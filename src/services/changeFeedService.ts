@@ -0,0 +1,80 @@
+import { listen } from '@tauri-apps/api/event';
+
+// Fans the backend's batched `db-changed` events out to per-table subscribers, so
+// views can patch themselves instead of re-querying after every command.
+class ChangeFeedService {
+  private handlers: Map<ChangeTable, Set<(change: ChangeRecord) => void>> = new Map();
+  private lagHandlers: Set<() => void> = new Set();
+
+  constructor() {
+    listen<DbChangedPayload>('db-changed', (event) => {
+      this.dispatch(event.payload);
+    }).catch(error => {
+      console.error("[ChangeFeedService] Failed to set up db-changed listener:", error);
+    });
+  }
+
+  // Returns an unsubscribe function
+  subscribe(table: ChangeTable, handler: (change: ChangeRecord) => void): () => void {
+    let handlers = this.handlers.get(table);
+    if (!handlers) {
+      handlers = new Set();
+      this.handlers.set(table, handlers);
+    }
+    handlers.add(handler);
+    return () => handlers?.delete(handler);
+  }
+
+  // Called when changes were dropped before reaching the frontend; reload from the database
+  onLagged(handler: () => void): () => void {
+    this.lagHandlers.add(handler);
+    return () => this.lagHandlers.delete(handler);
+  }
+
+  private dispatch(payload: DbChangedPayload) {
+    if (payload.lagged) {
+      console.warn("[ChangeFeedService] Change feed lagged, asking views to reload");
+      this.lagHandlers.forEach(handler => handler());
+    }
+    for (const change of payload.changes) {
+      this.handlers.get(change.table)?.forEach(handler => {
+        try {
+          handler(change);
+        } catch (error) {
+          console.error(`[ChangeFeedService] ${change.table} handler failed:`, error);
+        }
+      });
+    }
+  }
+}
+
+export const changeFeedService = new ChangeFeedService();
+
+export type ChangeTable =
+  | 'user'
+  | 'user_keys'
+  | 'chat'
+  | 'chat_local_delete'
+  | 'message'
+  | 'message_receipt'
+  | 'friend'
+  | 'participant'
+  | 'presence';
+
+// 'upsert' when the backend could not tell an insert from an update;
+// 'reset' when the whole table was cleared or replaced
+export type ChangeOp = 'insert' | 'update' | 'upsert' | 'delete' | 'reset';
+
+export interface ChangeRecord {
+  table: ChangeTable;
+  op: ChangeOp;
+  // Natural id of the row (client_message_id for messages); null for resets
+  key: string | null;
+  // Row after the change; null for deletes and resets
+  row: any | null;
+}
+
+export interface DbChangedPayload {
+  changes: ChangeRecord[];
+  lagged: boolean;
+}
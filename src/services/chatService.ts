@@ -6,6 +6,38 @@ import { sessionManager } from '../utils/sessionManager';
 import { Chat } from '../models/models';
 import { apiService } from '../api/apiService';
 
+// Ids changed by a delta sync; also emitted as chats-delta, friends-delta and participants-delta (with chat_id)
+export interface DeltaResult {
+  added: string[];
+  updated: string[];
+  removed: string[];
+}
+
+export type ExportFormat = "json" | "markdown" | "html";
+
+export interface ExportSummary {
+  path: string;
+  format: ExportFormat;
+  message_count: number;
+  bytes: number;
+}
+
+export interface ImportConflict {
+  kind: "message" | "participant";
+  id: string;
+  reason: string;
+}
+
+export interface ImportResult {
+  chat_id: string;
+  chat_created: boolean;
+  messages_added: number;
+  messages_updated: number;
+  messages_unchanged: number;
+  participants_added: number;
+  conflicts: ImportConflict[];
+}
+
 interface ChatNotificationEvent {
   detail: {
     type: string;
@@ -87,18 +119,8 @@ export class ChatService {
       if (message.type === 'chat' && message.message) {
         const chatMessage = message.message;
         console.log("[ChatService] Processing incoming chat message:", chatMessage);
-        
-        // Update chat last message and unread count
-        if (chatMessage.chat_id && chatMessage.content) {
-          const timestamp = new Date(chatMessage.sent_at).getTime();
-          await this.updateChatLastMessage(chatMessage.chat_id, chatMessage.content, timestamp);
-          // Get current unread count and increment it
-          const currentChat = await this.getChatById(chatMessage.chat_id);
-          if (currentChat) {
-            const newUnreadCount = (currentChat.unread_count || 0) + 1;
-            await this.updateChatUnreadCount(chatMessage.chat_id, newUnreadCount);
-          }
-        }
+        // The last message and unread count are kept up to date by the database when
+        // the message is stored, and arrive through the db-changed events
       }
     } catch (error) {
       console.error("[ChatService] Error handling incoming chat message:", error);
@@ -195,8 +217,8 @@ export class ChatService {
       }
 
       // Use chats_delta_update instead of fetch_all_chats_and_save to avoid re-adding deleted chats
-      await invoke("chats_delta_update", { token });
-      console.log("[ChatService] All chats synced successfully");
+      const delta = await invoke<DeltaResult>("chats_delta_update", { token });
+      console.log("[ChatService] All chats synced successfully:", delta);
     } catch (error) {
       console.error("[ChatService] Failed to sync all chats:", error);
     }
@@ -217,8 +239,8 @@ export class ChatService {
       }
 
       // Use chats_delta_update instead of fetch_all_chats_and_save to avoid re-adding deleted chats
-      await invoke("chats_delta_update", { token });
-      console.log("[ChatService] Chats delta sync completed successfully");
+      const delta = await invoke<DeltaResult>("chats_delta_update", { token });
+      console.log("[ChatService] Chats delta sync completed successfully:", delta);
     } catch (error) {
       console.error("[ChatService] Failed to sync chats delta:", error);
     }
@@ -234,24 +256,6 @@ export class ChatService {
     }
   }
 
-  async updateChatLastMessage(chat_id: string, last_message: string, timestamp: number): Promise<void> {
-    try {
-      await databaseServiceAsync.updateChatLastMessage(chat_id, last_message, timestamp);
-      console.log(`[ChatService] Chat last message updated for ${chat_id}`);
-    } catch (error) {
-      console.error(`[ChatService] Failed to update chat last message for ${chat_id}:`, error);
-    }
-  }
-
-  async updateChatUnreadCount(chat_id: string, unread_count: number): Promise<void> {
-    try {
-      await databaseServiceAsync.updateChatUnreadCount(chat_id, unread_count);
-      console.log(`[ChatService] Chat unread count updated for ${chat_id}: ${unread_count}`);
-    } catch (error) {
-      console.error(`[ChatService] Failed to update chat unread count for ${chat_id}:`, error);
-    }
-  }
-
   async deleteChat(chat_id: string): Promise<void> {
     try {
       const token = await this.getToken();
@@ -357,6 +361,22 @@ export class ChatService {
       throw error;
     }
   }
+
+  // JSON exports can be imported again; markdown and html are for reading
+  async exportChat(chat_id: string, format: ExportFormat, path: string): Promise<ExportSummary> {
+    const summary = await invoke<ExportSummary>("export_chat", { chatId: chat_id, format, path });
+    console.log(`[ChatService] Exported ${summary.message_count} messages to ${summary.path}`);
+    return summary;
+  }
+
+  // Emits chat-imported; a chat the server no longer has turns read-only at the next chat sync
+  async importChatArchive(path: string): Promise<ImportResult> {
+    const result = await invoke<ImportResult>("import_chat_archive", { path });
+    if (result.conflicts.length > 0) {
+      console.warn(`[ChatService] Import of ${result.chat_id} had ${result.conflicts.length} conflicts:`, result.conflicts);
+    }
+    return result;
+  }
 }
 
 export const chatService = ChatService.getInstance(); 
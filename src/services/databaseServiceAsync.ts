@@ -40,6 +40,14 @@ export class DatabaseServiceAsync {
     return await invoke<boolean>('db_get_dark_mode', { userId: user_id });
   }
 
+  async update_send_read_receipts(user_id: string, enabled: boolean): Promise<void> {
+    return await invoke('db_update_send_read_receipts', { userId: user_id, enabled });
+  }
+
+  async get_send_read_receipts(user_id: string): Promise<boolean> {
+    return await invoke<boolean>('db_get_send_read_receipts', { userId: user_id });
+  }
+
   async clearUserData(): Promise<void> {
     return await invoke('db_clear_user_data');
   }
@@ -57,14 +65,6 @@ export class DatabaseServiceAsync {
     return await invoke<Chat[]>('db_get_all_chats');
   }
 
-  async updateChatUnreadCount(chat_id: string, unread_count: number): Promise<void> {
-    await invoke('db_update_chat_unread_count', { chatId: chat_id, unreadCount: unread_count });
-  }
-
-  async updateChatLastMessage(chat_id: string, content?: string, timestamp?: number): Promise<void> {
-    await invoke('db_update_chat_last_message', { chatId: chat_id, content, timestamp });
-  }
-
   async deleteChatById(chat_id: string): Promise<void> {
     await invoke('db_delete_chat_by_id', { chatId: chat_id });
   }
@@ -116,6 +116,11 @@ export class DatabaseServiceAsync {
     await invoke('db_update_message_sent_status', { clientMessageId: client_message_id, isSent: is_sent });
   }
 
+  // Transitions that would move the message backwards are ignored and return null
+  async updateMessageStatus(client_message_id: string, status: MessageSendStatus): Promise<Message | null> {
+    return await invoke<Message | null>('db_update_message_status', { clientMessageId: client_message_id, status });
+  }
+
   async updateMessageSentStatusByServerId(server_id: string, is_sent: boolean): Promise<void> {
     console.log("[DatabaseService] updateMessageSentStatusByServerId called with:", { server_id, is_sent });
     console.log("[DatabaseService] Invoking with parameters:", { serverId: server_id, isSent: is_sent });
@@ -248,6 +253,38 @@ export class DatabaseServiceAsync {
     return await invoke<DatabaseStats>('db_get_stats');
   }
 
+  // Backup operations
+  async backupDatabase(path: string): Promise<BackupInfo> {
+    return await invoke<BackupInfo>('db_backup', { path });
+  }
+
+  // Emits database-restored on success; cached state should be reloaded afterwards
+  async restoreDatabase(path: string): Promise<RestoreReport> {
+    return await invoke<RestoreReport>('db_restore', { path });
+  }
+
+  async integrityCheck(full = false): Promise<IntegrityReport> {
+    return await invoke<IntegrityReport>('db_integrity_check', { full });
+  }
+
+  // Omit pages to free every page on the freelist
+  async incrementalVacuum(pages?: number): Promise<VacuumReport> {
+    return await invoke<VacuumReport>('db_incremental_vacuum', { pages });
+  }
+
+  async walCheckpoint(mode: CheckpointMode = 'passive'): Promise<CheckpointReport> {
+    return await invoke<CheckpointReport>('db_wal_checkpoint', { mode });
+  }
+
+  async getStorageReport(): Promise<StorageReport> {
+    return await invoke<StorageReport>('db_storage_report');
+  }
+
+  // Fails if the database is healthy; emits database-recovered on success
+  async recoverDatabase(): Promise<RecoveryReport> {
+    return await invoke<RecoveryReport>('db_recover');
+  }
+
   async performHealthCheck(): Promise<boolean> {
     try {
       const result = await this.healthCheck();
@@ -328,3 +365,82 @@ export enum MessageSendStatus {
   READ = "read",
   FAILED = "failed"
 } 
+
+export interface BackupInfo {
+  path: string;
+  size_bytes: number;
+  schema_version: number;
+  created_at: number;
+}
+
+export interface RestoreReport {
+  restored_from: string;
+  safety_copy: string | null;
+  backup_version: number;
+  schema_version: number;
+}
+
+export interface IntegrityReport {
+  mode: 'integrity_check' | 'quick_check';
+  ok: boolean;
+  problems: string[];
+}
+
+export interface VacuumReport {
+  converted: boolean;
+  freelist_pages_before: number;
+  freelist_pages_after: number;
+  bytes_reclaimed: number;
+}
+
+export type CheckpointMode = 'passive' | 'full' | 'restart' | 'truncate';
+
+export interface CheckpointReport {
+  mode: CheckpointMode;
+  journal_mode: string;
+  busy: boolean;
+  wal_pages: number;
+  checkpointed_pages: number;
+}
+
+export interface ObjectSize {
+  name: string;
+  kind: string;
+  bytes: number;
+}
+
+export interface ChatStorage {
+  chat_id: string;
+  name: string | null;
+  message_count: number;
+  approx_bytes: number;
+}
+
+export interface AgeBucket {
+  label: 'last_24_hours' | 'last_7_days' | 'last_30_days' | 'last_year' | 'older';
+  message_count: number;
+}
+
+export interface StorageReport {
+  file_bytes: number;
+  wal_bytes: number;
+  page_size: number;
+  page_count: number;
+  freelist_pages: number;
+  tables: ObjectSize[];
+  indexes: ObjectSize[];
+  chats: ChatStorage[];
+  message_age: AgeBucket[];
+}
+
+export interface TableRecovery {
+  table: string;
+  copied: number;
+  unreadable: number;
+}
+
+export interface RecoveryReport {
+  problems: string[];
+  damaged_copy: string;
+  tables: TableRecovery[];
+}
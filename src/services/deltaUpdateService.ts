@@ -116,7 +116,8 @@ export class DeltaUpdateService {
           content: message.content,
           timestamp: new Date(message.sent_at || message.timestamp).getTime(),
           is_read: Boolean(message.is_read),
-          is_sent: Boolean(message.is_sent),
+          // Anything the server returns has been sent
+          is_sent: true,
           is_delivered: Boolean(message.is_delivered),
           is_failed: Boolean(message.is_failed),
           reply_to_message_id: message.reply_to_message_id || undefined
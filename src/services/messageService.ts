@@ -2,13 +2,13 @@ import { databaseServiceAsync } from './databaseServiceAsync';
 import { encryptionService } from '../encrypt/encryptionService';
 import { messageLinkingManager } from '../linking/messageLinkingManager';
 import { invoke } from '@tauri-apps/api/core';
-import { listen } from '@tauri-apps/api/event';
-import { MessageEntity, Message } from "../models/models";
+import { MessageEntity, Message, MessageSendStatus } from "../models/models";
 import { sessionManager } from "../utils/sessionManager";
 import { websocketService } from '../websocket/websocketService';
 import { chatService } from './chatService';
 import { normalizeTimestamp } from '../utils/timestampUtils';
 import { participantService } from '../participant/participantService';
+import { changeFeedService } from './changeFeedService';
 
 /**
  * MessageService - Handles all message operations
@@ -36,6 +36,7 @@ export class MessageService {
   
   // FIXED: Track processed chat notifications to prevent duplicates
   private processedChatNotifications: Set<string> = new Set();
+  private unsubscribeMessageChanges: (() => void) | null = null;
 
   constructor() {
     console.log("[MessageService] Constructor called - creating new MessageService instance");
@@ -86,13 +87,13 @@ export class MessageService {
     try {
       console.log("[MessageService] Setting up Tauri event listeners...");
 
-      // Listen for test events to verify event system
-      listen<{ payload: unknown }>("test-event", async (event) => {
-        console.log("[MessageService] Test event received:", event.payload);
-      }).then(() => {
-        console.log("[MessageService] test-event listener set up successfully");
-      }).catch(error => {
-        console.error("[MessageService] Failed to set up test-event listener:", error);
+      // New unread rows are messages that just arrived; backfilled history and
+      // imports are stored as read and show up through paging instead
+      this.unsubscribeMessageChanges?.();
+      this.unsubscribeMessageChanges = changeFeedService.subscribe('message', (change) => {
+        if (change.op === 'insert' && change.row && !change.row.is_read) {
+          this.handleMessageSaved(change.row as MessageSavedPayload);
+        }
       });
 
       console.log("[MessageService] All Tauri event listeners set up successfully");
@@ -185,7 +186,7 @@ export class MessageService {
         chat_id: chat_id,
         sender_id: sender_id,
         content: content, // Store decrypted content in database
-        timestamp: Date.now() * 1_000_000, // Convert to nanoseconds for consistent precision
+        timestamp: messageEntity.timestamp, // Milliseconds, the unit the database stores
         is_read: false,
         is_sent: false,
         is_delivered: false,
@@ -251,7 +252,7 @@ export class MessageService {
         chat_id: chat_id,
         sender_id: sender_id,
         content: content, // Store decrypted content in database
-        timestamp: Date.now() * 1_000_000, // Convert to nanoseconds for consistent precision
+        timestamp: messageEntity.timestamp, // Milliseconds, the unit the database stores
         is_read: false,
         is_sent: false,
         is_delivered: false,
@@ -457,6 +458,36 @@ export class MessageService {
     }
   }
 
+  async getMessagePage(chatId: string, options: MessagePageOptions = {}): Promise<MessagePage> {
+    // Older pages fall through to the server once local history runs out
+    const token = await sessionManager.getToken();
+    return await invoke<MessagePage>('get_message_page', {
+      chatId,
+      cursor: options.cursor ?? null,
+      direction: options.direction ?? 'older',
+      limit: options.limit ?? null,
+      token: token ?? null
+    });
+  }
+
+  async getMessageReadState(messageId: string): Promise<MessageReadState> {
+    // "Read by read_count of recipient_count"
+    return await invoke<MessageReadState>('get_message_read_state', { messageId });
+  }
+
+  async searchMessages(query: string, options: MessageSearchOptions = {}): Promise<MessageSearchPage> {
+    // Snippets wrap matched terms in \u0002 ... \u0003 for highlighting
+    return await invoke<MessageSearchPage>('search_messages', {
+      query,
+      chatId: options.chatId ?? null,
+      senderId: options.senderId ?? null,
+      from: options.from ?? null,
+      to: options.to ?? null,
+      limit: options.limit ?? null,
+      cursor: options.cursor ?? null
+    });
+  }
+
   async getMessageById(message_id: string): Promise<MessageEntity | null> {
     try {
       const message = await databaseServiceAsync.getMessageById(message_id);
@@ -574,6 +605,11 @@ export class MessageService {
         throw new Error("No current user ID available");
       }
 
+      const chat = await chatService.getChatById(chatId);
+      if (chat?.is_read_only) {
+        throw new Error("This chat is read-only");
+      }
+
       const clientMessageId = generateUUID();
 
       // DEBUG: Check if content is already encrypted
@@ -804,9 +840,13 @@ export class MessageService {
           // Handle status updates (sent, delivered, read)
           await this.handleIncomingMessageStatus(messageData);
           break;
-        case "message-saved":
-          console.log("[MessageService] Message-saved event received, processing...");
-          await this.handleMessageSaved(messageData.message || messageData);
+        case "message-updated":
+          console.log("[MessageService] Message-updated event received, processing...");
+          await this.handleMessageUpdated(messageData.message || messageData);
+          break;
+        case "messages-expired":
+          console.log("[MessageService] Messages-expired event received, processing...");
+          this.handleMessagesExpired(messageData.message || messageData);
           break;
         case "message-status-update":
           console.log("[MessageService] Message-status-update event received, processing...");
@@ -1060,7 +1100,7 @@ export class MessageService {
     }
   }
 
-  // Handle message saved events from Rust backend
+  // Handle message rows inserted by the Rust backend (from the db-changed feed)
   async handleMessageSaved(payload: MessageSavedPayload) {
     try {
       console.log("[MessageService] handleMessageSaved called with payload:", payload);
@@ -1122,6 +1162,48 @@ export class MessageService {
     }
   }
 
+  // Handle message updated events from Rust backend (server copy merged into an existing row,
+  // or a sender name filled in after the message was stored)
+  async handleMessageUpdated(payload: MessageUpdatedPayload) {
+    try {
+      const { message_id, client_message_id, chat_id, is_read, is_delivered } = payload;
+      console.log("[MessageService] Message updated event received:", { message_id, client_message_id, chat_id });
+
+      // The row already exists in the UI under its client ID - refresh it instead of adding a duplicate
+      const status = is_read ? "read" : is_delivered ? "delivered" : "sent";
+      window.dispatchEvent(new CustomEvent('message-status-updated', {
+        detail: { messageId: message_id, clientMessageId: client_message_id, status }
+      }));
+    } catch (error) {
+      console.error("[MessageService] Error handling message updated event:", error);
+    }
+  }
+
+  handleMessagesExpired(payload: MessagesExpiredPayload) {
+    const { chat_id, message_ids, client_message_ids, last_message_content, last_message_timestamp } = payload;
+    console.log(`[MessageService] ${client_message_ids.length} messages expired in chat ${chat_id}`);
+
+    // Already deleted in the database; the open chat view and chat list just need to catch up
+    window.dispatchEvent(new CustomEvent('messages-expired', {
+      detail: {
+        chatId: chat_id,
+        messageIds: message_ids,
+        clientMessageIds: client_message_ids,
+        lastMessageContent: last_message_content,
+        lastMessageTimestamp: last_message_timestamp != null ? normalizeTimestamp(last_message_timestamp) : null
+      }
+    }));
+  }
+
+  async setChatMessageTtl(chatId: string, ttlSeconds: number | null): Promise<void> {
+    // null turns disappearing messages off
+    await invoke('set_chat_message_ttl', { chatId, ttlSeconds });
+  }
+
+  async getChatMessageTtl(chatId: string): Promise<number | null> {
+    return await invoke<number | null>('get_chat_message_ttl', { chatId });
+  }
+
   // Handle chat notifications (new chats created with current user)
   async handleChatNotification(messageData: any) {
     try {
@@ -2202,6 +2284,30 @@ interface MessageSavedPayload {
   reply_to_message_id?: string;
 }
 
+interface MessageUpdatedPayload {
+  message_id?: string;
+  client_message_id: string;
+  chat_id: string;
+  sender_id: string;
+  content: string;
+  timestamp: number;
+  is_read: boolean;
+  is_sent: boolean;
+  is_delivered: boolean;
+  is_failed: boolean;
+  status: MessageSendStatus;
+  sender_username?: string;
+  reply_to_message_id?: string;
+}
+
+interface MessagesExpiredPayload {
+  chat_id: string;
+  message_ids: string[];
+  client_message_ids: string[];
+  last_message_content: string | null;
+  last_message_timestamp: number | null;
+}
+
 interface ChatNotificationPayload {
   type: string;
   chat_id: string;
@@ -2234,3 +2340,61 @@ interface RequestNotificationPayload {
   chat_id?: string;
   message?: string;
 }
+
+export interface MessageSearchOptions {
+  chatId?: string;
+  senderId?: string;
+  from?: number;
+  to?: number;
+  limit?: number;
+  cursor?: string;
+}
+
+export interface MessageSearchHit {
+  message: Message;
+  chat_name: string | null;
+  is_group: boolean;
+  snippet: string;
+  rank: number;
+  previous_content: string | null;
+  next_content: string | null;
+}
+
+export interface MessageSearchPage {
+  hits: MessageSearchHit[];
+  next_cursor: string | null;
+}
+
+export type PageDirection = 'older' | 'newer';
+
+export interface MessagePageOptions {
+  cursor?: string;
+  direction?: PageDirection;
+  limit?: number;
+}
+
+export interface MessagePage {
+  // Oldest first; next_cursor continues in the same direction
+  messages: Message[];
+  next_cursor: string | null;
+  has_more: boolean;
+}
+
+export type ReceiptStatus = 'delivered' | 'read';
+
+export interface MessageReceipt {
+  message_id: string;
+  user_id: string;
+  username: string | null;
+  status: ReceiptStatus;
+  at: number;
+}
+
+export interface MessageReadState {
+  message_id: string;
+  chat_id: string;
+  recipient_count: number;
+  delivered_count: number;
+  read_count: number;
+  readers: MessageReceipt[];
+}
@@ -29,6 +29,26 @@ export class SettingsService {
     }
   }
 
+  // Delivery receipts are always sent; this only controls read receipts
+  async update_send_read_receipts(user_id: string, enabled: boolean): Promise<void> {
+    try {
+      await databaseServiceAsync.update_send_read_receipts(user_id, enabled);
+      console.log(`[SettingsService] Read receipts ${enabled ? 'enabled' : 'disabled'} for user ${user_id}`);
+    } catch (error) {
+      console.error('[SettingsService] Failed to update read receipt setting:', error);
+      throw error;
+    }
+  }
+
+  async get_send_read_receipts(user_id: string): Promise<boolean> {
+    try {
+      return await databaseServiceAsync.get_send_read_receipts(user_id);
+    } catch (error) {
+      console.error('[SettingsService] Failed to get read receipt setting:', error);
+      return true;
+    }
+  }
+
   async update_color_scheme(user_id: string, color_scheme: string): Promise<void> {
     try {
       await databaseServiceAsync.update_color_scheme(user_id, color_scheme);
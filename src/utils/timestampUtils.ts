@@ -131,18 +131,27 @@ export function getRelativeDateLabel(timestamp: number): string {
 }
 
 /**
- * Ensure timestamp is in milliseconds (convert from seconds or nanoseconds if needed)
+ * Ensure timestamp is in milliseconds (convert from seconds, microseconds or nanoseconds if needed).
+ * The database stores milliseconds; the bands match Timestamp::from_any on the Rust side.
  */
 export function ensureMilliseconds(timestamp: number): number {
-  // If timestamp is in nanoseconds (greater than 1000000000000000000), convert to milliseconds
-  if (timestamp > 1000000000000000000) {
-    return Math.floor(timestamp / 1_000_000);
+  if (timestamp <= 0) {
+    return timestamp;
   }
-  // If timestamp is in seconds (less than 10000000000), convert to milliseconds
-  if (timestamp < 10000000000) {
+  // Seconds
+  if (timestamp < 1e11) {
     return timestamp * 1000;
   }
-  return timestamp;
+  // Milliseconds
+  if (timestamp < 1e14) {
+    return timestamp;
+  }
+  // Microseconds
+  if (timestamp < 1e17) {
+    return Math.floor(timestamp / 1000);
+  }
+  // Nanoseconds
+  return Math.floor(timestamp / 1_000_000);
 }
 
 /**
@@ -101,9 +101,12 @@ export type WebSocketMessageData =
   | { type: "request-notification"; message: FriendRequestNotification }
   | { type: "chat-notification"; message: ChatNotification }
   | { type: "error"; message: ErrorMessage }
-  | { type: "message-saved"; message: any }
+  | { type: "message-updated"; message: any }
+  | { type: "messages-expired"; message: any }
   | { type: "message-status-update"; message: any }
   | { type: "typing"; message: any }
+  | { type: "typing-update"; message: any }
+  | { type: "presence-changed"; message: any }
   | { type: "read-receipt"; message: any };
 
 export class WebSocketService {
@@ -115,6 +118,7 @@ export class WebSocketService {
   private lastHeartbeat: number = 0;
   private maxReconnectAttempts: number = 5;
   private heartbeatInterval: number = 30000;
+  private lastActivityReport: number = 0;
 
   constructor() {
     this.setupEventListeners();
@@ -152,19 +156,70 @@ export class WebSocketService {
       console.error("[WebSocketService] Failed to set up message listener:", error);
     });
 
-    // Listen for message-saved events from Rust backend
-    listen<any>("message-saved", (event) => {
-      console.log("[WebSocketService] Received message-saved event:", event.payload);
+    // Listen for message-updated events (incoming message merged into an existing row)
+    listen<any>("message-updated", (event) => {
+      console.log("[WebSocketService] Received message-updated event:", event.payload);
       if (event.payload) {
         this.notifyMessageHandlers({
-          type: "message-saved",
+          type: "message-updated",
           message: event.payload
         });
       }
     }).catch(error => {
-      console.error("[WebSocketService] Failed to set up message-saved listener:", error);
+      console.error("[WebSocketService] Failed to set up message-updated listener:", error);
     });
 
+    // Listen for messages-expired events (disappearing messages removed by the retention task)
+    listen<any>("messages-expired", (event) => {
+      if (event.payload) {
+        this.notifyMessageHandlers({
+          type: "messages-expired",
+          message: event.payload
+        });
+      }
+    }).catch(error => {
+      console.error("[WebSocketService] Failed to set up messages-expired listener:", error);
+    });
+
+    // Listen for typing-update events (who is typing in a chat)
+    listen<any>("typing-update", (event) => {
+      if (event.payload) {
+        this.notifyMessageHandlers({
+          type: "typing-update",
+          message: event.payload
+        });
+      }
+    }).catch(error => {
+      console.error("[WebSocketService] Failed to set up typing-update listener:", error);
+    });
+
+    // Listen for presence-changed events (friend/participant online, away or offline)
+    listen<any>("presence-changed", (event) => {
+      if (event.payload) {
+        this.notifyMessageHandlers({
+          type: "presence-changed",
+          message: event.payload
+        });
+      }
+    }).catch(error => {
+      console.error("[WebSocketService] Failed to set up presence-changed listener:", error);
+    });
+
+    // Report user activity so the backend can switch between online and away
+    const reportActivity = () => {
+      const now = Date.now();
+      if (now - this.lastActivityReport < 30000) return;
+      this.lastActivityReport = now;
+      invoke("report_user_activity").catch(error => {
+        console.warn("[WebSocketService] Failed to report user activity:", error);
+      });
+    };
+    if (typeof window !== "undefined") {
+      ["mousemove", "keydown", "mousedown", "focus"].forEach(eventName => {
+        window.addEventListener(eventName, reportActivity, { passive: true });
+      });
+    }
+
     // Listen for message-status-update events from Rust backend
     listen<any>("message-status-update", (event) => {
       console.log("[WebSocketService] Received message-status-update event:", event.payload);
@@ -486,16 +541,20 @@ export class WebSocketService {
   }
 
   async sendTypingIndicator(chatId: string, isTyping: boolean): Promise<void> {
-    const message: WebSocketMessageData = {
-      type: "typing",
-      message: {
-        chat_id: chatId,
-        is_typing: isTyping,
-        timestamp: new Date().toISOString()
-      } as any
-    };
-    
-    await this.sendMessage(message);
+    // Throttled in the backend; returns false when nothing was sent
+    try {
+      await invoke<boolean>("set_typing", { chatId, isTyping });
+    } catch (error) {
+      console.warn("[WebSocketService] Failed to send typing indicator:", error);
+    }
+  }
+
+  async getDiagnostics(): Promise<Record<string, any>> {
+    return await invoke("get_websocket_diagnostics");
+  }
+
+  async getPresence(userIds: string[]): Promise<Array<{ user_id: string; status: "online" | "away" | "offline"; last_seen: number | null }>> {
+    return await invoke("get_presence", { userIds });
   }
 
   async sendReadReceipt(chatId: string, messageId: string): Promise<void> {